@@ -0,0 +1,86 @@
+//! Rolls the same expressions with two evaluators under the same seeds and
+//! shows where they disagree, for triaging the bugs of the interpreter.
+//!
+//! `dicemind-diverge --against value "3x(4d6kh3)"` checks one expression
+//! under many seeds, without an expression the expressions are generated.
+
+use clap::{arg, value_parser, ArgAction, Command};
+use dicemind::{
+    divergence::{diverge, Evaluation},
+    generate::Constraints,
+    prelude::*,
+};
+use rand::{rngs::StdRng, SeedableRng};
+
+fn command() -> Command {
+    Command::new("dicemind-diverge")
+        .about("Find the expressions two evaluators roll differently under the same seed")
+        .arg(
+            arg!([EXPR] "The expression to check, generated ones are checked without it")
+                .value_parser(value_parser!(String)),
+        )
+        .arg(
+            arg!(--seeds <N> "How many seeds, or generated expressions, to try")
+                .value_parser(value_parser!(u64))
+                .num_args(1)
+                .action(ArgAction::Set),
+        )
+        .arg(
+            arg!(--against <EVALUATOR> "What the total of the roll is compared with")
+                .value_parser(["value", "display"])
+                .num_args(1)
+                .action(ArgAction::Set),
+        )
+}
+
+fn roll(expr: &Expression, seed: u64) -> Evaluation {
+    StandardNaiveRoller::new_seeded(seed)
+        .roll_naive(expr)
+        .and_then(|value| value.total())
+}
+
+// The total of the value is documented to be the total of the naive value
+fn value(expr: &Expression, seed: u64) -> Evaluation {
+    StandardNaiveRoller::new_seeded(seed)
+        .roll(expr)
+        .and_then(|value| value.total())
+}
+
+// The displayed expression should parse back into one that rolls the same
+fn display(expr: &Expression, seed: u64) -> Evaluation {
+    let displayed = expr.to_string();
+    let parsed = parse(&displayed).unwrap_or_else(|err| panic!("`{displayed}`: {err}"));
+    roll(&parsed, seed)
+}
+
+fn main() {
+    let m = command().get_matches();
+
+    let seeds = m.get_one::<u64>("seeds").copied().unwrap_or(1000);
+    let against: fn(&Expression, u64) -> Evaluation =
+        match m.get_one::<String>("against").map(String::as_str) {
+            Some("display") => display,
+            _ => value,
+        };
+
+    let expr = m.get_one::<String>("EXPR").map(|input| {
+        parse(input).unwrap_or_else(|err| {
+            eprintln!("err. {err}");
+            std::process::exit(2);
+        })
+    });
+
+    for seed in 0..seeds {
+        let expr = expr.clone().unwrap_or_else(|| {
+            Expression::arbitrary(&mut StdRng::seed_from_u64(seed), &Constraints::default())
+        });
+
+        if let Some(divergence) = diverge(&expr, seed, roll, against) {
+            println!("in `{expr}`");
+            print!("{divergence}");
+            std::process::exit(1);
+        }
+    }
+
+    println!("ok. no divergence in {seeds} rolls");
+}
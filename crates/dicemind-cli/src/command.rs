@@ -1,7 +1,21 @@
+use std::path::PathBuf;
+
 use clap::{arg, value_parser, ArgAction, Command};
 
 pub fn command() -> Command {
     Command::new("dicemind")
+        .subcommand(
+            Command::new("doctor")
+                .about("Check that the dice are fair and the expressions roll, for bug reports"),
+        )
+        .subcommand(
+            Command::new("schedule")
+                .about("Roll the rolls of a schedule when they are due, posting them to their webhooks")
+                .arg(
+                    arg!(<FILE> "One roll a line, like `0 9 * * * @encounter -> https://...`")
+                        .value_parser(value_parser!(PathBuf)),
+                ),
+        )
         .subcommand(
             Command::new("simulate")
                 .short_flag('s')
@@ -34,7 +48,56 @@ pub fn command() -> Command {
                 .action(ArgAction::Append),
         )
         .arg(
-            arg!(--seed)
+            arg!(--seed <SEED> "A number or a phrase like crimson-otter-42")
+                .value_parser(value_parser!(String))
+                .num_args(1)
+                .action(ArgAction::Set),
+        )
+        .arg(
+            arg!(--macros <FILE> "A file with macro definitions like `attack := d20 + 7`")
+                .value_parser(value_parser!(PathBuf))
+                .num_args(1)
+                .action(ArgAction::Set),
+        )
+        .arg(
+            arg!(--notify <URL> "Post every roll to a webhook, Discord webhooks are recognized")
+                .value_parser(value_parser!(String))
+                .action(ArgAction::Append),
+        )
+        .arg(
+            arg!(--"show-seed" "Print the seed phrase alongside every roll")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(--art "Draw the dice of a single dice roll, the d6 as their faces")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(--"at-least" <TARGET> "Print the chance of rolling the target or more, like a DC")
+                .value_parser(value_parser!(i64))
+                .num_args(1)
+                .action(ArgAction::Set),
+        )
+        .arg(
+            arg!(-v --verbose "Print every dice term with its dice, like `4d6kh3: [5, 6, 3, ~2~] = 14`")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(--explain "Explain how every roll came to its total, step by step")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(--json "Print every roll as a JSON object on a line of its own, for the scripts")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["art", "explain", "at-least", "verbose"]),
+        )
+        .arg(
+            arg!(--ndjson "Stream every roll as a JSON line with its seed and the time it was rolled")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["art", "explain", "at-least", "verbose"]),
+        )
+        .arg(
+            arg!(--repeat <N> "Roll every expression N times")
                 .value_parser(value_parser!(u64))
                 .num_args(1)
                 .action(ArgAction::Set),
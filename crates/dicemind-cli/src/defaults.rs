@@ -1,3 +1,4 @@
 pub const DEFAULT_TRIALS: u64 = 10_000;
 pub const DEFAULT_HEIGHT: u32 = 20;
 pub const DEFAULT_WIDTH: u32 = 60;
+pub const CHART_COLOR: (u8, u8, u8) = (114, 159, 207);
@@ -0,0 +1,135 @@
+use std::path::Path;
+
+use dicemind::prelude::*;
+
+// Expressions every build should handle, with the bounds of their totals
+const CORPUS: &[(&str, i64, i64)] = &[
+    ("d20", 1, 20),
+    ("2d6 + 3", 5, 15),
+    ("2d20kh", 1, 20),
+    ("4d6kh3", 3, 18),
+    ("(2d6 + 2) * (2d20kh + 3 + 2 > 13)", 0, 14),
+    ("10d10s>=8f<=1", -10, 10),
+    ("{2d6, 1d8 + 3}kh", 4, 12),
+    ("6x(4d6kh3)", 18, 108),
+    ("max(2d6, d12)", 2, 12),
+    ("abs(d6 - d6)", 0, 5),
+    ("-(1 + 2) * 3", -9, -9),
+];
+
+// Chi-squared with 5 degrees of freedom at p = 0.001
+const CHI_SQUARED_D6: f64 = 20.515;
+const SMOKE_ROLLS: usize = 6000;
+
+struct Report {
+    failures: usize,
+}
+
+impl Report {
+    fn check(&mut self, name: &str, result: Result<String, String>) {
+        match result {
+            Ok(msg) => println!("ok.  {name}: {msg}"),
+            Err(msg) => {
+                self.failures += 1;
+                println!("err. {name}: {msg}");
+            }
+        }
+    }
+}
+
+fn rng_distribution() -> Result<String, String> {
+    let value = StandardNaiveRoller::default()
+        .roll_naive(parse(&format!("{SMOKE_ROLLS}d6")).map_err(|e| e.to_string())?)
+        .map_err(|e| e.to_string())?;
+
+    let mut faces = [0usize; 6];
+    for d in value.dice() {
+        faces[(d.value - 1) as usize] += 1;
+    }
+
+    let expected = SMOKE_ROLLS as f64 / 6.;
+    let chi_squared: f64 = faces
+        .iter()
+        .map(|&n| (n as f64 - expected).powi(2) / expected)
+        .sum();
+
+    let msg = format!("{SMOKE_ROLLS}d6 came up {faces:?}, chi-squared {chi_squared:.2}");
+    if chi_squared < CHI_SQUARED_D6 {
+        Ok(msg)
+    } else {
+        // One in a thousand healthy runs ends up here, so try again first
+        Err(format!("{msg}, the dice look biased"))
+    }
+}
+
+fn rng_determinism() -> Result<String, String> {
+    let phrase = random_seed_phrase();
+    let expr = parse("20d20").map_err(|e| e.to_string())?;
+
+    let mut rolls = (0..2).map(|_| {
+        StandardNaiveRoller::new_seeded(seed_from_phrase(&phrase))
+            .roll_naive(&expr)
+            .map(|value| value.dice().map(|d| d.value).collect::<Vec<_>>())
+    });
+
+    if rolls.next() == rolls.next() {
+        Ok(format!("`{phrase}` replays the same rolls"))
+    } else {
+        Err(format!("`{phrase}` rolled differently twice"))
+    }
+}
+
+fn corpus() -> Result<String, String> {
+    let mut roller = StandardNaiveRoller::default();
+
+    for &(input, min, max) in CORPUS {
+        let expr = parse(input).map_err(|e| format!("`{input}` does not parse: {e}"))?;
+        let total = roller
+            .roll_naive(expr)
+            .and_then(|value| value.total())
+            .map_err(|e| format!("`{input}` does not roll: {e}"))?;
+
+        if !(min..=max).contains(&total) {
+            return Err(format!("`{input}` rolled {total}, outside of {min}..={max}"));
+        }
+    }
+
+    Ok(format!("{} expressions parsed and rolled", CORPUS.len()))
+}
+
+fn macros(path: &Path) -> Result<String, String> {
+    let source = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+
+    let mut macros = MacroRegistry::new();
+    let names = macros
+        .load(&source)
+        .map_err(|e| format!("{}: {e}", path.display()))?;
+
+    Ok(format!("{} defines {} macros", path.display(), names.len()))
+}
+
+/// Run the self-checks and print the environment, for bug reports.
+/// Returns whether every check passed.
+pub fn doctor(macros_path: Option<&Path>) -> bool {
+    println!("dicemind-cli {}", env!("CARGO_PKG_VERSION"));
+    println!(
+        "platform {} {} ({})",
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        std::env::consts::FAMILY
+    );
+    if let Ok(term) = std::env::var("TERM") {
+        println!("terminal {term}");
+    }
+    println!();
+
+    let mut report = Report { failures: 0 };
+    report.check("rng", rng_distribution());
+    report.check("seeds", rng_determinism());
+    report.check("corpus", corpus());
+    if let Some(path) = macros_path {
+        report.check("macros", macros(path));
+    }
+
+    report.failures == 0
+}
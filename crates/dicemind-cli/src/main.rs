@@ -1,14 +1,27 @@
 #![feature(coroutines, coroutine_trait, iter_from_coroutine)]
 
-use defaults::{DEFAULT_HEIGHT, DEFAULT_TRIALS, DEFAULT_WIDTH};
-use dicemind::prelude::*;
+use defaults::{CHART_COLOR, DEFAULT_HEIGHT, DEFAULT_TRIALS, DEFAULT_WIDTH};
+use dicemind::{
+    distribution::distribution,
+    explain::explain_trace,
+    interpreter::{evaluate_constant, NaiveResult, NaiveValue, Provenance, Trace},
+    prelude::*,
+    render::{render_breakdown, render_roll, RenderOptions},
+    schedule::Schedule,
+    sinks::{RollEvent, Sink, Sinks, WebhookSink},
+};
 use human_panic::setup_panic;
-use simulate::{print_chart, SimulationOptions};
-use std::error::Error;
+use report::RollReport;
+use schedule::run_schedule;
+use simulate::{print_chart, run_trials, SimulationOptions};
+use std::{error::Error, path::PathBuf};
 
 mod command;
 mod defaults;
+mod doctor;
 mod options;
+mod report;
+mod schedule;
 mod simulate;
 
 use command::*;
@@ -16,42 +29,255 @@ use options::*;
 
 fn repl(
     inputs: impl Iterator<Item = Result<String, Box<dyn Error + 'static>>>,
-    action: impl Fn(Expression) -> Result<(), Box<dyn Error + 'static>>,
+    json: bool,
+    action: impl Fn(u64, &str, Expression) -> Result<(), Box<dyn Error + 'static>>,
 ) -> Result<(), Box<dyn Error + 'static>> {
-    for input in inputs {
+    for (line, input) in (0..).zip(inputs) {
         let input = input?;
-        match parse(&input) {
-            Ok(expr) => action(expr)?,
-            Err(err) => println!("err. {err}"),
+        match parse_all_errors(&input) {
+            (Some(expr), diagnostics) if diagnostics.is_empty() => action(line, &input, expr)?,
+            (_, diagnostics) if json => {
+                let errors = diagnostics.iter().map(ToString::to_string);
+                let report = RollReport::unparsed(&input, errors.collect::<Vec<_>>().join("; "));
+                println!("{}", report.to_json());
+            }
+            (_, diagnostics) => {
+                for diagnostic in diagnostics {
+                    // Lines up with the input after the `dice? ` prompt
+                    println!("      {}", diagnostic.underline());
+                    println!("err. {diagnostic}");
+                }
+            }
         }
     }
 
     Ok(())
 }
 
-fn roll(expr: Expression) -> Result<(), Box<dyn Error + 'static>> {
-    let mut fast_roller = StandardNaiveRoller::default();
+/// The result with the trace, the provenance of the dice and the seed
+/// phrase it was rolled with, if any. The trace is only recorded to be
+/// explained, it is empty otherwise.
+fn roll_dice(
+    seed: &SeedOptions,
+    options: &RollerOptions,
+    expr: &Expression,
+    explain: bool,
+    record_dice: bool,
+) -> (NaiveResult, Trace, Provenance, Option<String>) {
+    // A fresh phrase for every roll, so any of them can be replayed
+    let phrase = seed
+        .phrase
+        .clone()
+        .or_else(|| seed.show.then(random_seed_phrase));
 
-    println!("{:?}", fast_roller.roll(expr));
+    let fast_roller = match &phrase {
+        // Editing the expression keeps the rolls of the untouched terms
+        Some(phrase) => {
+            StandardNaiveRoller::new_seeded(seed_from_phrase(phrase)).with_isolated_streams()
+        }
+        None => StandardNaiveRoller::default(),
+    }
+    .with_options(options.clone());
+    let fast_roller = match record_dice {
+        true => fast_roller.with_provenance(),
+        false => fast_roller,
+    };
+    let mut fast_roller = match explain {
+        true => fast_roller.with_trace(),
+        false => fast_roller,
+    };
 
-    Ok(())
+    let result = fast_roller.roll_naive(expr);
+    let trace = fast_roller.trace().cloned().unwrap_or_default();
+    let provenance = fast_roller.provenance().cloned().unwrap_or_default();
+    (result, trace, provenance, phrase)
+}
+
+fn roll(
+    seed: SeedOptions,
+    options: RollerOptions,
+    sinks: Sinks,
+    render: Option<RenderOptions>,
+    explain: bool,
+    at_least: Option<i64>,
+    output: OutputOptions,
+) -> impl Fn(u64, &str, Expression) -> Result<(), Box<dyn Error + 'static>> {
+    move |line, input, expr| {
+        for repetition in 0..output.repeat {
+            let seed = seed.line(line).repetition(repetition);
+
+            // `2+2*10` needs neither a roller nor a seed
+            let (result, trace, provenance, phrase) = match evaluate_constant(&expr) {
+                Some(total) => (
+                    total.map(NaiveValue::Constant),
+                    Trace::default(),
+                    Provenance::default(),
+                    None,
+                ),
+                // The report lists every dice, which only the provenance keeps
+                None => roll_dice(
+                    &seed,
+                    &options,
+                    &expr,
+                    explain,
+                    output.verbose || output.json,
+                ),
+            };
+
+            if output.json {
+                let report = RollReport::new(input, &expr, &result, &provenance, phrase);
+                let report = if output.timestamps {
+                    report.with_timestamp()
+                } else {
+                    report
+                };
+                println!("{}", report.to_json());
+                notify(&sinks, input, result);
+                continue;
+            }
+
+            if let Some(phrase) = phrase.filter(|_| seed.show) {
+                println!("seed. {phrase}");
+            }
+            println!("{:?}", result);
+
+            if output.verbose && result.is_ok() {
+                for line in render_breakdown(&expr, &provenance, &RenderOptions::default()) {
+                    println!("breakdown. {line}");
+                }
+            }
+
+            if explain {
+                println!("explain. {}", explain_trace(&expr, &trace, &result));
+            }
+
+            if let Some(target) = at_least {
+                match distribution(&expr, &options) {
+                    Ok(chances) => println!(
+                        "chance. {:.2}% to roll {target} or more",
+                        chances.prob_at_least(target) * 100.
+                    ),
+                    Err(err) => println!("err. {err}"),
+                }
+            }
+
+            let dice = render
+                .zip(result.as_ref().ok())
+                .and_then(|(render, value)| render_roll(&expr, value, &render));
+            if let Some(dice) = dice {
+                println!("dice. {dice}");
+            }
+
+            notify(&sinks, input, result);
+        }
+
+        Ok(())
+    }
+}
+
+fn notify(sinks: &Sinks, input: &str, result: NaiveResult) {
+    if let (Ok(value), false) = (result, sinks.is_empty()) {
+        let actor = std::env::var("USER").unwrap_or_else(|_| "dicemind".to_string());
+        if let Err(err) = sinks.notify(&RollEvent::new(actor, input, value)) {
+            println!("err. {err}");
+        }
+    }
 }
 
 fn sim(
-    _options: SimulationOptions,
-    _display: DisplayOptions,
-) -> Box<dyn Fn(Expression) -> Result<(), Box<dyn Error + 'static>>> {
-    todo!()
+    trials: u64,
+    seed: SeedOptions,
+    roller_options: RollerOptions,
+    display: DisplayOptions,
+) -> impl Fn(u64, &str, Expression) -> Result<(), Box<dyn Error + 'static>> {
+    move |line, _, expr| {
+        let options = SimulationOptions {
+            trials,
+            seed: seed.line(line).phrase.as_deref().map(seed_from_phrase),
+        };
+        let histogram = match run_trials(&expr, options, &roller_options) {
+            Ok(histogram) => histogram,
+            Err(err) => {
+                println!("err. {err}");
+                return Ok(());
+            }
+        };
+
+        print_chart(display, std::iter::once((CHART_COLOR, &histogram)));
+        if let (Some(mean), Some(mode), Some(min), Some(max)) = (
+            histogram.mean(),
+            histogram.mode(),
+            histogram.min(),
+            histogram.max(),
+        ) {
+            println!(
+                "sim. {} trials, mean {mean:.2}, mode {mode}, from {min} to {max}",
+                histogram.trials()
+            );
+        }
+
+        Ok(())
+    }
 }
 
 pub fn main() -> Result<(), Box<dyn Error + 'static>> {
     setup_panic!();
     let m = command().get_matches();
 
-    let inputs = input_method_from_args(&m);
+    if let Some(("doctor", _)) = m.subcommand() {
+        let healthy = doctor::doctor(m.get_one::<PathBuf>("macros").map(PathBuf::as_path));
+        std::process::exit(if healthy { 0 } else { 1 });
+    }
+
+    // Every streamed record can be replayed from its seed
+    let stream = m.get_flag("ndjson");
+    let output = OutputOptions {
+        json: stream || m.get_flag("json"),
+        timestamps: stream,
+        verbose: m.get_flag("verbose"),
+        repeat: m.get_one::<u64>("repeat").copied().unwrap_or(1),
+    };
+    let inputs = input_method_from_args(&m, output.json);
+    let seed = SeedOptions {
+        phrase: m.get_one::<String>("seed").cloned(),
+        show: stream || m.get_flag("show-seed"),
+    };
+
+    let mut sinks = Sinks::default();
+    for url in m.get_many::<String>("notify").into_iter().flatten() {
+        sinks.push(WebhookSink::from_url(url));
+    }
+
+    let mut macros = MacroRegistry::default();
+    if let Some(path) = m.get_one::<PathBuf>("macros") {
+        macros.load(&std::fs::read_to_string(path)?)?;
+    }
+    let roller_options = RollerOptions::builder().macros(macros).build();
 
     match m.subcommand() {
-        None => repl(inputs, roll)?,
+        None => {
+            let render = m.get_flag("art").then(RenderOptions::art);
+            let explain = m.get_flag("explain");
+            let at_least = m.get_one::<i64>("at-least").copied();
+            repl(
+                inputs,
+                output.json,
+                roll(
+                    seed,
+                    roller_options,
+                    sinks,
+                    render,
+                    explain,
+                    at_least,
+                    output,
+                ),
+            )?
+        }
+        Some(("schedule", c)) => {
+            let path = c.get_one::<PathBuf>("FILE").expect("the file is required");
+            let schedule = Schedule::load(&std::fs::read_to_string(path)?)?;
+            run_schedule(&schedule, &roller_options, &sinks)?;
+        }
         Some(("simulate", c)) => {
             let trials = c
                 .get_one::<u64>("trials")
@@ -66,8 +292,11 @@ pub fn main() -> Result<(), Box<dyn Error + 'static>> {
 
             repl(
                 inputs,
+                false,
                 sim(
-                    SimulationOptions { trials },
+                    trials,
+                    seed,
+                    roller_options,
                     DisplayOptions { height, width },
                 ),
             )?;
@@ -1,6 +1,6 @@
 use std::{
     error::Error,
-    io::{stdout, Write},
+    io::{stdin, stdout, Write},
 };
 
 use clap::ArgMatches;
@@ -12,6 +12,55 @@ pub struct DisplayOptions {
     pub width: u32,
 }
 
+/// How many times every expression is rolled and how the rolls are printed
+#[derive(Debug, Default, Hash, Clone, Copy)]
+pub struct OutputOptions {
+    pub json: bool,
+    /// Every JSON record has the time it was rolled at
+    pub timestamps: bool,
+    /// Every dice term is printed with its dice
+    pub verbose: bool,
+    pub repeat: u64,
+}
+
+#[derive(Debug, Default, Hash, Clone)]
+pub struct SeedOptions {
+    pub phrase: Option<String>,
+    pub show: bool,
+}
+
+impl SeedOptions {
+    /// The seed of the expression on the `n`th input line, counting from
+    /// 0. The first line keeps the phrase, the others add `#` and their
+    /// number to it, `crimson-otter-42#2` replays the third.
+    pub fn line(&self, n: u64) -> SeedOptions {
+        let phrase = self.phrase.as_ref().map(|phrase| match n {
+            0 => phrase.clone(),
+            n => format!("{phrase}#{n}"),
+        });
+
+        SeedOptions {
+            phrase,
+            show: self.show,
+        }
+    }
+
+    /// The seed of the `n`th roll of the same expression, counting from 0.
+    /// The first roll keeps the phrase, the others add their number to it,
+    /// `crimson-otter-42/2` replays the third.
+    pub fn repetition(&self, n: u64) -> SeedOptions {
+        let phrase = self.phrase.as_ref().map(|phrase| match n {
+            0 => phrase.clone(),
+            n => format!("{phrase}/{n}"),
+        });
+
+        SeedOptions {
+            phrase,
+            show: self.show,
+        }
+    }
+}
+
 fn stdin_input() -> impl Iterator<Item = Result<String, Box<dyn Error + 'static>>> {
     std::iter::from_coroutine({
         || {
@@ -53,24 +102,36 @@ fn stdin_input() -> impl Iterator<Item = Result<String, Box<dyn Error + 'static>
     })
 }
 
+// One expression a line without a prompt, for the scripts piping them in
+fn piped_input() -> impl Iterator<Item = Result<String, Box<dyn Error + 'static>>> {
+    stdin()
+        .lines()
+        .map(|line| line.map(|line| line.trim().to_string()))
+        .filter(|line| !matches!(line, Ok(line) if line.is_empty()))
+        .map(|line| line.map_err(|err| err.into()))
+}
+
+/// The expressions from the arguments or the standard input, `quiet`
+/// neither echoes nor prompts for them so that only the results are printed
 pub fn input_method_from_args(
     args: &ArgMatches,
+    quiet: bool,
 ) -> Box<dyn Iterator<Item = Result<String, Box<dyn Error + 'static>>>> {
     // XXX: A bit of a mess
     let exprs: Option<Vec<_>> = args
         .get_many::<String>("exprs")
         .map(|iter| iter.cloned().collect::<Vec<_>>());
 
-    if let Some(exprs) = exprs {
-        Box::new(
-            exprs
-                .into_iter()
-                .map(|s| -> Result<String, Box<dyn Error + 'static>> {
+    match (exprs, quiet) {
+        (Some(exprs), quiet) => Box::new(exprs.into_iter().map(
+            move |s| -> Result<String, Box<dyn Error + 'static>> {
+                if !quiet {
                     println!("dice? {}", s);
-                    Ok(s)
-                }),
-        )
-    } else {
-        Box::new(stdin_input())
+                }
+                Ok(s)
+            },
+        )),
+        (None, true) => Box::new(piped_input()),
+        (None, false) => Box::new(stdin_input()),
     }
 }
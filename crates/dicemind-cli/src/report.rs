@@ -0,0 +1,133 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use dicemind::{
+    interpreter::{NaiveResult, Provenance, TaggedDiceRoll},
+    syntax::{AnnotationString, Expression},
+};
+use serde::Serialize;
+
+/// A roll as a single JSON object for `--json`, for the scripts that
+/// would otherwise scrape `ok. 14`
+#[derive(Debug, Clone, Serialize)]
+pub struct RollReport {
+    pub input: String,
+    /// The expression as it was understood, normalized, `None` when the
+    /// input did not parse
+    pub expression: Option<String>,
+    pub total: Option<i64>,
+    pub dice: Vec<DiceReport>,
+    pub annotations: Vec<AnnotationString>,
+    pub seed: Option<String>,
+    pub error: Option<String>,
+    /// When the roll was made in milliseconds since the Unix epoch, only
+    /// the streamed records have it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiceReport {
+    pub value: i64,
+    /// `discarded`, `exploded`, `success` and the rest of the tags in
+    /// lower case
+    pub tags: Vec<String>,
+}
+
+impl From<&TaggedDiceRoll> for DiceReport {
+    fn from(dice: &TaggedDiceRoll) -> Self {
+        Self {
+            value: dice.value,
+            tags: dice
+                .tag
+                .iter_names()
+                .map(|(name, _)| name.to_lowercase())
+                .collect(),
+        }
+    }
+}
+
+impl RollReport {
+    /// The dice come from the provenance, the value of `4d6kh3 + d8` is a
+    /// sum and keeps none of them
+    pub fn new(
+        input: &str,
+        expr: &Expression,
+        result: &NaiveResult,
+        provenance: &Provenance,
+        seed: Option<String>,
+    ) -> Self {
+        // In the order of the nodes, the outer annotations first
+        let annotations = expr
+            .nodes()
+            .into_iter()
+            .filter_map(|node| match node {
+                Expression::Annotated { annotation, .. } => Some(annotation.clone()),
+                _ => None,
+            })
+            .collect();
+
+        let total = result.clone().and_then(|value| value.total());
+
+        Self {
+            input: input.to_string(),
+            expression: Some(expr.clone().normalize().to_string()),
+            total: total.as_ref().ok().copied(),
+            dice: provenance.dice().iter().map(DiceReport::from).collect(),
+            annotations,
+            seed,
+            error: total.err().map(|err| err.to_string()),
+            timestamp: None,
+        }
+    }
+
+    /// An input that did not parse, with its errors
+    pub fn unparsed(input: &str, error: impl ToString) -> Self {
+        Self {
+            input: input.to_string(),
+            expression: None,
+            total: None,
+            dice: vec![],
+            annotations: vec![],
+            seed: None,
+            error: Some(error.to_string()),
+            timestamp: None,
+        }
+    }
+
+    /// Stamp the report with the current time
+    pub fn with_timestamp(mut self) -> Self {
+        let since_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        self.timestamp = Some(since_epoch.as_millis() as u64);
+        self
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("a report is always valid JSON")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dicemind::{interpreter::StandardNaiveRoller, parser::parse};
+
+    use super::RollReport;
+
+    #[test]
+    fn test_report_dice() {
+        let expr = parse("4d6kh3 [str] + d8").unwrap();
+        let mut roller = StandardNaiveRoller::new_seeded(7).with_provenance();
+        let result = roller.roll_naive(&expr);
+        let report = RollReport::new("", &expr, &result, roller.provenance().unwrap(), None);
+
+        assert_eq!(report.dice.len(), 5);
+        let discarded = report
+            .dice
+            .iter()
+            .filter(|d| d.tags.iter().any(|t| t == "discarded"));
+        assert_eq!(discarded.count(), 1);
+        assert_eq!(report.annotations.len(), 1);
+        assert_eq!(report.total, result.unwrap().total().ok());
+    }
+}
@@ -0,0 +1,61 @@
+use std::{
+    error::Error,
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use dicemind::{
+    prelude::*,
+    schedule::Schedule,
+    sinks::{RollEvent, Sink, Sinks, WebhookSink},
+};
+
+/// Roll every roll of the schedule when it is due, for as long as there
+/// is one left. The rolls with a webhook of their own are posted there,
+/// the rest to the `--notify` sinks.
+pub fn run_schedule(
+    schedule: &Schedule,
+    options: &RollerOptions,
+    sinks: &Sinks,
+) -> Result<(), Box<dyn Error + 'static>> {
+    let mut roller = StandardNaiveRoller::default().with_options(options.clone());
+    let mut now = unix_now();
+
+    while let Some((at, rolls)) = schedule.next_after(now) {
+        thread::sleep(Duration::from_secs((at - unix_now()).max(0) as u64));
+
+        for roll in rolls {
+            let rolled = roller
+                .roll_naive(&roll.expression)
+                .and_then(|value| Ok((value.total()?, value)));
+            let (total, value) = match rolled {
+                Ok(rolled) => rolled,
+                Err(err) => {
+                    println!("err. {}: {err}", roll.source);
+                    continue;
+                }
+            };
+            println!("sched. {}: {total}", roll.source);
+
+            let event = RollEvent::new("schedule", &roll.source, value);
+            let posted = match &roll.webhook {
+                Some(url) => WebhookSink::from_url(url).notify(&event),
+                None => sinks.notify(&event),
+            };
+            if let Err(err) = posted {
+                println!("err. {err}");
+            }
+        }
+
+        now = at;
+    }
+
+    Ok(())
+}
+
+fn unix_now() -> i64 {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    since_epoch.as_secs() as i64
+}
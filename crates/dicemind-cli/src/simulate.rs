@@ -1,6 +1,11 @@
-use std::{collections::HashMap, error::Error};
-
-use dicemind::{interpreter::StandardNaiveRoller, syntax::Expression};
+use dicemind::{
+    histogram::Histogram,
+    interpreter::RollerResult,
+    prelude::RollerOptions,
+    seed::derive_seed,
+    simulation::{simulate, simulate_seeded},
+    syntax::Expression,
+};
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use textplots::{Chart, ColorPlot, Shape};
 
@@ -9,25 +14,60 @@ use crate::DisplayOptions;
 #[derive(Debug, Default, Hash, PartialEq, Clone, Copy)]
 pub struct SimulationOptions {
     pub trials: u64,
+    pub seed: Option<u64>,
+}
+
+// The batches of a seeded simulation, as many on any machine so that the
+// seed gives the same histogram everywhere
+const SEEDED_BATCHES: u64 = 64;
+
+/// Roll the expression over every core, a batch of the trials on each with
+/// a roller of its own, and add the batches up. With a seed every batch is
+/// seeded with one derived from it and the number of the batch.
+pub fn run_trials(
+    expr: &Expression,
+    simulation_options: SimulationOptions,
+    roller_options: &RollerOptions,
+) -> RollerResult<Histogram> {
+    let SimulationOptions { trials, seed } = simulation_options;
+    let batches = match seed {
+        Some(_) => SEEDED_BATCHES,
+        None => rayon::current_num_threads().max(1) as u64,
+    };
+
+    (0..batches)
+        .into_par_iter()
+        .map(|batch| {
+            // The first batches take the trials left over
+            let trials = trials / batches + u64::from(batch < trials % batches);
+            match seed {
+                Some(seed) => {
+                    simulate_seeded(expr, trials, roller_options, derive_seed(seed, batch))
+                }
+                None => simulate(expr, trials, roller_options),
+            }
+        })
+        .try_reduce(Histogram::new, |mut histogram, batch| {
+            histogram.merge(&batch);
+            Ok(histogram)
+        })
 }
 
 pub fn print_chart<'a>(
     display_options: DisplayOptions,
-    frequency_tables: impl Iterator<Item = ((u8, u8, u8), &'a Vec<(i64, i64)>)>,
+    histograms: impl Iterator<Item = ((u8, u8, u8), &'a Histogram)>,
 ) {
     let DisplayOptions { height, width, .. } = display_options;
 
-    for (color, table) in frequency_tables {
-        let (min_x, max_x, max_y) = table
+    for (color, histogram) in histograms {
+        let (Some(min_x), Some(max_x)) = (histogram.min(), histogram.max()) else {
+            continue;
+        };
+        let max_y = histogram.iter().map(|(_, n)| n).max().unwrap_or(1);
+
+        let values: Vec<_> = histogram
             .iter()
-            .cloned()
-            .fold((i64::MAX, i64::MIN, 0), |(xmi, xma, yma), (x, y)| {
-                (xmi.min(x), xma.max(x), yma.max(y))
-            });
-
-        let values: Vec<_> = table
-            .into_iter()
-            .map(|&(a, b)| (a as f32, (b as f64 / max_y as f64) as f32))
+            .map(|(a, b)| (a as f32, (b as f64 / max_y as f64) as f32))
             .collect();
 
         Chart::new_with_y_range(width, height, min_x as f32, max_x as f32, 0., 1.)
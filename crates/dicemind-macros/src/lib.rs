@@ -0,0 +1,303 @@
+//! Expressions that are parsed while the crate using them compiles
+
+use std::cmp::Ordering;
+
+use dicemind::{
+    parser::parse,
+    syntax::{Affix, Augmentation, BinaryOperator, Expression, Selector, SelectorOp},
+};
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::LitStr;
+
+/// Parse the expression at compile time, the typos are build errors
+/// instead of panics. Expands to the `dicemind::syntax::Expression`, the
+/// crate has to depend on `dicemind` as well.
+///
+/// ```
+/// let expr = dicemind_macros::dice!("2d20kh1 + 5");
+/// assert_eq!(expr, dicemind::parser::parse("2d20kh1 + 5").unwrap());
+/// ```
+///
+/// ```compile_fail
+/// let expr = dicemind_macros::dice!("2d20kh1 +");
+/// ```
+#[proc_macro]
+pub fn dice(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let literal = syn::parse_macro_input!(input as LitStr);
+
+    match parse(&literal.value()) {
+        Ok(expr) => expression(&expr).into(),
+        Err(e) => syn::Error::new(literal.span(), e).to_compile_error().into(),
+    }
+}
+
+fn expression(expr: &Expression) -> TokenStream {
+    let path = quote!(::dicemind::syntax::Expression);
+
+    match expr {
+        Expression::Dice {
+            quantity,
+            power,
+            augmentations,
+        } => {
+            let quantity = optional(quantity.as_deref().map(boxed));
+            let power = optional(power.as_deref().map(boxed));
+            let augmentations = augmentations.iter().map(augmentation);
+            quote!(#path::Dice {
+                quantity: #quantity,
+                power: #power,
+                augmentations: ::core::iter::FromIterator::from_iter([#(#augmentations),*]),
+            })
+        }
+        Expression::Weighted {
+            quantity,
+            faces,
+            augmentations,
+        } => {
+            let quantity = optional(quantity.as_deref().map(boxed));
+            let faces = faces.iter().map(|face| {
+                let (value, weight) = (number(&face.value), number(&face.weight));
+                quote!(::dicemind::syntax::Face {
+                    value: #value,
+                    weight: #weight,
+                })
+            });
+            let augmentations = augmentations.iter().map(augmentation);
+            quote!(#path::Weighted {
+                quantity: #quantity,
+                faces: ::std::boxed::Box::new(::std::vec![#(#faces),*]),
+                augmentations: ::core::iter::FromIterator::from_iter([#(#augmentations),*]),
+            })
+        }
+        Expression::Binop { operator, lhs, rhs } => {
+            let operator = binary_operator(*operator);
+            let (lhs, rhs) = (boxed(lhs), boxed(rhs));
+            quote!(#path::Binop {
+                operator: #operator,
+                lhs: #lhs,
+                rhs: #rhs,
+            })
+        }
+        Expression::Constant(n) => {
+            let n = number(n);
+            quote!(#path::Constant(#n))
+        }
+        Expression::Decimal(d) => {
+            let (digits, scale) = (number(&d.digits), d.scale);
+            quote!(#path::Decimal(::dicemind::syntax::Decimal {
+                digits: #digits,
+                scale: #scale,
+            }))
+        }
+        Expression::Annotated {
+            expression,
+            annotation,
+        } => {
+            let expression = boxed(expression);
+            let annotation = annotation.as_str();
+            quote!(#path::Annotated {
+                expression: #expression,
+                annotation: ::core::convert::From::from(#annotation),
+            })
+        }
+        Expression::Subexpression(expr) => {
+            let expr = boxed(expr);
+            quote!(#path::Subexpression(#expr))
+        }
+        Expression::Tagged {
+            expression,
+            metadata,
+        } => {
+            let expression = boxed(expression);
+            let metadata = metadata.iter().map(|(key, value)| {
+                let (key, value) = (key.as_str(), value.as_str());
+                quote!((::core::convert::From::from(#key), ::core::convert::From::from(#value)))
+            });
+            quote!(#path::Tagged {
+                expression: #expression,
+                metadata: ::core::iter::FromIterator::from_iter([#(#metadata),*]),
+            })
+        }
+        Expression::UnaryNegation(expr) => {
+            let expr = boxed(expr);
+            quote!(#path::UnaryNegation(#expr))
+        }
+        Expression::Repeat { count, expression } => {
+            let (count, expression) = (boxed(count), boxed(expression));
+            quote!(#path::Repeat {
+                count: #count,
+                expression: #expression,
+            })
+        }
+        Expression::Call { function, args } => {
+            let function = function.as_str();
+            let args = args.iter().map(self::expression);
+            quote!(#path::Call {
+                function: ::core::convert::From::from(#function),
+                args: ::std::vec![#(#args),*],
+            })
+        }
+        Expression::Group {
+            members,
+            augmentations,
+        } => {
+            let members = members.iter().map(self::expression);
+            let augmentations = augmentations.iter().map(augmentation);
+            quote!(#path::Group {
+                members: ::std::vec![#(#members),*],
+                augmentations: ::core::iter::FromIterator::from_iter([#(#augmentations),*]),
+            })
+        }
+        Expression::Reference(name) => {
+            let name = name.as_str();
+            quote!(#path::Reference(::core::convert::From::from(#name)))
+        }
+        Expression::Conditional {
+            condition,
+            then,
+            otherwise,
+        } => {
+            let (condition, then, otherwise) = (boxed(condition), boxed(then), boxed(otherwise));
+            quote!(#path::Conditional {
+                condition: #condition,
+                then: #then,
+                otherwise: #otherwise,
+            })
+        }
+    }
+}
+
+fn boxed(expr: &Expression) -> TokenStream {
+    let expr = expression(expr);
+    quote!(::std::boxed::Box::new(#expr))
+}
+
+fn optional(tokens: Option<TokenStream>) -> TokenStream {
+    match tokens {
+        Some(tokens) => quote!(::core::option::Option::Some(#tokens)),
+        None => quote!(::core::option::Option::None),
+    }
+}
+
+fn binary_operator(operator: BinaryOperator) -> TokenStream {
+    let variant = match operator {
+        BinaryOperator::Chain => quote!(Chain),
+        BinaryOperator::Equals => quote!(Equals),
+        BinaryOperator::LessThan => quote!(LessThan),
+        BinaryOperator::GreaterThan => quote!(GreaterThan),
+        BinaryOperator::Add => quote!(Add),
+        BinaryOperator::Subtract => quote!(Subtract),
+        BinaryOperator::Multiply => quote!(Multiply),
+    };
+
+    quote!(::dicemind::syntax::BinaryOperator::#variant)
+}
+
+fn number(n: &impl ToString) -> TokenStream {
+    let n = n.to_string();
+    quote!(::core::str::FromStr::from_str(#n).unwrap())
+}
+
+fn selector(selector: &Selector) -> TokenStream {
+    let relation = match selector.relation {
+        Ordering::Less => quote!(Less),
+        Ordering::Equal => quote!(Equal),
+        Ordering::Greater => quote!(Greater),
+    };
+    let inclusive = selector.inclusive;
+    let n = number(&selector.n);
+
+    quote!(::dicemind::syntax::Selector {
+        relation: ::core::cmp::Ordering::#relation,
+        inclusive: #inclusive,
+        n: #n,
+    })
+}
+
+fn augmentation(augmentation: &Augmentation) -> TokenStream {
+    let path = quote!(::dicemind::syntax::Augmentation);
+    let optional_number = |n: &Option<_>| optional(n.as_ref().map(number));
+    let optional_selector = |s: &Option<_>| optional(s.as_ref().map(selector));
+
+    match augmentation {
+        Augmentation::Truncate { op, affix, n } => {
+            let op = match op {
+                SelectorOp::Drop => quote!(Drop),
+                SelectorOp::Keep => quote!(Keep),
+            };
+            let affix = match affix {
+                Affix::High => quote!(High),
+                Affix::Low => quote!(Low),
+            };
+            let n = optional_number(n);
+            quote!(#path::Truncate {
+                op: ::dicemind::syntax::SelectorOp::#op,
+                affix: ::dicemind::syntax::Affix::#affix,
+                n: #n,
+            })
+        }
+        Augmentation::Filter { op, selector: s } => {
+            let op = match op {
+                SelectorOp::Drop => quote!(Drop),
+                SelectorOp::Keep => quote!(Keep),
+            };
+            let s = selector(s);
+            quote!(#path::Filter {
+                op: ::dicemind::syntax::SelectorOp::#op,
+                selector: #s,
+            })
+        }
+        Augmentation::Emphasis { n } => {
+            let n = optional_number(n);
+            quote!(#path::Emphasis { n: #n })
+        }
+        Augmentation::Explode { selector: s } => {
+            let s = optional_selector(s);
+            quote!(#path::Explode { selector: #s })
+        }
+        Augmentation::Compound { selector: s } => {
+            let s = optional_selector(s);
+            quote!(#path::Compound { selector: #s })
+        }
+        Augmentation::Reroll { selector: s, once } => {
+            let s = selector(s);
+            quote!(#path::Reroll {
+                selector: #s,
+                once: #once,
+            })
+        }
+        Augmentation::CriticalSuccess { selector: s } => {
+            let s = selector(s);
+            quote!(#path::CriticalSuccess { selector: #s })
+        }
+        Augmentation::CriticalFailure { selector: s } => {
+            let s = selector(s);
+            quote!(#path::CriticalFailure { selector: #s })
+        }
+        Augmentation::Minimum { n } => {
+            let n = number(n);
+            quote!(#path::Minimum { n: #n })
+        }
+        Augmentation::Maximum { n } => {
+            let n = number(n);
+            quote!(#path::Maximum { n: #n })
+        }
+        Augmentation::CountSuccesses { selector: s } => {
+            let s = selector(s);
+            quote!(#path::CountSuccesses { selector: #s })
+        }
+        Augmentation::CountFailures { selector: s } => {
+            let s = selector(s);
+            quote!(#path::CountFailures { selector: #s })
+        }
+        Augmentation::Custom { token, n } => {
+            let token = token.as_str();
+            let n = optional_number(n);
+            quote!(#path::Custom {
+                token: ::core::convert::From::from(#token),
+                n: #n,
+            })
+        }
+    }
+}
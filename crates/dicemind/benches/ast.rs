@@ -0,0 +1,205 @@
+#![feature(test)]
+
+extern crate test;
+
+use dicemind::{
+    cache::ExpressionCache,
+    compiler::{compile, Vm},
+    interpreter::{evaluate_constant, DiceSampler},
+    prelude::*,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use test::{black_box, Bencher};
+
+// A bit of everything the rollers see, the repetition clones its
+// expression for every roll
+const INPUTS: &[&str] = &[
+    "d20 + 5",
+    "4d6kh3",
+    "6x(4d6kh3)",
+    "2d20kh1 + 7 [attack]",
+    "{d20 + 5, 2d6}kh1 * 2",
+    "d20 > 15 ? 2d6 [fire] + 1d8 : 0",
+    "3d6!>=5 + 8d10s>=7",
+    "max(2d6, d12) + (1d4 + 2) * 3",
+];
+
+fn trees() -> Vec<Expression> {
+    INPUTS.iter().map(|input| parse(input).unwrap()).collect()
+}
+
+#[bench]
+fn bench_parse(b: &mut Bencher) {
+    b.iter(|| {
+        for input in INPUTS {
+            black_box(parse(black_box(input)).unwrap());
+        }
+    });
+}
+
+// The same inputs again, all of them hit the cache
+#[bench]
+fn bench_parse_cached(b: &mut Bencher) {
+    let mut cache = ExpressionCache::default();
+    b.iter(|| {
+        for input in INPUTS {
+            black_box(cache.parse(black_box(input)).unwrap());
+        }
+    });
+}
+
+#[bench]
+fn bench_clone(b: &mut Bencher) {
+    let trees = trees();
+    b.iter(|| black_box(&trees).clone());
+}
+
+#[bench]
+fn bench_parse_and_roll(b: &mut Bencher) {
+    let mut roller = StandardNaiveRoller::new_seeded(0);
+    b.iter(|| {
+        for input in INPUTS {
+            black_box(roller.roll_naive(parse(input).unwrap()).unwrap());
+        }
+    });
+}
+
+#[bench]
+fn bench_roll_borrowed(b: &mut Bencher) {
+    let trees = trees();
+    let mut roller = StandardNaiveRoller::new_seeded(0);
+    b.iter(|| {
+        for expr in &trees {
+            black_box(roller.roll_naive(expr).unwrap());
+        }
+    });
+}
+
+// The inputs that compile, rolled by the roller and by the programs
+fn compiled() -> Vec<Expression> {
+    trees()
+        .into_iter()
+        .filter(|expr| compile(expr).is_ok())
+        .collect()
+}
+
+#[bench]
+fn bench_roll_compilable(b: &mut Bencher) {
+    let trees = compiled();
+    let mut roller = StandardNaiveRoller::new_seeded(0);
+    b.iter(|| {
+        for expr in &trees {
+            black_box(roller.roll_naive(expr).unwrap());
+        }
+    });
+}
+
+#[bench]
+fn bench_run_compiled(b: &mut Bencher) {
+    let programs: Vec<_> = compiled()
+        .iter()
+        .map(|expr| compile(expr).unwrap())
+        .collect();
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut vm = Vm::default();
+    b.iter(|| {
+        for program in &programs {
+            black_box(vm.run(program, &mut rng).unwrap());
+        }
+    });
+}
+
+// What a chat sees when the dice are used as a calculator
+const ARITHMETIC: &[&str] = &[
+    "2+2*10",
+    "(17 - 3) * 4",
+    "max(12, 7) + 3 * -2",
+    "1 > 0 ? 8 : 9",
+];
+
+#[bench]
+fn bench_arithmetic_roll(b: &mut Bencher) {
+    let trees: Vec<_> = ARITHMETIC
+        .iter()
+        .map(|input| parse(input).unwrap())
+        .collect();
+    b.iter(|| {
+        for expr in &trees {
+            black_box(StandardNaiveRoller::default().roll_naive(expr).unwrap());
+        }
+    });
+}
+
+#[bench]
+fn bench_arithmetic_fast_path(b: &mut Bencher) {
+    let trees: Vec<_> = ARITHMETIC
+        .iter()
+        .map(|input| parse(input).unwrap())
+        .collect();
+    b.iter(|| {
+        for expr in &trees {
+            black_box(evaluate_constant(expr).unwrap().unwrap());
+        }
+    });
+}
+
+// The hot loop of the simulations, a hundred plain dice
+#[bench]
+fn bench_roll_many_dice(b: &mut Bencher) {
+    let expr = parse("100d6").unwrap();
+    let mut roller = StandardNaiveRoller::new_seeded(0);
+    b.iter(|| black_box(roller.roll_naive(&expr).unwrap()));
+}
+
+#[bench]
+fn bench_roll_many_dice_packed(b: &mut Bencher) {
+    let expr = parse("100d6").unwrap();
+    let options = RollerOptions::default().with_packed_dice(true);
+    let mut roller = StandardNaiveRoller::new_seeded(0).with_options(options);
+    b.iter(|| black_box(roller.roll_naive(&expr).unwrap()));
+}
+
+#[bench]
+fn bench_sample_one_by_one(b: &mut Bencher) {
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut dice = [0; 100];
+    b.iter(|| {
+        for face in dice.iter_mut() {
+            *face = rng.gen_range(1..=6i64);
+        }
+        black_box(&dice);
+    });
+}
+
+#[bench]
+fn bench_sample_batched(b: &mut Bencher) {
+    let mut rng = StdRng::seed_from_u64(0);
+    let sampler = DiceSampler::new(6);
+    let mut dice = [0; 100];
+    b.iter(|| {
+        sampler.fill(&mut rng, &mut dice);
+        black_box(&dice);
+    });
+}
+
+#[bench]
+fn bench_sample_alias_table(b: &mut Bencher) {
+    let table = parse("100d6")
+        .unwrap()
+        .distribution()
+        .unwrap()
+        .alias_table();
+    let mut rng = StdRng::seed_from_u64(0);
+    b.iter(|| black_box(table.sample(&mut rng)));
+}
+
+#[bench]
+fn bench_sample_packed(b: &mut Bencher) {
+    let mut rng = StdRng::seed_from_u64(0);
+    let sampler = DiceSampler::new(6);
+    let mut dice = [0; 100];
+    b.iter(|| {
+        sampler.fill_packed(&mut rng, &mut dice);
+        black_box(&dice);
+    });
+}
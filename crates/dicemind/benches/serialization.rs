@@ -0,0 +1,47 @@
+#![feature(test)]
+
+extern crate test;
+
+use dicemind::{
+    interpreter::NaiveValue,
+    prelude::*,
+    serialization::{to_bincode, to_cbor, to_postcard},
+};
+use test::Bencher;
+
+fn roll_tree() -> NaiveValue {
+    StandardNaiveRoller::new_seeded(0)
+        .roll_naive(parse("100d6").unwrap())
+        .unwrap()
+}
+
+// `bytes` is set to the encoded size, so the reported throughput
+// doubles as a size comparison between the formats
+
+#[bench]
+fn bench_bincode_roll(b: &mut Bencher) {
+    let value = roll_tree();
+    b.bytes = to_bincode(&value).unwrap().len() as u64;
+    b.iter(|| to_bincode(&value).unwrap());
+}
+
+#[bench]
+fn bench_cbor_roll(b: &mut Bencher) {
+    let value = roll_tree();
+    b.bytes = to_cbor(&value).unwrap().len() as u64;
+    b.iter(|| to_cbor(&value).unwrap());
+}
+
+#[bench]
+fn bench_json_roll(b: &mut Bencher) {
+    let value = roll_tree();
+    b.bytes = serde_json::to_vec(&value).unwrap().len() as u64;
+    b.iter(|| serde_json::to_vec(&value).unwrap());
+}
+
+#[bench]
+fn bench_postcard_roll(b: &mut Bencher) {
+    let value = roll_tree();
+    b.bytes = to_postcard(&value).unwrap().len() as u64;
+    b.iter(|| to_postcard(&value).unwrap());
+}
@@ -0,0 +1,476 @@
+//! Parsing each distinct command once. A bot that sees `!roll d20 + 5` a
+//! thousand times a minute parses it the first time and shares the parsed
+//! expression afterwards, or compiles it the first time with `RollCache`.
+
+use std::{mem, sync::Arc};
+
+use hashlink::LruCache;
+use rand::{CryptoRng, Rng, RngCore};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    compiler::{compile_with, CompileError, Program, Vm},
+    interner::Interner,
+    interpreter::{NaiveRoller, RollerError, RollerResult},
+    options::RollerOptions,
+    parser::{parse, ParsingError},
+    syntax::Expression,
+};
+
+/// How well the cache is doing
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// The expressions pushed out to make room for the newer ones
+    pub evictions: u64,
+}
+
+// The entries keyed by the normalized input, the least recently used one
+// is evicted when another one is needed
+#[derive(Debug, Clone)]
+struct Lru<V> {
+    entries: LruCache<String, V>,
+    capacity: usize,
+    stats: CacheStats,
+}
+
+impl<V: Clone> Lru<V> {
+    fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            entries: LruCache::new(capacity),
+            capacity,
+            stats: CacheStats::default(),
+        }
+    }
+
+    // The entry for the key, counted as a hit or a miss
+    fn get(&mut self, key: &str) -> Option<V> {
+        match self.entries.get(key) {
+            Some(value) => {
+                self.stats.hits += 1;
+                Some(value.clone())
+            }
+            None => {
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    // Keep the entry, giving back the one evicted to make room for it
+    fn insert(&mut self, key: String, value: V) -> Option<V> {
+        let evicted = if self.entries.len() >= self.capacity {
+            self.stats.evictions += 1;
+            self.entries.remove_lru().map(|(_, value)| value)
+        } else {
+            None
+        };
+
+        self.entries.insert(key, value);
+        evicted
+    }
+}
+
+/// The parsed expressions keyed by their normalized input, the inputs
+/// that only differ in the whitespace share an entry. The labels of the
+/// cached expressions are interned, so the entries share those as well.
+#[derive(Debug, Clone)]
+pub struct ExpressionCache {
+    lru: Lru<Arc<Expression>>,
+    interner: Interner,
+}
+
+impl Default for ExpressionCache {
+    fn default() -> Self {
+        Self::new(1024)
+    }
+}
+
+impl ExpressionCache {
+    /// Keep at most `capacity` expressions, the least recently used one
+    /// is evicted when another one is needed
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            lru: Lru::new(capacity),
+            interner: Interner::new(),
+        }
+    }
+
+    /// The cached expression for the input, parsing it on a miss. The
+    /// errors are not cached, their spans point into the input as given.
+    pub fn parse(&mut self, input: &str) -> Result<Arc<Expression>, ParsingError> {
+        let key = normalize_input(input);
+        if let Some(expr) = self.lru.get(&key) {
+            return Ok(expr);
+        }
+
+        let mut expr = parse(input)?;
+        self.interner.intern_expression(&mut expr);
+        let expr = Arc::new(expr);
+
+        // The labels only the evicted expression used are dropped with it
+        if let Some(evicted) = self.lru.insert(key, expr.clone()) {
+            self.interner.release_expression(&evicted);
+        }
+
+        Ok(expr)
+    }
+
+    pub fn len(&self) -> usize {
+        self.lru.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lru.entries.is_empty()
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.lru.stats
+    }
+
+    /// Drop the expressions and the interned labels, the stats are kept
+    pub fn clear(&mut self) {
+        self.lru.entries.clear();
+        self.interner.clear();
+    }
+}
+
+#[derive(Debug, Clone, Error, PartialEq)]
+pub enum RollCacheError {
+    #[error(transparent)]
+    Parsing(#[from] ParsingError),
+    #[error(transparent)]
+    Compile(#[from] CompileError),
+    #[error(transparent)]
+    Roller(#[from] RollerError),
+}
+
+/// The compiled programs keyed by their normalized input, for the servers
+/// rolling the same commands over and over. The programs are compiled
+/// with the options of the cache. The expressions that can't be compiled
+/// are kept parsed and rolled by the naive roller, like `into_fn` does.
+/// Whether an expression compiles doesn't change, so the compile errors
+/// are cached along with the programs, unlike the parsing errors.
+///
+/// ```
+/// use dicemind::{cache::RollCache, prelude::*};
+/// use rand::{rngs::StdRng, SeedableRng};
+///
+/// let mut cache = RollCache::new(256, RollerOptions::default());
+/// let mut rng = StdRng::seed_from_u64(0);
+/// let total = cache.roll("d20 + 5", &mut rng).unwrap();
+/// assert!((6..=25).contains(&total));
+/// assert_eq!(cache.stats().misses, 1);
+/// ```
+#[derive(Debug, Clone)]
+pub struct RollCache {
+    lru: Lru<Result<Cached, CompileError>>,
+    options: RollerOptions,
+    // Shared by the rolls, so that they allocate nothing
+    vm: Vm,
+}
+
+// How a cached command is rolled
+#[derive(Debug, Clone)]
+enum Cached {
+    Compiled(Arc<Program>),
+    // The expressions the compiler refuses, rolled by the naive roller
+    Walked(Arc<Expression>),
+}
+
+impl Default for RollCache {
+    fn default() -> Self {
+        Self::new(1024, RollerOptions::default())
+    }
+}
+
+impl RollCache {
+    /// Keep at most `capacity` programs compiled with the options
+    pub fn new(capacity: usize, options: RollerOptions) -> Self {
+        Self {
+            lru: Lru::new(capacity),
+            options,
+            vm: Vm::default(),
+        }
+    }
+
+    /// The cached program for the input, compiling it on a miss. The
+    /// expressions rolled by the naive roller have no program and give
+    /// `CompileError::Unsupported`.
+    pub fn compile(&mut self, input: &str) -> Result<Arc<Program>, RollCacheError> {
+        match self.entry(input)? {
+            Cached::Compiled(program) => Ok(program),
+            Cached::Walked(expr) => Err(CompileError::Unsupported {
+                expression: Expression::clone(&expr),
+            }
+            .into()),
+        }
+    }
+
+    /// Roll the cached program for the input once
+    pub fn roll(
+        &mut self,
+        input: &str,
+        rng: &mut (impl RngCore + ?Sized),
+    ) -> Result<i64, RollCacheError> {
+        match self.entry(input)? {
+            Cached::Compiled(program) => Ok(self.vm.run(&program, rng)?),
+            Cached::Walked(expr) => Ok(self.walk(&expr, NaiveRoller::from_rng(rng))?),
+        }
+    }
+
+    /// Roll the cached program for the input once with a cryptographically
+    /// secure generator, the only way a cache with secure options rolls
+    pub fn roll_secure(
+        &mut self,
+        input: &str,
+        rng: &mut (impl RngCore + CryptoRng + ?Sized),
+    ) -> Result<i64, RollCacheError> {
+        match self.entry(input)? {
+            Cached::Compiled(program) => Ok(self.vm.run_secure(&program, rng)?),
+            Cached::Walked(expr) => Ok(self.walk(&expr, NaiveRoller::from_secure_rng(rng))?),
+        }
+    }
+
+    fn entry(&mut self, input: &str) -> Result<Cached, RollCacheError> {
+        let key = normalize_input(input);
+        if let Some(cached) = self.lru.get(&key) {
+            return Ok(cached?);
+        }
+
+        let expr = parse(input)?;
+        let cached = match compile_with(&expr, &self.options) {
+            Ok(program) => Ok(Cached::Compiled(Arc::new(program))),
+            Err(CompileError::Unsupported { .. }) => Ok(Cached::Walked(Arc::new(expr))),
+            Err(err) => Err(err),
+        };
+        self.lru.insert(key, cached.clone());
+
+        Ok(cached?)
+    }
+
+    // The options are lent to the roller for the roll
+    fn walk<R: Rng>(&mut self, expr: &Expression, roller: NaiveRoller<R>) -> RollerResult<i64> {
+        let mut roller = roller.with_options(mem::take(&mut self.options));
+        let total = roller.roll_naive(expr).and_then(|value| value.total());
+        self.options = mem::take(roller.options_mut());
+        total
+    }
+
+    pub fn options(&self) -> &RollerOptions {
+        &self.options
+    }
+
+    pub fn len(&self) -> usize {
+        self.lru.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lru.entries.is_empty()
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.lru.stats
+    }
+
+    /// Drop the programs, the stats are kept
+    pub fn clear(&mut self) {
+        self.lru.entries.clear();
+    }
+}
+
+// Trims the input and collapses the runs of whitespace into a single
+// space. Inside the annotations the whitespace is a part of the label
+// and is kept as it is.
+fn normalize_input(input: &str) -> String {
+    let mut normalized = String::with_capacity(input.len());
+    let mut brackets = 0usize;
+    let mut space = false;
+
+    for c in input.trim().chars() {
+        match c {
+            '[' => brackets += 1,
+            ']' => brackets = brackets.saturating_sub(1),
+            _ if c.is_whitespace() && brackets == 0 => {
+                space = true;
+                continue;
+            }
+            _ => {}
+        }
+
+        if space {
+            normalized.push(' ');
+            space = false;
+        }
+        normalized.push(c);
+    }
+
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rand::{
+        rngs::{OsRng, StdRng},
+        SeedableRng,
+    };
+
+    use crate::{
+        cache::{normalize_input, CacheStats, ExpressionCache, RollCache, RollCacheError},
+        compiler::CompileError,
+        interpreter::RollerError,
+        options::{FixedDice, RollerOptions},
+        parser::parse,
+        prelude::StandardNaiveRoller,
+    };
+
+    #[test]
+    fn test_normalize_input() {
+        assert_eq!(normalize_input("  d20 +\t 5  "), "d20 + 5");
+        assert_eq!(
+            normalize_input("2d6 [fire  damage]  + 1"),
+            "2d6 [fire  damage] + 1"
+        );
+        assert_eq!(normalize_input("d20+5"), "d20+5");
+    }
+
+    #[test]
+    fn test_cache() {
+        let mut cache = ExpressionCache::new(2);
+
+        let first = cache.parse("d20 + 5").unwrap();
+        let second = cache.parse("  d20  +  5 ").unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(*first, parse("d20 + 5").unwrap());
+        assert!(StandardNaiveRoller::new_seeded(0)
+            .roll_naive(&*second)
+            .is_ok());
+
+        assert_eq!(
+            cache.parse("d20 + (").unwrap_err(),
+            parse("d20 + (").unwrap_err()
+        );
+        assert_eq!(cache.len(), 1);
+
+        cache.parse("4d6kh3").unwrap();
+        cache.parse("d20 + 5").unwrap();
+        // `4d6kh3` was used the longest ago
+        cache.parse("2d6 [fire]").unwrap();
+        assert_eq!(cache.len(), 2);
+        assert!(Arc::ptr_eq(&first, &cache.parse("d20 + 5").unwrap()));
+
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 3,
+                misses: 4,
+                evictions: 1
+            }
+        );
+
+        cache.clear();
+        assert!(cache.is_empty());
+        assert!(!Arc::ptr_eq(&first, &cache.parse("d20 + 5").unwrap()));
+
+        // The labels of the evicted expressions are not kept around
+        let mut cache = ExpressionCache::new(1);
+        cache.parse("d20 [the longsword of the dragons]").unwrap();
+        assert_eq!(cache.interner.stats().unique, 1);
+        cache.parse("d20 [the shortbow of the wyverns]").unwrap();
+        assert_eq!(cache.interner.stats().unique, 1);
+        cache.parse("d6 + 5").unwrap();
+        assert_eq!(cache.interner.stats().stored_bytes, 0);
+    }
+
+    #[test]
+    fn test_roll_cache() {
+        let mut cache = RollCache::new(2, RollerOptions::default());
+        let mut rng = StdRng::seed_from_u64(0);
+
+        let program = cache.compile("4d6kh3 + 2").unwrap();
+        assert!(Arc::ptr_eq(
+            &program,
+            &cache.compile(" 4d6kh3  + 2").unwrap()
+        ));
+
+        // The same totals as the roller for the same seed
+        let mut roller = StandardNaiveRoller::new_seeded(0);
+        for _ in 0..10 {
+            let expected = roller
+                .roll_naive(parse("4d6kh3 + 2").unwrap())
+                .unwrap()
+                .total()
+                .unwrap();
+            assert_eq!(cache.roll("4d6kh3 + 2", &mut rng), Ok(expected));
+        }
+
+        assert_eq!(
+            cache.roll("d20 + (", &mut rng),
+            Err(RollCacheError::Parsing(parse("d20 + (").unwrap_err()))
+        );
+        // The augments are rolled by the naive roller
+        assert_eq!(
+            cache.compile("4d6!").unwrap_err(),
+            RollCacheError::Compile(CompileError::Unsupported {
+                expression: parse("4d6!").unwrap(),
+            })
+        );
+        let exploded = roller
+            .roll_naive(parse("4d6!").unwrap())
+            .unwrap()
+            .total()
+            .unwrap();
+        assert_eq!(cache.roll("4d6!", &mut rng), Ok(exploded));
+        assert_eq!(
+            cache.roll("1d6dl2", &mut rng),
+            Err(RollCacheError::Roller(RollerError::TruncationFailure {
+                rolled: 1,
+                removed: 2
+            }))
+        );
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 12,
+                misses: 4,
+                evictions: 1
+            }
+        );
+
+        cache.clear();
+        assert!(cache.is_empty());
+
+        // The programs always roll their dice, the naive roller fixes them
+        let fixed = RollerOptions::builder()
+            .fixed_dice(FixedDice::Maximum)
+            .build();
+        let mut cache = RollCache::new(2, fixed);
+        assert_eq!(cache.roll("d20 + 2d6", &mut rng), Ok(32));
+        assert_eq!(cache.options().fixed_dice(), Some(FixedDice::Maximum));
+    }
+
+    #[test]
+    fn test_secure() {
+        let mut cache = RollCache::new(2, RollerOptions::default().with_secure(true));
+
+        let total = cache.roll_secure("10d20 + d6", &mut OsRng).unwrap();
+        assert!((11..=206).contains(&total));
+        assert_eq!(
+            cache.roll("10d20 + d6", &mut StdRng::seed_from_u64(0)),
+            Err(RollCacheError::Roller(RollerError::InsecureRng))
+        );
+
+        assert!((4..).contains(&cache.roll_secure("4d6!", &mut OsRng).unwrap()));
+        assert_eq!(
+            cache.roll("4d6!", &mut StdRng::seed_from_u64(0)),
+            Err(RollCacheError::Roller(RollerError::InsecureRng))
+        );
+    }
+}
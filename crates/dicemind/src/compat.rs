@@ -0,0 +1,104 @@
+//! The entry points of the older versions, kept so that the code written
+//! against them still compiles. They are thin wrappers over `NaiveRoller`
+//! and will be removed once the downstream crates have moved over.
+#![allow(deprecated)]
+
+use std::borrow::Borrow;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    interpreter::{NaiveRoller, RollerError, RollerResult, Value},
+    options::RollerOptions,
+    parser::parse,
+    syntax::Expression,
+};
+
+#[deprecated(note = "use `NaiveRoller`, the total is `NaiveValue::total`")]
+#[derive(Debug, Clone)]
+pub struct FastRoller<R: Rng = StdRng> {
+    roller: NaiveRoller<R>,
+}
+
+#[deprecated(note = "use `StandardNaiveRoller`")]
+pub type StandardFastRoller = FastRoller;
+
+impl<R: SeedableRng + Rng> FastRoller<R> {
+    pub fn new_seeded(seed: u64) -> Self {
+        Self {
+            roller: NaiveRoller::new_seeded(seed),
+        }
+    }
+}
+
+impl<R: SeedableRng + Rng> Default for FastRoller<R> {
+    fn default() -> Self {
+        Self {
+            roller: NaiveRoller::default(),
+        }
+    }
+}
+
+impl<R: Rng> FastRoller<R> {
+    pub fn with_options(mut self, options: RollerOptions) -> Self {
+        self.roller = self.roller.with_options(options);
+        self
+    }
+
+    /// Roll and sum everything up, the totals that don't fit are an overflow
+    pub fn roll(&mut self, expr: impl Borrow<Expression>) -> RollerResult<i32> {
+        let total = self.roller.roll_naive(expr)?.total()?;
+        i32::try_from(total).map_err(|_| RollerError::Overflow)
+    }
+
+    /// The comparisons are 1 and 0 in `roll`, this tells them apart from
+    /// the numbers
+    pub fn value(&mut self, expr: impl Borrow<Expression>) -> RollerResult<Value> {
+        self.roller.roll(expr)
+    }
+
+    /// The roller this one wraps, for migrating one call at a time
+    pub fn into_naive(self) -> NaiveRoller<R> {
+        self.roller
+    }
+}
+
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub enum CompatError {
+    #[error(transparent)]
+    Parsing(#[from] crate::parser::ParsingError),
+    #[error(transparent)]
+    Roller(#[from] RollerError),
+}
+
+/// Parse and roll in one go
+#[deprecated(note = "use `parse` and `StandardNaiveRoller::roll`")]
+pub fn roll(input: &str) -> Result<i32, CompatError> {
+    Ok(StandardFastRoller::default().roll(parse(input)?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(deprecated)]
+
+    use crate::{
+        compat::{roll, StandardFastRoller},
+        interpreter::StandardNaiveRoller,
+        prelude::parse,
+    };
+
+    #[test]
+    fn test_compat() {
+        let expr = parse("4d6kh3 + 2").unwrap();
+
+        let old = StandardFastRoller::new_seeded(7).roll(&expr).unwrap();
+        let new = StandardNaiveRoller::new_seeded(7).roll_naive(expr).unwrap();
+        assert_eq!(old as i64, new.total().unwrap());
+
+        assert!((3..=8).contains(&roll("1d6 + 2").unwrap()));
+        assert!(roll("1 +").is_err());
+        assert!(StandardFastRoller::default()
+            .roll(parse("3000000000").unwrap())
+            .is_err());
+    }
+}
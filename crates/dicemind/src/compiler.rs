@@ -0,0 +1,747 @@
+//! Expressions compiled to a flat list of instructions, for rolling the
+//! same expression over and over without walking the tree every time. A
+//! program rolls the same totals as the naive roller for the same seed.
+//!
+//! ```
+//! use dicemind::{compiler::compile, prelude::*};
+//! use rand::{rngs::StdRng, SeedableRng};
+//!
+//! let program = compile(&parse("4d6kh3 + 2").unwrap()).unwrap();
+//! let mut rng = StdRng::seed_from_u64(0);
+//! let total = program.run(&mut rng).unwrap();
+//! assert!((5..=20).contains(&total));
+//! ```
+
+use std::{cmp::Ordering, fmt::Display, mem};
+
+use rand::{CryptoRng, RngCore};
+use thiserror::Error;
+
+use crate::{
+    functions::{builtin, Function},
+    interpreter::{evaluate_constant, DiceSampler, Limit, NaiveRoller, RollerError, RollerResult},
+    options::RollerOptions,
+    syntax::{Affix, Augmentation, BinaryOperator, Expression, SelectorOp},
+};
+
+#[derive(Debug, Clone, Error, PartialEq)]
+pub enum CompileError {
+    #[error("`{expression}` can't be compiled")]
+    Unsupported { expression: Expression },
+    /// Every roll of the expression fails
+    #[error(transparent)]
+    Fails(#[from] RollerError),
+}
+
+pub type CompileResult<T> = Result<T, CompileError>;
+
+/// Which of the dice of a roll count towards its total
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Truncation {
+    KeepHighest(usize),
+    KeepLowest(usize),
+    DropHighest(usize),
+    DropLowest(usize),
+}
+
+/// A step of a program, working on a stack of totals
+#[derive(Debug, Clone, Copy)]
+pub enum Instruction {
+    Push(i64),
+    /// Pop the power and the quantity, push the total of the dice
+    Roll(Option<Truncation>),
+    Add,
+    Subtract,
+    Multiply,
+    Equals,
+    LessThan,
+    GreaterThan,
+    Negate,
+    /// Throw away the total on the top, the left side of a chain
+    Pop,
+    /// Call a builtin function with as many totals from the top
+    Call(&'static Function, usize),
+    /// Pop the condition, jump unless it holds
+    JumpIfZero(usize),
+    Jump(usize),
+    /// Pop the count of a repetition, jump past it for none
+    Repeat(usize),
+    /// Add the total on the top to the repetition, jump back to its start
+    /// for as long as there are rolls left
+    Next(usize),
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use Instruction::*;
+
+        match self {
+            Push(n) => write!(f, "push {n}"),
+            Roll(None) => write!(f, "roll"),
+            Roll(Some(truncation)) => write!(f, "roll {truncation:?}"),
+            Add => write!(f, "add"),
+            Subtract => write!(f, "sub"),
+            Multiply => write!(f, "mul"),
+            Equals => write!(f, "eq"),
+            LessThan => write!(f, "lt"),
+            GreaterThan => write!(f, "gt"),
+            Negate => write!(f, "neg"),
+            Pop => write!(f, "pop"),
+            Call(function, n) => write!(f, "call {} {n}", function.name),
+            JumpIfZero(to) => write!(f, "jz {to}"),
+            Jump(to) => write!(f, "jmp {to}"),
+            Repeat(to) => write!(f, "repeat {to}"),
+            Next(to) => write!(f, "next {to}"),
+        }
+    }
+}
+
+/// An expression compiled with the options it is rolled with
+#[derive(Debug, Clone)]
+pub struct Program {
+    instructions: Vec<Instruction>,
+    max_dice_per_term: Option<usize>,
+    max_total_dice: Option<usize>,
+    fuel: Option<u64>,
+    packed_dice: bool,
+    secure: bool,
+}
+
+impl Program {
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
+    /// Roll the program once on a fresh machine
+    pub fn run(&self, rng: &mut (impl RngCore + ?Sized)) -> RollerResult<i64> {
+        Vm::default().run(self, rng)
+    }
+
+    /// Roll the program once with a cryptographically secure generator,
+    /// the only way a program compiled with secure options rolls
+    pub fn run_secure(&self, rng: &mut (impl RngCore + CryptoRng + ?Sized)) -> RollerResult<i64> {
+        Vm::default().run_secure(self, rng)
+    }
+}
+
+/// Compile the expression with the default options
+pub fn compile(expr: &Expression) -> CompileResult<Program> {
+    compile_with(expr, &RollerOptions::default())
+}
+
+/// Compile the expression, the macros are expanded, the terms without
+/// dice are folded and the dice without a quantity or a power take them
+/// from the options. The dice with
+/// augments other than a single truncation, the weighted dice, the
+/// decimals, the groups
+/// with augments and the functions that aren't builtin can't be compiled,
+/// the builtin
+/// functions are assumed to not be shadowed by registered ones. Neither
+/// can the dice when the options fix them, see `RollerOptions::with_fixed_dice`.
+pub fn compile_with(expr: &Expression, options: &RollerOptions) -> CompileResult<Program> {
+    let mut compiler = Compiler {
+        options,
+        instructions: vec![],
+    };
+    compiler.compile(expr)?;
+
+    Ok(Program {
+        instructions: compiler.instructions,
+        max_dice_per_term: options.max_dice_per_term(),
+        max_total_dice: options.max_total_dice(),
+        fuel: options.fuel(),
+        packed_dice: options.packed_dice(),
+        secure: options.secure(),
+    })
+}
+
+// How a closure from `into_fn` rolls
+enum Plan {
+    Constant(RollerResult<i64>),
+    Compiled(Program, Vm),
+    // The options are lent to a roller for every roll
+    Walked(Expression, RollerOptions),
+}
+
+impl Expression {
+    /// Turn the expression into a closure rolling its total with the
+    /// generator it is given. Everything that can be worked out before
+    /// rolling is, once: the constants, the defaults, the macros and the
+    /// truncations. The expressions that can't be compiled are rolled by
+    /// the naive roller, and either way the totals are the ones the roller
+    /// rolls for the same seed.
+    pub fn into_fn<R: RngCore + ?Sized>(
+        self,
+        options: &RollerOptions,
+    ) -> impl FnMut(&mut R) -> RollerResult<i64> {
+        // The generator can't be told apart, so it is never trusted
+        let mut plan = match evaluate_constant(&self) {
+            _ if options.secure() => Plan::Constant(Err(RollerError::InsecureRng)),
+            Some(total) => Plan::Constant(total),
+            None => match compile_with(&self, options) {
+                Ok(program) => Plan::Compiled(program, Vm::default()),
+                Err(CompileError::Fails(err)) => Plan::Constant(Err(err)),
+                Err(CompileError::Unsupported { .. }) => Plan::Walked(self, options.clone()),
+            },
+        };
+
+        move |rng: &mut R| match &mut plan {
+            Plan::Constant(total) => total.clone(),
+            Plan::Compiled(program, vm) => vm.run(program, rng),
+            Plan::Walked(expr, options) => {
+                let mut roller = NaiveRoller::from_rng(rng).with_options(mem::take(options));
+                let total = roller.roll_naive(&*expr).and_then(|value| value.total());
+                *options = mem::take(roller.options_mut());
+                total
+            }
+        }
+    }
+}
+
+struct Compiler<'a> {
+    options: &'a RollerOptions,
+    instructions: Vec<Instruction>,
+}
+
+impl Compiler<'_> {
+    fn emit(&mut self, instruction: Instruction) -> usize {
+        self.instructions.push(instruction);
+        self.instructions.len() - 1
+    }
+
+    fn here(&self) -> usize {
+        self.instructions.len()
+    }
+
+    // Point the jump emitted at `at` to the next instruction
+    fn land(&mut self, at: usize) {
+        let here = self.here();
+        match &mut self.instructions[at] {
+            Instruction::JumpIfZero(to) | Instruction::Jump(to) | Instruction::Repeat(to) => {
+                *to = here
+            }
+            _ => unreachable!("only the jumps land"),
+        }
+    }
+
+    fn push(&mut self, n: impl Into<crate::syntax::Integer>) -> CompileResult<()> {
+        let n = n.into();
+        let n = i64::try_from(&n).map_err(|_| RollerError::ValueTooLarge { value: n })?;
+        self.emit(Instruction::Push(n));
+        Ok(())
+    }
+
+    fn compile(&mut self, expr: &Expression) -> CompileResult<()> {
+        let unsupported = || CompileError::Unsupported {
+            expression: expr.clone(),
+        };
+
+        // The folds that fail are left to fail when run, like the roller
+        if let Some(Ok(total)) = evaluate_constant(expr) {
+            self.emit(Instruction::Push(total));
+            return Ok(());
+        }
+
+        match expr {
+            Expression::Constant(c) => self.push(c.clone())?,
+            Expression::Dice {
+                quantity,
+                power,
+                augmentations,
+            } => {
+                // A program always rolls its dice
+                if self.options.fixed_dice().is_some() {
+                    return Err(unsupported());
+                }
+
+                let truncation = match augmentations.as_slice() {
+                    [] => None,
+                    [Augmentation::Truncate { op, affix, n }] => {
+                        let n = n
+                            .as_ref()
+                            .and_then(|n| usize::try_from(n).ok())
+                            .unwrap_or(1);
+                        Some(match (op, affix) {
+                            (SelectorOp::Keep, Affix::High) => Truncation::KeepHighest(n),
+                            (SelectorOp::Keep, Affix::Low) => Truncation::KeepLowest(n),
+                            (SelectorOp::Drop, Affix::High) => Truncation::DropHighest(n),
+                            (SelectorOp::Drop, Affix::Low) => Truncation::DropLowest(n),
+                        })
+                    }
+                    _ => return Err(unsupported()),
+                };
+
+                match quantity {
+                    Some(quantity) => self.compile(quantity)?,
+                    None => self.push(self.options.quantity())?,
+                }
+                match power {
+                    Some(power) => self.compile(power)?,
+                    None => self.push(self.options.power())?,
+                }
+                self.emit(Instruction::Roll(truncation));
+            }
+            Expression::Binop { operator, lhs, rhs } => {
+                self.compile(lhs)?;
+                if *operator == BinaryOperator::Chain {
+                    self.emit(Instruction::Pop);
+                }
+                self.compile(rhs)?;
+
+                match operator {
+                    BinaryOperator::Add => self.emit(Instruction::Add),
+                    BinaryOperator::Subtract => self.emit(Instruction::Subtract),
+                    BinaryOperator::Multiply => self.emit(Instruction::Multiply),
+                    BinaryOperator::Equals => self.emit(Instruction::Equals),
+                    BinaryOperator::LessThan => self.emit(Instruction::LessThan),
+                    BinaryOperator::GreaterThan => self.emit(Instruction::GreaterThan),
+                    BinaryOperator::Chain => 0,
+                };
+            }
+            Expression::UnaryNegation(expr) => {
+                self.compile(expr)?;
+                self.emit(Instruction::Negate);
+            }
+            Expression::Subexpression(expr)
+            | Expression::Annotated {
+                expression: expr, ..
+            }
+            | Expression::Tagged {
+                expression: expr, ..
+            } => self.compile(expr)?,
+            Expression::Repeat { count, expression } => {
+                self.compile(count)?;
+                let repeat = self.emit(Instruction::Repeat(0));
+                let start = self.here();
+                self.compile(expression)?;
+                self.emit(Instruction::Next(start));
+                self.land(repeat);
+            }
+            Expression::Conditional {
+                condition,
+                then,
+                otherwise,
+            } => {
+                self.compile(condition)?;
+                let jump = self.emit(Instruction::JumpIfZero(0));
+                self.compile(then)?;
+                let end = self.emit(Instruction::Jump(0));
+                self.land(jump);
+                self.compile(otherwise)?;
+                self.land(end);
+            }
+            Expression::Group {
+                members,
+                augmentations,
+            } if augmentations.is_empty() => {
+                self.push(0)?;
+                for member in members {
+                    self.compile(member)?;
+                    self.emit(Instruction::Add);
+                }
+            }
+            Expression::Call { function, args } => {
+                let function = builtin(function).ok_or_else(unsupported)?;
+                for arg in args {
+                    self.compile(arg)?;
+                }
+                self.emit(Instruction::Call(function, args.len()));
+            }
+            Expression::Reference(name) => {
+                let body = self
+                    .options
+                    .macros()
+                    .get(name)
+                    .ok_or_else(|| RollerError::UnknownMacro { name: name.clone() })?;
+                self.compile(body)?;
+            }
+            Expression::Group { .. } | Expression::Weighted { .. } | Expression::Decimal(_) => {
+                return Err(unsupported())
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Runs the programs, keeping its stacks between the runs so that rolling
+/// over and over allocates nothing
+#[derive(Debug, Clone, Default)]
+pub struct Vm {
+    stack: Vec<i64>,
+    // The total so far and the rolls left of every repetition entered
+    repeats: Vec<(i64, i64)>,
+    dice: Vec<i64>,
+    fuel: Option<u64>,
+    dice_rolled: usize,
+}
+
+impl Vm {
+    /// Roll the program once. A unit of fuel is burnt for every
+    /// instruction and every dice, close to what the roller burns.
+    pub fn run(
+        &mut self,
+        program: &Program,
+        rng: &mut (impl RngCore + ?Sized),
+    ) -> RollerResult<i64> {
+        if program.secure {
+            return Err(RollerError::InsecureRng);
+        }
+
+        self.execute(program, rng)
+    }
+
+    /// Roll the program once with a cryptographically secure generator
+    pub fn run_secure(
+        &mut self,
+        program: &Program,
+        rng: &mut (impl RngCore + CryptoRng + ?Sized),
+    ) -> RollerResult<i64> {
+        self.execute(program, rng)
+    }
+
+    fn execute(
+        &mut self,
+        program: &Program,
+        rng: &mut (impl RngCore + ?Sized),
+    ) -> RollerResult<i64> {
+        use Instruction::*;
+        use RollerError::Overflow;
+
+        self.stack.clear();
+        self.repeats.clear();
+        self.fuel = program.fuel;
+        self.dice_rolled = 0;
+
+        let mut next = 0;
+        while let Some(&instruction) = program.instructions.get(next) {
+            next += 1;
+            self.burn(program, 1)?;
+
+            match instruction {
+                Push(n) => self.stack.push(n),
+                Roll(truncation) => {
+                    let power = self.pop();
+                    let quantity = self.pop();
+                    let total = self.roll(program, rng, quantity, power, truncation)?;
+                    self.stack.push(total);
+                }
+                Add => self.binop(|a, b| a.checked_add(b).ok_or(Overflow))?,
+                Subtract => self.binop(|a, b| a.checked_sub(b).ok_or(Overflow))?,
+                Multiply => self.binop(|a, b| a.checked_mul(b).ok_or(Overflow))?,
+                Equals => self.binop(|a, b| Ok((a == b) as i64))?,
+                LessThan => self.binop(|a, b| Ok((a < b) as i64))?,
+                GreaterThan => self.binop(|a, b| Ok((a > b) as i64))?,
+                Negate => {
+                    let value = self.pop().checked_neg().ok_or(Overflow)?;
+                    self.stack.push(value);
+                }
+                Pop => {
+                    self.pop();
+                }
+                Call(function, n) => {
+                    let args = self.stack.split_off(self.stack.len() - n);
+                    let value = function.call(&args)?;
+                    self.stack.push(value);
+                }
+                JumpIfZero(to) => {
+                    if self.pop() == 0 {
+                        next = to;
+                    }
+                }
+                Jump(to) => next = to,
+                Repeat(to) => {
+                    let count = self.pop();
+                    match count.cmp(&0) {
+                        Ordering::Less => return Err(RollerError::InvalidRepetition { count }),
+                        Ordering::Equal => {
+                            self.stack.push(0);
+                            next = to;
+                        }
+                        Ordering::Greater => self.repeats.push((0, count)),
+                    }
+                }
+                Next(to) => {
+                    let value = self.pop();
+                    let (total, left) = self.repeats.last_mut().expect("entered the repetition");
+                    *total = total.checked_add(value).ok_or(Overflow)?;
+                    *left -= 1;
+
+                    if *left > 0 {
+                        next = to;
+                    } else {
+                        let (total, _) = self.repeats.pop().expect("entered the repetition");
+                        self.stack.push(total);
+                    }
+                }
+            }
+        }
+
+        Ok(self.pop())
+    }
+
+    fn pop(&mut self) -> i64 {
+        self.stack
+            .pop()
+            .expect("the programs keep the stack balanced")
+    }
+
+    fn binop(&mut self, f: impl FnOnce(i64, i64) -> RollerResult<i64>) -> RollerResult<()> {
+        let rhs = self.pop();
+        let lhs = self.pop();
+        self.stack.push(f(lhs, rhs)?);
+        Ok(())
+    }
+
+    fn burn(&mut self, program: &Program, steps: u64) -> RollerResult<()> {
+        let Some(left) = self.fuel else {
+            return Ok(());
+        };
+
+        match left.checked_sub(steps) {
+            Some(left) => {
+                self.fuel = Some(left);
+                Ok(())
+            }
+            None => Err(RollerError::OutOfFuel {
+                fuel: program.fuel.unwrap_or_default(),
+            }),
+        }
+    }
+
+    // The dice the way the roller rolls them, the same numbers are drawn
+    fn roll(
+        &mut self,
+        program: &Program,
+        rng: &mut (impl RngCore + ?Sized),
+        quantity: i64,
+        power: i64,
+        truncation: Option<Truncation>,
+    ) -> RollerResult<i64> {
+        let count = usize::try_from(quantity.unsigned_abs()).unwrap_or(usize::MAX);
+        if let Some(max) = program.max_dice_per_term.filter(|max| count > *max) {
+            return Err(RollerError::LimitExceeded {
+                limit: Limit::DicePerTerm,
+                max,
+            });
+        }
+        self.burn(program, count as u64)?;
+        self.dice_rolled = self.dice_rolled.saturating_add(count);
+        if let Some(max) = program.max_total_dice.filter(|max| self.dice_rolled > *max) {
+            return Err(RollerError::LimitExceeded {
+                limit: Limit::TotalDice,
+                max,
+            });
+        }
+
+        self.dice.clear();
+        if quantity != 0 && power != 0 {
+            self.dice.resize(count, 0);
+            let sampler = DiceSampler::new(power);
+            if program.packed_dice {
+                sampler.fill_packed(rng, &mut self.dice);
+            } else {
+                sampler.fill(rng, &mut self.dice);
+            }
+            if quantity < 0 {
+                self.dice.iter_mut().for_each(|d| *d = -*d);
+            }
+        }
+
+        let kept = match truncation {
+            None => &self.dice[..],
+            Some(truncation) => {
+                self.dice.sort_unstable();
+                let len = self.dice.len();
+                let dropped = |n: usize| {
+                    len.checked_sub(n).ok_or(RollerError::TruncationFailure {
+                        rolled: len as u32,
+                        removed: n as u32,
+                    })
+                };
+
+                match truncation {
+                    Truncation::KeepHighest(n) => &self.dice[len - n.min(len)..],
+                    Truncation::KeepLowest(n) => &self.dice[..n.min(len)],
+                    Truncation::DropHighest(n) => &self.dice[..dropped(n)?],
+                    Truncation::DropLowest(n) => &self.dice[len - dropped(n)?..],
+                }
+            }
+        };
+
+        kept.iter()
+            .try_fold(0i64, |total, d| total.checked_add(*d))
+            .ok_or(RollerError::Overflow)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{
+        rngs::{OsRng, StdRng},
+        SeedableRng,
+    };
+
+    use crate::{
+        compiler::{compile, compile_with, CompileError, Vm},
+        dialect::Roll20,
+        interpreter::{Limit, RollerError, StandardNaiveRoller},
+        macros::MacroRegistry,
+        options::{FixedDice, RollerOptions},
+        parser::{parse, parse_dialect},
+    };
+
+    #[test]
+    fn test_compile() {
+        let mut macros = MacroRegistry::default();
+        macros.define("attack", parse("d20 + 7").unwrap()).unwrap();
+        let options = RollerOptions::builder().macros(macros).build();
+
+        // The same totals as the roller for the same seed
+        let mut vm = Vm::default();
+        for input in [
+            "d20 + 5",
+            "4d6kh3",
+            "-3d6dl1 * 2",
+            "6x(4d6kh3)",
+            "(d4)x(d6) - 3",
+            "d20 > 15 ? 2d6 [fire] + 1d8 : 0",
+            "{d20 + 5, 2d6} * 2",
+            "max(2d6, d12) + (1d4 + 2) * 3",
+            "@attack, d4",
+            "(d6)d(d6)kl2 + d-4",
+            "10d6kh20 + 2d10dh1",
+        ] {
+            let program = compile_with(&parse(input).unwrap(), &options).unwrap();
+            let mut roller = StandardNaiveRoller::new_seeded(0).with_options(options.clone());
+            let mut rng = StdRng::seed_from_u64(0);
+
+            for _ in 0..20 {
+                let expected = roller
+                    .roll_naive(parse(input).unwrap())
+                    .and_then(|v| v.total());
+                assert_eq!(vm.run(&program, &mut rng), expected, "{input}");
+            }
+        }
+
+        let program = compile(&parse("2d6 + 3").unwrap()).unwrap();
+        let listing: Vec<_> = program
+            .instructions()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(listing, vec!["push 2", "push 6", "roll", "push 3", "add"]);
+
+        let mut rng = StdRng::seed_from_u64(0);
+        for (input, err) in [
+            (
+                "1d6dl2",
+                RollerError::TruncationFailure {
+                    rolled: 1,
+                    removed: 2,
+                },
+            ),
+            ("(0 - 2)x(d6)", RollerError::InvalidRepetition { count: -2 }),
+        ] {
+            let program = compile(&parse(input).unwrap()).unwrap();
+            assert_eq!(program.run(&mut rng), Err(err), "{input}");
+        }
+
+        let limited = RollerOptions::builder().max_total_dice(10).build();
+        let program = compile_with(&parse("6d6 + 6d6").unwrap(), &limited).unwrap();
+        assert_eq!(
+            program.run(&mut rng),
+            Err(RollerError::LimitExceeded {
+                limit: Limit::TotalDice,
+                max: 10
+            })
+        );
+
+        for expr in [
+            parse("4d6!").unwrap(),
+            parse("{d20, d20}kh1").unwrap(),
+            parse_dialect("d6r1", &Roll20).unwrap(),
+        ] {
+            assert_eq!(
+                compile(&expr).err(),
+                Some(CompileError::Unsupported { expression: expr })
+            );
+        }
+        assert!(matches!(
+            compile(&parse("@attack").unwrap()),
+            Err(CompileError::Fails(RollerError::UnknownMacro { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_into_fn() {
+        let options = RollerOptions::builder().fuel(1000).build();
+
+        // Compiled, folded and walked alike roll what the roller rolls
+        for expr in [
+            parse("4d6kh3 + 2 * (3 + 4)").unwrap(),
+            parse("4d6!").unwrap(),
+            parse_dialect("2d6r1 + d4", &Roll20).unwrap(),
+            parse("max(3, 5) - 1").unwrap(),
+        ] {
+            let mut roller = StandardNaiveRoller::new_seeded(5).with_options(options.clone());
+            let mut rng = StdRng::seed_from_u64(5);
+            let expected: Vec<_> = (0..20)
+                .map(|_| roller.roll_naive(&expr).and_then(|v| v.total()))
+                .collect();
+
+            let mut roll = expr.clone().into_fn(&options);
+            let totals: Vec<_> = (0..20).map(|_| roll(&mut rng)).collect();
+            assert_eq!(totals, expected, "{expr}");
+        }
+
+        let mut roll = parse("(0 - 2)x(3)").unwrap().into_fn::<StdRng>(&options);
+        assert_eq!(
+            roll(&mut StdRng::seed_from_u64(0)),
+            Err(RollerError::InvalidRepetition { count: -2 })
+        );
+
+        let program = compile(&parse("d6 + 2 * 3").unwrap()).unwrap();
+        let listing: Vec<_> = program
+            .instructions()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(listing, vec!["push 1", "push 6", "roll", "push 6", "add"]);
+    }
+
+    #[test]
+    fn test_secure() {
+        let secure = RollerOptions::default().with_secure(true);
+        let expr = parse("10d20 + d6").unwrap();
+        let program = compile_with(&expr, &secure).unwrap();
+
+        let total = program.run_secure(&mut OsRng).unwrap();
+        assert!((11..=206).contains(&total));
+        assert_eq!(
+            program.run(&mut StdRng::seed_from_u64(0)),
+            Err(RollerError::InsecureRng)
+        );
+
+        let mut roll = expr.into_fn(&secure);
+        assert_eq!(roll(&mut OsRng), Err(RollerError::InsecureRng));
+    }
+
+    #[test]
+    fn test_fixed_dice() {
+        let fixed = RollerOptions::builder()
+            .fixed_dice(FixedDice::Midpoint)
+            .build();
+        let expr = parse("d20 + 5").unwrap();
+        assert_eq!(
+            compile_with(&expr, &fixed).map(|_| ()),
+            Err(CompileError::Unsupported {
+                expression: parse("d20").unwrap()
+            })
+        );
+
+        // The closures roll them with the naive roller instead
+        let mut roll = expr.into_fn(&fixed);
+        assert_eq!(roll(&mut StdRng::seed_from_u64(0)), Ok(15));
+    }
+}
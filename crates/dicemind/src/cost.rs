@@ -0,0 +1,387 @@
+//! Estimating how expensive an expression is to roll before rolling it,
+//! so a server can turn down `999999d999999!` instead of timing out in
+//! the middle of the roll.
+//!
+//! ```
+//! use dicemind::{cost::Cost, prelude::*};
+//!
+//! let budget = Cost { dice: 1000, exploding: 100, nodes: 256 };
+//!
+//! assert!(parse("6x(4d6kh3)").unwrap().cost().fits(&budget));
+//! assert!(!parse("999999d999999!").unwrap().cost().fits(&budget));
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+use num::bigint::Sign;
+
+use crate::{
+    options::RollerOptions,
+    syntax::{Augmentation, Expression, Integer, PositiveInteger},
+};
+
+/// The upper bounds of the work a roll does. The dice whose quantity is
+/// rolled are counted by the largest magnitude it could roll, negative or
+/// not, and a repetition counts
+/// the dice of every roll. The macros are not expanded, a reference is a
+/// single node without dice.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Cost {
+    /// How many dice are rolled, before any explosions or rerolls
+    pub dice: u64,
+    /// How many of those dice can explode or be rerolled, each of them
+    /// can roll any number of extra dice
+    pub exploding: u64,
+    /// The size of the expression as written
+    pub nodes: u64,
+}
+
+impl Cost {
+    /// Whether none of the bounds are above the ones of the budget
+    pub fn fits(&self, budget: &Cost) -> bool {
+        self.dice <= budget.dice && self.exploding <= budget.exploding && self.nodes <= budget.nodes
+    }
+
+    fn add(self, other: Cost) -> Cost {
+        Cost {
+            dice: self.dice.saturating_add(other.dice),
+            exploding: self.exploding.saturating_add(other.exploding),
+            nodes: self.nodes.saturating_add(other.nodes),
+        }
+    }
+
+    fn max(self, other: Cost) -> Cost {
+        Cost {
+            dice: self.dice.max(other.dice),
+            exploding: self.exploding.max(other.exploding),
+            nodes: self.nodes.max(other.nodes),
+        }
+    }
+
+    // The written nodes are not repeated, the rolled dice are
+    fn repeated(self, count: u64) -> Cost {
+        Cost {
+            dice: self.dice.saturating_mul(count),
+            exploding: self.exploding.saturating_mul(count),
+            nodes: self.nodes,
+        }
+    }
+}
+
+impl Expression {
+    /// An estimate of how expensive the expression is to roll, without
+    /// rolling it
+    pub fn cost(&self) -> Cost {
+        let node = Cost {
+            nodes: 1,
+            ..Cost::default()
+        };
+
+        match self {
+            Expression::Dice {
+                quantity,
+                augmentations,
+                ..
+            }
+            | Expression::Weighted {
+                quantity,
+                augmentations,
+                ..
+            } => {
+                let dice = quantity.as_deref().map_or(1, maximum);
+                let exploding = if augmentations.iter().any(rolls_again) {
+                    dice
+                } else {
+                    0
+                };
+
+                self.children()
+                    .into_iter()
+                    .map(Expression::cost)
+                    .fold(node, Cost::add)
+                    .add(Cost {
+                        dice,
+                        exploding,
+                        nodes: 0,
+                    })
+            }
+            Expression::Repeat { count, expression } => node
+                .add(count.cost())
+                .add(expression.cost().repeated(maximum(count))),
+            // Only one of the branches is rolled
+            Expression::Conditional {
+                condition,
+                then,
+                otherwise,
+            } => {
+                let (then, otherwise) = (then.cost(), otherwise.cost());
+                node.add(condition.cost()).add(Cost {
+                    nodes: then.nodes.saturating_add(otherwise.nodes),
+                    ..then.max(otherwise)
+                })
+            }
+            expr => expr
+                .children()
+                .into_iter()
+                .map(Expression::cost)
+                .fold(node, Cost::add),
+        }
+    }
+}
+
+// The fewest dice a roll of the expression rolls whatever the dice come
+// up, the dice of the macros are not counted
+pub(crate) fn fewest_dice(expr: &Expression, options: &RollerOptions) -> usize {
+    let count = |n: &Integer| usize::try_from(n).unwrap_or(0);
+
+    let own = match expr {
+        Expression::Dice { quantity, .. } | Expression::Weighted { quantity, .. } => {
+            match quantity.as_deref() {
+                None => usize::try_from(&options.quantity()).unwrap_or(0),
+                Some(Expression::Constant(n)) => count(n),
+                Some(_) => 0,
+            }
+        }
+        // Only one of the branches is rolled, and the repeated expression
+        // is rolled as many times as the count says
+        Expression::Conditional {
+            condition,
+            then,
+            otherwise,
+        } => {
+            let branch = fewest_dice(then, options).min(fewest_dice(otherwise, options));
+            return fewest_dice(condition, options).saturating_add(branch);
+        }
+        Expression::Repeat {
+            count: n,
+            expression,
+        } => {
+            let repeated = match n.as_ref() {
+                Expression::Constant(n) => count(n),
+                _ => 0,
+            };
+            return fewest_dice(n, options)
+                .saturating_add(fewest_dice(expression, options).saturating_mul(repeated));
+        }
+        _ => 0,
+    };
+
+    expr.children()
+        .into_iter()
+        .map(|child| fewest_dice(child, options))
+        .fold(own, usize::saturating_add)
+}
+
+fn rolls_again(augment: &Augmentation) -> bool {
+    matches!(
+        augment,
+        Augmentation::Explode { .. } | Augmentation::Compound { .. } | Augmentation::Reroll { .. }
+    )
+}
+
+// The lowest and the highest value an expression could roll
+type Bounds = (i128, i128);
+
+const UNBOUNDED: Bounds = (i128::MIN, i128::MAX);
+
+fn integer(n: &Integer) -> i128 {
+    i128::try_from(n).unwrap_or(if n.sign() == Sign::Minus {
+        i128::MIN
+    } else {
+        i128::MAX
+    })
+}
+
+fn positive(n: &PositiveInteger) -> i128 {
+    i128::try_from(n).unwrap_or(i128::MAX)
+}
+
+fn add((a, b): Bounds, (c, d): Bounds) -> Bounds {
+    (a.saturating_add(c), b.saturating_add(d))
+}
+
+fn mul((a, b): Bounds, (c, d): Bounds) -> Bounds {
+    let products = [
+        a.saturating_mul(c),
+        a.saturating_mul(d),
+        b.saturating_mul(c),
+        b.saturating_mul(d),
+    ];
+    (
+        products.into_iter().min().unwrap_or_default(),
+        products.into_iter().max().unwrap_or_default(),
+    )
+}
+
+fn union((a, b): Bounds, (c, d): Bounds) -> Bounds {
+    (a.min(c), b.max(d))
+}
+
+// The quantity times the faces, and nothing when all of them are dropped
+fn dice_bounds(
+    quantity: Bounds,
+    (mut lowest, mut highest): Bounds,
+    augments: &[Augmentation],
+) -> Bounds {
+    for augment in augments {
+        match augment {
+            Augmentation::Minimum { n } => {
+                lowest = lowest.max(positive(n));
+                highest = highest.max(positive(n));
+            }
+            Augmentation::Maximum { n } => {
+                lowest = lowest.min(positive(n));
+                highest = highest.min(positive(n));
+            }
+            Augmentation::Explode { .. }
+            | Augmentation::Compound { .. }
+            | Augmentation::Custom { .. } => return UNBOUNDED,
+            _ => {}
+        }
+    }
+
+    // A pool counts every dice as a success or a failure at most
+    if augments.iter().any(Augmentation::is_counting) {
+        (lowest, highest) = (-1, 1);
+    }
+
+    union(mul(quantity, (lowest, highest)), (0, 0))
+}
+
+// The lowest and the highest value the expression could roll, the
+// quantities and the repetitions are costed by the larger of their
+// magnitudes. Anything that can grow without a bound, or that is not
+// known before the roll, saturates.
+fn bounds(expr: &Expression) -> Bounds {
+    use crate::syntax::BinaryOperator::*;
+
+    let quantity = |quantity: &Option<Box<Expression>>| quantity.as_deref().map_or((1, 1), bounds);
+
+    match expr {
+        Expression::Constant(c) => (integer(c), integer(c)),
+        Expression::Dice {
+            quantity: q,
+            power,
+            augmentations,
+        } => {
+            let (lowest, highest) = power.as_deref().map_or((6, 6), bounds);
+            // The faces of a dice with a negative power are negative
+            let faces = (lowest.min(1), highest.max(-1));
+            dice_bounds(quantity(q), faces, augmentations)
+        }
+        Expression::Weighted {
+            quantity: q,
+            faces,
+            augmentations,
+        } => {
+            let values = faces.iter().map(|face| integer(&face.value));
+            let faces = (
+                values.clone().min().unwrap_or_default(),
+                values.max().unwrap_or_default(),
+            );
+            dice_bounds(quantity(q), faces, augmentations)
+        }
+        Expression::Binop { operator, lhs, rhs } => match operator {
+            Add => add(bounds(lhs), bounds(rhs)),
+            Subtract => {
+                let (lowest, highest) = bounds(rhs);
+                add(
+                    bounds(lhs),
+                    (highest.saturating_neg(), lowest.saturating_neg()),
+                )
+            }
+            Multiply => mul(bounds(lhs), bounds(rhs)),
+            Chain => bounds(rhs),
+            Equals | LessThan | GreaterThan => (0, 1),
+        },
+        Expression::UnaryNegation(expr) => {
+            let (lowest, highest) = bounds(expr);
+            (highest.saturating_neg(), lowest.saturating_neg())
+        }
+        Expression::Subexpression(expr)
+        | Expression::Annotated {
+            expression: expr, ..
+        }
+        | Expression::Tagged {
+            expression: expr, ..
+        } => bounds(expr),
+        Expression::Repeat { count, expression } => {
+            union(mul(bounds(count), bounds(expression)), (0, 0))
+        }
+        Expression::Conditional {
+            then, otherwise, ..
+        } => union(bounds(then), bounds(otherwise)),
+        // Every member is either kept or dropped
+        Expression::Group { members, .. } => members
+            .iter()
+            .map(|member| union(bounds(member), (0, 0)))
+            .fold((0, 0), add),
+        Expression::Decimal(d) => d.to_ratio().map_or(UNBOUNDED, |d| {
+            (d.floor().to_integer().into(), d.ceil().to_integer().into())
+        }),
+        Expression::Call { .. } | Expression::Reference(_) => UNBOUNDED,
+    }
+}
+
+// How many dice or repetitions the expression could ask for at most,
+// whatever its sign
+fn maximum(expr: &Expression) -> u64 {
+    let (lowest, highest) = bounds(expr);
+    let magnitude = lowest.unsigned_abs().max(highest.unsigned_abs());
+    u64::try_from(magnitude).unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{cost::Cost, parser::parse};
+
+    fn cost(input: &str) -> Cost {
+        parse(input).unwrap().cost()
+    }
+
+    #[test]
+    fn test_cost() {
+        assert_eq!(
+            cost("d20 + 5"),
+            Cost {
+                dice: 1,
+                exploding: 0,
+                nodes: 4
+            }
+        );
+        assert_eq!(cost("6x(4d6kh3)").dice, 24);
+        assert_eq!(cost("3d6! + 2d10").exploding, 3);
+        assert_eq!(cost("(2d4)d6").dice, 10);
+        assert_eq!(cost("d20 > 10 ? 8d6 : 2d6").dice, 9);
+
+        let huge = cost("999999d999999!");
+        assert_eq!(huge.dice, 999999);
+        assert_eq!(huge.exploding, 999999);
+
+        // Nothing bounds how often an exploding count repeats the roll
+        assert_eq!(cost("(d6!)x(d6)").dice, u64::MAX);
+        assert_eq!(cost("(d4)x(d6)").dice, 5);
+        assert_eq!(
+            cost("100000x(100000x(100000x(100000x(d6))))").dice,
+            u64::MAX
+        );
+
+        let budget = Cost {
+            dice: 100,
+            exploding: 10,
+            nodes: 64,
+        };
+        assert!(cost("4d6kh3 + 2d8 [fire]").fits(&budget));
+        assert!(!cost("20d6!").fits(&budget));
+        assert!(!cost("101d6").fits(&budget));
+
+        // The negative quantities and counts are as large as their magnitudes
+        assert_eq!(cost("(0-1000)*(0-1000)d6").dice, 1000);
+        assert_eq!(cost("((0-1000)*(0-1000))d6").dice, 1_000_000);
+        assert_eq!(cost("(-20)d6").dice, 20);
+        assert_eq!(cost("(10 - -5)d6").dice, 15);
+        assert_eq!(cost("(d{-30:1, 2:1})d6").dice, 1 + 30);
+        assert_eq!(cost("(2 - d20)x(d6)").dice, 1 + 18);
+        assert!(!cost("(0-1000)*(0-1000)d6").fits(&budget));
+    }
+}
@@ -0,0 +1,617 @@
+use std::{
+    cmp::Ordering,
+    fmt::{Formatter, Result as FmtResult},
+};
+
+use num::Zero;
+
+use crate::{
+    parser::{
+        parse_augment_emphasis, parse_augment_explode, parse_count, parse_filter, parse_number,
+        parse_selector, parse_truncation,
+    },
+    syntax::{Affix, Augmentation, BinaryOperator, Selector, SelectorOp},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Associativity {
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PrefixOperator {
+    Plus,
+    Negate,
+}
+
+/// Parses a single augmentation from the start of the chars
+pub type AugmentParser = fn(&[char]) -> Option<(Augmentation, &[char])>;
+
+/// The infix operators of the native notation, how tightly they
+/// bind comes from the precedence of the `BinaryOperator`
+pub const INFIX_OPERATORS: &[(char, BinaryOperator, Associativity)] = &[
+    (',', BinaryOperator::Chain, Associativity::Left),
+    ('=', BinaryOperator::Equals, Associativity::Left),
+    ('<', BinaryOperator::LessThan, Associativity::Left),
+    ('>', BinaryOperator::GreaterThan, Associativity::Left),
+    ('+', BinaryOperator::Add, Associativity::Left),
+    ('-', BinaryOperator::Subtract, Associativity::Left),
+    ('*', BinaryOperator::Multiply, Associativity::Left),
+];
+
+/// The prefix operators, they bind tighter than any infix operator
+pub const PREFIX_OPERATORS: &[(char, PrefixOperator)] =
+    &[('+', PrefixOperator::Plus), ('-', PrefixOperator::Negate)];
+
+/// Which spellings of the augments and which operators are accepted.
+/// Everything else, the dice, groups, calls and annotations, is shared
+/// by all of the dialects and the expressions are rolled the same way.
+pub trait Dialect {
+    /// Tried in order right after the dice and the groups
+    fn augments(&self) -> &[AugmentParser];
+
+    fn infix_operators(&self) -> &[(char, BinaryOperator, Associativity)] {
+        INFIX_OPERATORS
+    }
+
+    fn prefix_operators(&self) -> &[(char, PrefixOperator)] {
+        PREFIX_OPERATORS
+    }
+
+    /// Spell the augment the way the dialect parses it back, the ones it
+    /// does not have are written natively
+    fn write_augment(&self, augment: &Augmentation, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{augment}")
+    }
+
+    /// Whether `cond ? a : b` rolls one of the branches
+    fn conditionals(&self) -> bool {
+        false
+    }
+
+    /// Whether `adv` and `dis` are short for `2d20kh1` and `2d20kl1`,
+    /// and `d8a` and `d8d` for `2d8kh1` and `2d8kl1`
+    fn advantage(&self) -> bool {
+        false
+    }
+
+    fn infix_operator(&self, symbol: char) -> Option<(BinaryOperator, Associativity)> {
+        self.infix_operators()
+            .iter()
+            .find(|(c, ..)| *c == symbol)
+            .map(|&(_, operator, associativity)| (operator, associativity))
+    }
+
+    fn prefix_operator(&self, symbol: char) -> Option<PrefixOperator> {
+        self.prefix_operators()
+            .iter()
+            .find(|(c, _)| *c == symbol)
+            .map(|&(_, operator)| operator)
+    }
+}
+
+/// The notation described in the README
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Native;
+
+impl Dialect for Native {
+    fn augments(&self) -> &[AugmentParser] {
+        &[
+            parse_augment_emphasis,
+            parse_augment_explode,
+            parse_truncation,
+            parse_filter,
+            parse_count,
+        ]
+    }
+
+    fn conditionals(&self) -> bool {
+        true
+    }
+
+    fn advantage(&self) -> bool {
+        true
+    }
+}
+
+/// The notation of the Roll20 chat, so the macros can be pasted as is.
+///
+/// * `4d6k3`, `4d6d1` - keep the highest and drop the lowest without `h` and `l`.
+/// * `d20ro<2`, `d6r1` - reroll once or until the dice stop matching.
+/// * `3d6!`, `3d6!!>5` - explode and compound the explosions into one dice.
+/// * `d20cs>19cf<3` - change what counts as a critical.
+/// * `8d10>7f1` - count the dice at or above 7 as successes and the ones as failures.
+///
+/// The comparison points are inclusive, `>7` matches a 7 too. The comparison
+/// operators are not accepted outside of the dice, just as in Roll20.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Roll20;
+
+// Both Roll20 and Foundry only have the arithmetic ones
+const ARITHMETIC_OPERATORS: &[(char, BinaryOperator, Associativity)] = &[
+    ('+', BinaryOperator::Add, Associativity::Left),
+    ('-', BinaryOperator::Subtract, Associativity::Left),
+    ('*', BinaryOperator::Multiply, Associativity::Left),
+];
+
+impl Dialect for Roll20 {
+    fn augments(&self) -> &[AugmentParser] {
+        &[
+            parse_roll20_explode,
+            parse_roll20_reroll,
+            parse_roll20_truncation,
+            parse_roll20_critical,
+            parse_roll20_failures,
+            parse_roll20_successes,
+        ]
+    }
+
+    fn infix_operators(&self) -> &[(char, BinaryOperator, Associativity)] {
+        ARITHMETIC_OPERATORS
+    }
+
+    fn write_augment(&self, augment: &Augmentation, f: &mut Formatter<'_>) -> FmtResult {
+        use Augmentation::*;
+
+        let (token, selector) = match augment {
+            Reroll { selector, once } => (if *once { "ro" } else { "r" }, Some(selector)),
+            Explode { selector } => ("!", selector.as_ref()),
+            Compound { selector } => ("!!", selector.as_ref()),
+            CriticalSuccess { selector } => ("cs", Some(selector)),
+            CriticalFailure { selector } => ("cf", Some(selector)),
+            CountFailures { selector } => ("f", Some(selector)),
+            // The target number goes right after the dice, `3d6>4`
+            CountSuccesses { selector } if selector.relation == Ordering::Equal => {
+                return write!(f, "={}", selector.n)
+            }
+            CountSuccesses { selector } => ("", Some(selector)),
+            _ => return write!(f, "{augment}"),
+        };
+
+        match selector.map(roll20_point) {
+            None => f.write_str(token),
+            Some(Some(point)) => write!(f, "{token}{point}"),
+            // There is no point below 0 to compare with
+            Some(None) => write!(f, "{augment}"),
+        }
+    }
+}
+
+// The Roll20 comparison points include the number, `<3` is written `<2`
+fn roll20_point(selector: &Selector) -> Option<String> {
+    let Selector {
+        relation,
+        inclusive,
+        n,
+    } = selector;
+
+    Some(match (relation, inclusive) {
+        (Ordering::Equal, _) => format!("{n}"),
+        (Ordering::Less, true) => format!("<{n}"),
+        (Ordering::Greater, true) => format!(">{n}"),
+        (Ordering::Less, false) if n.is_zero() => return None,
+        (Ordering::Less, false) => format!("<{}", n - 1u32),
+        (Ordering::Greater, false) => format!(">{}", n + 1u32),
+    })
+}
+
+/// The notation of the Foundry VTT rolls, so the macros can be copied over.
+///
+/// * `2d20kh`, `4d6dl` - the same truncation as in the native notation,
+///   `k3` and `d1` keep the highest and drop the lowest.
+/// * `d20r1`, `d6rr<3` - reroll once or until the dice stop matching,
+///   a bare `r` rerolls the ones.
+/// * `3d6x`, `d10x>=8` - explode on the maximum or on the matching dice.
+/// * `d20min10`, `4d6max5` - raise or lower the dice to the bound.
+///
+/// The comparisons are the native ones, `<3` does not match a 3. Just as in
+/// Roll20 only the arithmetic operators are accepted outside of the dice.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Foundry;
+
+impl Dialect for Foundry {
+    fn augments(&self) -> &[AugmentParser] {
+        &[
+            parse_foundry_bound,
+            parse_foundry_reroll,
+            parse_foundry_explode,
+            parse_roll20_truncation,
+        ]
+    }
+
+    fn infix_operators(&self) -> &[(char, BinaryOperator, Associativity)] {
+        ARITHMETIC_OPERATORS
+    }
+
+    fn write_augment(&self, augment: &Augmentation, f: &mut Formatter<'_>) -> FmtResult {
+        let (token, selector) = match augment {
+            Augmentation::Reroll { selector, once } => {
+                (if *once { "r" } else { "rr" }, Some(selector))
+            }
+            Augmentation::Explode { selector } => ("x", selector.as_ref()),
+            _ => return write!(f, "{augment}"),
+        };
+
+        f.write_str(token)?;
+        match selector {
+            // A bare number is matched exactly
+            Some(selector) if selector.relation == Ordering::Equal => write!(f, "{}", selector.n),
+            Some(selector) => write!(f, "{selector}"),
+            None => Ok(()),
+        }
+    }
+}
+
+// `>3` and `<3` include the 3, a bare number is matched exactly
+fn parse_roll20_point(chars: &[char]) -> Option<(Selector, &[char])> {
+    let (relation, chars) = match chars.first()? {
+        '>' => (Ordering::Greater, &chars[1..]),
+        '<' => (Ordering::Less, &chars[1..]),
+        '=' => (Ordering::Equal, &chars[1..]),
+        _ => (Ordering::Equal, chars),
+    };
+
+    let (n, rest) = parse_number(chars)?;
+
+    Some((
+        Selector {
+            relation,
+            inclusive: relation != Ordering::Equal,
+            n,
+        },
+        rest,
+    ))
+}
+
+fn parse_roll20_explode(chars: &[char]) -> Option<(Augmentation, &[char])> {
+    let (compound, chars) = match chars {
+        ['!', '!', rest @ ..] => (true, rest),
+        ['!', rest @ ..] => (false, rest),
+        _ => return None,
+    };
+
+    let (selector, rest) = match parse_roll20_point(chars) {
+        Some((selector, rest)) => (Some(selector), rest),
+        None => (None, chars),
+    };
+
+    let augment = if compound {
+        Augmentation::Compound { selector }
+    } else {
+        Augmentation::Explode { selector }
+    };
+
+    Some((augment, rest))
+}
+
+fn parse_roll20_reroll(chars: &[char]) -> Option<(Augmentation, &[char])> {
+    let (once, chars) = match chars {
+        ['r', 'o', rest @ ..] => (true, rest),
+        ['r', rest @ ..] => (false, rest),
+        _ => return None,
+    };
+
+    let (selector, rest) = parse_roll20_point(chars)?;
+    Some((Augmentation::Reroll { selector, once }, rest))
+}
+
+// `k3` keeps the highest and `d3` drops the lowest
+fn parse_roll20_truncation(chars: &[char]) -> Option<(Augmentation, &[char])> {
+    let (op, chars) = match chars.first()? {
+        'k' => (SelectorOp::Keep, &chars[1..]),
+        'd' => (SelectorOp::Drop, &chars[1..]),
+        _ => return None,
+    };
+
+    let (affix, chars) = match chars.first() {
+        Some('h') => (Some(Affix::High), &chars[1..]),
+        Some('l') => (Some(Affix::Low), &chars[1..]),
+        _ => (None, chars),
+    };
+
+    let (n, rest) = match parse_number(chars) {
+        Some((n, rest)) => (Some(n), rest),
+        // A lone `d` is the next dice, not a truncation
+        None if affix.is_none() => return None,
+        None => (None, chars),
+    };
+
+    let affix = affix.unwrap_or(match op {
+        SelectorOp::Keep => Affix::High,
+        SelectorOp::Drop => Affix::Low,
+    });
+
+    Some((Augmentation::Truncate { op, affix, n }, rest))
+}
+
+fn parse_roll20_critical(chars: &[char]) -> Option<(Augmentation, &[char])> {
+    let (success, chars) = match chars {
+        ['c', 's', rest @ ..] => (true, rest),
+        ['c', 'f', rest @ ..] => (false, rest),
+        _ => return None,
+    };
+
+    let (selector, rest) = parse_roll20_point(chars)?;
+    let augment = if success {
+        Augmentation::CriticalSuccess { selector }
+    } else {
+        Augmentation::CriticalFailure { selector }
+    };
+
+    Some((augment, rest))
+}
+
+fn parse_roll20_failures(chars: &[char]) -> Option<(Augmentation, &[char])> {
+    let chars = chars.strip_prefix(&['f'])?;
+    let (selector, rest) = parse_roll20_point(chars)?;
+
+    Some((Augmentation::CountFailures { selector }, rest))
+}
+
+// The target number right after the dice, `3d6>4`
+fn parse_roll20_successes(chars: &[char]) -> Option<(Augmentation, &[char])> {
+    if !matches!(chars.first()?, '>' | '<' | '=') {
+        return None;
+    }
+
+    let (selector, rest) = parse_roll20_point(chars)?;
+    Some((Augmentation::CountSuccesses { selector }, rest))
+}
+
+// A bare number is matched exactly
+fn parse_foundry_point(chars: &[char]) -> Option<(Selector, &[char])> {
+    parse_selector(chars).or_else(|| {
+        let (n, rest) = parse_number(chars)?;
+        Some((
+            Selector {
+                relation: Ordering::Equal,
+                inclusive: false,
+                n,
+            },
+            rest,
+        ))
+    })
+}
+
+fn parse_foundry_bound(chars: &[char]) -> Option<(Augmentation, &[char])> {
+    let (minimum, chars) = match chars {
+        ['m', 'i', 'n', rest @ ..] => (true, rest),
+        ['m', 'a', 'x', rest @ ..] => (false, rest),
+        _ => return None,
+    };
+
+    let (n, rest) = parse_number(chars)?;
+    let augment = if minimum {
+        Augmentation::Minimum { n }
+    } else {
+        Augmentation::Maximum { n }
+    };
+
+    Some((augment, rest))
+}
+
+fn parse_foundry_reroll(chars: &[char]) -> Option<(Augmentation, &[char])> {
+    let (once, chars) = match chars {
+        ['r', 'r', rest @ ..] => (false, rest),
+        ['r', rest @ ..] => (true, rest),
+        _ => return None,
+    };
+
+    let (selector, rest) = parse_foundry_point(chars).unwrap_or((
+        Selector {
+            relation: Ordering::Equal,
+            inclusive: false,
+            n: 1u32.into(),
+        },
+        chars,
+    ));
+
+    Some((Augmentation::Reroll { selector, once }, rest))
+}
+
+fn parse_foundry_explode(chars: &[char]) -> Option<(Augmentation, &[char])> {
+    let chars = chars.strip_prefix(&['x'])?;
+
+    let (selector, rest) = match parse_foundry_point(chars) {
+        Some((selector, rest)) => (Some(selector), rest),
+        None => (None, chars),
+    };
+
+    Some((Augmentation::Explode { selector }, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use crate::{
+        dialect::{Dialect, Foundry, Native, Roll20},
+        parser::{parse, parse_dialect},
+        prelude::StandardNaiveRoller,
+        syntax::{Affix, Augmentation, Expression, Selector, SelectorOp},
+    };
+
+    fn augments(input: &str) -> Vec<Augmentation> {
+        match parse_dialect(input, &Roll20) {
+            Ok(Expression::Dice { augmentations, .. }) => augmentations.into_vec(),
+            other => panic!("{input} parsed as {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_roll20() {
+        assert_eq!(
+            augments("4d6k3"),
+            [Augmentation::Truncate {
+                op: SelectorOp::Keep,
+                affix: Affix::High,
+                n: Some(3u32.into()),
+            }]
+        );
+        assert_eq!(
+            augments("d20ro<2"),
+            [Augmentation::Reroll {
+                selector: Selector {
+                    relation: Ordering::Less,
+                    inclusive: true,
+                    n: 2u32.into(),
+                },
+                once: true,
+            }]
+        );
+        assert!(matches!(
+            &augments("3d6!!")[..],
+            [Augmentation::Compound { selector: None }]
+        ));
+        assert!(matches!(
+            &augments("d20cs>19cf<3")[..],
+            [
+                Augmentation::CriticalSuccess { .. },
+                Augmentation::CriticalFailure { .. }
+            ]
+        ));
+        assert!(matches!(
+            &augments("8d10>7f1")[..],
+            [
+                Augmentation::CountSuccesses { .. },
+                Augmentation::CountFailures { .. }
+            ]
+        ));
+
+        // Inline labels are shared with the native notation
+        assert!(matches!(
+            parse_dialect("1d20 + 5[STR] + 2[prof]", &Roll20),
+            Ok(Expression::Binop { .. })
+        ));
+
+        // The dialects do not accept each other's spellings
+        assert!(parse_dialect("d20 > 10", &Roll20).is_err());
+        assert!(parse("d20ro1").is_err());
+        assert_eq!(parse_dialect("2d6 + 1", &Native), parse("2d6 + 1"));
+    }
+
+    #[test]
+    fn test_roll20_rolls() {
+        let mut roller = StandardNaiveRoller::new_seeded(0);
+
+        for _ in 0..100 {
+            let roll = |roller: &mut StandardNaiveRoller, input| {
+                roller
+                    .roll_naive(parse_dialect(input, &Roll20).unwrap())
+                    .unwrap()
+            };
+
+            let value = roll(&mut roller, "10d6r<2");
+            assert!(value
+                .dice()
+                .filter(|d| !d.is_discarded())
+                .all(|d| d.value > 2));
+
+            let value = roll(&mut roller, "d6!!");
+            assert!(value.total().unwrap() >= 1 && value.total().unwrap() % 6 != 0);
+
+            let value = roll(&mut roller, "d20cs>19");
+            let critical = value.dice().any(|d| d.is_critical_success());
+            assert_eq!(critical, value.total().unwrap() >= 19);
+
+            let value = roll(&mut roller, "6d10>7f1");
+            assert!((-6..=6).contains(&value.total().unwrap()));
+        }
+
+        let mut roller = StandardNaiveRoller::new_seeded(0);
+        assert!(roller
+            .roll_naive(parse_dialect("d6r<6", &Roll20).unwrap())
+            .is_err());
+    }
+
+    #[test]
+    fn test_foundry() {
+        let augments = |input| match parse_dialect(input, &Foundry) {
+            Ok(Expression::Dice { augmentations, .. }) => augmentations.into_vec(),
+            other => panic!("{input} parsed as {other:?}"),
+        };
+
+        assert_eq!(
+            augments("d20r"),
+            [Augmentation::Reroll {
+                selector: Selector {
+                    relation: Ordering::Equal,
+                    inclusive: false,
+                    n: 1u32.into(),
+                },
+                once: true,
+            }]
+        );
+        assert!(matches!(
+            &augments("2d20khr1")[..],
+            [
+                Augmentation::Truncate { .. },
+                Augmentation::Reroll { once: true, .. }
+            ]
+        ));
+        assert!(matches!(
+            &augments("d6rr<3x>=5")[..],
+            [
+                Augmentation::Reroll { once: false, .. },
+                Augmentation::Explode { selector: Some(_) }
+            ]
+        ));
+        assert_eq!(
+            augments("d20min10max15"),
+            [
+                Augmentation::Minimum { n: 10u32.into() },
+                Augmentation::Maximum { n: 15u32.into() }
+            ]
+        );
+
+        assert!(parse_dialect("2d6[fire] + 1d4 [cold] + 3", &Foundry).is_ok());
+        assert!(parse_dialect("d20 > 10", &Foundry).is_err());
+        assert!(parse("d20min10").is_err());
+
+        let mut roller = StandardNaiveRoller::new_seeded(0);
+        for _ in 0..100 {
+            let value = roller
+                .roll_naive(parse_dialect("4d20min10max15", &Foundry).unwrap())
+                .unwrap();
+            assert!(value.dice().all(|d| (10..=15).contains(&d.value)));
+        }
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        let cases: [(&dyn Dialect, &[&str]); 2] = [
+            (
+                &Roll20,
+                &[
+                    "d20ro<2",
+                    "3d6!!>5 + d6r1",
+                    "d20cs>19cf<3 - 4d6k3",
+                    "8d10>7f1 + 2d6=6",
+                    "d6!",
+                ],
+            ),
+            (
+                &Foundry,
+                &["d20r", "d6rr<3x>=5", "2d20khr1 + d20min10max15", "4d6dl"],
+            ),
+        ];
+
+        for (dialect, inputs) in cases {
+            for input in inputs {
+                let expr = parse_dialect(input, dialect).unwrap();
+                let displayed = expr.display_in(dialect).to_string();
+                assert_eq!(parse_dialect(&displayed, dialect), Ok(expr), "{input}");
+            }
+        }
+
+        let expr = parse_dialect("d20ro<2 + d6r1", &Roll20).unwrap();
+        assert_eq!(expr.display_in(&Roll20).to_string(), "d20ro<2 + d6r1");
+        let expr = parse_dialect("d6rr<3 + d20r", &Foundry).unwrap();
+        assert_eq!(expr.display_in(&Foundry).to_string(), "d6rr<3 + d20r1");
+
+        // The native selectors are written as Roll20 points
+        let expr = parse("d20!>19").unwrap();
+        assert_eq!(expr.display_in(&Roll20).to_string(), "d20!>20");
+    }
+}
@@ -0,0 +1,276 @@
+//! Rolls that are made in secret and shown later, like the ones a GM
+//! whispers to themselves. Only a commitment of a hidden roll is shown
+//! at first. Revealing it shows the dice along with the salt, so anyone
+//! holding the commitment can check that the roll was not changed since.
+
+use std::{fmt, str::FromStr};
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::interpreter::{Hits, NaiveValue, TaggedDiceRoll};
+
+// Bumped whenever `encode` changes, so an old commitment fails to verify
+// instead of verifying against something else
+const ENCODING_VERSION: u8 = 1;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum CommitmentError {
+    #[error("A commitment is 64 hex digits, got {0}")]
+    Length(usize),
+    #[error("Not a hex digit in the commitment")]
+    Digit,
+}
+
+/// The SHA-256 of a hidden roll, shown as hex
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Commitment(pub [u8; 32]);
+
+impl fmt::Display for Commitment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|b| write!(f, "{b:02x}"))
+    }
+}
+
+impl FromStr for Commitment {
+    type Err = CommitmentError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != 64 {
+            return Err(CommitmentError::Length(s.len()));
+        }
+
+        let mut digest = [0; 32];
+        for (i, b) in digest.iter_mut().enumerate() {
+            *b = s
+                .get(2 * i..2 * i + 2)
+                .filter(|pair| pair.bytes().all(|c| c.is_ascii_hexdigit()))
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or(CommitmentError::Digit)?;
+        }
+
+        Ok(Self(digest))
+    }
+}
+
+/// A roll that is already made but not shown yet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HiddenRoll {
+    revealed: RevealedRoll,
+}
+
+impl HiddenRoll {
+    pub fn new(expression: impl Into<String>, value: NaiveValue) -> Self {
+        Self::new_salted(expression, value, rand::thread_rng().gen())
+    }
+
+    /// With a chosen salt, the salts must not be guessable for the
+    /// commitments to actually hide anything
+    pub fn new_salted(expression: impl Into<String>, value: NaiveValue, salt: [u8; 16]) -> Self {
+        Self {
+            revealed: RevealedRoll {
+                expression: expression.into(),
+                value,
+                salt,
+            },
+        }
+    }
+
+    pub fn expression(&self) -> &str {
+        &self.revealed.expression
+    }
+
+    pub fn commitment(&self) -> Commitment {
+        self.revealed.commitment()
+    }
+
+    /// Show the roll, it can be checked against the commitment
+    pub fn reveal(self) -> RevealedRoll {
+        self.revealed
+    }
+}
+
+/// Only the commitment, the dice and the total are not shown
+impl fmt::Display for HiddenRoll {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "`{}` rolled in secret: {}",
+            self.expression(),
+            self.commitment()
+        )
+    }
+}
+
+/// A hidden roll after it was revealed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RevealedRoll {
+    pub expression: String,
+    pub value: NaiveValue,
+    pub salt: [u8; 16],
+}
+
+impl RevealedRoll {
+    /// The hash of the salt, the expression and the value in an encoding
+    /// of their own, the serialized forms are free to change
+    pub fn commitment(&self) -> Commitment {
+        let mut encoded = vec![ENCODING_VERSION];
+        encoded.extend_from_slice(&self.salt);
+        // The length keeps the expression from bleeding into the value
+        encoded.extend_from_slice(&(self.expression.len() as u64).to_le_bytes());
+        encoded.extend_from_slice(self.expression.as_bytes());
+        encode(&self.value, &mut encoded);
+
+        Commitment(Sha256::digest(encoded).into())
+    }
+
+    /// Whether this is the roll the commitment was made for
+    pub fn verify(&self, commitment: &Commitment) -> bool {
+        self.commitment() == *commitment
+    }
+}
+
+// Every variant starts with a byte of its own, the lists with their length.
+// The nodes of the dice are left out, they are not a part of the result.
+fn encode(value: &NaiveValue, out: &mut Vec<u8>) {
+    let int = |out: &mut Vec<u8>, n: i64| out.extend_from_slice(&n.to_le_bytes());
+    let len = |out: &mut Vec<u8>, n: usize| out.extend_from_slice(&(n as u64).to_le_bytes());
+    let dice = |out: &mut Vec<u8>, dice: &[TaggedDiceRoll]| {
+        len(out, dice.len());
+        for d in dice {
+            int(out, d.value);
+            out.extend_from_slice(&d.tag.bits().to_le_bytes());
+        }
+    };
+
+    match value {
+        NaiveValue::Constant(c) => {
+            out.push(0);
+            int(out, *c);
+        }
+        NaiveValue::Dice(d) => {
+            out.push(1);
+            dice(out, d);
+        }
+        NaiveValue::Pool(d) => {
+            out.push(2);
+            dice(out, d);
+        }
+        NaiveValue::List(values) => {
+            out.push(3);
+            len(out, values.len());
+            values.iter().for_each(|value| encode(value, out));
+        }
+        NaiveValue::Summary(summary) => {
+            out.push(4);
+            int(out, summary.total);
+            len(out, summary.dice);
+            len(out, summary.discarded);
+            for bound in [summary.lowest, summary.highest] {
+                out.push(bound.is_some() as u8);
+                int(out, bound.unwrap_or_default());
+            }
+            let Hits {
+                successes,
+                failures,
+            } = summary.hits.unwrap_or_default();
+            out.push(summary.hits.is_some() as u8);
+            int(out, successes);
+            int(out, failures);
+        }
+        NaiveValue::Decimal {
+            exact,
+            rounding,
+            precision,
+        } => {
+            out.push(5);
+            int(out, *exact.numer());
+            int(out, *exact.denom());
+            out.push(*rounding as u8);
+            out.extend_from_slice(&precision.to_le_bytes());
+        }
+    }
+}
+
+impl fmt::Display for RevealedRoll {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.value.total() {
+            Ok(total) => write!(f, "`{}`: {total}", self.expression)?,
+            Err(err) => write!(f, "`{}`: {err}", self.expression)?,
+        }
+
+        let dice = self
+            .value
+            .dice()
+            .filter(|d| !d.is_discarded())
+            .map(|d| d.value.to_string())
+            .collect::<Vec<_>>();
+        if !dice.is_empty() {
+            write!(f, " [{}]", dice.join(", "))?;
+        }
+
+        write!(f, ", salt ")?;
+        self.salt.iter().try_for_each(|b| write!(f, "{b:02x}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        disclosure::{Commitment, CommitmentError, HiddenRoll},
+        interpreter::{NaiveValue, StandardNaiveRoller, TaggedDiceRoll},
+        parser::parse,
+    };
+
+    #[test]
+    fn test_hidden_rolls() {
+        let value = StandardNaiveRoller::new_seeded(3)
+            .roll_naive(parse("4d6kh3").unwrap())
+            .unwrap();
+        let total = value.total().unwrap();
+
+        let hidden = HiddenRoll::new("4d6kh3", value);
+        let commitment = hidden.commitment();
+        assert_eq!(
+            hidden.to_string(),
+            format!("`4d6kh3` rolled in secret: {commitment}")
+        );
+
+        let revealed = hidden.reveal();
+        assert!(revealed.verify(&commitment));
+        assert!(revealed.verify(&commitment.to_string().parse().unwrap()));
+        assert!(revealed
+            .to_string()
+            .starts_with(&format!("`4d6kh3`: {total} [")));
+
+        let mut tampered = revealed.clone();
+        tampered.value = NaiveValue::Constant(total + 1);
+        assert!(!tampered.verify(&commitment));
+
+        let mut tampered = revealed;
+        tampered.expression = "4d6kh2".into();
+        assert!(!tampered.verify(&commitment));
+
+        // Every roll is salted, the same result does not give it away
+        let a = HiddenRoll::new("1", NaiveValue::Constant(1));
+        let b = HiddenRoll::new("1", NaiveValue::Constant(1));
+        assert_ne!(a.commitment(), b.commitment());
+
+        // The encoding is pinned, the commitments made before an upgrade
+        // still verify
+        let dice = [3, 5].map(TaggedDiceRoll::from).into_iter().collect();
+        let pinned = HiddenRoll::new_salted("2d6", NaiveValue::Dice(dice), [7; 16]);
+        assert_eq!(
+            pinned.commitment().to_string(),
+            "ab7537d86c247eb71b935138565b425273651ba0026ae0a9393c8f20bdfb5a17"
+        );
+
+        assert_eq!("ab".parse::<Commitment>(), Err(CommitmentError::Length(2)));
+        assert_eq!(
+            "z".repeat(64).parse::<Commitment>(),
+            Err(CommitmentError::Digit)
+        );
+    }
+}
@@ -0,0 +1,1140 @@
+//! The exact chances of every total an expression can roll, worked out
+//! without rolling it.
+//!
+//! ```
+//! use dicemind::prelude::*;
+//!
+//! let stats = parse("4d6kh3").unwrap().distribution().unwrap();
+//! assert!((stats.probability(18) - 21. / 1296.).abs() < 1e-12);
+//! ```
+
+use std::collections::BTreeMap;
+
+use rand::Rng;
+use thiserror::Error;
+
+use crate::{
+    interpreter::{faces, RollerError, WeightedSampler},
+    macros::MacroError,
+    options::RollerOptions,
+    syntax::{Affix, Augmentation, BinaryOperator, Expression, SelectorOp},
+};
+
+/// The most totals a distribution can have, anything wider is refused
+pub const MAX_OUTCOMES: usize = 1 << 20;
+
+// The most steps the sums and the order statistics of a single term can
+// take, about a second of work
+const MAX_STEPS: u128 = 1 << 28;
+
+/// How far to follow the explosions that chain, every step is another
+/// dice exploding
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Precision {
+    /// The most steps to follow
+    pub max_depth: usize,
+    /// The largest chance of the totals that are left out
+    pub tolerance: f64,
+}
+
+impl Default for Precision {
+    fn default() -> Self {
+        Self {
+            max_depth: 128,
+            tolerance: 1e-12,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Error, PartialEq)]
+pub enum DistributionError {
+    #[error("The distribution of `{expression}` can't be worked out exactly")]
+    Unsupported { expression: Expression },
+    #[error("The distribution has too many totals to be worked out")]
+    TooLarge,
+    /// The explosions were followed as far as allowed, but the chance of
+    /// the totals left out is above the tolerance
+    #[error("The explosions still had a {missing} chance to go on after {depth} steps")]
+    Imprecise { depth: usize, missing: f64 },
+    /// A roll of the expression can fail, so the chances of its totals
+    /// don't add up. `(d4)d6dl2` fails whenever the d4 rolls a 1.
+    #[error(transparent)]
+    Fails(#[from] RollerError),
+    /// A macro refers to itself
+    #[error(transparent)]
+    Macro(MacroError),
+}
+
+pub type DistributionResult<T> = Result<T, DistributionError>;
+
+/// The chance of rolling every total between the lowest and the highest
+#[derive(Debug, Clone, PartialEq)]
+pub struct Distribution {
+    min: i64,
+    probabilities: Vec<f64>,
+}
+
+impl Distribution {
+    /// Always the same total
+    pub fn constant(total: i64) -> Self {
+        Self {
+            min: total,
+            probabilities: vec![1.],
+        }
+    }
+
+    /// Every total equally likely, like the faces of a dice
+    pub fn uniform(totals: impl IntoIterator<Item = i64>) -> DistributionResult<Self> {
+        let totals: Vec<_> = totals.into_iter().collect();
+        let chance = 1. / totals.len() as f64;
+        Self::from_weights(totals.into_iter().map(|total| (total, chance)))
+    }
+
+    // The chances of the same total are added up
+    fn from_weights(weights: impl IntoIterator<Item = (i64, f64)>) -> DistributionResult<Self> {
+        let weights: BTreeMap<i64, f64> =
+            weights
+                .into_iter()
+                .fold(BTreeMap::new(), |mut acc, (total, p)| {
+                    *acc.entry(total).or_default() += p;
+                    acc
+                });
+
+        let (Some((&min, _)), Some((&max, _))) =
+            (weights.first_key_value(), weights.last_key_value())
+        else {
+            return Ok(Self::constant(0));
+        };
+        if max.abs_diff(min) >= MAX_OUTCOMES as u64 {
+            return Err(DistributionError::TooLarge);
+        }
+
+        let mut probabilities = vec![0.; (max - min) as usize + 1];
+        for (total, p) in weights {
+            probabilities[(total - min) as usize] += p;
+        }
+
+        Ok(Self { min, probabilities })
+    }
+
+    pub fn min(&self) -> i64 {
+        self.min
+    }
+
+    pub fn max(&self) -> i64 {
+        self.min + self.probabilities.len() as i64 - 1
+    }
+
+    /// The chance of rolling exactly the total
+    pub fn probability(&self, total: i64) -> f64 {
+        total
+            .checked_sub(self.min)
+            .and_then(|i| usize::try_from(i).ok())
+            .and_then(|i| self.probabilities.get(i))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// The chance of rolling the total or less
+    pub fn cdf(&self, total: i64) -> f64 {
+        if total < self.min {
+            0.
+        } else if total >= self.max() {
+            self.probabilities.iter().sum()
+        } else {
+            let end = (total - self.min) as usize + 1;
+            self.probabilities[..end].iter().sum()
+        }
+    }
+
+    /// The chance of rolling more than the total
+    pub fn sf(&self, total: i64) -> f64 {
+        if total < self.min {
+            self.probabilities.iter().sum()
+        } else if total >= self.max() {
+            0.
+        } else {
+            let start = (total - self.min) as usize + 1;
+            self.probabilities[start..].iter().sum()
+        }
+    }
+
+    /// The chance of rolling the target or more, beating DC 15 with
+    /// `d20 + 7` is `prob_at_least(15)`
+    pub fn prob_at_least(&self, target: i64) -> f64 {
+        match target.checked_sub(1) {
+            Some(total) => self.sf(total),
+            None => self.probabilities.iter().sum(),
+        }
+    }
+
+    /// Every total that can be rolled with its chance, from the lowest
+    pub fn iter(&self) -> impl Iterator<Item = (i64, f64)> + '_ {
+        self.probabilities
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (self.min + i as i64, *p))
+            .filter(|(_, p)| *p > 0.)
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.iter().map(|(total, p)| total as f64 * p).sum()
+    }
+
+    pub fn variance(&self) -> f64 {
+        let mean = self.mean();
+        self.iter()
+            .map(|(total, p)| (total as f64 - mean).powi(2) * p)
+            .sum()
+    }
+
+    pub fn stddev(&self) -> f64 {
+        self.variance().sqrt()
+    }
+
+    /// The lowest total rolled at least `p` of the time or less, the
+    /// median is `quantile(0.5)`. Anything below 0 is the lowest total,
+    /// anything above 1 is the highest.
+    pub fn quantile(&self, p: f64) -> i64 {
+        // The sums of the chances are a little off
+        let p = p - 1e-12;
+
+        let mut below = 0.;
+        for (i, chance) in self.probabilities.iter().enumerate() {
+            below += chance;
+            if below >= p {
+                return self.min + i as i64;
+            }
+        }
+
+        self.max()
+    }
+
+    pub fn median(&self) -> i64 {
+        self.quantile(0.5)
+    }
+
+    /// The distance between the lower and the upper quartile, the totals
+    /// rolled half of the time
+    pub fn iqr(&self) -> i64 {
+        self.quantile(0.75) - self.quantile(0.25)
+    }
+
+    /// The chances of rolling more, the same and less than the other,
+    /// with both rolled independently
+    pub fn compare(&self, other: &Distribution) -> Comparison {
+        // The chance of the other rolling below every total of its own
+        let mut below = Vec::with_capacity(other.probabilities.len());
+        let mut sum = 0.;
+        for p in &other.probabilities {
+            below.push(sum);
+            sum += p;
+        }
+
+        let mut comparison = Comparison::default();
+        for (total, p) in self.iter() {
+            let (less, equal) = match total.checked_sub(other.min) {
+                Some(i) if i < 0 => (0., 0.),
+                Some(i) if (i as usize) < below.len() => {
+                    (below[i as usize], other.probabilities[i as usize])
+                }
+                _ => (sum, 0.),
+            };
+
+            comparison.greater += p * less;
+            comparison.equal += p * equal;
+            comparison.less += p * (sum - less - equal);
+        }
+        comparison
+    }
+
+    /// Everything to label a chart of the distribution with
+    pub fn summary(&self) -> Summary {
+        Summary {
+            min: self.min(),
+            max: self.max(),
+            mean: self.mean(),
+            stddev: self.stddev(),
+            lower_quartile: self.quantile(0.25),
+            median: self.median(),
+            upper_quartile: self.quantile(0.75),
+        }
+    }
+
+    /// A table rolling the totals with these chances in constant time, no
+    /// matter how many dice it took to work them out. The chances left out
+    /// by the explosions are spread over the rest.
+    pub fn alias_table(&self) -> AliasTable {
+        // Walker's method as Vose lays it out, every slot holds its own
+        // total and the chance to roll another one instead
+        let len = self.probabilities.len();
+        let sum: f64 = self.probabilities.iter().sum();
+        let mut chances: Vec<f64> = self
+            .probabilities
+            .iter()
+            .map(|p| p * len as f64 / sum)
+            .collect();
+        let mut aliases: Vec<usize> = (0..len).collect();
+
+        let (mut small, mut large): (Vec<_>, Vec<_>) = (0..len).partition(|&i| chances[i] < 1.);
+        while let (Some(&s), Some(&l)) = (small.last(), large.last()) {
+            small.pop();
+            aliases[s] = l;
+            chances[l] -= 1. - chances[s];
+            if chances[l] < 1. {
+                large.pop();
+                small.push(l);
+            }
+        }
+        // The slots left over are full, up to the rounding
+        for i in small.into_iter().chain(large) {
+            chances[i] = 1.;
+        }
+
+        AliasTable {
+            min: self.min,
+            chances,
+            aliases,
+        }
+    }
+
+    /// The distribution of the totals of both rolled together
+    pub fn combine(
+        &self,
+        other: &Distribution,
+        f: impl Fn(i64, i64) -> Option<i64>,
+    ) -> DistributionResult<Distribution> {
+        let mut weights = vec![];
+        for (a, p) in self.iter() {
+            for (b, q) in other.iter() {
+                weights.push((f(a, b).ok_or(RollerError::Overflow)?, p * q));
+            }
+        }
+
+        Self::from_weights(weights)
+    }
+
+    pub fn map(&self, f: impl Fn(i64) -> Option<i64>) -> DistributionResult<Distribution> {
+        let weights = self
+            .iter()
+            .map(|(total, p)| Ok((f(total).ok_or(RollerError::Overflow)?, p)))
+            .collect::<DistributionResult<Vec<_>>>()?;
+
+        Self::from_weights(weights)
+    }
+
+    /// The sum of both, faster than `combine` for the wide ones
+    pub fn add(&self, other: &Distribution) -> DistributionResult<Distribution> {
+        let min = self
+            .min
+            .checked_add(other.min)
+            .ok_or(RollerError::Overflow)?;
+        self.max()
+            .checked_add(other.max())
+            .ok_or(RollerError::Overflow)?;
+
+        let len = self.probabilities.len() + other.probabilities.len() - 1;
+        if len > MAX_OUTCOMES {
+            return Err(DistributionError::TooLarge);
+        }
+
+        let mut probabilities = vec![0.; len];
+        for (i, p) in self.probabilities.iter().enumerate() {
+            for (j, q) in other.probabilities.iter().enumerate() {
+                probabilities[i + j] += p * q;
+            }
+        }
+
+        Ok(Self { min, probabilities })
+    }
+
+    /// The sum of `n` of these rolled independently
+    pub fn repeat(&self, n: u64) -> DistributionResult<Distribution> {
+        let len = self.probabilities.len() as u128;
+        if (n as u128).pow(2) * len.pow(2) / 2 > MAX_STEPS {
+            return Err(DistributionError::TooLarge);
+        }
+
+        (0..n).try_fold(Self::constant(0), |acc, _| acc.add(self))
+    }
+
+    // Each of the distributions with its chance
+    fn mixture(
+        parts: impl IntoIterator<Item = (f64, DistributionResult<Distribution>)>,
+    ) -> DistributionResult<Distribution> {
+        let mut weights = vec![];
+        for (chance, part) in parts {
+            weights.extend(part?.iter().map(|(total, p)| (total, chance * p)));
+        }
+
+        Self::from_weights(weights)
+    }
+}
+
+/// The numbers describing a distribution at a glance
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Summary {
+    pub min: i64,
+    pub max: i64,
+    pub mean: f64,
+    pub stddev: f64,
+    pub lower_quartile: i64,
+    pub median: i64,
+    pub upper_quartile: i64,
+}
+
+/// Rolls the totals of a distribution, see `Distribution::alias_table`.
+/// Every roll draws a slot and a chance, so `100d6` rolls as quickly as
+/// `d6` and the lopsided distributions as quickly as the even ones.
+///
+/// ```
+/// use dicemind::prelude::*;
+///
+/// let table = parse("100d6").unwrap().distribution().unwrap().alias_table();
+/// let total = table.sample(&mut rand::thread_rng());
+/// assert!((100..=600).contains(&total));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct AliasTable {
+    min: i64,
+    // The chance of every slot to roll its own total
+    chances: Vec<f64>,
+    // The total rolled otherwise, counted from the lowest
+    aliases: Vec<usize>,
+}
+
+impl AliasTable {
+    pub fn sample(&self, rng: &mut (impl Rng + ?Sized)) -> i64 {
+        let slot = rng.gen_range(0..self.chances.len());
+        let offset = if rng.gen::<f64>() < self.chances[slot] {
+            slot
+        } else {
+            self.aliases[slot]
+        };
+
+        self.min + offset as i64
+    }
+}
+
+/// How two rolls compare, see `compare`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Comparison {
+    /// The chance of the first one rolling more
+    pub greater: f64,
+    pub equal: f64,
+    pub less: f64,
+}
+
+impl Expression {
+    /// The exact chances of every total, with the default options
+    pub fn distribution(&self) -> DistributionResult<Distribution> {
+        distribution(self, &RollerOptions::default())
+    }
+}
+
+/// The exact chances of every total of the expression. The dice are
+/// rolled independently, so the two sides of `d20 - d20` don't cancel
+/// out. The truncations are worked out from the order statistics of the
+/// dice. The explosions that chain can go on forever, they are followed
+/// until the chance of going on is below the tolerance of the `Precision`
+/// and the totals past that are left out. The rest of the augments and
+/// the function calls aren't supported.
+pub fn distribution(
+    expr: &Expression,
+    options: &RollerOptions,
+) -> DistributionResult<Distribution> {
+    distribution_with(expr, options, &Precision::default())
+}
+
+/// The exact chances of the first expression rolling more, the same or
+/// less than the second, `2d6 + 3` beats `d12 + 4` five times out of twelve
+pub fn compare(
+    a: &Expression,
+    b: &Expression,
+    options: &RollerOptions,
+) -> DistributionResult<Comparison> {
+    Ok(distribution(a, options)?.compare(&distribution(b, options)?))
+}
+
+/// `distribution` with the explosions followed as far as the precision
+/// asks for
+pub fn distribution_with(
+    expr: &Expression,
+    options: &RollerOptions,
+    precision: &Precision,
+) -> DistributionResult<Distribution> {
+    use BinaryOperator::*;
+
+    let unsupported = || DistributionError::Unsupported {
+        expression: expr.clone(),
+    };
+
+    match expr {
+        Expression::Constant(c) => {
+            Ok(Distribution::constant(i64::try_from(c).map_err(|_| {
+                RollerError::ValueTooLarge { value: c.clone() }
+            })?))
+        }
+        Expression::Dice {
+            quantity,
+            power,
+            augmentations,
+        } => {
+            let assumed = |n| Distribution::constant(i64::try_from(n).unwrap_or(i64::MAX));
+            let quantity = match quantity {
+                Some(quantity) => distribution_with(quantity, options, precision)?,
+                None => assumed(options.quantity()),
+            };
+            let power = match power {
+                Some(power) => distribution_with(power, options, precision)?,
+                None => assumed(options.power()),
+            };
+
+            let mut parts = vec![];
+            for (q, p) in quantity.iter() {
+                for (power, r) in power.iter() {
+                    parts.push((
+                        p * r,
+                        plain_dice(expr, q, power, augmentations, options, precision),
+                    ));
+                }
+            }
+            Distribution::mixture(parts)
+        }
+        Expression::Weighted {
+            quantity,
+            faces,
+            augmentations,
+        } => {
+            let quantity = match quantity {
+                Some(quantity) => distribution_with(quantity, options, precision)?,
+                None => {
+                    Distribution::constant(i64::try_from(options.quantity()).unwrap_or(i64::MAX))
+                }
+            };
+
+            let sampler = WeightedSampler::new(faces)?;
+            let mut chances = BTreeMap::<i64, f64>::new();
+            for (face, chance) in sampler.chances() {
+                *chances.entry(face).or_default() += chance;
+            }
+            let chances: Vec<(i64, f64)> = chances.into_iter().collect();
+
+            Distribution::mixture(quantity.iter().map(|(q, p)| {
+                let rolled = dice(
+                    expr,
+                    q,
+                    &chances,
+                    sampler.highest(),
+                    augmentations,
+                    options,
+                    precision,
+                );
+                (p, rolled)
+            }))
+        }
+        Expression::Binop { operator, lhs, rhs } => {
+            let lhs = distribution_with(lhs, options, precision)?;
+            let rhs = distribution_with(rhs, options, precision)?;
+
+            match operator {
+                Add => lhs.add(&rhs),
+                Subtract => lhs.add(&rhs.map(i64::checked_neg)?),
+                Multiply => lhs.combine(&rhs, i64::checked_mul),
+                Equals => lhs.combine(&rhs, |a, b| Some((a == b) as i64)),
+                LessThan => lhs.combine(&rhs, |a, b| Some((a < b) as i64)),
+                GreaterThan => lhs.combine(&rhs, |a, b| Some((a > b) as i64)),
+                Chain => Ok(rhs),
+            }
+        }
+        Expression::UnaryNegation(expr) => {
+            distribution_with(expr, options, precision)?.map(i64::checked_neg)
+        }
+        Expression::Subexpression(expr)
+        | Expression::Annotated {
+            expression: expr, ..
+        }
+        | Expression::Tagged {
+            expression: expr, ..
+        } => distribution_with(expr, options, precision),
+        Expression::Repeat { count, expression } => {
+            let count = distribution_with(count, options, precision)?;
+            let once = distribution_with(expression, options, precision)?;
+
+            Distribution::mixture(count.iter().map(|(count, p)| {
+                let repeated = match u64::try_from(count) {
+                    Ok(count) => once.repeat(count),
+                    Err(_) => Err(RollerError::InvalidRepetition { count }.into()),
+                };
+                (p, repeated)
+            }))
+        }
+        Expression::Conditional {
+            condition,
+            then,
+            otherwise,
+        } => {
+            let condition = distribution_with(condition, options, precision)?;
+            let yes = 1. - condition.probability(0);
+
+            Distribution::mixture([
+                (yes, distribution_with(then, options, precision)),
+                (1. - yes, distribution_with(otherwise, options, precision)),
+            ])
+        }
+        Expression::Group {
+            members,
+            augmentations,
+        } if augmentations.is_empty() => members
+            .iter()
+            .try_fold(Distribution::constant(0), |acc, member| {
+                acc.add(&distribution_with(member, options, precision)?)
+            }),
+        // Expanded up front, so that a cycle is an error and not a stack
+        // overflow
+        Expression::Reference(_) => {
+            let body = options
+                .macros()
+                .expand(expr.clone())
+                .map_err(|err| match err {
+                    MacroError::UnknownMacro { name } => RollerError::UnknownMacro { name }.into(),
+                    err => DistributionError::Macro(err),
+                })?;
+            distribution_with(&body, options, precision)
+        }
+        Expression::Group { .. } | Expression::Call { .. } | Expression::Decimal(_) => {
+            Err(unsupported())
+        }
+    }
+}
+
+// The totals of `quantity` dice with the power, the way the naive roller
+// rolls them
+fn plain_dice(
+    expr: &Expression,
+    quantity: i64,
+    power: i64,
+    augments: &[Augmentation],
+    options: &RollerOptions,
+    precision: &Precision,
+) -> DistributionResult<Distribution> {
+    if power == 0 {
+        return Ok(Distribution::constant(0));
+    }
+
+    if power.unsigned_abs() > MAX_OUTCOMES as u64 {
+        return Err(DistributionError::TooLarge);
+    }
+
+    let chance = 1. / power.unsigned_abs() as f64;
+    let fresh: Vec<(i64, f64)> = faces(power).map(|value| (value, chance)).collect();
+    dice(expr, quantity, &fresh, power, augments, options, precision)
+}
+
+// The totals of `quantity` dice with the chances of a single one, `highest`
+// is the face that explodes unless the augments say otherwise
+fn dice(
+    expr: &Expression,
+    quantity: i64,
+    fresh: &[(i64, f64)],
+    highest: i64,
+    augments: &[Augmentation],
+    options: &RollerOptions,
+    precision: &Precision,
+) -> DistributionResult<Distribution> {
+    if quantity == 0 {
+        return Ok(Distribution::constant(0));
+    }
+
+    let faces: Vec<(i64, f64)> = fresh
+        .iter()
+        .map(|&(value, chance)| (value * quantity.signum(), chance))
+        .collect();
+    let count = quantity.unsigned_abs();
+
+    // Only the dice that end up as they were rolled can be truncated
+    let (augments, truncation) = match augments {
+        [rest @ .., Augmentation::Truncate { op, affix, n }]
+            if !rest
+                .iter()
+                .any(|augment| matches!(augment, Augmentation::Explode { .. })) =>
+        {
+            (rest, Some((op, affix, n)))
+        }
+        augments => (augments, None),
+    };
+
+    let die = match augments {
+        [] => faces,
+        [Augmentation::Explode { selector }] => {
+            let explodes = |value: i64| match selector {
+                Some(selector) => selector.matches(value),
+                None => value == highest,
+            };
+
+            explode(
+                &faces,
+                fresh,
+                &explodes,
+                options.chain_explosions(),
+                precision,
+            )?
+        }
+        [Augmentation::Compound { selector }] => {
+            let explodes = |value: i64| match selector {
+                Some(selector) => selector.matches(value),
+                None => value == highest,
+            };
+
+            explode(&faces, fresh, &explodes, true, precision)?
+        }
+        _ => {
+            return Err(DistributionError::Unsupported {
+                expression: expr.clone(),
+            })
+        }
+    };
+
+    match truncation {
+        None => Distribution::from_weights(die)?.repeat(count),
+        Some((op, affix, n)) => {
+            // Same as the roller, anything too large to count is a single dice
+            let n = n.as_ref().and_then(|n| u64::try_from(n).ok()).unwrap_or(1);
+
+            // Dropping the n high dice is the same as keeping the rest low
+            let kept = match op {
+                SelectorOp::Keep => n.min(count),
+                SelectorOp::Drop => count.checked_sub(n).ok_or(RollerError::TruncationFailure {
+                    rolled: count as u32,
+                    removed: n as u32,
+                })?,
+            };
+            let highest = matches!(
+                (op, affix),
+                (SelectorOp::Keep, Affix::High) | (SelectorOp::Drop, Affix::Low)
+            );
+
+            keep(die, count, kept, highest)
+        }
+    }
+}
+
+// A single dice that explodes, `first` are the chances of the dice as it
+// was first rolled and `fresh` of every dice from an explosion, without the
+// sign of the quantity. They explode again if they chain. The explosions
+// that could never stop fail like they do in the roller.
+fn explode(
+    first: &[(i64, f64)],
+    fresh: &[(i64, f64)],
+    explodes: &dyn Fn(i64) -> bool,
+    chain: bool,
+    precision: &Precision,
+) -> DistributionResult<Vec<(i64, f64)>> {
+    let going_on: f64 = fresh
+        .iter()
+        .filter(|(v, _)| explodes(*v))
+        .map(|(_, p)| p)
+        .sum();
+
+    // What the explosions add to a dice that exploded, one step deeper
+    // every time around
+    let tail: BTreeMap<i64, f64> = if chain {
+        if going_on >= 1. {
+            return Err(RollerError::InfiniteExplosion.into());
+        }
+
+        let mut tail = BTreeMap::new();
+        let mut missing = 1.;
+        let mut depth = 0;
+        while missing > precision.tolerance {
+            if depth >= precision.max_depth {
+                return Err(DistributionError::Imprecise { depth, missing });
+            }
+
+            let mut deeper = BTreeMap::new();
+            for &(value, p) in fresh {
+                if explodes(value) {
+                    for (&rest, &q) in &tail {
+                        let total = value.checked_add(rest).ok_or(RollerError::Overflow)?;
+                        *deeper.entry(total).or_default() += p * q;
+                    }
+                } else {
+                    *deeper.entry(value).or_default() += p;
+                }
+            }
+
+            tail = deeper;
+            missing *= going_on;
+            depth += 1;
+        }
+        tail
+    } else {
+        fresh.iter().copied().collect()
+    };
+
+    if tail.len() > MAX_OUTCOMES {
+        return Err(DistributionError::TooLarge);
+    }
+
+    let mut die = vec![];
+    for &(value, p) in first {
+        if explodes(value) {
+            for (&rest, &q) in &tail {
+                die.push((value.checked_add(rest).ok_or(RollerError::Overflow)?, p * q));
+            }
+        } else {
+            die.push((value, p));
+        }
+    }
+    Ok(die)
+}
+
+// The sum of the `kept` highest or lowest of `count` dice. The faces are
+// gone through from the one kept first, every step deciding how many of
+// the dice show the face. The dice showing it are kept while there is
+// room left, so only how many were placed and the sum kept are tracked.
+fn keep(
+    mut faces: Vec<(i64, f64)>,
+    count: u64,
+    kept: u64,
+    highest: bool,
+) -> DistributionResult<Distribution> {
+    faces.sort_unstable_by_key(|(face, _)| *face);
+    if highest {
+        faces.reverse();
+    }
+
+    let widest = faces
+        .iter()
+        .map(|(face, _)| face.unsigned_abs())
+        .max()
+        .unwrap_or_default();
+    let steps =
+        faces.len() as u128 * (count as u128 + 1).pow(2) * (kept as u128 * widest as u128 + 1);
+    if steps > MAX_STEPS {
+        return Err(DistributionError::TooLarge);
+    }
+
+    let count = count as usize;
+    let kept = kept as usize;
+    // The ways to pick which of the dice show it, `binomial[n][c]`
+    let mut binomial: Vec<Vec<f64>> = vec![vec![1.]];
+    for n in 1..=count {
+        let previous = binomial[n - 1].windows(2).map(|pair| pair[0] + pair[1]);
+        let row = std::iter::once(1.).chain(previous).chain([1.]).collect();
+        binomial.push(row);
+    }
+
+    // `placed[a]` are the chances of the kept sums with `a` dice placed
+    let mut placed: Vec<BTreeMap<i64, f64>> = vec![BTreeMap::new(); count + 1];
+    placed[0].insert(0, 1.);
+
+    for &(face, chance) in &faces {
+        // The chance of every amount of dice showing the face
+        let powers: Vec<f64> = (0..=count).map(|c| chance.powi(c as i32)).collect();
+        let mut next: Vec<BTreeMap<i64, f64>> = vec![BTreeMap::new(); count + 1];
+
+        for (a, sums) in placed.iter().enumerate() {
+            for (&sum, &p) in sums {
+                for c in 0..=count - a {
+                    let taken = c.min(kept.saturating_sub(a)) as i64;
+                    let sum = sum + taken * face;
+                    *next[a + c].entry(sum).or_default() += p * binomial[count - a][c] * powers[c];
+                }
+            }
+        }
+
+        placed = next;
+    }
+
+    Distribution::from_weights(placed.pop().unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::{
+        dialect::Roll20,
+        distribution::{
+            compare, distribution as dist, distribution_with, Distribution, DistributionError,
+            Precision,
+        },
+        histogram::Histogram,
+        interpreter::{RollerError, StandardNaiveRoller},
+        options::RollerOptions,
+        parser::{parse, parse_dialect},
+    };
+
+    fn distribution(input: &str) -> Distribution {
+        parse(input).unwrap().distribution().unwrap()
+    }
+
+    fn same(a: &Distribution, b: &Distribution) -> bool {
+        (a.min(), a.max()) == (b.min(), b.max())
+            && (a.min()..=a.max()).all(|total| close(a.probability(total), b.probability(total)))
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn test_distribution() {
+        let d20 = distribution("d20 + 5");
+        assert_eq!((d20.min(), d20.max()), (6, 25));
+        assert!(close(d20.probability(10), 0.05));
+        assert!(close(d20.mean(), 15.5));
+
+        let three = distribution("3d6");
+        assert!(close(three.probability(10), 27. / 216.));
+        assert!(close(three.iter().map(|(_, p)| p).sum(), 1.));
+
+        assert!(close(distribution("d20 + 5 > 15").probability(1), 0.5));
+        assert!(close(distribution("2x(d4)").mean(), 5.));
+        assert!(close(distribution("(d4)d6").mean(), 2.5 * 3.5));
+        assert!(close(distribution("-d6").probability(-6), 1. / 6.));
+
+        let attack = distribution("d20 + 7");
+        assert!(close(attack.prob_at_least(15), 0.65));
+        assert!(close(attack.cdf(14), 0.35));
+        assert!(close(attack.sf(14), 0.65));
+        assert!(close(attack.cdf(27) + attack.sf(27), 1.));
+        for (below, above) in [(i64::MIN, 8), (27, i64::MAX)] {
+            assert!(close(attack.cdf(below) + attack.sf(below), 1.));
+            assert!(close(attack.cdf(above) + attack.sf(above), 1.));
+        }
+        assert!(close(attack.prob_at_least(i64::MIN), 1.));
+        assert_eq!(attack.prob_at_least(28), 0.);
+        assert_eq!(attack.cdf(7), 0.);
+        // The totals far away from the negative ones don't overflow
+        let penalty = distribution("d20 - 30");
+        assert!(close(penalty.cdf(i64::MAX), 1.));
+        assert_eq!(penalty.sf(i64::MAX), 0.);
+        assert!(close(penalty.sf(i64::MIN), 1.));
+        assert!(close(penalty.cdf(-20), 0.5));
+
+        assert_eq!(attack.median(), 17);
+        assert_eq!(attack.iqr(), 10);
+        assert_eq!((attack.quantile(-1.), attack.quantile(0.)), (8, 8));
+        assert_eq!((attack.quantile(1.), attack.quantile(2.)), (27, 27));
+        assert_eq!(distribution("3d6").median(), 10);
+        assert!(close(distribution("2d6").variance(), 35. / 6.));
+        let summary = distribution("4d6kh3").summary();
+        assert_eq!((summary.min, summary.max), (3, 18));
+        assert!(summary.lower_quartile < summary.median && summary.median < summary.upper_quartile);
+        assert!(close(
+            summary.stddev.powi(2),
+            distribution("4d6kh3").variance()
+        ));
+
+        assert!(matches!(
+            parse_dialect("d6r1", &Roll20).unwrap().distribution(),
+            Err(DistributionError::Unsupported { .. })
+        ));
+        assert_eq!(
+            parse("1d6dl2").unwrap().distribution(),
+            Err(DistributionError::Fails(RollerError::TruncationFailure {
+                rolled: 1,
+                removed: 2
+            }))
+        );
+        // The d4 only rolls a 1 once in four, the distribution is still refused
+        assert!(matches!(
+            parse("(d4)d6dl2").unwrap().distribution(),
+            Err(DistributionError::Fails(
+                RollerError::TruncationFailure { .. }
+            ))
+        ));
+        assert!(matches!(
+            parse("@missing").unwrap().distribution(),
+            Err(DistributionError::Fails(RollerError::UnknownMacro { .. }))
+        ));
+        for huge in ["1000000d1000000", "100000d2", "50d1000kh10"] {
+            assert_eq!(
+                parse(huge).unwrap().distribution(),
+                Err(DistributionError::TooLarge)
+            );
+        }
+    }
+
+    #[test]
+    fn test_weighted_distribution() {
+        let loaded = distribution("d{1:1, 6:3}");
+        assert!(close(loaded.probability(6), 0.75));
+        assert!(close(loaded.probability(3), 0.));
+        assert!(same(&distribution("d{1:1, 2:1, 3:1}"), &distribution("d3")));
+        assert!(same(
+            &distribution("2d{0:1, 1:1}kh1"),
+            &distribution("d{0:1, 1:3}")
+        ));
+        assert!(same(&distribution("d{2:1, 2:1, 5:0}"), &distribution("2")));
+        // The highest face explodes like the power of the plain dice
+        assert!(same(&distribution("d{1:1, 2:1}!"), &distribution("d2!")));
+        assert!(close(distribution("-3d{-1:1, 1:1}").mean(), 0.));
+
+        assert_eq!(
+            parse("d{1:0}").unwrap().distribution(),
+            Err(DistributionError::Fails(RollerError::NoFaces))
+        );
+    }
+
+    #[test]
+    fn test_explosion_distribution() {
+        let once = distribution("d6!");
+        assert_eq!((once.min(), once.max()), (1, 12));
+        assert!(close(once.probability(5), 1. / 6.));
+        assert!(close(once.probability(6), 0.));
+        assert!(close(once.probability(9), 1. / 36.));
+        assert!(close(once.mean(), 3.5 + 3.5 / 6.));
+
+        let chained = RollerOptions::default().with_chain_explosions(true);
+        let expr = parse("3d6! + 2").unwrap();
+        let exploding = dist(&expr, &chained).unwrap();
+        assert!(close(exploding.iter().map(|(_, p)| p).sum(), 1.));
+        assert!(close(exploding.mean(), 3. * 4.2 + 2.));
+
+        let roll20 = |input: &str| parse_dialect(input, &Roll20).unwrap();
+        let compound =
+            distribution_with(&roll20("d6!!"), &Default::default(), &Default::default()).unwrap();
+        assert!(close(compound.mean(), 4.2));
+        let high = roll20("d6!>5").distribution().unwrap();
+        assert!(close(high.probability(7), 2. / 36.));
+
+        // The compounded dice are still single dice to truncate
+        let expr = roll20("3d6!!kh1");
+        let mut roller = StandardNaiveRoller::new_seeded(0);
+        let rolled = (0..20000)
+            .map(|_| roller.roll_naive(&expr).unwrap().total().unwrap() as f64)
+            .sum::<f64>()
+            / 20000.;
+        assert!((rolled - expr.distribution().unwrap().mean()).abs() < 0.1);
+
+        let coarse = Precision {
+            max_depth: 4,
+            tolerance: 1e-12,
+        };
+        assert!(matches!(
+            distribution_with(&parse("d6!").unwrap(), &chained, &coarse),
+            Err(DistributionError::Imprecise { depth: 4, .. })
+        ));
+        assert_eq!(
+            dist(&parse("d1!").unwrap(), &chained),
+            Err(DistributionError::Fails(RollerError::InfiniteExplosion))
+        );
+        assert!(matches!(
+            parse("4d6!kh3").unwrap().distribution(),
+            Err(DistributionError::Unsupported { .. })
+        ));
+    }
+
+    #[test]
+    fn test_truncation_distribution() {
+        let stats = distribution("4d6kh3");
+        assert_eq!((stats.min(), stats.max()), (3, 18));
+        assert!(close(stats.probability(18), 21. / 1296.));
+        assert!(close(stats.probability(3), 1. / 1296.));
+        assert!(close(stats.mean(), 15869. / 1296.));
+        assert!(same(&stats, &distribution("4d6dl1")));
+
+        let advantage = distribution("2d20kh1");
+        assert!(close(advantage.probability(20), 39. / 400.));
+        assert!(close(advantage.probability(1), 1. / 400.));
+        let disadvantage = distribution("2d20kl1");
+        assert!(close(disadvantage.probability(1), 39. / 400.));
+        assert!(same(&disadvantage, &distribution("2d20dh1")));
+
+        // Keeping everything or more is keeping them all
+        assert!(same(&distribution("3d6kh5"), &distribution("3d6")));
+
+        // The same as rolling it, on average
+        let mut roller = StandardNaiveRoller::new_seeded(0);
+        let expr = parse("5d8kl2 + 3").unwrap();
+        let rolled = (0..20000)
+            .map(|_| roller.roll_naive(&expr).unwrap().total().unwrap() as f64)
+            .sum::<f64>()
+            / 20000.;
+        assert!((rolled - distribution("5d8kl2 + 3").mean()).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_compare() {
+        let options = RollerOptions::default();
+        let comparison = compare(
+            &parse("2d6 + 3").unwrap(),
+            &parse("d12 + 4").unwrap(),
+            &options,
+        )
+        .unwrap();
+        assert!(close(
+            comparison.greater + comparison.equal + comparison.less,
+            1.
+        ));
+        assert!(close(comparison.greater, 5. / 12.));
+        assert!(close(comparison.equal, 1. / 12.));
+        let reversed = compare(
+            &parse("d12 + 4").unwrap(),
+            &parse("2d6 + 3").unwrap(),
+            &options,
+        )
+        .unwrap();
+        assert!(close(reversed.less, comparison.greater));
+
+        let d6 = distribution("d6");
+        let same = d6.compare(&d6);
+        assert!(close(same.equal, 1. / 6.));
+        assert!(close(same.greater, same.less));
+        // Out of reach on either side
+        for versus in [
+            d6.compare(&distribution("d4 + 6")),
+            distribution("d4 - 4").compare(&d6),
+        ] {
+            assert_eq!((versus.greater, versus.equal), (0., 0.));
+            assert!(close(versus.less, 1.));
+        }
+        let versus = d6.compare(&distribution("d4 - 4"));
+        assert_eq!((versus.equal, versus.less), (0., 0.));
+        assert!(close(versus.greater, 1.));
+        assert!(close(
+            distribution("d20").compare(&distribution("10")).greater,
+            0.5
+        ));
+    }
+
+    #[test]
+    fn test_alias_table() {
+        // The counts of the table and of the roller are both off by six
+        // deviations about once in a hundred million runs
+        for input in ["3d6", "10d6kh1", "d4 * 10 - 2", "d20 > 19", "7"] {
+            let expr = parse(input).unwrap();
+            let distribution = expr.distribution().unwrap();
+            let table = distribution.alias_table();
+
+            let trials = 60_000;
+            let mut rng = StdRng::seed_from_u64(0);
+            let sampled: Histogram = (0..trials).map(|_| table.sample(&mut rng)).collect();
+            let mut roller = StandardNaiveRoller::new_seeded(0);
+            let rolled: Histogram = (0..trials)
+                .map(|_| roller.roll_naive(&expr).unwrap().total().unwrap())
+                .collect();
+
+            for total in distribution.min()..=distribution.max() {
+                let p = distribution.probability(total);
+                let expected = trials as f64 * p;
+                let deviation = (expected * (1. - p)).sqrt();
+                for histogram in [&sampled, &rolled] {
+                    let count = histogram.count(total) as f64;
+                    assert!(
+                        (count - expected).abs() <= 6. * deviation,
+                        "{input} rolled {total} {count} times"
+                    );
+                }
+            }
+            // The totals that can't be rolled never are, `d4 * 10` skips
+            // everything between the tens
+            assert!(sampled
+                .iter()
+                .all(|(total, _)| distribution.probability(total) > 0.));
+        }
+    }
+}
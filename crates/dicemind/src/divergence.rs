@@ -0,0 +1,192 @@
+//! Finding where two evaluators of the same expression disagree, for
+//! triaging the bugs of the interpreter. When the totals differ under a
+//! seed, the expression is shrunk to the smallest part that still differs
+//! on its own, and every node of that part is evaluated by both sides.
+//!
+//! ```
+//! use dicemind::{divergence::diverge, prelude::*};
+//!
+//! let naive = |expr: &Expression, seed| StandardNaiveRoller::new_seeded(seed).roll_naive(expr).and_then(|v| v.total());
+//! let value = |expr: &Expression, seed| StandardNaiveRoller::new_seeded(seed).roll(expr).and_then(|v| v.total());
+//!
+//! let expr = parse("3x(4d6kh3) + 2").unwrap();
+//! assert!(diverge(&expr, 0, naive, value).is_none());
+//! ```
+
+use std::fmt::Display;
+
+use crate::{interpreter::RollerResult, syntax::Expression};
+
+pub type Evaluation = RollerResult<i64>;
+
+/// A node of the diverging expression, as evaluated by both sides
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceLine {
+    /// How deep the node is in the diverging expression
+    pub depth: usize,
+    pub expression: Expression,
+    pub left: Evaluation,
+    pub right: Evaluation,
+}
+
+impl TraceLine {
+    pub fn diverges(&self) -> bool {
+        self.left != self.right
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    pub seed: u64,
+    /// The smallest part of the expression the evaluators disagree on
+    pub expression: Expression,
+    /// The nodes of the expression, parents first
+    pub trace: Vec<TraceLine>,
+}
+
+/// Evaluate the expression with both evaluators under the seed, and find
+/// out where they disagree if they do
+pub fn diverge(
+    expr: &Expression,
+    seed: u64,
+    mut left: impl FnMut(&Expression, u64) -> Evaluation,
+    mut right: impl FnMut(&Expression, u64) -> Evaluation,
+) -> Option<Divergence> {
+    let mut diverges = |expr: &Expression| left(expr, seed) != right(expr, seed);
+    if !diverges(expr) {
+        return None;
+    }
+
+    // Descend while any of the children still diverges on its own
+    let mut smallest = expr;
+    while let Some(child) = smallest.children().into_iter().find(|e| diverges(e)) {
+        smallest = child;
+    }
+
+    let mut trace = vec![];
+    let mut stack = vec![(0, smallest)];
+    while let Some((depth, expr)) = stack.pop() {
+        trace.push(TraceLine {
+            depth,
+            expression: expr.clone(),
+            left: left(expr, seed),
+            right: right(expr, seed),
+        });
+
+        stack.extend(expr.children().into_iter().rev().map(|e| (depth + 1, e)));
+    }
+
+    Some(Divergence {
+        seed,
+        expression: smallest.clone(),
+        trace,
+    })
+}
+
+/// The trace side by side, the diverging nodes are marked with `*`
+impl Display for Divergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let evaluation = |e: &Evaluation| match e {
+            Ok(total) => total.to_string(),
+            Err(err) => format!("error: {err}"),
+        };
+
+        let rows: Vec<_> = self
+            .trace
+            .iter()
+            .map(|line| {
+                (
+                    format!("{}{}", "  ".repeat(line.depth), line.expression),
+                    evaluation(&line.left),
+                    evaluation(&line.right),
+                    line.diverges(),
+                )
+            })
+            .collect();
+
+        let width = |column: fn(&(String, String, String, bool)) -> &String| {
+            rows.iter().map(|row| column(row).chars().count()).max()
+        };
+        let expressions = width(|row| &row.0).unwrap_or_default();
+        let lefts = width(|row| &row.1).unwrap_or_default();
+        let rights = width(|row| &row.2).unwrap_or_default();
+
+        writeln!(f, "`{}` diverges under seed {}", self.expression, self.seed)?;
+        for (expression, left, right, diverges) in &rows {
+            let mark = if *diverges { " *" } else { "" };
+            writeln!(
+                f,
+                "{expression:expressions$} | {left:lefts$} | {right:rights$}{mark}"
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        divergence::diverge,
+        parser::parse,
+        prelude::StandardNaiveRoller,
+        syntax::{Affix, Augmentation, Expression},
+        transformer::Transformer,
+    };
+
+    fn roll(expr: &Expression, seed: u64) -> super::Evaluation {
+        StandardNaiveRoller::new_seeded(seed)
+            .roll_naive(expr)
+            .map(|v| v.total().unwrap())
+    }
+
+    // Keeps the lowest dice where the highest should be kept
+    fn buggy(expr: &Expression, seed: u64) -> super::Evaluation {
+        let mut swap = |expr: Expression| match expr {
+            Expression::Dice {
+                quantity,
+                power,
+                mut augmentations,
+            } => {
+                for augment in &mut augmentations {
+                    if let Augmentation::Truncate { affix, .. } = augment {
+                        *affix = Affix::Low;
+                    }
+                }
+
+                Expression::Dice {
+                    quantity,
+                    power,
+                    augmentations,
+                }
+            }
+            expr => expr,
+        };
+
+        roll(&swap.fold(expr.clone()), seed)
+    }
+
+    #[test]
+    fn test_diverge() {
+        let expr = parse("3 + 2 * (4d6kh1 - 1)").unwrap();
+        assert!(diverge(&expr, 0, roll, roll).is_none());
+
+        let divergence = (0..100)
+            .find_map(|seed| diverge(&expr, seed, roll, buggy))
+            .unwrap();
+
+        assert_eq!(divergence.expression, parse("4d6kh1").unwrap());
+        assert_eq!(divergence.trace.len(), 3);
+        assert!(divergence.trace[0].diverges());
+        assert!(!divergence.trace[1].diverges());
+
+        let shown = divergence.to_string();
+        let lines: Vec<_> = shown.lines().collect();
+        assert_eq!(
+            lines[0],
+            format!("`4d6kh1` diverges under seed {}", divergence.seed)
+        );
+        assert!(lines[1].starts_with("4d6kh1 | ") && lines[1].ends_with(" *"));
+        assert!(lines[2].starts_with("  4    | "));
+    }
+}
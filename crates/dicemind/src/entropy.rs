@@ -0,0 +1,249 @@
+//! Rolling with randomness from outside of the machine, for the players
+//! who only trust the dice rolled by the atmospheric noise. The bytes are
+//! fetched in batches, a roll doesn't mean a request for every dice.
+//!
+//! ```no_run
+//! use dicemind::{entropy::{EntropyPool, RandomOrg}, interpreter::NaiveRoller, prelude::*};
+//!
+//! let mut pool = EntropyPool::new(RandomOrg::new());
+//! // Fail here rather than in the middle of the roll
+//! pool.prefetch(64).unwrap();
+//!
+//! let total = NaiveRoller::from_rng(pool).roll(parse("4d6kh3").unwrap()).unwrap().total().unwrap();
+//! ```
+
+use std::{collections::VecDeque, time::Duration};
+
+use rand::RngCore;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum EntropyError {
+    #[error("Could not fetch the entropy: {0}")]
+    Http(#[from] Box<ureq::Error>),
+    #[error("Could not read the entropy: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("The entropy source answered with something else than bytes: {0:?}")]
+    Malformed(String),
+}
+
+pub type EntropyResult<T> = Result<T, EntropyError>;
+
+/// Anywhere the random bytes can be fetched from
+pub trait EntropySource {
+    /// Exactly `bytes` bytes that were never handed out before
+    fn fetch(&mut self, bytes: usize) -> EntropyResult<Vec<u8>>;
+}
+
+/// The integers of random.org, generated from the atmospheric noise. It
+/// needs no key, but every address has a daily quota of bits.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RandomOrg {
+    pub url: String,
+}
+
+impl RandomOrg {
+    /// How many numbers random.org hands out in a single request
+    pub const MAX_BATCH: usize = 10_000;
+
+    pub fn new() -> Self {
+        Self::with_url("https://www.random.org/integers/")
+    }
+
+    /// Anything that answers like random.org, a mirror or a proxy
+    pub fn with_url(url: impl Into<String>) -> Self {
+        Self { url: url.into() }
+    }
+}
+
+impl Default for RandomOrg {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EntropySource for RandomOrg {
+    fn fetch(&mut self, bytes: usize) -> EntropyResult<Vec<u8>> {
+        let mut out = Vec::with_capacity(bytes);
+
+        while out.len() < bytes {
+            let count = (bytes - out.len()).min(Self::MAX_BATCH);
+            let body = ureq::get(&self.url)
+                .timeout(Duration::from_secs(10))
+                .query("num", &count.to_string())
+                .query("min", "0")
+                .query("max", "255")
+                .query("col", "1")
+                .query("base", "16")
+                .query("format", "plain")
+                .query("rnd", "new")
+                .call()
+                .map_err(Box::new)?
+                .into_string()?;
+
+            let fetched = parse_bytes(&body)?;
+            if fetched.len() != count {
+                return Err(EntropyError::Malformed(body));
+            }
+            out.extend(fetched);
+        }
+
+        Ok(out)
+    }
+}
+
+// A byte in hex on every line
+fn parse_bytes(body: &str) -> EntropyResult<Vec<u8>> {
+    body.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            u8::from_str_radix(line, 16).map_err(|_| EntropyError::Malformed(line.to_string()))
+        })
+        .collect()
+}
+
+/// Hands out the bytes of the source as a generator, fetching a whole
+/// batch whenever it runs out. A failed fetch panics in the methods that
+/// can't fail, `prefetch` before the roll to handle the errors instead.
+#[derive(Debug)]
+pub struct EntropyPool<S: EntropySource> {
+    source: S,
+    batch: usize,
+    buffer: VecDeque<u8>,
+}
+
+impl<S: EntropySource> EntropyPool<S> {
+    pub fn new(source: S) -> Self {
+        Self {
+            source,
+            batch: 1024,
+            buffer: VecDeque::new(),
+        }
+    }
+
+    /// How many bytes to fetch at once, 1024 unless set
+    pub fn with_batch(mut self, batch: usize) -> Self {
+        self.batch = batch.max(1);
+        self
+    }
+
+    /// How many bytes are left before the next fetch
+    pub fn available(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Make sure there are at least `bytes` bytes in the pool
+    pub fn prefetch(&mut self, bytes: usize) -> EntropyResult<()> {
+        if self.buffer.len() < bytes {
+            let missing = bytes - self.buffer.len();
+            // Rounded up to whole batches
+            let fetched = self
+                .source
+                .fetch(missing.div_ceil(self.batch) * self.batch)?;
+            self.buffer.extend(fetched);
+        }
+
+        Ok(())
+    }
+}
+
+impl<S: EntropySource> RngCore for EntropyPool<S> {
+    fn next_u32(&mut self) -> u32 {
+        let mut bytes = [0; 4];
+        self.fill_bytes(&mut bytes);
+        u32::from_le_bytes(bytes)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0; 8];
+        self.fill_bytes(&mut bytes);
+        u64::from_le_bytes(bytes)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        if let Err(err) = self.try_fill_bytes(dest) {
+            panic!("{err}");
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.prefetch(dest.len()).map_err(rand::Error::new)?;
+        let fetched = self.buffer.drain(..dest.len());
+        for (byte, fetched) in dest.iter_mut().zip(fetched) {
+            *byte = fetched;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        entropy::{parse_bytes, EntropyError, EntropyPool, EntropyResult, EntropySource},
+        interpreter::NaiveRoller,
+        parser::parse,
+    };
+
+    // Counts up from zero and remembers every fetch
+    #[derive(Default)]
+    struct Counting {
+        next: u8,
+        fetches: Vec<usize>,
+    }
+
+    impl EntropySource for Counting {
+        fn fetch(&mut self, bytes: usize) -> EntropyResult<Vec<u8>> {
+            self.fetches.push(bytes);
+            Ok((0..bytes)
+                .map(|_| {
+                    self.next = self.next.wrapping_add(1);
+                    self.next
+                })
+                .collect())
+        }
+    }
+
+    struct Offline;
+
+    impl EntropySource for Offline {
+        fn fetch(&mut self, _: usize) -> EntropyResult<Vec<u8>> {
+            Err(EntropyError::Malformed("offline".to_string()))
+        }
+    }
+
+    #[test]
+    fn test_entropy_pool() {
+        let mut roller = NaiveRoller::from_rng(EntropyPool::new(Counting::default()));
+        for _ in 0..10 {
+            let total = roller
+                .roll_naive(parse("8d20 + d6").unwrap())
+                .unwrap()
+                .total()
+                .unwrap();
+            assert!((9..=166).contains(&total));
+        }
+
+        let mut pool = EntropyPool::new(Counting::default()).with_batch(100);
+        pool.prefetch(150).unwrap();
+        assert_eq!(pool.available(), 200);
+        assert_eq!(pool.source.fetches, vec![200]);
+        pool.prefetch(10).unwrap();
+        assert_eq!(pool.source.fetches.len(), 1);
+
+        let mut offline = EntropyPool::new(Offline);
+        assert!(offline.prefetch(1).is_err());
+        let mut roller = NaiveRoller::from_rng(offline);
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            roller.roll_naive(parse("d20").unwrap())
+        }));
+        assert!(panicked.is_err());
+    }
+
+    #[test]
+    fn test_parse_bytes() {
+        assert_eq!(parse_bytes("ff\n0\n1a\n").unwrap(), vec![255, 0, 26]);
+        assert!(parse_bytes("Error: You have used your quota").is_err());
+    }
+}
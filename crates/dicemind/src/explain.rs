@@ -0,0 +1,217 @@
+//! Explaining how a roll came to its total, for the front ends to show
+//! right under the result.
+//!
+//! ```
+//! use dicemind::{explain::explain, prelude::*};
+//!
+//! let explained = explain(&parse("4d6kh3 + 2").unwrap(), 0);
+//! assert!(explained.starts_with("4d6kh3: rolled ["));
+//! ```
+
+use crate::{
+    interpreter::{NaiveResult, StandardNaiveRoller, Step, TaggedDiceRoll, Trace},
+    syntax::{Augmentation, Expression},
+};
+
+/// Roll the expression under the seed and explain the roll, like
+/// `4d6kh3: rolled [5, 2, 6, 3] → dropped 2 → 14; 4d6kh3 + 2 → 16`
+pub fn explain(expr: &Expression, seed: u64) -> String {
+    let mut roller = StandardNaiveRoller::new_seeded(seed).with_trace();
+    let result = roller.roll_naive(expr);
+
+    explain_trace(expr, roller.trace().unwrap_or(&Trace::default()), &result)
+}
+
+/// Explain a roll that was already made with a trace. Every dice and
+/// every group with augments gets its own part, in the order they were
+/// rolled, and the whole expression comes last with its result.
+pub fn explain_trace(expr: &Expression, trace: &Trace, result: &NaiveResult) -> String {
+    let mut parts = vec![];
+
+    // The dice and groups visited but not rolled yet, the dice roll right
+    // after their children and apply their augments right after rolling
+    let mut open: Vec<&Expression> = vec![];
+    let mut current: Option<Part> = None;
+    let nodes = trace.nodes();
+
+    for step in &trace.steps {
+        match step {
+            Step::Visit { node } => match nodes.get(node.0 as usize).copied() {
+                Some(expression @ (Expression::Dice { .. } | Expression::Weighted { .. })) => {
+                    open.push(expression)
+                }
+                Some(expression @ Expression::Group { augmentations, .. })
+                    if !augmentations.is_empty() =>
+                {
+                    open.push(expression)
+                }
+                _ => {}
+            },
+            Step::Roll { dice, .. } | Step::RollWeighted { dice, .. } => {
+                parts.extend(current.take().map(Part::finish));
+                current = Some(Part::new(
+                    open.pop(),
+                    format!("rolled {}", list(dice)),
+                    dice,
+                ));
+            }
+            Step::Augment { augment, dice } => {
+                let owned = current
+                    .as_ref()
+                    .is_some_and(|part| part.augments < part.expected);
+                if !owned {
+                    // The augments of a group come after all of its members
+                    parts.extend(current.take().map(Part::finish));
+                    let members: Vec<_> =
+                        dice.iter().map(|d| TaggedDiceRoll::from(d.value)).collect();
+                    current = Some(Part::new(open.pop(), list(&members), &members));
+                }
+
+                if let Some(part) = &mut current {
+                    part.augment(augment, dice);
+                }
+            }
+        }
+    }
+    parts.extend(current.map(Part::finish));
+
+    let result = match result {
+        Ok(value) => match value.total() {
+            Ok(total) => total.to_string(),
+            Err(err) => format!("error: {err}"),
+        },
+        Err(err) => format!("error: {err}"),
+    };
+    parts.push(format!("{expr} → {result}"));
+
+    parts.join("; ")
+}
+
+// The part of the explanation about a single dice or group
+struct Part {
+    label: String,
+    steps: Vec<String>,
+    dice: Vec<TaggedDiceRoll>,
+    // How many augments were explained so far, and how many there are
+    augments: usize,
+    expected: usize,
+}
+
+impl Part {
+    fn new(expr: Option<&Expression>, first: String, dice: &[TaggedDiceRoll]) -> Self {
+        let expected = match expr {
+            Some(Expression::Dice { augmentations, .. })
+            | Some(Expression::Weighted { augmentations, .. })
+            | Some(Expression::Group { augmentations, .. }) => augmentations.len(),
+            _ => 0,
+        };
+
+        Self {
+            label: expr.map(Expression::to_string).unwrap_or_default(),
+            steps: vec![first],
+            dice: dice.to_vec(),
+            augments: 0,
+            expected,
+        }
+    }
+
+    fn augment(&mut self, augment: &Augmentation, after: &[TaggedDiceRoll]) {
+        self.augments += 1;
+        let before = std::mem::replace(&mut self.dice, after.to_vec());
+
+        let changed = before.iter().zip(after).any(|(b, a)| b.value != a.value);
+        let added = after.get(before.len()..).unwrap_or_default();
+        let dropped: Vec<_> = before
+            .iter()
+            .zip(after)
+            .filter(|(b, a)| !b.is_discarded() && a.is_discarded())
+            .map(|(_, a)| a.value.to_string())
+            .collect();
+
+        self.steps.push(if changed {
+            format!("{augment} {}", list(after))
+        } else if !added.is_empty() {
+            format!("exploded {}", list(added))
+        } else if !dropped.is_empty() {
+            format!("dropped {}", dropped.join(", "))
+        } else {
+            augment.to_string()
+        });
+    }
+
+    fn finish(self) -> String {
+        let total: i64 = self
+            .dice
+            .iter()
+            .filter(|d| !d.is_discarded())
+            .map(|d| d.value)
+            .sum();
+
+        format!("{}: {} → {total}", self.label, self.steps.join(" → "))
+    }
+}
+
+fn list(dice: &[TaggedDiceRoll]) -> String {
+    let values: Vec<_> = dice.iter().map(|d| d.value.to_string()).collect();
+    format!("[{}]", values.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        explain::explain,
+        interpreter::{StandardNaiveRoller, Step},
+        parser::parse,
+    };
+
+    #[test]
+    fn test_explain() {
+        let explain = |input: &str, seed| explain(&parse(input).unwrap(), seed);
+
+        for seed in 0..20 {
+            let total = StandardNaiveRoller::new_seeded(seed)
+                .roll_naive(parse("4d6kh3 + 2").unwrap())
+                .unwrap()
+                .total()
+                .unwrap();
+            let explained = explain("4d6kh3 + 2", seed);
+            let (dice, whole) = explained.split_once("; ").unwrap();
+
+            assert!(dice.starts_with("4d6kh3: rolled ["), "{explained}");
+            assert!(dice.contains("] → dropped "), "{explained}");
+            assert!(dice.ends_with(&format!(" → {}", total - 2)), "{explained}");
+            assert_eq!(whole, format!("4d6kh3 + 2 → {total}"));
+        }
+
+        assert_eq!(explain("2 + 2 * 10", 0), "2 + 2 * 10 → 22");
+        assert_eq!(
+            explain("1 + @missing", 0),
+            "1 + @missing → error: Unknown macro `@missing`"
+        );
+
+        // Every dice gets its part, the groups after their members
+        let explained = explain("{d20, d20 + 4}kh1", 3);
+        let parts: Vec<_> = explained.split("; ").collect();
+        assert_eq!(parts.len(), 4, "{explained}");
+        assert!(parts[0].starts_with("d20: rolled ["));
+        assert!(parts[1].starts_with("d20: rolled ["));
+        assert!(parts[2].starts_with("{d20, d20 + 4}kh1: ["));
+        assert!(parts[2].contains("→ dropped "));
+
+        // The explosions show the dice they added
+        let mut roller = StandardNaiveRoller::new_seeded(0).with_trace();
+        let seed = (0..100)
+            .find(|seed| {
+                roller = StandardNaiveRoller::new_seeded(*seed).with_trace();
+                roller.roll_naive(parse("3d6!").unwrap()).unwrap();
+                roller
+                    .trace()
+                    .unwrap()
+                    .steps
+                    .iter()
+                    .any(|step| matches!(step, Step::Augment { dice, .. } if dice.len() > 3))
+            })
+            .unwrap();
+        assert!(explain("3d6!", seed).contains("→ exploded ["));
+    }
+}
@@ -0,0 +1,259 @@
+use std::{collections::BTreeMap, fmt::Write};
+
+use crate::{
+    interpreter::RollerError,
+    parser::parse,
+    storage::{RollQuery, RollStore, StorageResult, StoredRoll},
+    syntax::{AnnotationString, Expression},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum RecapFormat {
+    #[default]
+    Markdown,
+    Html,
+}
+
+/// Render a recap of the rolls, grouped by actor and then by annotation
+pub fn recap(rolls: &[StoredRoll], format: RecapFormat) -> String {
+    let mut actors = Grouped::new();
+
+    for roll in rolls {
+        actors
+            .entry(&roll.record.actor)
+            .or_default()
+            .entry(annotation_of(&roll.record.expression))
+            .or_default()
+            .push(roll);
+    }
+
+    let mut out = String::new();
+    match format {
+        RecapFormat::Markdown => markdown(&mut out, &actors),
+        RecapFormat::Html => html(&mut out, &actors),
+    }
+    .expect("Writing into a string never fails");
+
+    out
+}
+
+impl RollStore {
+    /// Recap every roll matching the query
+    pub fn recap(&self, query: &RollQuery, format: RecapFormat) -> StorageResult<String> {
+        Ok(recap(&self.query(query)?, format))
+    }
+}
+
+type Grouped<'a> = BTreeMap<&'a str, BTreeMap<Option<AnnotationString>, Vec<&'a StoredRoll>>>;
+
+fn markdown(out: &mut String, actors: &Grouped) -> std::fmt::Result {
+    writeln!(out, "# Session recap")?;
+
+    for (actor, annotations) in actors {
+        writeln!(out, "\n## {actor}")?;
+
+        for (annotation, rolls) in annotations {
+            writeln!(out, "\n### {}\n", annotation.as_deref().unwrap_or("Other"))?;
+            writeln!(out, "| Time | Expression | Total |")?;
+            writeln!(out, "| --- | --- | --- |")?;
+
+            for roll in rolls {
+                let total = total(roll);
+                let total = match notability(roll) {
+                    Some(note) => format!("**{total}** ({note})"),
+                    None => total.to_string(),
+                };
+
+                writeln!(
+                    out,
+                    "| {} | `{}` | {total} |",
+                    format_timestamp(roll.record.rolled_at),
+                    roll.record.expression.replace('|', "\\|"),
+                )?;
+            }
+
+            writeln!(out, "\n{} rolls, {} in total", rolls.len(), sum(rolls))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn html(out: &mut String, actors: &Grouped) -> std::fmt::Result {
+    writeln!(out, "<h1>Session recap</h1>")?;
+
+    for (actor, annotations) in actors {
+        writeln!(out, "<h2>{}</h2>", escape(actor))?;
+
+        for (annotation, rolls) in annotations {
+            writeln!(
+                out,
+                "<h3>{}</h3>",
+                escape(annotation.as_deref().unwrap_or("Other"))
+            )?;
+            writeln!(out, "<table>")?;
+            writeln!(
+                out,
+                "<tr><th>Time</th><th>Expression</th><th>Total</th></tr>"
+            )?;
+
+            for roll in rolls {
+                let class = match notability(roll) {
+                    Some("critical success") => " class=\"crit\"",
+                    Some(_) => " class=\"fumble\"",
+                    None => "",
+                };
+
+                writeln!(
+                    out,
+                    "<tr{class}><td>{}</td><td><code>{}</code></td><td>{}</td></tr>",
+                    format_timestamp(roll.record.rolled_at),
+                    escape(&roll.record.expression),
+                    total(roll),
+                )?;
+            }
+
+            writeln!(out, "</table>")?;
+            writeln!(out, "<p>{} rolls, {} in total</p>", rolls.len(), sum(rolls))?;
+        }
+    }
+
+    Ok(())
+}
+
+// The stored rolls were totalled when they were inserted, only a roll
+// stored some other way can have a total that doesn't fit
+fn total(roll: &StoredRoll) -> String {
+    match roll.record.total() {
+        Ok(total) => total.to_string(),
+        Err(err) => err.to_string(),
+    }
+}
+
+fn sum(rolls: &[&StoredRoll]) -> String {
+    let sum = rolls
+        .iter()
+        .try_fold(0i64, |acc, roll| acc.checked_add(roll.record.total().ok()?));
+    match sum {
+        Some(sum) => sum.to_string(),
+        None => RollerError::Overflow.to_string(),
+    }
+}
+
+fn notability(roll: &StoredRoll) -> Option<&'static str> {
+    if roll.record.value.dice().any(|d| d.is_critical_success()) {
+        Some("critical success")
+    } else if roll.record.value.dice().any(|d| d.is_critical_failure()) {
+        Some("critical failure")
+    } else {
+        None
+    }
+}
+
+fn annotation_of(expression: &str) -> Option<AnnotationString> {
+    fn find(expr: &Expression) -> Option<AnnotationString> {
+        use Expression::*;
+
+        match expr {
+            Annotated { annotation, .. } => Some(annotation.clone()),
+            Dice {
+                quantity, power, ..
+            } => quantity
+                .as_deref()
+                .and_then(find)
+                .or_else(|| power.as_deref().and_then(find)),
+            Weighted { quantity, .. } => quantity.as_deref().and_then(find),
+            Binop { lhs, rhs, .. } => find(lhs).or_else(|| find(rhs)),
+            Subexpression(e) | UnaryNegation(e) | Tagged { expression: e, .. } => find(e),
+            Repeat { count, expression } => find(count).or_else(|| find(expression)),
+            Group { members, .. } => members.iter().find_map(find),
+            Conditional {
+                condition,
+                then,
+                otherwise,
+            } => find(condition)
+                .or_else(|| find(then))
+                .or_else(|| find(otherwise)),
+            Call { args, .. } => args.iter().find_map(find),
+            Constant(_) | Decimal(_) | Reference(_) => None,
+        }
+    }
+
+    parse(expression).ok().as_ref().and_then(find)
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// Unix seconds as `YYYY-MM-DD HH:MM` in UTC
+fn format_timestamp(secs: i64) -> String {
+    let days = secs.div_euclid(86_400);
+    let time = secs.rem_euclid(86_400);
+
+    // Howard Hinnant's days-to-civil
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}",
+        time / 3600,
+        time % 3600 / 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        export::{format_timestamp, RecapFormat},
+        prelude::{parse, StandardNaiveRoller},
+        storage::{RollQuery, RollRecord, RollStore},
+    };
+
+    #[test]
+    fn test_timestamp() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00");
+        assert_eq!(format_timestamp(1_709_251_200), "2024-03-01 00:00");
+    }
+
+    #[test]
+    fn test_recap() {
+        let store = RollStore::open_in_memory().unwrap();
+        let mut roller = StandardNaiveRoller::new_seeded(0);
+
+        for (actor, expr) in [
+            ("alice", "d20 + 5 [attack]"),
+            ("alice", "2d6 [damage]"),
+            ("bob", "d20 [attack]"),
+            ("bob", "1d1"),
+        ] {
+            let value = roller.roll_naive(parse(expr).unwrap()).unwrap();
+            store.insert(&RollRecord::new(actor, expr, value)).unwrap();
+        }
+
+        let md = store
+            .recap(&RollQuery::default(), RecapFormat::Markdown)
+            .unwrap();
+        assert!(md.contains("## alice"));
+        assert!(md.contains("### damage"));
+        assert!(md.contains("### Other"));
+        // a d1 always rolls its maximum
+        assert!(md.contains("**1** (critical success)"));
+
+        let html = store
+            .recap(&RollQuery::default(), RecapFormat::Html)
+            .unwrap();
+        assert!(html.contains("<h2>bob</h2>"));
+        assert!(html.contains("class=\"crit\""));
+    }
+}
@@ -0,0 +1,158 @@
+use std::{collections::BTreeMap, fmt::Debug, sync::Arc};
+
+use rand::RngCore;
+
+use crate::{
+    interpreter::{RollerResult, TaggedDiceRoll},
+    syntax::{AugmentationToken, PositiveInteger},
+};
+
+/// House notation for the dice, like `t` for brutal dice that
+/// reroll the low values. Registered augments are parsed as
+/// `Augmentation::Custom` and evaluated by the registry passed
+/// to the roller.
+pub trait CustomAugment: Send + Sync {
+    /// What the augment is written as, right after the dice
+    fn token(&self) -> &str;
+
+    /// Parse the arguments after the token, an optional number by default
+    fn parse_argument<'a>(&self, chars: &'a [char]) -> (Option<PositiveInteger>, &'a [char]) {
+        let len = chars.iter().take_while(|c| c.is_ascii_digit()).count();
+        if len == 0 {
+            return (None, chars);
+        }
+
+        let n = chars[..len].iter().collect::<String>().parse().ok();
+        (n, &chars[len..])
+    }
+
+    /// Change the rolled dice, `power` is the amount of sides the dice have
+    fn apply(
+        &self,
+        n: Option<i64>,
+        power: i64,
+        dice: &mut Vec<TaggedDiceRoll>,
+        rng: &mut dyn RngCore,
+    ) -> RollerResult<()>;
+}
+
+/// Custom augments keyed by their token
+#[derive(Clone, Default)]
+pub struct AugmentRegistry {
+    augments: BTreeMap<AugmentationToken, Arc<dyn CustomAugment>>,
+}
+
+impl Debug for AugmentRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.augments.keys()).finish()
+    }
+}
+
+impl AugmentRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register an augment, replacing the one with the same token
+    pub fn register(&mut self, augment: impl CustomAugment + 'static) {
+        self.augments
+            .insert(augment.token().into(), Arc::new(augment));
+    }
+
+    pub fn with(mut self, augment: impl CustomAugment + 'static) -> Self {
+        self.register(augment);
+        self
+    }
+
+    pub fn get(&self, token: &str) -> Option<&dyn CustomAugment> {
+        self.augments.get(token).map(|a| a.as_ref())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.augments.is_empty()
+    }
+
+    /// The augment whose token the chars start with, the longest token wins
+    pub(crate) fn find<'a>(&self, chars: &'a [char]) -> Option<(&dyn CustomAugment, &'a [char])> {
+        self.augments
+            .iter()
+            .rev()
+            .filter(|(token, _)| !token.is_empty())
+            .find_map(|(token, augment)| {
+                let len = token.chars().count();
+                let matches = chars.len() >= len && token.chars().eq(chars[..len].iter().cloned());
+                matches.then(|| (augment.as_ref(), &chars[len..]))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, RngCore};
+
+    use crate::{
+        extensions::{AugmentRegistry, CustomAugment},
+        interpreter::{NaiveValue, RollerResult, TaggedDiceRoll},
+        parser::parse_with,
+        prelude::StandardNaiveRoller,
+        syntax::{Augmentation, Expression},
+    };
+
+    // Reroll the dice at or below n once, keeping the new value
+    struct Brutal;
+
+    impl CustomAugment for Brutal {
+        fn token(&self) -> &str {
+            "t"
+        }
+
+        fn apply(
+            &self,
+            n: Option<i64>,
+            power: i64,
+            dice: &mut Vec<TaggedDiceRoll>,
+            rng: &mut dyn RngCore,
+        ) -> RollerResult<()> {
+            let n = n.unwrap_or(1);
+
+            for d in dice.iter_mut() {
+                if d.value <= n {
+                    *d = TaggedDiceRoll::from(rng.gen_range(1..=power));
+                }
+            }
+
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_custom_augment() {
+        let augments = AugmentRegistry::new().with(Brutal);
+
+        let Ok(Expression::Dice { augmentations, .. }) = parse_with("2d6t2kh", &augments) else {
+            panic!()
+        };
+        assert!(matches!(
+            &augmentations[..],
+            [Augmentation::Custom { token, n: Some(_) }, Augmentation::Truncate { .. }] if token == "t"
+        ));
+
+        // Unregistered tokens are still errors
+        assert!(parse_with("2d6t2", &AugmentRegistry::new()).is_err());
+
+        let mut roller = StandardNaiveRoller::new_seeded(0).with_augments(augments.clone());
+        for _ in 0..100 {
+            let Ok(NaiveValue::Dice(dice)) =
+                roller.roll_naive(parse_with("10d6t5", &augments).unwrap())
+            else {
+                panic!()
+            };
+            assert_eq!(dice.len(), 10);
+        }
+
+        let mut plain = StandardNaiveRoller::new_seeded(0);
+        assert!(plain
+            .roll_naive(parse_with("d6t", &augments).unwrap())
+            .is_err());
+    }
+}
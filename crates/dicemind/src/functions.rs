@@ -0,0 +1,363 @@
+use std::{collections::BTreeMap, fmt::Debug, sync::Arc};
+
+use rand::RngCore;
+
+use crate::{
+    interpreter::{NaiveValue, RollerError, RollerResult},
+    options::Rounding,
+    syntax::{Expression, FunctionName},
+    systems::earthdawn,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Arity {
+    Exactly(usize),
+    AtLeast(usize),
+}
+
+impl Arity {
+    pub fn accepts(&self, n: usize) -> bool {
+        match *self {
+            Arity::Exactly(m) => n == m,
+            Arity::AtLeast(m) => n >= m,
+        }
+    }
+
+    fn check(&self, function: &str, n: usize) -> RollerResult<()> {
+        if self.accepts(n) {
+            Ok(())
+        } else {
+            Err(RollerError::InvalidArity {
+                function: FunctionName::new(function),
+                arity: n,
+            })
+        }
+    }
+}
+
+/// A function that can be called from an expression, like `max(2d6, d12)`
+#[derive(Debug, Clone, Copy)]
+pub struct Function {
+    pub name: &'static str,
+    pub arity: Arity,
+    pub eval: fn(&[i64]) -> RollerResult<i64>,
+}
+
+impl Function {
+    /// Evaluate the function, checking the amount of arguments first
+    pub fn call(&self, args: &[i64]) -> RollerResult<i64> {
+        self.arity.check(self.name, args.len())?;
+        (self.eval)(args)
+    }
+}
+
+// The rounding functions leave the integers as is, a decimal is
+// rounded their own way before it ever becomes a total, see
+// `FunctionRegistry::call`
+pub const BUILTINS: &[Function] = &[
+    Function {
+        name: "min",
+        arity: Arity::AtLeast(1),
+        eval: |args| Ok(args.iter().copied().min().unwrap_or_default()),
+    },
+    Function {
+        name: "max",
+        arity: Arity::AtLeast(1),
+        eval: |args| Ok(args.iter().copied().max().unwrap_or_default()),
+    },
+    Function {
+        name: "abs",
+        arity: Arity::Exactly(1),
+        eval: |args| args[0].checked_abs().ok_or(RollerError::Overflow),
+    },
+    Function {
+        name: "floor",
+        arity: Arity::Exactly(1),
+        eval: |args| Ok(args[0]),
+    },
+    Function {
+        name: "ceil",
+        arity: Arity::Exactly(1),
+        eval: |args| Ok(args[0]),
+    },
+    Function {
+        name: "round",
+        arity: Arity::Exactly(1),
+        eval: |args| Ok(args[0]),
+    },
+];
+
+pub fn builtin(name: &str) -> Option<&'static Function> {
+    BUILTINS.iter().find(|f| f.name == name)
+}
+
+// The builtins that roll dice of their own, see `rolling_builtin`
+const ROLLING_BUILTINS: &[&str] = &["step"];
+
+/// The dice rolled in place of a builtin that can't work on the totals
+/// alone, `step(7)` rolls `d12!`. `None` for the functions that don't roll,
+/// a registered function with the same name shadows these too
+pub fn rolling_builtin(name: &str, args: &[i64]) -> Option<RollerResult<Expression>> {
+    match name {
+        "step" => Some(Arity::Exactly(1).check(name, args.len()).and_then(|_| {
+            u32::try_from(args[0])
+                .ok()
+                .and_then(earthdawn::step)
+                .ok_or(RollerError::InvalidStep { step: args[0] })
+        })),
+        _ => None,
+    }
+}
+
+// How the rounding builtins round a decimal
+fn builtin_rounding(name: &str) -> Option<Rounding> {
+    match name {
+        "floor" => Some(Rounding::Floor),
+        "ceil" => Some(Rounding::Ceiling),
+        "round" => Some(Rounding::HalfUp),
+        _ => None,
+    }
+}
+
+type TotalsEval = dyn Fn(&[i64], &mut dyn RngCore) -> RollerResult<i64> + Send + Sync;
+type ValuesEval = dyn Fn(&[NaiveValue], &mut dyn RngCore) -> RollerResult<i64> + Send + Sync;
+
+#[derive(Clone)]
+enum CustomEval {
+    // Most functions only care about the totals of the arguments
+    Totals(Arc<TotalsEval>),
+    // Others need to look at the individual dice
+    Values(Arc<ValuesEval>),
+}
+
+/// A function registered at runtime, it can roll dice of its own
+#[derive(Clone)]
+pub struct CustomFunction {
+    pub arity: Arity,
+    eval: CustomEval,
+}
+
+impl CustomFunction {
+    pub fn call(&self, name: &str, args: &[NaiveValue], rng: &mut dyn RngCore) -> RollerResult<i64> {
+        self.arity.check(name, args.len())?;
+
+        match &self.eval {
+            CustomEval::Totals(eval) => eval(&totals(args)?, rng),
+            CustomEval::Values(eval) => eval(args, rng),
+        }
+    }
+}
+
+fn totals(args: &[NaiveValue]) -> RollerResult<Vec<i64>> {
+    args.iter().map(NaiveValue::total).collect()
+}
+
+/// Functions available to the expressions on top of the builtins,
+/// a registered function shadows the builtin with the same name
+#[derive(Clone, Default)]
+pub struct FunctionRegistry {
+    functions: BTreeMap<FunctionName, CustomFunction>,
+}
+
+impl Debug for FunctionRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.functions.keys()).finish()
+    }
+}
+
+impl FunctionRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(
+        &mut self,
+        name: impl Into<FunctionName>,
+        arity: Arity,
+        eval: impl Fn(&[i64], &mut dyn RngCore) -> RollerResult<i64> + Send + Sync + 'static,
+    ) {
+        self.functions.insert(
+            name.into(),
+            CustomFunction {
+                arity,
+                eval: CustomEval::Totals(Arc::new(eval)),
+            },
+        );
+    }
+
+    /// Register a function that gets the rolled values instead of their totals
+    pub fn register_with_dice(
+        &mut self,
+        name: impl Into<FunctionName>,
+        arity: Arity,
+        eval: impl Fn(&[NaiveValue], &mut dyn RngCore) -> RollerResult<i64> + Send + Sync + 'static,
+    ) {
+        self.functions.insert(
+            name.into(),
+            CustomFunction {
+                arity,
+                eval: CustomEval::Values(Arc::new(eval)),
+            },
+        );
+    }
+
+    pub fn with(
+        mut self,
+        name: impl Into<FunctionName>,
+        arity: Arity,
+        eval: impl Fn(&[i64], &mut dyn RngCore) -> RollerResult<i64> + Send + Sync + 'static,
+    ) -> Self {
+        self.register(name, arity, eval);
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&CustomFunction> {
+        self.functions.get(name)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.functions.contains_key(name)
+            || builtin(name).is_some()
+            || ROLLING_BUILTINS.contains(&name)
+    }
+
+    /// Call the registered function or the builtin with the name
+    pub fn call(
+        &self,
+        name: &str,
+        args: &[NaiveValue],
+        rng: &mut dyn RngCore,
+    ) -> RollerResult<i64> {
+        match (self.get(name), builtin(name)) {
+            (Some(custom), _) => custom.call(name, args, rng),
+            (None, Some(builtin)) => match (builtin_rounding(name), args) {
+                (Some(rounding), [NaiveValue::Decimal { exact, .. }]) => Ok(rounding.round(exact)),
+                _ => builtin.call(&totals(args)?),
+            },
+            (None, None) => Err(RollerError::UnknownFunction {
+                function: FunctionName::new(name),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use crate::{
+        functions::{builtin, Arity, FunctionRegistry},
+        interpreter::{NodeId, RollerError, StandardBigRoller},
+        prelude::{parse, StandardNaiveRoller},
+    };
+
+    #[test]
+    fn test_builtins() {
+        assert_eq!(builtin("abs").unwrap().call(&[-4]), Ok(4));
+        assert_eq!(builtin("max").unwrap().call(&[1, 7, 3]), Ok(7));
+        assert!(builtin("abs").unwrap().call(&[1, 2]).is_err());
+        assert!(builtin("min").unwrap().call(&[]).is_err());
+        assert!(builtin("sqrt").is_none());
+    }
+
+    #[test]
+    fn test_registry() {
+        let functions = FunctionRegistry::new()
+            .with("bless", Arity::Exactly(0), |_, rng| Ok(rng.gen_range(1..=4)))
+            .with("sneak", Arity::Exactly(1), |args, _| Ok((args[0] + 1) / 2))
+            .with("abs", Arity::Exactly(1), |_, _| Ok(-1));
+
+        let mut functions = functions;
+        functions.register_with_dice("sixes", Arity::Exactly(1), |args, _| {
+            Ok(args[0].dice().filter(|d| d.value == 6).count() as i64)
+        });
+
+        let mut roller = StandardNaiveRoller::new_seeded(0).with_functions(functions);
+
+        for _ in 0..100 {
+            let value = roller.roll_naive(parse("d20 + bless()").unwrap()).unwrap();
+            assert!((2..=24).contains(&value.total().unwrap()));
+        }
+
+        assert_eq!(
+            roller
+                .roll_naive(parse("sneak(5)").unwrap())
+                .unwrap()
+                .total()
+                .unwrap(),
+            3
+        );
+        assert_eq!(
+            roller
+                .roll_naive(parse("max(1, 2)").unwrap())
+                .unwrap()
+                .total()
+                .unwrap(),
+            2
+        );
+        assert_eq!(
+            roller
+                .roll_naive(parse("abs(4)").unwrap())
+                .unwrap()
+                .total()
+                .unwrap(),
+            -1
+        );
+        assert!((0..=10).contains(
+            &roller
+                .roll_naive(parse("sixes(10d6)").unwrap())
+                .unwrap()
+                .total()
+                .unwrap()
+        ));
+        assert_eq!(
+            roller
+                .roll_naive(parse("sixes(6)").unwrap())
+                .unwrap()
+                .total()
+                .unwrap(),
+            0
+        );
+        assert!(roller.roll_naive(parse("sneak()").unwrap()).is_err());
+        assert!(roller.roll_naive(parse("curse()").unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_rolling_builtins() {
+        let mut roller = StandardNaiveRoller::new_seeded(0).with_provenance();
+        for _ in 0..100 {
+            let value = roller.roll_naive(parse("step(13)").unwrap()).unwrap();
+            assert!(value.total().unwrap() >= 2);
+
+            // The dice are the call's
+            let dice = roller.provenance().unwrap().dice();
+            assert!(dice.len() >= 2);
+            assert!(dice.iter().all(|d| d.node == Some(NodeId(0))));
+        }
+        assert!(matches!(
+            roller.roll_naive(parse("step(0)").unwrap()),
+            Err(RollerError::InvalidStep { step: 0 })
+        ));
+        assert!(roller.roll_naive(parse("step(1, 2)").unwrap()).is_err());
+
+        // The steps explode, which the big roller refuses
+        let mut big = StandardBigRoller::new_seeded(0);
+        assert!(matches!(
+            big.roll(parse("step(7)").unwrap()),
+            Err(RollerError::Unsupported { .. })
+        ));
+
+        // A registered `step` is called instead
+        let functions =
+            FunctionRegistry::new().with("step", Arity::Exactly(1), |args, _| Ok(args[0]));
+        let mut roller = StandardNaiveRoller::new_seeded(0).with_functions(functions);
+        assert_eq!(
+            roller
+                .roll_naive(parse("step(7)").unwrap())
+                .unwrap()
+                .total()
+                .unwrap(),
+            7
+        );
+    }
+}
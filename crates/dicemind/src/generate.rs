@@ -0,0 +1,348 @@
+//! Random expressions, for the fuzzing and the differential tests and for
+//! the demo content of the front ends.
+//!
+//! ```
+//! use dicemind::{generate::Constraints, prelude::*};
+//! use rand::{rngs::StdRng, SeedableRng};
+//!
+//! let expr = Expression::arbitrary(&mut StdRng::seed_from_u64(7), &Constraints::default());
+//! assert!(StandardNaiveRoller::new_seeded(0).roll(&expr).is_ok());
+//! ```
+
+use rand::{seq::SliceRandom, Rng};
+use serde::{Deserialize, Serialize};
+
+use crate::syntax::{
+    Affix, Augmentation, Augmentations, BinaryOperator, Expression, Selector, SelectorOp,
+};
+
+/// How big the generated expressions can get
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Constraints {
+    /// How deep the operators, calls and groups can nest
+    pub max_depth: usize,
+    /// How many nodes the whole tree can have, the constants included
+    pub max_size: usize,
+    pub max_quantity: u32,
+    pub max_power: u32,
+    pub max_constant: u32,
+}
+
+impl Default for Constraints {
+    fn default() -> Self {
+        Self {
+            max_depth: 4,
+            max_size: 16,
+            max_quantity: 10,
+            max_power: 20,
+            max_constant: 20,
+        }
+    }
+}
+
+impl Expression {
+    /// A random expression within the constraints. It is displayed as
+    /// valid notation and rolls with the standard roller: only the builtin
+    /// functions are called, there are no references to macros and the
+    /// dice always have at least one side.
+    pub fn arbitrary(rng: &mut impl Rng, constraints: &Constraints) -> Expression {
+        Generator {
+            rng,
+            constraints,
+            size: constraints.max_size.max(1),
+        }
+        .expression(0)
+    }
+}
+
+const OPERATORS: &[BinaryOperator] = &[
+    BinaryOperator::Add,
+    BinaryOperator::Add,
+    BinaryOperator::Subtract,
+    BinaryOperator::Multiply,
+    BinaryOperator::Equals,
+    BinaryOperator::LessThan,
+    BinaryOperator::GreaterThan,
+    BinaryOperator::Chain,
+];
+
+const POWERS: &[u32] = &[2, 4, 6, 8, 10, 12, 20, 100];
+
+const ANNOTATIONS: &[&str] = &["fire", "hit", "damage", "str", "init"];
+
+struct Generator<'a, R> {
+    rng: &'a mut R,
+    constraints: &'a Constraints,
+    // The nodes that are left to generate, always at least one
+    size: usize,
+}
+
+impl<R: Rng> Generator<'_, R> {
+    fn expression(&mut self, depth: usize) -> Expression {
+        let nested = depth < self.constraints.max_depth;
+
+        // The leaves come first, so an exhausted fuzzer input ends the tree
+        match self.rng.gen_range(0..12) {
+            4 | 5 if nested && self.size >= 3 => {
+                self.size -= 1;
+                Expression::Binop {
+                    operator: *OPERATORS.choose(self.rng).unwrap(),
+                    lhs: self.child(depth, 1),
+                    rhs: self.child(depth, 0),
+                }
+            }
+            6 if nested && self.size >= 2 => {
+                self.size -= 1;
+                Expression::UnaryNegation(self.child(depth, 0))
+            }
+            7 if nested && self.size >= 2 => {
+                self.size -= 1;
+                Expression::Annotated {
+                    expression: self.child(depth, 0),
+                    annotation: (*ANNOTATIONS.choose(self.rng).unwrap()).into(),
+                }
+            }
+            8 if nested && self.size >= 4 => {
+                self.size -= 1;
+                Expression::Conditional {
+                    condition: self.child(depth, 2),
+                    then: self.child(depth, 1),
+                    otherwise: self.child(depth, 0),
+                }
+            }
+            9 if nested && self.size >= 3 => {
+                self.size -= 2;
+                Expression::Repeat {
+                    count: Box::new(Expression::Constant(self.rng.gen_range(1..=3).into())),
+                    expression: self.child(depth, 0),
+                }
+            }
+            10 if nested && self.size >= 2 => {
+                self.size -= 1;
+                let function = *["min", "max", "abs"].choose(self.rng).unwrap();
+                let n = match function {
+                    "abs" => 1,
+                    _ => self.rng.gen_range(1..=3).min(self.size),
+                };
+
+                Expression::Call {
+                    function: function.into(),
+                    args: self.children(depth, n),
+                }
+            }
+            11 if nested && self.size >= 2 => {
+                self.size -= 1;
+                let n = self.rng.gen_range(1..=3).min(self.size);
+                let members = self.children(depth, n);
+
+                let mut augmentations = Augmentations::new();
+                if self.rng.gen_bool(0.5) {
+                    augmentations.push(self.truncate(n as u32));
+                }
+
+                Expression::Group {
+                    members,
+                    augmentations,
+                }
+            }
+            _ => self.leaf(),
+        }
+    }
+
+    // Leaves enough nodes for the siblings that come after it
+    fn child(&mut self, depth: usize, later: usize) -> Box<Expression> {
+        self.size -= later;
+        let expr = self.expression(depth + 1);
+        self.size += later;
+
+        Box::new(expr)
+    }
+
+    fn children(&mut self, depth: usize, n: usize) -> Vec<Expression> {
+        (0..n).map(|i| *self.child(depth, n - i - 1)).collect()
+    }
+
+    fn leaf(&mut self) -> Expression {
+        if self.size >= 3 && self.rng.gen_bool(0.6) {
+            return self.dice();
+        }
+
+        self.size -= 1;
+        Expression::Constant(self.rng.gen_range(0..=self.constraints.max_constant).into())
+    }
+
+    fn dice(&mut self) -> Expression {
+        let quantity = match self.rng.gen_bool(0.7) {
+            true => self.rng.gen_range(1..=self.constraints.max_quantity.max(1)),
+            false => 1,
+        };
+
+        let power = POWERS
+            .iter()
+            .copied()
+            .filter(|power| *power <= self.constraints.max_power)
+            .collect::<Vec<_>>()
+            .choose(self.rng)
+            .copied()
+            .unwrap_or_else(|| self.rng.gen_range(1..=self.constraints.max_power.max(1)));
+
+        let mut augmentations = Augmentations::new();
+        match self.rng.gen_range(0..8) {
+            0 | 1 if quantity > 1 => augmentations.push(self.truncate(quantity)),
+            2 if power > 1 => augmentations.push(Augmentation::Explode { selector: None }),
+            3 => augmentations.push(Augmentation::CountSuccesses {
+                selector: Selector {
+                    relation: std::cmp::Ordering::Greater,
+                    inclusive: true,
+                    n: self.rng.gen_range(1..=power).into(),
+                },
+            }),
+            _ => {}
+        }
+
+        // `d20` is written without the quantity
+        let quantity = match quantity {
+            1 if self.rng.gen_bool(0.5) => {
+                self.size -= 2;
+                None
+            }
+            _ => {
+                self.size -= 3;
+                Some(Box::new(Expression::Constant(quantity.into())))
+            }
+        };
+
+        Expression::Dice {
+            quantity,
+            power: Some(Box::new(Expression::Constant(power.into()))),
+            augmentations,
+        }
+    }
+
+    // Keeps or drops some of the dice, never more than there are
+    fn truncate(&mut self, quantity: u32) -> Augmentation {
+        let op = *[SelectorOp::Keep, SelectorOp::Drop]
+            .choose(self.rng)
+            .unwrap();
+        let affix = [Affix::High, Affix::Low].choose(self.rng).unwrap().clone();
+        let n = self.rng.gen_range(1..=quantity.max(1));
+
+        Augmentation::Truncate {
+            op,
+            affix,
+            n: Some(n.into()),
+        }
+    }
+}
+
+/// The choices of the generator are read from the fuzzer input, so a
+/// mutation of the input is a small change to the tree
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Expression {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(Expression::arbitrary(
+            &mut Choices(u),
+            &Constraints::default(),
+        ))
+    }
+}
+
+// Reads the bytes of the input as random numbers, zeros once it runs out
+#[cfg(feature = "arbitrary")]
+struct Choices<'a, 'b>(&'b mut arbitrary::Unstructured<'a>);
+
+#[cfg(feature = "arbitrary")]
+impl rand::RngCore for Choices<'_, '_> {
+    fn next_u32(&mut self) -> u32 {
+        self.0.arbitrary().unwrap_or_default()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0.arbitrary().unwrap_or_default()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        dest.fill(0);
+        let _ = self.0.fill_buffer(dest);
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::{
+        generate::Constraints, parser::parse, prelude::StandardNaiveRoller, syntax::Expression,
+        transformer::Transformer,
+    };
+
+    // Counts the nodes
+    struct Shape {
+        size: usize,
+    }
+
+    impl Transformer for Shape {
+        fn transform(&mut self, expr: Expression) -> Expression {
+            self.size += 1;
+            expr
+        }
+    }
+
+    #[test]
+    fn test_arbitrary() {
+        let constraints = Constraints::default();
+        let mut roller = StandardNaiveRoller::new_seeded(0);
+
+        for seed in 0..1000 {
+            let expr = Expression::arbitrary(&mut StdRng::seed_from_u64(seed), &constraints);
+            let displayed = expr.to_string();
+
+            assert_eq!(
+                parse(&displayed).map(Expression::normalize).as_ref(),
+                Ok(&expr),
+                "{displayed}"
+            );
+            assert!(roller.roll_naive(&expr).is_ok(), "{displayed}");
+
+            let mut shape = Shape { size: 0 };
+            shape.fold(expr);
+            assert!(shape.size <= constraints.max_size, "{displayed}");
+        }
+
+        let tiny = Constraints {
+            max_depth: 0,
+            max_size: 1,
+            ..Default::default()
+        };
+        for seed in 0..100 {
+            let expr = Expression::arbitrary(&mut StdRng::seed_from_u64(seed), &tiny);
+            assert!(matches!(expr, Expression::Constant(_)));
+        }
+    }
+
+    #[cfg(feature = "arbitrary")]
+    #[test]
+    fn test_fuzzer_input() {
+        use arbitrary::{Arbitrary, Unstructured};
+
+        // The inherent `Expression::arbitrary` takes the random generator
+        let expr = |bytes: &[u8]| {
+            <Expression as Arbitrary>::arbitrary(&mut Unstructured::new(bytes)).unwrap()
+        };
+
+        assert!(StandardNaiveRoller::new_seeded(0)
+            .roll_naive(&expr(&[]))
+            .is_ok());
+        for bytes in [&[1u8, 2, 3][..], &[255; 64], b"2d6 + 3 * max(d20, 4)"] {
+            assert_eq!(expr(bytes), expr(bytes));
+            assert!(StandardNaiveRoller::new_seeded(0)
+                .roll_naive(&expr(bytes))
+                .is_ok());
+        }
+    }
+}
@@ -0,0 +1,507 @@
+//! Checks the parser against the grammar in `grammar.ebnf`, both on the
+//! expressions generated from the grammar and on their near misses. The
+//! generated expressions also check that the displayed expressions parse
+//! back into the same ones.
+
+use std::collections::BTreeMap;
+
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use crate::{parser::parse, syntax::Expression};
+
+const GRAMMAR: &str = include_str!("../grammar.ebnf");
+
+// Past this many nested rules the generator takes the first alternatives
+const MAX_DEPTH: usize = 10;
+
+// Chars the near misses are made of
+const ALPHABET: &[char] = &[
+    '1', '2', '6', 'd', 'e', 'k', 'h', 'l', 's', 'f', 'x', 'm', 'a', '!', '%', '@', '_', '>', '<',
+    '=', '+', '-', '*', ',', '?', ':', '(', ')', '{', '}', '[', ']', ' ', '\t',
+];
+
+#[derive(Debug, Clone)]
+enum Node {
+    Literal(Vec<char>),
+    Rule(String),
+    Class(Class),
+    Sequence(Vec<Node>),
+    Choice(Vec<Node>),
+    Optional(Box<Node>),
+    Repeat(Box<Node>),
+    Not(Box<Node>),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Class {
+    Digit,
+    Lower,
+    Word,
+    White,
+    Any,
+}
+
+impl Class {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "DIGIT" => Class::Digit,
+            "LOWER" => Class::Lower,
+            "WORD" => Class::Word,
+            "WHITE" => Class::White,
+            "ANY" => Class::Any,
+            _ => return None,
+        })
+    }
+
+    fn contains(self, c: char) -> bool {
+        match self {
+            Class::Digit => c.is_ascii_digit(),
+            Class::Lower => c.is_ascii_lowercase(),
+            Class::Word => c.is_ascii_alphanumeric() || c == '_',
+            Class::White => c.is_whitespace(),
+            Class::Any => true,
+        }
+    }
+
+    fn sample(self, rng: &mut impl Rng) -> char {
+        let chars: &[char] = match self {
+            Class::Digit => &['0', '1', '2', '3', '6', '8', '9'],
+            Class::Lower => &['a', 'b', 'd', 'e', 'h', 'k', 'm', 'x'],
+            Class::Word => &['a', 'd', 'x', 'Z', '_', '0', '7'],
+            Class::White => &[' ', ' ', '\t'],
+            Class::Any => ALPHABET,
+        };
+
+        *chars.choose(rng).unwrap()
+    }
+}
+
+struct Grammar {
+    rules: BTreeMap<String, Node>,
+}
+
+impl Grammar {
+    fn load(source: &str) -> Self {
+        let chars = strip_comments(source);
+        let mut reader = Reader {
+            chars: &chars,
+            pos: 0,
+        };
+
+        let mut rules = BTreeMap::new();
+        while !reader.at_end() {
+            let name = reader.name();
+            reader.expect('=');
+            let body = reader.choice();
+            reader.expect(';');
+
+            assert!(
+                rules.insert(name.clone(), body).is_none(),
+                "`{name}` is defined twice"
+            );
+        }
+
+        let grammar = Self { rules };
+        for body in grammar.rules.values() {
+            grammar.check_names(body);
+        }
+
+        grammar
+    }
+
+    fn check_names(&self, node: &Node) {
+        match node {
+            Node::Rule(name) => assert!(
+                self.rules.contains_key(name),
+                "`{name}` is used but not defined"
+            ),
+            Node::Sequence(nodes) | Node::Choice(nodes) => {
+                nodes.iter().for_each(|n| self.check_names(n))
+            }
+            Node::Optional(n) | Node::Repeat(n) | Node::Not(n) => self.check_names(n),
+            Node::Literal(_) | Node::Class(_) => {}
+        }
+    }
+
+    fn accepts(&self, input: &str) -> bool {
+        let chars: Vec<char> = input.chars().collect();
+        self.eat(&self.rules["input"], &chars, 0).is_some()
+    }
+
+    // Where the node stops matching, or none if it does not
+    fn eat(&self, node: &Node, input: &[char], pos: usize) -> Option<usize> {
+        match node {
+            Node::Literal(literal) => input[pos..]
+                .starts_with(literal)
+                .then_some(pos + literal.len()),
+            Node::Rule(name) => self.eat(&self.rules[name], input, pos),
+            Node::Class(class) => input
+                .get(pos)
+                .filter(|c| class.contains(**c))
+                .map(|_| pos + 1),
+            Node::Sequence(nodes) => nodes
+                .iter()
+                .try_fold(pos, |pos, node| self.eat(node, input, pos)),
+            Node::Choice(nodes) => nodes.iter().find_map(|node| self.eat(node, input, pos)),
+            Node::Optional(node) => Some(self.eat(node, input, pos).unwrap_or(pos)),
+            Node::Repeat(node) => {
+                let mut pos = pos;
+                while let Some(next) = self.eat(node, input, pos).filter(|next| *next > pos) {
+                    pos = next;
+                }
+                Some(pos)
+            }
+            Node::Not(node) => self.eat(node, input, pos).is_none().then_some(pos),
+        }
+    }
+
+    fn generate(&self, rng: &mut impl Rng) -> String {
+        let mut out = String::new();
+        self.expand(&self.rules["input"], rng, 0, &mut out);
+        out
+    }
+
+    fn expand(&self, node: &Node, rng: &mut impl Rng, depth: usize, out: &mut String) {
+        let deep = depth > MAX_DEPTH;
+
+        match node {
+            Node::Literal(literal) => out.extend(literal),
+            Node::Rule(name) => self.expand(&self.rules[name], rng, depth + 1, out),
+            Node::Class(class) => out.push(class.sample(rng)),
+            Node::Sequence(nodes) => nodes
+                .iter()
+                .for_each(|node| self.expand(node, rng, depth, out)),
+            Node::Choice(nodes) => {
+                let node = if deep {
+                    &nodes[0]
+                } else {
+                    nodes.choose(rng).unwrap()
+                };
+                self.expand(node, rng, depth, out);
+            }
+            Node::Optional(node) => {
+                if !deep && rng.gen_bool(0.3) {
+                    self.expand(node, rng, depth, out);
+                }
+            }
+            Node::Repeat(node) => {
+                while !deep && rng.gen_bool(0.4) {
+                    self.expand(node, rng, depth, out);
+                }
+            }
+            // The lookaheads are left for the parser to check
+            Node::Not(_) => {}
+        }
+    }
+}
+
+fn strip_comments(source: &str) -> Vec<char> {
+    let mut chars = vec![];
+    let mut rest = source;
+
+    while let Some(start) = rest.find("(*") {
+        chars.extend(rest[..start].chars());
+        let end = rest[start..].find("*)").expect("unclosed comment");
+        rest = &rest[start + end + 2..];
+    }
+
+    chars.extend(rest.chars());
+    chars
+}
+
+// Reads the grammar itself
+struct Reader<'a> {
+    chars: &'a [char],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn skip_whitespace(&mut self) {
+        while self.chars.get(self.pos).is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.chars.get(self.pos).copied()
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.peek().is_none()
+    }
+
+    fn expect(&mut self, c: char) {
+        assert_eq!(self.peek(), Some(c), "expected `{c}` at {}", self.pos);
+        self.pos += 1;
+    }
+
+    fn name(&mut self) -> String {
+        self.skip_whitespace();
+        let len = self.chars[self.pos..]
+            .iter()
+            .take_while(|c| c.is_ascii_alphanumeric() || **c == '_')
+            .count();
+        assert!(len != 0, "expected a name at {}", self.pos);
+
+        self.pos += len;
+        self.chars[self.pos - len..self.pos].iter().collect()
+    }
+
+    fn choice(&mut self) -> Node {
+        let mut nodes = vec![self.sequence()];
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            nodes.push(self.sequence());
+        }
+
+        if nodes.len() == 1 {
+            nodes.pop().unwrap()
+        } else {
+            Node::Choice(nodes)
+        }
+    }
+
+    fn sequence(&mut self) -> Node {
+        let mut nodes = vec![];
+        while !matches!(self.peek(), None | Some('|' | ';' | ')' | ']' | '}')) {
+            nodes.push(self.prefixed());
+        }
+
+        assert!(!nodes.is_empty(), "empty sequence at {}", self.pos);
+        if nodes.len() == 1 {
+            nodes.pop().unwrap()
+        } else {
+            Node::Sequence(nodes)
+        }
+    }
+
+    fn prefixed(&mut self) -> Node {
+        if self.peek() == Some('!') {
+            self.pos += 1;
+            return Node::Not(Box::new(self.prefixed()));
+        }
+
+        match self.peek().unwrap() {
+            '"' => {
+                self.pos += 1;
+                let len = self.chars[self.pos..]
+                    .iter()
+                    .position(|c| *c == '"')
+                    .expect("unclosed literal");
+
+                let literal = self.chars[self.pos..self.pos + len].to_vec();
+                self.pos += len + 1;
+                Node::Literal(literal)
+            }
+            open @ ('(' | '[' | '{') => {
+                self.pos += 1;
+                let node = self.choice();
+                let close = match open {
+                    '(' => ')',
+                    '[' => ']',
+                    _ => '}',
+                };
+                self.expect(close);
+
+                match open {
+                    '(' => node,
+                    '[' => Node::Optional(Box::new(node)),
+                    _ => Node::Repeat(Box::new(node)),
+                }
+            }
+            _ => {
+                let name = self.name();
+                match Class::from_name(&name) {
+                    Some(class) => Node::Class(class),
+                    None => Node::Rule(name),
+                }
+            }
+        }
+    }
+}
+
+const ACCEPTED: &[&str] = &[
+    "1",
+    "d20",
+    "2d6 + 3",
+    "d%",
+    "2d",
+    "4d6kh3",
+    "d20e2 + 5",
+    "10d10s>=8f<=1",
+    "3d6!>5!",
+    "2d6!>d6",
+    "2d6dl",
+    "(2d6 + 2) * (2d20kh + 3 + 2 > 13)",
+    "{2d6, 1d8 + 3}kh",
+    "{ }",
+    "{1}d6",
+    "6x(4d6kh3)",
+    "@n * 2",
+    "@str_mod + 2d(@dice)",
+    "max(2d6, d12)",
+    "dx(1)",
+    "2dmax(1, 2)",
+    "bless()",
+    "- - 3",
+    "2 *\t-d6",
+    "d20 [to [hit]] + 5 [str] [mod]",
+    "-2d6 [fire] * 2",
+    "1, 2, 3",
+    "6x(1, 2)",
+    "adv + 5",
+    "dis [init]",
+    "d8a",
+    "d20d",
+    "d20 > 14 ? 2d6 : 0, 1",
+    "{1 ? 2 : 3 ? 4 : 5}kh",
+    "3d{1:3, 2:1}kh1",
+    "d{ - 1 : 1 ,0:1 }!",
+    "1.5 * 2d6",
+    "-0.25*d6+1.05",
+];
+
+const REJECTED: &[&str] = &[
+    "",
+    "  ",
+    "2 d6",
+    "2d 6",
+    "2d6 kh",
+    "max (1)",
+    "2d6d6",
+    "d6(3)",
+    "2dd(6)",
+    "2d6!>3d6",
+    "2d6!>=d6",
+    "2d6x(2)",
+    "6 x(2)",
+    "2max(1)",
+    "dmax(1)kh",
+    "@nx(2)",
+    "1 2",
+    "()",
+    "{1,}",
+    "(1}",
+    "1 +",
+    "*2",
+    "1 <= 2",
+    "[a] 1",
+    "1 + [a]",
+    "d20 [hit",
+    "d20 hit]",
+    "Max(1)",
+    "d %",
+    "%",
+    "@",
+    "2d20a",
+    "d20kha",
+    "advd",
+    "1 ? 2",
+    "1 ? 2 : 3 : 4",
+    "d{1:2, 3}",
+    "d{1:}",
+    "d{1:1}a",
+    "1.",
+    ".5",
+    "1 .5",
+    "1. 5",
+    "1.5.2",
+    "d{1.5:1}",
+];
+
+#[test]
+fn test_grammar_examples() {
+    let grammar = Grammar::load(GRAMMAR);
+
+    for input in ACCEPTED {
+        assert!(grammar.accepts(input), "the grammar rejects `{input}`");
+        assert!(parse(input).is_ok(), "the parser rejects `{input}`");
+    }
+
+    for input in REJECTED {
+        assert!(!grammar.accepts(input), "the grammar accepts `{input}`");
+        assert!(parse(input).is_err(), "the parser accepts `{input}`");
+    }
+}
+
+fn conforms(grammar: &Grammar, input: &str) -> bool {
+    let expected = grammar.accepts(input);
+    let parsed = parse(input);
+
+    assert_eq!(
+        expected,
+        parsed.is_ok(),
+        "`{input}` is {} by the grammar, the parser gave {parsed:?}",
+        if expected { "accepted" } else { "rejected" },
+    );
+
+    expected
+}
+
+#[test]
+fn test_grammar_conformance() {
+    let grammar = Grammar::load(GRAMMAR);
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut accepted = 0;
+
+    for _ in 0..5000 {
+        let input = grammar.generate(&mut rng);
+        accepted += conforms(&grammar, &input) as usize;
+
+        // The near misses, mostly invalid
+        let mut chars: Vec<char> = input.chars().collect();
+        let i = rng.gen_range(0..=chars.len());
+        match rng.gen_range(0..3) {
+            0 if i < chars.len() => {
+                chars.remove(i);
+            }
+            1 if i < chars.len() => chars[i] = *ALPHABET.choose(&mut rng).unwrap(),
+            _ => chars.insert(i, *ALPHABET.choose(&mut rng).unwrap()),
+        }
+        conforms(&grammar, &chars.into_iter().collect::<String>());
+    }
+
+    // Most of the generated expressions should be valid,
+    // otherwise the test only checks the errors
+    assert!(
+        accepted > 2500,
+        "only {accepted} generated inputs were valid"
+    );
+
+    for _ in 0..5000 {
+        let len = rng.gen_range(1..8);
+        let input: String = (0..len)
+            .map(|_| *ALPHABET.choose(&mut rng).unwrap())
+            .collect();
+        conforms(&grammar, &input);
+    }
+}
+
+#[test]
+fn test_display_round_trip() {
+    let grammar = Grammar::load(GRAMMAR);
+    let mut rng = StdRng::seed_from_u64(1);
+
+    for _ in 0..5000 {
+        let input = grammar.generate(&mut rng);
+        let Ok(expr) = parse(&input) else {
+            continue;
+        };
+
+        let displayed = expr.to_string();
+        assert_eq!(
+            parse(&displayed).as_ref(),
+            Ok(&expr),
+            "`{input}` is displayed as `{displayed}`"
+        );
+
+        let normalized = expr.normalize();
+        let displayed = normalized.to_string();
+        assert_eq!(
+            parse(&displayed).map(Expression::normalize),
+            Ok(normalized),
+            "`{input}` is normalized to `{displayed}`"
+        );
+    }
+}
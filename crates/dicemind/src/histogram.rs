@@ -0,0 +1,142 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// How many times every total came up, the result of rolling an
+/// expression over and over. The histograms of the batches rolled apart,
+/// on other threads or in a browser, `merge` into one.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Histogram {
+    counts: BTreeMap<i64, u64>,
+}
+
+impl Histogram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, total: i64) {
+        self.record_n(total, 1);
+    }
+
+    /// The total came up `n` more times
+    pub fn record_n(&mut self, total: i64, n: u64) {
+        if n > 0 {
+            *self.counts.entry(total).or_default() += n;
+        }
+    }
+
+    pub fn merge(&mut self, other: &Histogram) {
+        for (&total, &n) in &other.counts {
+            self.record_n(total, n);
+        }
+    }
+
+    /// How many times the total came up
+    pub fn count(&self, total: i64) -> u64 {
+        self.counts.get(&total).copied().unwrap_or_default()
+    }
+
+    /// How many totals were recorded
+    pub fn trials(&self) -> u64 {
+        self.counts.values().sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    /// Every total that came up with the times it did, from the lowest
+    pub fn iter(&self) -> impl Iterator<Item = (i64, u64)> + '_ {
+        self.counts.iter().map(|(&total, &n)| (total, n))
+    }
+
+    /// Every total that came up with the share of the trials it came up
+    /// in, the shares sum up to one
+    pub fn frequencies(&self) -> impl Iterator<Item = (i64, f64)> + '_ {
+        let trials = self.trials() as f64;
+        self.iter()
+            .map(move |(total, n)| (total, n as f64 / trials))
+    }
+
+    pub fn min(&self) -> Option<i64> {
+        self.counts.keys().next().copied()
+    }
+
+    pub fn max(&self) -> Option<i64> {
+        self.counts.keys().next_back().copied()
+    }
+
+    /// The total that came up the most times, the lowest of them on a tie
+    pub fn mode(&self) -> Option<i64> {
+        self.counts
+            .iter()
+            .rev()
+            .max_by_key(|(_, n)| **n)
+            .map(|(total, _)| *total)
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        let trials = self.trials();
+        (trials > 0).then(|| {
+            self.iter()
+                .map(|(total, n)| total as f64 * n as f64)
+                .sum::<f64>()
+                / trials as f64
+        })
+    }
+}
+
+impl Extend<i64> for Histogram {
+    fn extend<T: IntoIterator<Item = i64>>(&mut self, totals: T) {
+        for total in totals {
+            self.record(total);
+        }
+    }
+}
+
+impl FromIterator<i64> for Histogram {
+    fn from_iter<T: IntoIterator<Item = i64>>(totals: T) -> Self {
+        let mut histogram = Self::new();
+        histogram.extend(totals);
+        histogram
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::histogram::Histogram;
+
+    #[test]
+    fn test_histogram() {
+        let mut histogram: Histogram = [3, 4, 4, 7].into_iter().collect();
+        assert_eq!((histogram.count(4), histogram.count(5)), (2, 0));
+        assert_eq!(histogram.trials(), 4);
+        assert_eq!((histogram.min(), histogram.max()), (Some(3), Some(7)));
+        assert_eq!(histogram.mode(), Some(4));
+        assert_eq!(histogram.mean(), Some(4.5));
+
+        let other: Histogram = [-1, 7, 7, 7].into_iter().collect();
+        histogram.merge(&other);
+        assert_eq!(histogram.trials(), 8);
+        assert_eq!(histogram.mode(), Some(7));
+        assert_eq!(
+            histogram.iter().collect::<Vec<_>>(),
+            vec![(-1, 1), (3, 1), (4, 2), (7, 4)]
+        );
+        assert_eq!(
+            histogram.frequencies().collect::<Vec<_>>(),
+            vec![(-1, 0.125), (3, 0.125), (4, 0.25), (7, 0.5)]
+        );
+
+        let saved = serde_json::to_string(&histogram).unwrap();
+        assert_eq!(saved, r#"{"-1":1,"3":1,"4":2,"7":4}"#);
+        assert_eq!(histogram, serde_json::from_str(&saved).unwrap());
+
+        let empty = Histogram::new();
+        assert!(empty.is_empty());
+        assert_eq!((empty.mean(), empty.mode()), (None, None));
+        assert_eq!(empty.frequencies().count(), 0);
+    }
+}
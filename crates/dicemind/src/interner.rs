@@ -0,0 +1,216 @@
+//! Sharing the storage of the labels that keep coming up. A session that
+//! parses the same `[attack]` and `@damage` a million times only needs
+//! one copy of each. The short labels are stored inline by `SmolStr` and
+//! take no storage to begin with, so only the long ones are shared.
+
+use std::collections::{hash_map::Entry, HashMap};
+
+use serde::{Deserialize, Serialize};
+use smol_str::SmolStr;
+
+use crate::syntax::Expression;
+
+/// How much the interner has saved so far
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InternerStats {
+    /// The distinct long labels that are stored, the released ones are
+    /// no longer counted
+    pub unique: usize,
+    /// Every label that went through the interner
+    pub interned: usize,
+    /// The labels that got an already stored copy
+    pub shared: usize,
+    /// The bytes of the labels that are stored
+    pub stored_bytes: usize,
+    /// The bytes the shared labels would have taken as their own copies
+    pub saved_bytes: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Interner {
+    // The stored labels with the number of times they were handed out and
+    // not released yet
+    strings: HashMap<SmolStr, usize>,
+    stats: InternerStats,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The stored copy of the label, the clones of it point to the same
+    /// storage
+    pub fn intern(&mut self, label: &str) -> SmolStr {
+        self.stats.interned += 1;
+
+        let label = SmolStr::new(label);
+        if !label.is_heap_allocated() {
+            return label;
+        }
+
+        match self.strings.entry(label) {
+            Entry::Occupied(mut stored) => {
+                *stored.get_mut() += 1;
+                self.stats.shared += 1;
+                self.stats.saved_bytes += stored.key().len();
+                stored.key().clone()
+            }
+            Entry::Vacant(vacant) => {
+                let label = vacant.key().clone();
+                self.stats.unique += 1;
+                self.stats.stored_bytes += label.len();
+                vacant.insert(1);
+                label
+            }
+        }
+    }
+
+    /// Give back a label handed out by `intern`, the stored copy is
+    /// dropped once every label handed out for it is given back
+    pub fn release(&mut self, label: &str) {
+        let Some(uses) = self.strings.get_mut(label) else {
+            return;
+        };
+
+        *uses -= 1;
+        if *uses == 0 {
+            self.strings.remove(label);
+            self.stats.unique -= 1;
+            self.stats.stored_bytes -= label.len();
+        }
+    }
+
+    /// Give back the labels of an expression that went through
+    /// `intern_expression`
+    pub fn release_expression(&mut self, expr: &Expression) {
+        for node in expr.nodes() {
+            match node {
+                Expression::Annotated { annotation, .. } => self.release(annotation),
+                Expression::Call { function, .. } => self.release(function),
+                Expression::Reference(name) => self.release(name),
+                _ => {}
+            }
+        }
+    }
+
+    /// Replace the annotations, the macro names and the function names of
+    /// the expression with their stored copies
+    pub fn intern_expression(&mut self, expr: &mut Expression) {
+        match expr {
+            Expression::Dice {
+                quantity, power, ..
+            } => {
+                for e in [quantity, power].into_iter().flatten() {
+                    self.intern_expression(e);
+                }
+            }
+            Expression::Weighted { quantity, .. } => {
+                if let Some(quantity) = quantity {
+                    self.intern_expression(quantity);
+                }
+            }
+            Expression::Binop { lhs, rhs, .. } => {
+                self.intern_expression(lhs);
+                self.intern_expression(rhs);
+            }
+            Expression::Constant(_) | Expression::Decimal(_) => {}
+            Expression::Annotated {
+                expression,
+                annotation,
+            } => {
+                *annotation = self.intern(annotation);
+                self.intern_expression(expression);
+            }
+            Expression::Subexpression(e)
+            | Expression::UnaryNegation(e)
+            | Expression::Tagged { expression: e, .. } => self.intern_expression(e),
+            Expression::Repeat { count, expression } => {
+                self.intern_expression(count);
+                self.intern_expression(expression);
+            }
+            Expression::Call { function, args } => {
+                *function = self.intern(function);
+                args.iter_mut().for_each(|e| self.intern_expression(e));
+            }
+            Expression::Group { members, .. } => {
+                members.iter_mut().for_each(|e| self.intern_expression(e));
+            }
+            Expression::Reference(name) => *name = self.intern(name),
+            Expression::Conditional {
+                condition,
+                then,
+                otherwise,
+            } => {
+                self.intern_expression(condition);
+                self.intern_expression(then);
+                self.intern_expression(otherwise);
+            }
+        }
+    }
+
+    pub fn stats(&self) -> InternerStats {
+        self.stats
+    }
+
+    /// Forget the stored labels, the ones handed out stay valid
+    pub fn clear(&mut self) {
+        self.strings.clear();
+        self.stats = InternerStats::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{interner::Interner, parser::parse, syntax::Expression};
+
+    #[test]
+    fn test_interner() {
+        let mut interner = Interner::new();
+
+        let long = "the longsword of the ancient dragons";
+        let a = interner.intern(long);
+        let b = interner.intern(long);
+        assert_eq!(a, b);
+        assert_eq!(a.as_ptr(), b.as_ptr());
+
+        // Short labels are inline, there is nothing to share
+        assert!(!interner.intern("attack").is_heap_allocated());
+
+        let stats = interner.stats();
+        assert_eq!(stats.interned, 3);
+        assert_eq!((stats.unique, stats.shared), (1, 1));
+        assert_eq!(stats.stored_bytes, long.len());
+        assert_eq!(stats.saved_bytes, long.len());
+
+        let input = format!("d20 [{long}] + @a_macro_with_a_very_long_name * 2");
+        let mut exprs = (0..100).map(|_| parse(&input).unwrap()).collect::<Vec<_>>();
+        exprs.iter_mut().for_each(|e| interner.intern_expression(e));
+        assert_eq!(exprs[0], parse(&input).unwrap());
+
+        let annotations = exprs
+            .iter()
+            .map(|e| match e {
+                Expression::Binop {
+                    lhs: box Expression::Annotated { annotation, .. },
+                    ..
+                } => annotation.as_ptr(),
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+        assert!(annotations.iter().all(|&p| p == a.as_ptr()));
+
+        let stats = interner.stats();
+        assert_eq!(stats.unique, 2);
+        assert_eq!(stats.shared, 1 + 100 + 99);
+
+        // The stored copy goes once every expression gave its labels back
+        exprs.iter().for_each(|e| interner.release_expression(e));
+        assert_eq!(interner.stats().unique, 1);
+        interner.release(long);
+        interner.release(long);
+        let stats = interner.stats();
+        assert_eq!((stats.unique, stats.stored_bytes), (0, 0));
+        assert_ne!(interner.intern(long).as_ptr(), a.as_ptr());
+    }
+}
@@ -0,0 +1,156 @@
+use std::{borrow::Borrow, collections::HashSet};
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    syntax::{Affix, Augmentation, Expression, SelectorOp},
+    transformer::{Advantage, Transformer},
+};
+
+use super::{NaiveRoller, NaiveValue, NodeId, RollerResult, TaggedDiceRoll};
+
+/// A roll with advantage or disadvantage, see `NaiveRoller::roll_with_advantage`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdvantageRoll {
+    /// The expression as it was rolled, with the d20s rolled twice
+    pub expression: Expression,
+    pub value: NaiveValue,
+    /// Both dice of every d20 rolled twice, in the order they were rolled.
+    /// The one that was not kept is discarded.
+    pub candidates: Vec<[TaggedDiceRoll; 2]>,
+}
+
+impl<R: Rng> NaiveRoller<R> {
+    /// Roll every d20 of the arithmetic twice and keep the higher one, the
+    /// macros are expanded first so `@attack + 2` rolls its d20 twice too
+    pub fn roll_with_advantage(
+        &mut self,
+        expr: impl Borrow<Expression>,
+    ) -> RollerResult<AdvantageRoll> {
+        self.roll_twice(expr.borrow(), Affix::High)
+    }
+
+    /// Roll every d20 of the arithmetic twice and keep the lower one
+    pub fn roll_with_disadvantage(
+        &mut self,
+        expr: impl Borrow<Expression>,
+    ) -> RollerResult<AdvantageRoll> {
+        self.roll_twice(expr.borrow(), Affix::Low)
+    }
+
+    fn roll_twice(&mut self, expr: &Expression, affix: Affix) -> RollerResult<AdvantageRoll> {
+        // An unknown macro is left for the roll to fail on
+        let expanded = self.options().macros().expand(expr.clone());
+        let expression = Advantage(affix).fold(expanded.unwrap_or_else(|_| expr.clone()));
+
+        // The sums keep no dice, the candidates are picked out of all of
+        // them. The roll starts the provenance over, from no dice.
+        self.start_provenance(&expression);
+        let (value, dice) = self.with_dice_recorded(|roller| roller.roll_naive(&expression));
+        let value = value?;
+
+        let doubled: HashSet<_> = expression
+            .nodes()
+            .into_iter()
+            .enumerate()
+            .filter(|(_, node)| is_doubled_d20(node))
+            .map(|(i, _)| Some(NodeId(i as u32)))
+            .collect();
+        let candidates = dice
+            .into_iter()
+            .filter(|d| doubled.contains(&d.node))
+            .collect::<Vec<_>>()
+            .chunks_exact(2)
+            .map(|pair| [pair[0], pair[1]])
+            .collect();
+
+        Ok(AdvantageRoll {
+            expression,
+            value,
+            candidates,
+        })
+    }
+}
+
+// `2d20kh1` and `2d20kl1`, written or rewritten
+fn is_doubled_d20(expr: &Expression) -> bool {
+    let Expression::Dice {
+        quantity: Some(quantity),
+        power: Some(power),
+        augmentations,
+    } = expr
+    else {
+        return false;
+    };
+
+    let keeps_one = match augmentations.as_slice() {
+        [Augmentation::Truncate {
+            op: SelectorOp::Keep,
+            n: Some(n),
+            ..
+        }] => *n == 1u32.into(),
+        _ => false,
+    };
+
+    keeps_one
+        && **quantity == Expression::Constant(2.into())
+        && **power == Expression::Constant(20.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        interpreter::StandardNaiveRoller, macros::MacroRegistry, options::RollerOptions,
+        parser::parse,
+    };
+
+    #[test]
+    fn test_roll_with_advantage() {
+        let mut roller = StandardNaiveRoller::new_seeded(0);
+
+        for _ in 0..100 {
+            let roll = roller
+                .roll_with_advantage(parse("d20 + 5").unwrap())
+                .unwrap();
+            assert_eq!(roll.expression, parse("2d20kh1 + 5").unwrap());
+
+            let [[a, b]] = &roll.candidates[..] else {
+                panic!()
+            };
+            assert_eq!(roll.value.total().unwrap(), a.value.max(b.value) + 5);
+            assert_eq!(a.is_discarded() as u8 + b.is_discarded() as u8, 1);
+
+            let roll = roller
+                .roll_with_disadvantage(parse("2x(d20) [attacks]").unwrap())
+                .unwrap();
+            assert_eq!(roll.candidates.len(), 2);
+            let lowest: i64 = roll
+                .candidates
+                .iter()
+                .map(|[a, b]| a.value.min(b.value))
+                .sum();
+            assert_eq!(roll.value.total().unwrap(), lowest);
+        }
+
+        // Nothing to roll twice
+        let roll = roller
+            .roll_with_advantage(parse("2d6 + 3").unwrap())
+            .unwrap();
+        assert!(roll.candidates.is_empty());
+
+        let mut macros = MacroRegistry::default();
+        macros.define("attack", parse("d20 + 7").unwrap()).unwrap();
+        let mut roller = StandardNaiveRoller::new_seeded(0)
+            .with_options(RollerOptions::builder().macros(macros).build());
+        let roll = roller
+            .roll_with_advantage(parse("@attack > 15").unwrap())
+            .unwrap();
+        assert_eq!(roll.expression, parse("(2d20kh1 + 7) > 15").unwrap());
+        assert_eq!(roll.candidates.len(), 1);
+
+        assert!(roller
+            .roll_with_advantage(parse("@missing").unwrap())
+            .is_err());
+    }
+}
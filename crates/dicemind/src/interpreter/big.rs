@@ -0,0 +1,566 @@
+use std::borrow::Borrow;
+
+use num::{bigint::RandBigInt, One, Signed, ToPrimitive, Zero};
+use rand::{rngs::StdRng, CryptoRng, Rng, SeedableRng};
+
+use crate::{
+    cost::fewest_dice,
+    functions::{builtin, rolling_builtin},
+    options::{FixedDice, RollerOptions},
+    syntax::{
+        Augmentation, BinaryOperator, Decimal, Expression, Face, FunctionName, Integer, MacroName,
+    },
+    visitor::Visitor,
+};
+
+use super::{DiceSampler, Limit, RollerError, RollerResult, WeightedSampler};
+
+// How many dice are sampled at once
+const BATCH: usize = 256;
+
+/// Rolls with arbitrary precision, the totals never overflow and the
+/// dice can have as many faces as they like, `d1000000000000000000000`
+/// included. Only the totals are kept, so the augments and the functions
+/// other than the builtin ones are refused. The dice that fit into an
+/// `i64` are rolled the way `NaiveRoller` rolls them, the same seed rolls
+/// the same totals where neither of them overflows.
+#[derive(Debug, Clone)]
+pub struct BigRoller<R: Rng = StdRng> {
+    options: RollerOptions,
+    // How many dice the current roll has rolled, for the limits
+    dice_rolled: usize,
+    // The steps the current roll has left, if it is limited
+    fuel: Option<u64>,
+    // Set when the generator is known to be cryptographically secure
+    secure: bool,
+    rng: R,
+}
+
+pub type StandardBigRoller = BigRoller<StdRng>;
+
+impl<R: SeedableRng + Rng> BigRoller<R> {
+    pub fn new_seeded(seed: u64) -> Self {
+        Self::from_rng(R::seed_from_u64(seed))
+    }
+}
+
+impl<R: CryptoRng + Rng> BigRoller<R> {
+    /// Roll with a cryptographically secure generator, which the options
+    /// can demand with `RollerOptions::with_secure`
+    pub fn from_secure_rng(rng: R) -> Self {
+        Self {
+            secure: true,
+            ..Self::from_rng(rng)
+        }
+    }
+}
+
+impl<R: SeedableRng + Rng> Default for BigRoller<R> {
+    fn default() -> Self {
+        Self::from_rng(R::from_entropy())
+    }
+}
+
+impl<R: Rng> BigRoller<R> {
+    pub fn from_rng(rng: R) -> Self {
+        Self {
+            options: Default::default(),
+            dice_rolled: 0,
+            fuel: None,
+            secure: false,
+            rng,
+        }
+    }
+
+    pub fn with_options(mut self, options: RollerOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn options(&self) -> &RollerOptions {
+        &self.options
+    }
+
+    pub fn rng_mut(&mut self) -> &mut R {
+        &mut self.rng
+    }
+
+    /// Roll the total of the expression, the comparisons are 1 and 0
+    pub fn roll(&mut self, expr: impl Borrow<Expression>) -> RollerResult<Integer> {
+        if self.options.secure() && !self.secure {
+            return Err(RollerError::InsecureRng);
+        }
+
+        self.dice_rolled = 0;
+        self.fuel = self.options.fuel();
+        self.visit(expr.borrow())
+    }
+
+    fn burn(&mut self, steps: u64) -> RollerResult<()> {
+        let Some(left) = self.fuel else {
+            return Ok(());
+        };
+
+        match left.checked_sub(steps) {
+            Some(left) => {
+                self.fuel = Some(left);
+                Ok(())
+            }
+            None => Err(RollerError::OutOfFuel {
+                fuel: self.options.fuel().unwrap_or_default(),
+            }),
+        }
+    }
+
+    // Count the dice towards the limits, before a single one is rolled
+    fn tally(&mut self, count: usize) -> RollerResult<()> {
+        if let Some(max) = self.options.max_dice_per_term().filter(|max| count > *max) {
+            return Err(RollerError::LimitExceeded {
+                limit: Limit::DicePerTerm,
+                max,
+            });
+        }
+
+        self.burn(count as u64)?;
+        self.dice_rolled = self.dice_rolled.saturating_add(count);
+        match self.options.max_total_dice() {
+            Some(max) if self.dice_rolled > max => Err(RollerError::LimitExceeded {
+                limit: Limit::TotalDice,
+                max,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    // The sum of `count` dice from 1 to the power
+    fn sum(&mut self, count: usize, power: &Integer) -> Integer {
+        let Some(small) = power.to_i64() else {
+            let faces = Integer::from(power.magnitude().clone());
+            let total = (0..count).fold(Integer::zero(), |total, _| {
+                total + self.rng.gen_bigint_range(&Integer::one(), &(&faces + 1))
+            });
+            return total * power.signum();
+        };
+
+        // Every batch sums up to less than 2^71, an `i128` can't overflow
+        let sampler = DiceSampler::new(small);
+        let mut batch = [0; BATCH];
+        let mut total = Integer::zero();
+        let mut left = count;
+        while left > 0 {
+            let dice = &mut batch[..left.min(BATCH)];
+            if self.options.packed_dice() {
+                sampler.fill_packed(&mut self.rng, dice);
+            } else {
+                sampler.fill(&mut self.rng, dice);
+            }
+            total += dice.iter().map(|&d| d as i128).sum::<i128>();
+            left -= dice.len();
+        }
+
+        total
+    }
+
+    // The sum of `count` weighted dice, the weights are drawn from like
+    // `WeightedSampler` does when they fit
+    fn sum_weighted(&mut self, count: usize, faces: &[Face]) -> RollerResult<Integer> {
+        match WeightedSampler::new(faces) {
+            Ok(sampler) => {
+                // Less than 2^64 dice below 2^63 each, an `i128` can't overflow
+                let total: i128 = (0..count)
+                    .map(|_| sampler.sample(&mut self.rng) as i128)
+                    .sum();
+                return Ok(total.into());
+            }
+            Err(RollerError::NoFaces) => return Err(RollerError::NoFaces),
+            Err(_) => {}
+        }
+
+        let weights: Integer = faces
+            .iter()
+            .map(|face| Integer::from(face.weight.clone()))
+            .sum();
+        let mut total = Integer::zero();
+        for _ in 0..count {
+            let mut draw = self.rng.gen_bigint_range(&Integer::zero(), &weights);
+            for face in faces {
+                let weight = Integer::from(face.weight.clone());
+                if draw < weight {
+                    total += &face.value;
+                    break;
+                }
+                draw -= weight;
+            }
+        }
+
+        Ok(total)
+    }
+}
+
+// The face of a dice from the lowest to the highest face, the way
+// `FixedDice::face` takes it
+fn fixed_face(fixed: FixedDice, lowest: Integer, highest: Integer) -> Integer {
+    match fixed {
+        FixedDice::Minimum => lowest,
+        FixedDice::Maximum => highest,
+        FixedDice::Midpoint => num::Integer::div_floor(&(lowest + highest), &Integer::from(2)),
+        FixedDice::Constant(face) => Integer::from(face).clamp(lowest, highest),
+    }
+}
+
+impl<R: Rng> Visitor<RollerResult<Integer>> for BigRoller<R> {
+    // The augments are refused here, where the node is known
+    fn enter(&mut self, expr: &Expression) -> Option<RollerResult<Integer>> {
+        if let Err(err) = self.burn(1) {
+            return Some(Err(err));
+        }
+
+        match expr {
+            Expression::Dice { augmentations, .. }
+            | Expression::Group { augmentations, .. }
+            | Expression::Weighted { augmentations, .. }
+                if !augmentations.is_empty() =>
+            {
+                Some(Err(RollerError::Unsupported {
+                    expression: expr.clone(),
+                }))
+            }
+            _ => None,
+        }
+    }
+
+    fn visit_negation(&mut self, value: RollerResult<Integer>) -> RollerResult<Integer> {
+        value.map(|value| -value)
+    }
+
+    fn visit_dice(
+        &mut self,
+        quantity: RollerResult<Integer>,
+        power: RollerResult<Integer>,
+        _augments: &[Augmentation],
+    ) -> RollerResult<Integer> {
+        let power = power?;
+        let quantity = quantity?;
+
+        let count = quantity
+            .magnitude()
+            .to_usize()
+            .ok_or_else(|| RollerError::ValueTooLarge {
+                value: quantity.clone(),
+            })?;
+        self.tally(count)?;
+        if power.is_zero() {
+            return Ok(Integer::zero());
+        }
+
+        if let Some(fixed) = self.options.fixed_dice() {
+            let (lowest, highest) = match power.is_negative() {
+                true => (power, -Integer::one()),
+                false => (Integer::one(), power),
+            };
+            return Ok(fixed_face(fixed, lowest, highest) * quantity);
+        }
+
+        Ok(self.sum(count, &power) * quantity.signum())
+    }
+
+    fn visit_weighted(
+        &mut self,
+        quantity: RollerResult<Integer>,
+        faces: &[Face],
+        _augments: &[Augmentation],
+    ) -> RollerResult<Integer> {
+        let quantity = quantity?;
+
+        let count = quantity
+            .magnitude()
+            .to_usize()
+            .ok_or_else(|| RollerError::ValueTooLarge {
+                value: quantity.clone(),
+            })?;
+        self.tally(count)?;
+
+        if let Some(fixed) = self.options.fixed_dice() {
+            let values = faces
+                .iter()
+                .filter(|face| !face.weight.is_zero())
+                .map(|face| &face.value);
+            let lowest = values.clone().min().ok_or(RollerError::NoFaces)?;
+            let highest = values.max().ok_or(RollerError::NoFaces)?;
+            return Ok(fixed_face(fixed, lowest.clone(), highest.clone()) * quantity);
+        }
+
+        Ok(self.sum_weighted(count, faces)? * quantity.signum())
+    }
+
+    fn visit_group(
+        &mut self,
+        members: Vec<RollerResult<Integer>>,
+        _augments: &[Augmentation],
+    ) -> RollerResult<Integer> {
+        members.into_iter().sum()
+    }
+
+    fn visit_repeat(
+        &mut self,
+        count: RollerResult<Integer>,
+        expr: &Expression,
+    ) -> RollerResult<Integer> {
+        let count = count?;
+        if count.is_negative() {
+            let count = count.to_i64().unwrap_or(i64::MIN);
+            return Err(RollerError::InvalidRepetition { count });
+        }
+
+        // Every repetition visits at least its expression and rolls at
+        // least its fewest dice, a count past the fuel or the limits fails
+        // before the first one is rolled
+        let repetitions = count.to_u64().unwrap_or(u64::MAX);
+        if self.fuel.is_some_and(|left| repetitions > left) {
+            return Err(RollerError::OutOfFuel {
+                fuel: self.options.fuel().unwrap_or_default(),
+            });
+        }
+        if let Some(max) = self.options.max_total_dice() {
+            let dice = fewest_dice(expr, &self.options).saturating_mul(repetitions as usize);
+            if self.dice_rolled.saturating_add(dice) > max {
+                return Err(RollerError::LimitExceeded {
+                    limit: Limit::TotalDice,
+                    max,
+                });
+            }
+        }
+
+        let mut total = Integer::zero();
+        let mut left = count;
+        while left.is_positive() {
+            total += self.visit(expr)?;
+            left -= 1;
+        }
+
+        Ok(total)
+    }
+
+    fn visit_conditional(
+        &mut self,
+        condition: RollerResult<Integer>,
+        then: &Expression,
+        otherwise: &Expression,
+    ) -> RollerResult<Integer> {
+        if condition?.is_zero() {
+            self.visit(otherwise)
+        } else {
+            self.visit(then)
+        }
+    }
+
+    fn visit_call(
+        &mut self,
+        function: &FunctionName,
+        args: Vec<RollerResult<Integer>>,
+    ) -> RollerResult<Integer> {
+        // The builtins work on the machine integers
+        let args = args
+            .into_iter()
+            .map(|arg| {
+                let arg = arg?;
+                i64::try_from(&arg).map_err(|_| RollerError::ValueTooLarge { value: arg })
+            })
+            .collect::<RollerResult<Vec<_>>>()?;
+
+        if let Some(dice) = rolling_builtin(function, &args) {
+            return self.visit(&dice?);
+        }
+
+        let Some(builtin) = builtin(function) else {
+            return Err(RollerError::UnknownFunction {
+                function: function.clone(),
+            });
+        };
+
+        builtin.call(&args).map(Into::into)
+    }
+
+    fn visit_reference(&mut self, name: &MacroName) -> RollerResult<Integer> {
+        // The body is cloned since rolling it needs the roller mutably.
+        let body = self
+            .options
+            .macros()
+            .get(name)
+            .cloned()
+            .ok_or_else(|| RollerError::UnknownMacro { name: name.clone() })?;
+
+        self.visit(&body)
+    }
+
+    fn visit_constant(&mut self, c: &Integer) -> RollerResult<Integer> {
+        Ok(c.clone())
+    }
+
+    fn visit_decimal(&mut self, d: &Decimal) -> RollerResult<Integer> {
+        Err(RollerError::Unsupported {
+            expression: Expression::Decimal(d.clone()),
+        })
+    }
+
+    fn visit_binop(
+        &mut self,
+        op: BinaryOperator,
+        lhs: RollerResult<Integer>,
+        rhs: RollerResult<Integer>,
+    ) -> RollerResult<Integer> {
+        use BinaryOperator::*;
+
+        let lhs = lhs?;
+        let rhs = rhs?;
+
+        Ok(match op {
+            Equals => ((lhs == rhs) as i64).into(),
+            LessThan => ((lhs < rhs) as i64).into(),
+            GreaterThan => ((lhs > rhs) as i64).into(),
+            Add => lhs + rhs,
+            Subtract => lhs - rhs,
+            Multiply => lhs * rhs,
+            Chain => rhs,
+        })
+    }
+
+    fn default_quantity(&self) -> RollerResult<Integer> {
+        Ok(self.options.quantity().into())
+    }
+
+    fn default_power(&self) -> RollerResult<Integer> {
+        Ok(self.options.power().into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use num::{BigInt, One};
+    use rand::rngs::OsRng;
+
+    use crate::{
+        interpreter::{BigRoller, Limit, RollerError, StandardBigRoller, StandardNaiveRoller},
+        options::{FixedDice, RollerOptions},
+        parser::parse,
+    };
+
+    #[test]
+    fn test_big_roller() {
+        // The same totals as the naive roller where it doesn't overflow
+        for input in [
+            "1000d1000000",
+            "d20 + 5 * 3 - d-6",
+            "3x(2d6) > 20 ? max(d4, 3) : -d8",
+            "{d20, 2d6 + 1}, 4d6",
+            "2d{1:3, 6:1} - d{-1:1, 0:1, 1:1}",
+        ] {
+            let expr = parse(input).unwrap();
+            let mut big = StandardBigRoller::new_seeded(2);
+            let mut naive = StandardNaiveRoller::new_seeded(2);
+            for _ in 0..20 {
+                let expected = naive.roll_naive(&expr).unwrap().total().unwrap();
+                assert_eq!(big.roll(&expr), Ok(expected.into()), "{input}");
+            }
+        }
+
+        let mut roller = StandardBigRoller::new_seeded(0);
+        let huge = "9223372036854775807";
+        let total = roller
+            .roll(parse(&format!("{huge} * {huge} + 1")).unwrap())
+            .unwrap();
+        let expected = huge.parse::<BigInt>().unwrap().pow(2) + 1;
+        assert_eq!(total, expected);
+
+        let power = BigInt::one() << 100;
+        let total = roller.roll(parse(&format!("3d{power}")).unwrap()).unwrap();
+        assert!(total >= 3.into() && total <= power * 3);
+
+        assert_eq!(
+            roller.roll(parse("4d6kh3").unwrap()),
+            Err(RollerError::Unsupported {
+                expression: parse("4d6kh3").unwrap()
+            })
+        );
+        assert_eq!(
+            roller.roll(parse(&format!("max({huge} + 1, 2)")).unwrap()),
+            Err(RollerError::ValueTooLarge {
+                value: huge.parse::<BigInt>().unwrap() + 1
+            })
+        );
+
+        let limited = RollerOptions::builder().max_total_dice(100).build();
+        let mut roller = StandardBigRoller::new_seeded(0).with_options(limited);
+        assert_eq!(
+            roller.roll(parse("1000000000000d6").unwrap()),
+            Err(RollerError::LimitExceeded {
+                limit: Limit::TotalDice,
+                max: 100
+            })
+        );
+        // Nothing counts that many dice
+        for input in [
+            "1000000000000000000000000d6",
+            "1000000000000000000000000d{1:1}",
+        ] {
+            assert_eq!(
+                roller.roll(parse(input).unwrap()),
+                Err(RollerError::ValueTooLarge {
+                    value: "1000000000000000000000000".parse().unwrap()
+                }),
+                "{input}"
+            );
+        }
+        // The repetitions are counted before the first one is rolled
+        assert_eq!(
+            roller.roll(parse("1000000000000000000000000x(d6)").unwrap()),
+            Err(RollerError::LimitExceeded {
+                limit: Limit::TotalDice,
+                max: 100
+            })
+        );
+
+        let fueled = RollerOptions::default().with_fuel(1000);
+        let mut roller = StandardBigRoller::new_seeded(0).with_options(fueled);
+        assert_eq!(
+            roller.roll(parse("1000000000000000000000000x(1)").unwrap()),
+            Err(RollerError::OutOfFuel { fuel: 1000 })
+        );
+    }
+
+    #[test]
+    fn test_secure() {
+        let expr = parse("10d20 + d6").unwrap();
+        let secure = RollerOptions::default().with_secure(true);
+
+        let total = BigRoller::from_secure_rng(OsRng)
+            .with_options(secure.clone())
+            .roll(&expr)
+            .unwrap();
+        assert!(total >= 11.into() && total <= 206.into());
+
+        let mut roller = StandardBigRoller::new_seeded(0).with_options(secure);
+        assert_eq!(roller.roll(&expr), Err(RollerError::InsecureRng));
+    }
+
+    #[test]
+    fn test_fixed_dice() {
+        let roll = |input: &str, fixed| {
+            let options = RollerOptions::builder().fixed_dice(fixed).build();
+            StandardBigRoller::new_seeded(0)
+                .with_options(options)
+                .roll(parse(input).unwrap())
+        };
+
+        assert_eq!(roll("d20 + 5", FixedDice::Midpoint), Ok(15.into()));
+        assert_eq!(roll("-2d6", FixedDice::Maximum), Ok((-12).into()));
+        assert_eq!(roll("3d(-6)", FixedDice::Minimum), Ok((-18).into()));
+        assert_eq!(roll("2d{1:3, 6:1}", FixedDice::Maximum), Ok(12.into()));
+        assert_eq!(roll("d{2:1, 9:5}", FixedDice::Midpoint), Ok(5.into()));
+        assert_eq!(roll("3d6", FixedDice::Constant(10)), Ok(18.into()));
+
+        let power = BigInt::one() << 100;
+        let total = roll(&format!("3d{power}"), FixedDice::Maximum);
+        assert_eq!(total, Ok(power * 3));
+    }
+}
@@ -0,0 +1,158 @@
+use crate::{
+    functions::builtin,
+    syntax::{BinaryOperator, Expression},
+};
+
+use super::{RollerError, RollerResult};
+
+impl Expression {
+    /// Whether the expression rolls the same every time: there are no
+    /// dice, no macros that could roll some and only the builtin
+    /// functions are called
+    pub fn is_deterministic(&self) -> bool {
+        match self {
+            Expression::Dice { .. } | Expression::Weighted { .. } | Expression::Reference(_) => {
+                false
+            }
+            Expression::Call { function, .. } if builtin(function).is_none() => false,
+            expr => expr
+                .children()
+                .into_iter()
+                .all(Expression::is_deterministic),
+        }
+    }
+}
+
+/// The total of an expression without dice, like `2 + 2 * 10`, worked
+/// out without a roller and without touching any random generator. The
+/// builtin functions are assumed to not be shadowed by registered ones.
+/// Gives up on the expressions that are not deterministic, on the
+/// groups with augmentations and on the decimals, which are rounded the
+/// way the roller is told to, those are left to the roller.
+pub fn evaluate_constant(expr: &Expression) -> Option<RollerResult<i64>> {
+    foldable(expr).then(|| fold(expr))
+}
+
+fn foldable(expr: &Expression) -> bool {
+    match expr {
+        Expression::Group { augmentations, .. } if !augmentations.is_empty() => false,
+        Expression::Dice { .. }
+        | Expression::Weighted { .. }
+        | Expression::Decimal(_)
+        | Expression::Reference(_) => false,
+        Expression::Call { function, .. } if builtin(function).is_none() => false,
+        expr => expr.children().into_iter().all(foldable),
+    }
+}
+
+// The same arithmetic as the naive roller
+fn fold(expr: &Expression) -> RollerResult<i64> {
+    use BinaryOperator::*;
+    use RollerError::*;
+
+    match expr {
+        Expression::Constant(c) => i64::try_from(c).map_err(|_| ValueTooLarge { value: c.clone() }),
+        Expression::Binop { operator, lhs, rhs } => {
+            let lhs = fold(lhs)?;
+            let rhs = fold(rhs)?;
+
+            match operator {
+                Equals => Ok((lhs == rhs) as i64),
+                LessThan => Ok((lhs < rhs) as i64),
+                GreaterThan => Ok((lhs > rhs) as i64),
+                Add => lhs.checked_add(rhs).ok_or(Overflow),
+                Subtract => lhs.checked_sub(rhs).ok_or(Overflow),
+                Multiply => lhs.checked_mul(rhs).ok_or(Overflow),
+                Chain => Ok(rhs),
+            }
+        }
+        Expression::UnaryNegation(expr) => fold(expr)?.checked_neg().ok_or(Overflow),
+        Expression::Subexpression(expr)
+        | Expression::Annotated {
+            expression: expr, ..
+        }
+        | Expression::Tagged {
+            expression: expr, ..
+        } => fold(expr),
+        Expression::Repeat { count, expression } => match fold(count)? {
+            count if count < 0 => Err(InvalidRepetition { count }),
+            0 => Ok(0),
+            count => fold(expression)?.checked_mul(count).ok_or(Overflow),
+        },
+        Expression::Conditional {
+            condition,
+            then,
+            otherwise,
+        } => match fold(condition)? {
+            0 => fold(otherwise),
+            _ => fold(then),
+        },
+        Expression::Call { function, args } => {
+            let args = args.iter().map(fold).collect::<RollerResult<Vec<_>>>()?;
+            builtin(function)
+                .ok_or_else(|| UnknownFunction {
+                    function: function.clone(),
+                })?
+                .call(&args)
+        }
+        Expression::Group { members, .. } => members.iter().try_fold(0i64, |total, member| {
+            total.checked_add(fold(member)?).ok_or(Overflow)
+        }),
+        Expression::Dice { .. }
+        | Expression::Weighted { .. }
+        | Expression::Decimal(_)
+        | Expression::Reference(_) => {
+            unreachable!("only the foldable expressions are folded")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::{
+        generate::Constraints,
+        interpreter::{evaluate_constant, RollerError, StandardNaiveRoller},
+        parser::parse,
+        syntax::Expression,
+    };
+
+    #[test]
+    fn test_evaluate_constant() {
+        let evaluate = |input: &str| evaluate_constant(&parse(input).unwrap());
+
+        assert_eq!(evaluate("2+2*10"), Some(Ok(22)));
+        assert_eq!(evaluate("max(1, 7) > 5 ? 3x(2) : -1 [miss]"), Some(Ok(6)));
+        assert_eq!(evaluate("{1, 2 * 3}"), Some(Ok(7)));
+        assert_eq!(
+            evaluate("9223372036854775807 + 1"),
+            Some(Err(RollerError::Overflow))
+        );
+        assert_eq!(evaluate("d20 + 5"), None);
+        assert_eq!(evaluate("@attack"), None);
+        assert_eq!(evaluate("bless()"), None);
+        assert_eq!(evaluate("{3, 10, 7}kh2"), None);
+
+        assert!(parse("{3, 10, 7}kh2").unwrap().is_deterministic());
+        assert!(!parse("2 * (1 + d4)").unwrap().is_deterministic());
+
+        // Agrees with the roller on everything it folds
+        let mut folded = 0;
+        for seed in 0..2000 {
+            let expr =
+                Expression::arbitrary(&mut StdRng::seed_from_u64(seed), &Constraints::default());
+            if let Some(total) = evaluate_constant(&expr) {
+                folded += 1;
+                assert_eq!(
+                    total,
+                    StandardNaiveRoller::new_seeded(0)
+                        .roll_naive(&expr)
+                        .map(|v| v.total().unwrap()),
+                    "{expr}"
+                );
+            }
+        }
+        assert!(folded > 100, "only {folded} expressions were folded");
+    }
+}
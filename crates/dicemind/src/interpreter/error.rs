@@ -2,10 +2,10 @@ use thiserror::Error;
 
 use crate::{
     prelude::Expression,
-    syntax::{AnnotationString, Integer},
+    syntax::{AnnotationString, AugmentationToken, FunctionName, Integer, MacroName},
 };
 
-#[derive(Debug, Error)]
+#[derive(Debug, Clone, Error, PartialEq)]
 pub enum RollerError {
     // The input is too large
     #[error("Input value {value} too large and can't fit inside 2^63 - 1")]
@@ -19,6 +19,41 @@ pub enum RollerError {
     TruncationFailure { rolled: u32, removed: u32 },
     #[error("The dice roll will always explode")]
     InfiniteExplosion,
+    #[error("Only dice can explode, not groups")]
+    GroupExplosion,
+    #[error("The dice will be rerolled forever")]
+    InfiniteReroll,
+    #[error("Only dice can be rerolled, not groups")]
+    GroupReroll,
+    #[error("Can not repeat a roll {count} times")]
+    InvalidRepetition { count: i64 },
+    #[error("Unknown function `{function}`")]
+    UnknownFunction { function: FunctionName },
+    #[error("Function `{function}` can not take {arity} arguments")]
+    InvalidArity { function: FunctionName, arity: usize },
+    #[error("There is no step {step}, the steps start at 1")]
+    InvalidStep { step: i64 },
+    #[error("Script function `{function}` failed: {message}")]
+    Script {
+        function: FunctionName,
+        message: String,
+    },
+    #[error("No evaluator registered for the augment `{token}`")]
+    UnknownAugmentation { token: AugmentationToken },
+    #[error("Unknown macro `@{name}`")]
+    UnknownMacro { name: MacroName },
+    #[error("The roll ran out of its {fuel} steps of fuel")]
+    OutOfFuel { fuel: u64 },
+    #[error("The roll has to be secure, but the dice are not rolled from a secure generator")]
+    InsecureRng,
+    #[error("The roll is not the one recorded, it diverged at draw {at}")]
+    ReplayDiverged { at: usize },
+    #[error("The roll went over the limit of {max} {limit}")]
+    LimitExceeded { limit: Limit, max: usize },
+    #[error("The weighted dice has no face that can come up")]
+    NoFaces,
+    #[error("`{expression}` can't be rolled by this roller")]
+    Unsupported { expression: Expression },
     #[error("Annotation \"{annotation}\" denotes two different rolls: {first:?} and {second:?}")]
     DuplicateAnnotation {
         annotation: AnnotationString,
@@ -27,4 +62,15 @@ pub enum RollerError {
     },
 }
 
+/// The limits of the work a roll may do, set in the `RollerOptions`
+#[derive(Debug, Clone, Copy, Error, PartialEq, Eq, Hash)]
+pub enum Limit {
+    #[error("dice in a single term")]
+    DicePerTerm,
+    #[error("dice in the whole roll")]
+    TotalDice,
+    #[error("explosions in a row")]
+    ExplosionDepth,
+}
+
 pub type RollerResult<T> = Result<T, RollerError>;
@@ -1,5 +1,28 @@
+mod advantage;
+mod big;
+mod constant;
 mod naive;
 mod error;
+mod provenance;
+mod prune;
+mod replay;
+mod rng;
+mod sampler;
+mod streams;
+mod trace;
+mod validate;
+mod value;
 
+pub use advantage::*;
+pub use big::*;
+pub use constant::*;
 pub use naive::*;
 pub use error::*;
+pub use provenance::*;
+pub use prune::*;
+pub use replay::*;
+pub use rng::*;
+pub use sampler::*;
+pub use trace::*;
+pub use validate::*;
+pub use value::*;
@@ -1,49 +1,172 @@
-use std::{collections::HashSet, hash::RandomState};
+use std::{borrow::Borrow, collections::HashSet, hash::RandomState};
 
-use num::BigUint;
-use rand::{rngs::StdRng, Rng, SeedableRng};
+use num::{rational::Ratio, BigUint, CheckedAdd, CheckedMul, CheckedSub};
+use rand::{rngs::StdRng, CryptoRng, Rng, RngCore, SeedableRng};
+use serde::{Deserialize, Serialize};
 use smallvec::SmallVec;
 
 use crate::{
-    interpreter::RollerError,
+    cost::fewest_dice,
+    extensions::AugmentRegistry,
+    functions::{rolling_builtin, FunctionRegistry},
+    interpreter::{Limit, RollerError},
+    options::{CriticalRange, FixedDice, Rounding},
     prelude::{Expression, RollerOptions},
-    syntax::{Affix, Augmentation, BinaryOperator, Integer, Selector, SelectorOp},
+    syntax::{
+        Affix, Augmentation, BinaryOperator, Decimal, Face, FunctionName, Integer, MacroName,
+        PositiveInteger, Selector, SelectorOp,
+    },
     visitor::Visitor,
 };
 
-use super::RollerResult;
+use super::{
+    streams::Streams, DiceSampler, NodeId, Provenance, RollSummary, RollerResult, SecureRng, Step,
+    Trace, WeightedSampler,
+};
 
-fn roll_one(rng: &mut impl Rng, power: i64) -> TaggedDiceRoll {
+fn roll_one(rng: &mut impl Rng, power: i64, options: &RollerOptions) -> TaggedDiceRoll {
     if power == 0 {
         return TaggedDiceRoll::zero();
     }
 
-    TaggedDiceRoll::from(rng.gen_range(1..=power.abs()) * power.signum())
-        .with_fail_on_1()
-        .with_success_on(power)
+    rolled(DiceSampler::new(power).sample(rng), power, options)
+}
+
+fn rolled(value: i64, power: i64, options: &RollerOptions) -> TaggedDiceRoll {
+    TaggedDiceRoll::from(value)
+        .with_fail_on(options.critical_failure())
+        .with_success_on(options.critical_success(), power)
 }
 
 fn roll_many(
     rng: &mut impl Rng,
     quantity: i64,
     power: i64,
-) -> impl Iterator<Item = TaggedDiceRoll> {
-    // TODO: sanity check this cast
-    let mut dice = Vec::with_capacity(power.abs() as usize);
-
+    options: &RollerOptions,
+) -> RollerResult<Vec<TaggedDiceRoll>> {
     if quantity == 0 || power == 0 {
-        return dice.into_iter();
+        return Ok(vec![]);
     }
 
-    for _ in 0..quantity.abs() {
-        let rolled = roll_one(rng, power);
-        dice.push(TaggedDiceRoll {
-            value: rolled.value * quantity.signum(),
-            ..rolled
-        });
+    // Without the limits the quantity may not fit into the memory, or
+    // into a `usize` on the 32-bit targets
+    let too_large = || RollerError::ValueTooLarge {
+        value: quantity.into(),
+    };
+    let count = usize::try_from(quantity.unsigned_abs()).map_err(|_| too_large())?;
+    let mut faces = vec![];
+    faces.try_reserve_exact(count).map_err(|_| too_large())?;
+    faces.resize(count, 0);
+
+    let sampler = DiceSampler::new(power);
+    if options.packed_dice() {
+        sampler.fill_packed(rng, &mut faces);
+    } else {
+        sampler.fill(rng, &mut faces);
+    }
+
+    let dice = faces
+        .into_iter()
+        .map(|face| {
+            let rolled = rolled(face, power, options);
+            TaggedDiceRoll {
+                value: rolled.value * quantity.signum(),
+                ..rolled
+            }
+        })
+        .collect();
+    Ok(dice)
+}
+
+// What the augments roll again, the dice with a power or with weighted
+// faces
+#[derive(Debug, Clone, Copy)]
+enum Die<'a> {
+    Plain(i64),
+    Weighted(&'a WeightedSampler),
+}
+
+impl Die<'_> {
+    // The face the explosions and the critical successes are on unless
+    // the augments say otherwise
+    fn highest(&self) -> i64 {
+        match self {
+            Die::Plain(power) => *power,
+            Die::Weighted(sampler) => sampler.highest(),
+        }
+    }
+
+    fn faces(&self) -> Vec<i64> {
+        match self {
+            Die::Plain(power) => faces(*power).collect(),
+            Die::Weighted(sampler) => sampler.faces().to_vec(),
+        }
+    }
+
+    // The lowest and the highest face
+    fn bounds(&self) -> (i64, i64) {
+        match self {
+            Die::Plain(power) if *power < 0 => (*power, -1),
+            Die::Plain(power) => (1, *power),
+            Die::Weighted(sampler) => {
+                let faces = sampler.faces();
+                let lowest = faces.iter().copied().min().unwrap_or_default();
+                (lowest, sampler.highest())
+            }
+        }
+    }
+
+    fn fixed(&self, fixed: FixedDice, options: &RollerOptions) -> TaggedDiceRoll {
+        if let Die::Plain(0) = self {
+            return TaggedDiceRoll::zero();
+        }
+
+        let (lowest, highest) = self.bounds();
+        rolled(fixed.face(lowest, highest), self.highest(), options)
+    }
+
+    fn roll_one(&self, rng: &mut impl Rng, options: &RollerOptions) -> TaggedDiceRoll {
+        if let Some(fixed) = options.fixed_dice() {
+            return self.fixed(fixed, options);
+        }
+
+        match self {
+            Die::Plain(power) => roll_one(rng, *power, options),
+            Die::Weighted(sampler) => rolled(sampler.sample(rng), sampler.highest(), options),
+        }
     }
 
-    dice.into_iter()
+    fn roll_many(
+        &self,
+        rng: &mut impl Rng,
+        quantity: i64,
+        options: &RollerOptions,
+    ) -> RollerResult<Vec<TaggedDiceRoll>> {
+        match self {
+            Die::Plain(power) if options.fixed_dice().is_none() => {
+                roll_many(rng, quantity, *power, options)
+            }
+            Die::Plain(0) => Ok(vec![]),
+            _ => Ok((0..quantity.unsigned_abs())
+                .map(|_| {
+                    let rolled = self.roll_one(rng, options);
+                    TaggedDiceRoll {
+                        value: rolled.value * quantity.signum(),
+                        ..rolled
+                    }
+                })
+                .collect()),
+        }
+    }
+}
+
+// Tell which node rolled the dice, the explosions included
+fn attribute(dice: &mut [TaggedDiceRoll], node: Option<NodeId>) {
+    if let Some(node) = node {
+        for d in dice.iter_mut().filter(|d| d.node.is_none()) {
+            d.node = Some(node);
+        }
+    }
 }
 
 pub fn should_selector_discard(n: i64, selector: Selector, op: SelectorOp) -> bool {
@@ -64,23 +187,85 @@ pub fn should_selector_discard(n: i64, selector: Selector, op: SelectorOp) -> bo
     return false;
 }
 
-fn optional_big_uint_to_usize_or_1(n: Option<BigUint>) -> usize {
+fn optional_big_uint_to_usize_or_1(n: Option<&BigUint>) -> usize {
     n.map(|n| usize::try_from(n).ok())
         .flatten()
         .unwrap_or(1usize)
 }
 
+// Every value a dice with that power can roll
+pub(crate) fn faces(power: i64) -> impl Iterator<Item = i64> {
+    (1..=power.abs()).map(move |value| value * power.signum())
+}
+
+fn explosion_limit(options: &RollerOptions, depth: usize) -> RollerResult<()> {
+    match options.max_explosion_depth() {
+        Some(max) if depth > max => Err(RollerError::LimitExceeded {
+            limit: Limit::ExplosionDepth,
+            max,
+        }),
+        _ => Ok(()),
+    }
+}
+
+// Raise or lower the kept dice to the bound
+fn bound(
+    dice: &mut [TaggedDiceRoll],
+    n: &PositiveInteger,
+    clamp: fn(i64, i64) -> i64,
+) -> RollerResult<()> {
+    let bound =
+        i64::try_from(n).map_err(|_| RollerError::ValueTooLarge { value: n.clone().into() })?;
+
+    for d in dice.iter_mut().filter(|d| !d.is_discarded()) {
+        d.value = clamp(d.value, bound);
+    }
+
+    Ok(())
+}
+
+// Whether the generator can go on, a replay that ran out of its recording
+// fails an empty fill
+fn drawable(rng: &mut impl Rng) -> RollerResult<()> {
+    match rng.try_fill_bytes(&mut []) {
+        Err(err) => err
+            .inner()
+            .downcast_ref::<RollerError>()
+            .cloned()
+            .map_or(Ok(()), Err),
+        Ok(()) => Ok(()),
+    }
+}
+
+// Every dice rolled again is counted towards the limits as it is rolled
+#[allow(clippy::too_many_arguments)]
 fn augment(
     rng: &mut impl Rng,
     mut dice: Vec<TaggedDiceRoll>,
-    augments: impl Iterator<Item = Augmentation>,
+    augments: &[Augmentation],
     options: &RollerOptions,
-    power: i64,
+    custom: &AugmentRegistry,
+    die: Die,
+    trace: &mut Option<Trace>,
+    usage: &mut Usage,
 ) -> RollerResult<Vec<TaggedDiceRoll>> {
     for augment in augments {
+        // The fixed dice would come up the same every time they are rolled
+        // again, the explosions and the rerolls would never end
+        if options.fixed_dice().is_some()
+            && matches!(
+                augment,
+                Augmentation::Explode { .. }
+                    | Augmentation::Compound { .. }
+                    | Augmentation::Reroll { .. }
+            )
+        {
+            continue;
+        }
+
         match augment {
             Augmentation::Truncate { op, affix, n } => {
-                let n = optional_big_uint_to_usize_or_1(n);
+                let n = optional_big_uint_to_usize_or_1(n.as_ref());
 
                 let mut indices_high_to_low = Vec::<usize>::with_capacity(n);
                 for (i, _) in dice.iter().enumerate() {
@@ -92,14 +277,23 @@ fn augment(
                 use Affix::*;
                 use SelectorOp::*;
 
-                // Keeping high is the same as dropping low
-                // Keeping low is the same as dropping high
+                // Dropping the n high dice is the same as keeping the rest low
+                // Dropping the n low dice is the same as keeping the rest high
+                let keep = match op {
+                    Keep => n,
+                    Drop => dice.len().checked_sub(n).ok_or(RollerError::TruncationFailure {
+                        rolled: dice.len() as u32,
+                        removed: n as u32,
+                    })?,
+                };
+
                 match (op, affix) {
                     (Keep, Low) | (Drop, High) => {}
                     (Keep, High) | (Drop, Low) => indices_high_to_low.reverse(),
                 }
-                let keep_indices =
-                    HashSet::<_, RandomState>::from_iter(indices_high_to_low.into_iter().take(n));
+                let keep_indices = HashSet::<_, RandomState>::from_iter(
+                    indices_high_to_low.into_iter().take(keep),
+                );
 
                 for (i, d) in dice.iter_mut().enumerate() {
                     if !keep_indices.contains(&i) {
@@ -109,24 +303,25 @@ fn augment(
             }
             Augmentation::Filter { op, selector } => {
                 for d in &mut dice {
-                    if should_selector_discard(d.value, selector.clone(), op) {
+                    if should_selector_discard(d.value, selector.clone(), *op) {
                         d.mark_discarded();
                     }
                 }
             }
             Augmentation::Emphasis { n } => {
-                let n = optional_big_uint_to_usize_or_1(n);
+                let n = optional_big_uint_to_usize_or_1(n.as_ref());
             }
             Augmentation::Explode { selector } => {
                 let mut active_dice = &mut dice[..];
+                let mut depth = 0;
 
                 loop {
                     let mut exploded = 0;
 
                     for d in active_dice.iter_mut() {
                         let should_explode = match selector {
-                            Some(ref sel) => sel.matches(d.value),
-                            None => d.value == power,
+                            Some(sel) => sel.matches(d.value),
+                            None => d.value == die.highest(),
                         };
 
                         if should_explode {
@@ -135,10 +330,17 @@ fn augment(
                         }
                     }
 
+                    if exploded > 0 {
+                        depth += 1;
+                        explosion_limit(options, depth)?;
+                    }
+
+                    usage.tally(exploded, options)?;
+                    drawable(rng)?;
                     let idx = dice.len();
-                    dice.extend(roll_many(rng, exploded as i64, power));
+                    dice.extend(die.roll_many(rng, exploded as i64, options)?);
                     active_dice = &mut dice[idx..];
-                    
+
                     for d in active_dice.iter_mut() {
                         d.mark_explosive();
                     }
@@ -148,6 +350,100 @@ fn augment(
                     }
                 }
             }
+            Augmentation::Compound { selector } => {
+                let explodes = |value: i64| match selector {
+                    Some(sel) => sel.matches(value),
+                    None => value == die.highest(),
+                };
+
+                if die.faces().into_iter().all(explodes) {
+                    return Err(RollerError::InfiniteExplosion);
+                }
+
+                for d in dice.iter_mut().filter(|d| !d.is_discarded()) {
+                    let mut last = d.value;
+                    let mut depth = 0;
+                    while explodes(last) {
+                        depth += 1;
+                        explosion_limit(options, depth)?;
+                        usage.tally(1, options)?;
+                        drawable(rng)?;
+                        last = die.roll_one(rng, options).value;
+                        d.value = d.value.checked_add(last).ok_or(RollerError::Overflow)?;
+                        d.mark_exploded();
+                    }
+                }
+            }
+            Augmentation::Reroll { selector, once } => {
+                if !*once && die.faces().into_iter().all(|value| selector.matches(value)) {
+                    return Err(RollerError::InfiniteReroll);
+                }
+
+                for d in dice.iter_mut().filter(|d| !d.is_discarded()) {
+                    if selector.matches(d.value) {
+                        usage.tally(1, options)?;
+                        drawable(rng)?;
+                        *d = die.roll_one(rng, options);
+                        while !*once && selector.matches(d.value) {
+                            usage.tally(1, options)?;
+                            drawable(rng)?;
+                            *d = die.roll_one(rng, options);
+                        }
+                    }
+                }
+            }
+            Augmentation::CriticalSuccess { selector } => {
+                for d in &mut dice {
+                    d.tag.set(DiceRollTag::SUCCESS, selector.matches(d.value));
+                }
+            }
+            Augmentation::CriticalFailure { selector } => {
+                for d in &mut dice {
+                    d.tag.set(DiceRollTag::FAIL, selector.matches(d.value));
+                }
+            }
+            Augmentation::Minimum { n } => bound(&mut dice, n, i64::max)?,
+            Augmentation::Maximum { n } => bound(&mut dice, n, i64::min)?,
+            Augmentation::CountSuccesses { selector } => {
+                for d in &mut dice {
+                    if !d.is_discarded() && selector.matches(d.value) {
+                        d.mark_hit();
+                    }
+                }
+            }
+            Augmentation::CountFailures { selector } => {
+                for d in &mut dice {
+                    if !d.is_discarded() && selector.matches(d.value) {
+                        d.mark_miss();
+                    }
+                }
+            }
+            Augmentation::Custom { token, n } => {
+                let n = n
+                    .as_ref()
+                    .map(|n| {
+                        i64::try_from(n).map_err(|_| RollerError::ValueTooLarge {
+                            value: n.clone().into(),
+                        })
+                    })
+                    .transpose()?;
+
+                let before = dice.len();
+                custom
+                    .get(token)
+                    .ok_or_else(|| RollerError::UnknownAugmentation {
+                        token: token.clone(),
+                    })?
+                    .apply(n, die.highest(), &mut dice, rng)?;
+                usage.tally(dice.len().saturating_sub(before), options)?;
+            }
+        }
+
+        if let Some(trace) = trace {
+            trace.push(Step::Augment {
+                augment: augment.clone(),
+                dice: dice.clone(),
+            });
         }
     }
 
@@ -155,7 +451,7 @@ fn augment(
 }
 
 bitflags::bitflags! {
-    #[derive(Clone, Copy, Default, Debug)]
+    #[derive(Clone, Copy, Default, Debug, Serialize, Deserialize)]
     pub struct DiceRollTag: u32 {
         // The value of this dice is very low
         const FAIL = 1 << 0;
@@ -167,13 +463,21 @@ bitflags::bitflags! {
         const EXPLODED = 1 << 3;
         // This dice was discarded by a truncation or filter
         const DISCARDED = 1 << 4;
+        // This dice counts as a success in a pool
+        const HIT = 1 << 5;
+        // This dice counts as a failure in a pool, cancelling a success
+        const MISS = 1 << 6;
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct TaggedDiceRoll {
     pub tag: DiceRollTag,
     pub value: i64,
+    /// The node of the expression that rolled the dice, only known to the
+    /// rollers `with_provenance`
+    #[serde(default)]
+    pub node: Option<NodeId>,
 }
 
 impl Eq for TaggedDiceRoll {}
@@ -201,6 +505,7 @@ impl From<i64> for TaggedDiceRoll {
         Self {
             tag: DiceRollTag::empty(),
             value: n,
+            node: None,
         }
     }
 }
@@ -210,20 +515,18 @@ impl TaggedDiceRoll {
         Self::from(0)
     }
 
-    fn with_success_on(mut self, succ: i64) -> Self {
-        if self.value == succ {
+    // Naturally on the highest face
+    fn with_success_on(mut self, criticals: &CriticalRange, power: i64) -> Self {
+        if criticals.matches(self.value, power) {
             self.tag |= DiceRollTag::SUCCESS;
         }
 
         self
     }
 
-    fn with_fail_on_1(self) -> Self {
-        self.with_fail_on(1)
-    }
-
-    fn with_fail_on(mut self, fail: i64) -> Self {
-        if self.value == fail {
+    // Naturally on a 1
+    fn with_fail_on(mut self, criticals: &CriticalRange) -> Self {
+        if criticals.matches(self.value, 1) {
             self.tag |= DiceRollTag::FAIL;
         }
 
@@ -244,14 +547,79 @@ impl TaggedDiceRoll {
     fn mark_discarded(&mut self) {
         self.tag |= DiceRollTag::DISCARDED;
     }
+
+    /// Count this dice as a success
+    fn mark_hit(&mut self) {
+        self.tag |= DiceRollTag::HIT;
+    }
+
+    /// Count this dice as a failure
+    fn mark_miss(&mut self) {
+        self.tag |= DiceRollTag::MISS;
+    }
+
+    pub fn is_discarded(&self) -> bool {
+        self.tag.contains(DiceRollTag::DISCARDED)
+    }
+
+    pub fn is_critical_success(&self) -> bool {
+        self.tag.contains(DiceRollTag::SUCCESS) && !self.is_discarded()
+    }
+
+    pub fn is_critical_failure(&self) -> bool {
+        self.tag.contains(DiceRollTag::FAIL) && !self.is_discarded()
+    }
 }
 
 pub type StandardNaiveRoller = NaiveRoller;
 
-#[derive(Debug)]
+/// A roller that can only be built around a cryptographically secure
+/// generator seeded by the operating system, for the tournaments and the
+/// games played for money. It can't be seeded, so nobody can replay or
+/// predict its rolls, and it rolls with `RollerOptions::with_secure`.
+/// The functions that must not roll any other way take a `SecureRoller`,
+/// or a `NaiveRoller<R>` with `R: CryptoRng`.
+pub type SecureRoller = NaiveRoller<SecureRng>;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Hits {
+    pub successes: i64,
+    pub failures: i64,
+}
+
+impl Hits {
+    /// Successes left after every failure cancelled one out
+    pub fn net(&self) -> i64 {
+        self.successes - self.failures
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum NaiveValue {
     Constant(i64),
     Dice(SmallVec<[TaggedDiceRoll; 1]>),
+    // Dice that are tallied by hits instead of summed
+    Pool(SmallVec<[TaggedDiceRoll; 1]>),
+    // Results of the same roll repeated several times
+    List(Vec<NaiveValue>),
+    // What is left of a big roll after pruning it
+    Summary(RollSummary),
+    // A value with a fraction, rounded the way it says once it is a total
+    // and written out with as many places as its precision
+    Decimal {
+        exact: Ratio<i64>,
+        rounding: Rounding,
+        precision: u32,
+    },
+}
+
+/// A decimal the roll rounded to an integer before the total, the
+/// quantities, the powers and the rest, see `NaiveRoller::roundings`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Rounded {
+    pub exact: Ratio<i64>,
+    pub total: i64,
+    pub rounding: Rounding,
 }
 
 impl Default for NaiveValue {
@@ -261,12 +629,94 @@ impl Default for NaiveValue {
 }
 
 impl NaiveValue {
-    fn total(&self) -> i64 {
+    /// The sum of the kept dice or of the rolls, the dice can come up with
+    /// anything a weighted face says so it may not fit
+    pub fn total(&self) -> RollerResult<i64> {
+        let sum = |acc: i64, value| acc.checked_add(value).ok_or(RollerError::Overflow);
+
         match self {
-            NaiveValue::Constant(c) => *c,
+            NaiveValue::Constant(c) => Ok(*c),
             NaiveValue::Dice(dice) => dice
                 .iter()
-                .fold(0, |acc, TaggedDiceRoll { value, .. }| acc + value),
+                .filter(|d| !d.is_discarded())
+                .try_fold(0, |acc, TaggedDiceRoll { value, .. }| sum(acc, *value)),
+            NaiveValue::Pool(_) => Ok(self.hits().map(|hits| hits.net()).unwrap_or_default()),
+            NaiveValue::List(values) => values
+                .iter()
+                .try_fold(0, |acc, value| sum(acc, value.total()?)),
+            NaiveValue::Summary(summary) => Ok(summary.total),
+            NaiveValue::Decimal {
+                exact, rounding, ..
+            } => Ok(rounding.round(exact)),
+        }
+    }
+
+    /// How the total of a decimal is rounded, `None` for the integers
+    pub fn rounding(&self) -> Option<Rounding> {
+        match self {
+            NaiveValue::Decimal { rounding, .. } => Some(*rounding),
+            _ => None,
+        }
+    }
+
+    /// The decimal written out with as many places as its precision,
+    /// rounded the way its total is. `None` for the integers and when the
+    /// places don't fit into an `i64`.
+    pub fn decimal_string(&self) -> Option<String> {
+        let NaiveValue::Decimal {
+            exact,
+            rounding,
+            precision,
+        } = self
+        else {
+            return None;
+        };
+
+        let scale = 10i64.checked_pow(*precision)?;
+        let digits = rounding.round(&exact.checked_mul(&Ratio::from_integer(scale))?);
+        let sign = if digits < 0 { "-" } else { "" };
+        let (digits, scale) = (digits.unsigned_abs(), scale as u64);
+
+        Some(match precision {
+            0 => format!("{sign}{digits}"),
+            places => format!(
+                "{sign}{}.{:0width$}",
+                digits / scale,
+                digits % scale,
+                width = *places as usize
+            ),
+        })
+    }
+
+    /// The value before it is rounded, the total for anything but a decimal
+    pub fn exact(&self) -> RollerResult<Ratio<i64>> {
+        match self {
+            NaiveValue::Decimal { exact, .. } => Ok(*exact),
+            _ => Ok(Ratio::from_integer(self.total()?)),
+        }
+    }
+
+    /// Every dice that is a part of the value, including the discarded ones.
+    /// The dice of a summary are not kept.
+    pub fn dice(&self) -> Box<dyn Iterator<Item = &TaggedDiceRoll> + '_> {
+        match self {
+            NaiveValue::Constant(_) | NaiveValue::Summary(_) | NaiveValue::Decimal { .. } => {
+                Box::new(std::iter::empty())
+            }
+            NaiveValue::Dice(dice) | NaiveValue::Pool(dice) => Box::new(dice.iter()),
+            NaiveValue::List(values) => Box::new(values.iter().flat_map(NaiveValue::dice)),
+        }
+    }
+
+    /// Successes and failures of a pool, `None` if the dice are summed
+    pub fn hits(&self) -> Option<Hits> {
+        match self {
+            NaiveValue::Pool(dice) => Some(dice.iter().fold(Hits::default(), |acc, d| Hits {
+                successes: acc.successes + d.tag.contains(DiceRollTag::HIT) as i64,
+                failures: acc.failures + d.tag.contains(DiceRollTag::MISS) as i64,
+            })),
+            NaiveValue::Summary(summary) => summary.hits,
+            _ => None,
         }
     }
 }
@@ -276,60 +726,647 @@ pub type NaiveResult = RollerResult<NaiveValue>;
 #[derive(Debug, Clone)]
 pub struct NaiveRoller<R: Rng = StdRng> {
     options: RollerOptions,
+    augments: AugmentRegistry,
+    functions: FunctionRegistry,
+    // Set when every term rolls from its own stream
+    streams: Option<Streams>,
+    // What the current roll has used up of the limits
+    usage: Usage,
+    // Set when the steps of the rolls are recorded
+    trace: Option<Trace>,
+    // Set when the dice are traced back to their nodes
+    provenance: Option<Provenance>,
+    // The decimals the current roll rounded on the way
+    roundings: Vec<Rounded>,
+    // Set when the generator is known to be cryptographically secure
+    secure: bool,
     rng: R,
 }
 
 impl<R: SeedableRng + Rng> NaiveRoller<R> {
     pub fn new_seeded(seed: u64) -> Self {
+        Self::from_rng(R::seed_from_u64(seed))
+    }
+}
+
+impl<R: CryptoRng + Rng> NaiveRoller<R> {
+    /// Roll with a cryptographically secure generator, which the options
+    /// can demand with `RollerOptions::with_secure`
+    pub fn from_secure_rng(rng: R) -> Self {
         Self {
-            options: Default::default(),
-            rng: R::seed_from_u64(seed),
+            secure: true,
+            ..Self::from_rng(rng)
         }
     }
 }
 
+impl SecureRoller {
+    /// Roll with a fresh `SecureRng`, see `SecureRoller`
+    pub fn new_secure() -> Self {
+        Self::from_secure_rng(SecureRng::new())
+    }
+}
+
 impl<R: SeedableRng + Rng> Default for NaiveRoller<R> {
     fn default() -> Self {
+        Self::from_rng(R::from_entropy())
+    }
+}
+
+impl<R: Rng> NaiveRoller<R> {
+    /// Roll with the generator, see `DiceRng` for the generators picked
+    /// at runtime
+    pub fn from_rng(rng: R) -> Self {
         Self {
             options: Default::default(),
-            rng: R::from_entropy(),
+            augments: Default::default(),
+            functions: Default::default(),
+            streams: None,
+            usage: Usage::default(),
+            trace: None,
+            provenance: None,
+            roundings: vec![],
+            secure: false,
+            rng,
+        }
+    }
+
+    pub fn with_options(mut self, options: RollerOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Evaluate the custom augments with the registry
+    pub fn with_augments(mut self, augments: AugmentRegistry) -> Self {
+        self.augments = augments;
+        self
+    }
+
+    /// Resolve the function calls with the registry before the builtins
+    pub fn with_functions(mut self, functions: FunctionRegistry) -> Self {
+        self.functions = functions;
+        self
+    }
+
+    /// Roll every term from its own stream derived from the seed, so that
+    /// adding a term to an expression does not change what the others roll.
+    /// The stream depends on the amount and the size of the dice and on how
+    /// many terms rolling the same dice came before it.
+    pub fn with_isolated_streams(mut self) -> Self {
+        self.streams = Some(Streams::default());
+        self
+    }
+
+    /// Record every node visited, dice rolled and augment applied
+    pub fn with_trace(mut self) -> Self {
+        self.trace = Some(Trace::default());
+        self
+    }
+
+    /// What the last roll did, if the roller records it
+    pub fn trace(&self) -> Option<&Trace> {
+        self.trace.as_ref()
+    }
+
+    /// Tell the node that rolled every dice
+    pub fn with_provenance(mut self) -> Self {
+        self.provenance = Some(Provenance::default());
+        self
+    }
+
+    /// The dice of the last roll and their nodes, if the roller tells them
+    pub fn provenance(&self) -> Option<&Provenance> {
+        self.provenance.as_ref()
+    }
+
+    /// Every decimal the last roll rounded before its total, in the order
+    /// they were rounded. The total itself is rounded by its value, see
+    /// `NaiveValue::rounding`.
+    pub fn roundings(&self) -> &[Rounded] {
+        &self.roundings
+    }
+
+    pub fn rng(&self) -> &R {
+        &self.rng
+    }
+
+    pub fn rng_mut(&mut self) -> &mut R {
+        &mut self.rng
+    }
+
+    pub fn options(&self) -> &RollerOptions {
+        &self.options
+    }
+
+    pub fn options_mut(&mut self) -> &mut RollerOptions {
+        &mut self.options
+    }
+
+    /// The value with every dice that was rolled, `roll` tells what it
+    /// means. The expression is only borrowed, both `roll_naive(expr)` and
+    /// `roll_naive(&expr)` work and the latter can be rolled again
+    pub fn roll_naive(&mut self, expr: impl Borrow<Expression>) -> NaiveResult {
+        self.start_roll(expr.borrow())?;
+        self.start_provenance(expr.borrow());
+        self.visit(expr.borrow())
+    }
+
+    /// Roll with every dice taken on the same face whatever the options
+    /// say, the best and the worst case to show next to a roll
+    pub fn roll_fixed(&mut self, expr: impl Borrow<Expression>, fixed: FixedDice) -> NaiveResult {
+        let before = self.options.fixed_dice();
+        self.options.set_fixed_dice(Some(fixed));
+        let value = self.roll_naive(expr);
+        self.options.set_fixed_dice(before);
+
+        value
+    }
+
+    pub(super) fn start_provenance(&mut self, expr: &Expression) {
+        if let Some(provenance) = &mut self.provenance {
+            provenance.start(expr);
+        }
+    }
+
+    // The expanded expressions are traced as they are rolled
+    fn expand_trace(&mut self, expr: &Expression) {
+        if let Some(trace) = &mut self.trace {
+            trace.expand(expr);
+        }
+    }
+
+    fn close_trace_expansion(&mut self) {
+        if let Some(trace) = &mut self.trace {
+            trace.close_expansion();
+        }
+    }
+
+    /// Roll with every dice recorded, the ones the sums don't keep
+    /// included, whether the roller keeps the provenance or not
+    pub(super) fn with_dice_recorded<T>(
+        &mut self,
+        roll: impl FnOnce(&mut Self) -> T,
+    ) -> (T, Vec<TaggedDiceRoll>) {
+        let temporary = self.provenance.is_none();
+        let before = self
+            .provenance
+            .get_or_insert_with(Provenance::default)
+            .dice()
+            .len();
+
+        let result = roll(self);
+        let dice = self.provenance.as_ref().map_or(vec![], |p| {
+            p.dice().get(before..).unwrap_or_default().to_vec()
+        });
+        if temporary {
+            self.provenance = None;
+        }
+
+        (result, dice)
+    }
+
+    // Every roll draws the streams of its terms anew and starts the trace
+    // over. A roll that has to be secure is refused here, before a single
+    // dice is rolled.
+    pub(super) fn start_roll(&mut self, expr: &Expression) -> RollerResult<()> {
+        // The streams are derived from a single number, there is a lot
+        // less to guess than the generator itself
+        if self.options.secure() && !(self.secure && self.streams.is_none()) {
+            return Err(RollerError::InsecureRng);
+        }
+
+        self.usage = Usage {
+            dice_rolled: 0,
+            fuel: self.options.fuel(),
+        };
+        if let Some(trace) = &mut self.trace {
+            trace.start(expr);
+        }
+        self.roundings.clear();
+        if let Some(streams) = &mut self.streams {
+            streams.start(self.rng.gen());
+        }
+
+        Ok(())
+    }
+
+    // A registered function shadows the builtins, the ones that roll dice
+    // of their own roll them here
+    fn call(&mut self, function: &FunctionName, args: Vec<NaiveResult>) -> NaiveResult {
+        let args = args.into_iter().collect::<RollerResult<Vec<_>>>()?;
+
+        if self.functions.get(function).is_none() {
+            let totals = args
+                .iter()
+                .map(NaiveValue::total)
+                .collect::<RollerResult<Vec<_>>>()?;
+            if let Some(dice) = rolling_builtin(function, &totals) {
+                let dice = dice?;
+                self.expand_trace(&dice);
+                let value = self.visit(&dice);
+                self.close_trace_expansion();
+
+                return value;
+            }
         }
+
+        let mut isolated = self.streams.as_mut().map(|s| s.call(function));
+        let rng: &mut dyn RngCore = match &mut isolated {
+            Some(rng) => rng,
+            None => &mut self.rng,
+        };
+
+        self.functions
+            .call(function, &args, rng)
+            .map(NaiveValue::Constant)
     }
 }
 
-impl<R: Rng> NaiveRoller<R> {
-    pub fn roll(&mut self, expr: Expression) -> NaiveResult {
-        self.visit(expr)
+// What a roll has used up of its limits so far
+#[derive(Debug, Clone, Copy, Default)]
+struct Usage {
+    // How many dice the roll has rolled
+    dice_rolled: usize,
+    // The steps the roll has left, if it is limited
+    fuel: Option<u64>,
+}
+
+impl Usage {
+    fn burn(&mut self, steps: u64, options: &RollerOptions) -> RollerResult<()> {
+        let Some(left) = self.fuel else {
+            return Ok(());
+        };
+
+        match left.checked_sub(steps) {
+            Some(left) => {
+                self.fuel = Some(left);
+                Ok(())
+            }
+            None => Err(RollerError::OutOfFuel {
+                fuel: options.fuel().unwrap_or_default(),
+            }),
+        }
+    }
+
+    // Count the dice towards the limit of the whole roll
+    fn tally(&mut self, dice: usize, options: &RollerOptions) -> RollerResult<()> {
+        self.burn(dice as u64, options)?;
+        self.dice_rolled = self.dice_rolled.saturating_add(dice);
+        match options.max_total_dice() {
+            Some(max) if self.dice_rolled > max => Err(RollerError::LimitExceeded {
+                limit: Limit::TotalDice,
+                max,
+            }),
+            _ => Ok(()),
+        }
     }
 }
 
-impl<R: Rng> Visitor<NaiveResult> for NaiveRoller<R> {
-    fn visit_dice(
+impl<R: Rng> NaiveRoller<R> {
+    // The dice of either kind with the augments, the quantity can be
+    // negative
+    fn roll_dice(
         &mut self,
-        quantity: NaiveResult,
-        power: NaiveResult,
-        augments: SmallVec<[Augmentation; 1]>,
+        node: Option<NodeId>,
+        quantity: i64,
+        die: Die,
+        augments: &[Augmentation],
     ) -> NaiveResult {
-        let power = power?.total();
-        let quantity = quantity?.total();
+        // Checked before a single dice is rolled, `10000000d20` fails fast
+        let count = usize::try_from(quantity.unsigned_abs()).unwrap_or(usize::MAX);
+        if let Some(max) = self.options.max_dice_per_term().filter(|max| count > *max) {
+            return Err(RollerError::LimitExceeded {
+                limit: Limit::DicePerTerm,
+                max,
+            });
+        }
+        self.usage.tally(count, &self.options)?;
+
+        let mut isolated = self.streams.as_mut().map(|s| match die {
+            Die::Plain(power) => s.dice(quantity, power),
+            Die::Weighted(sampler) => s.weighted(quantity, sampler.faces()),
+        });
+        let mut rng: &mut dyn RngCore = match &mut isolated {
+            Some(rng) => rng,
+            None => &mut self.rng,
+        };
+
+        let mut dice_rolls =
+            SmallVec::from_vec(die.roll_many(&mut rng, quantity, &self.options)?);
+        attribute(&mut dice_rolls, node);
+        if let Some(trace) = &mut self.trace {
+            let dice = dice_rolls.to_vec();
+            trace.push(match die {
+                Die::Plain(power) => Step::Roll {
+                    quantity,
+                    power,
+                    dice,
+                },
+                Die::Weighted(sampler) => Step::RollWeighted {
+                    quantity,
+                    faces: sampler.faces().to_vec(),
+                    dice,
+                },
+            });
+        }
 
-        let dice_rolls = roll_many(&mut self.rng, quantity, power).collect();
         if augments.is_empty() {
+            if let Some(provenance) = &mut self.provenance {
+                provenance.record(&dice_rolls);
+            }
             Ok(NaiveValue::Dice(dice_rolls))
         } else {
+            let is_pool = augments.iter().any(Augmentation::is_counting);
+
             augment(
-                &mut self.rng,
+                &mut rng,
                 dice_rolls.into_vec(),
-                augments.into_iter(),
+                augments,
                 &self.options,
-                power,
+                &self.augments,
+                die,
+                &mut self.trace,
+                &mut self.usage,
+            )
+            .and_then(|mut dice| {
+                attribute(&mut dice, node);
+                if let Some(provenance) = &mut self.provenance {
+                    provenance.record(&dice);
+                }
+                Ok(dice)
+            })
+            .map(|dice| {
+                let dice = dice.into_iter().collect();
+                if is_pool {
+                    NaiveValue::Pool(dice)
+                } else {
+                    NaiveValue::Dice(dice)
+                }
+            })
+        }
+    }
+}
+
+impl<R: Rng> NaiveRoller<R> {
+    // The integers only ever meet the decimals here, the exact value is
+    // worked out and rounded once the options say so
+    fn decimal_binop(
+        &mut self,
+        op: BinaryOperator,
+        lhs: NaiveValue,
+        rhs: NaiveValue,
+    ) -> NaiveResult {
+        use BinaryOperator::*;
+
+        let (l, r) = (lhs.exact()?, rhs.exact()?);
+        let exact = match op {
+            Equals => return Ok(NaiveValue::Constant((l == r) as i64)),
+            LessThan => return Ok(NaiveValue::Constant((l < r) as i64)),
+            GreaterThan => return Ok(NaiveValue::Constant((l > r) as i64)),
+            Add => l.checked_add(&r),
+            Subtract => l.checked_sub(&r),
+            Multiply => l.checked_mul(&r),
+            Chain => return Ok(rhs),
+        };
+
+        self.decimal(exact.ok_or(RollerError::Overflow)?, true)
+    }
+
+    fn decimal(&mut self, exact: Ratio<i64>, step: bool) -> NaiveResult {
+        let value = NaiveValue::Decimal {
+            exact,
+            rounding: self.options.rounding(),
+            precision: self.options.precision(),
+        };
+
+        if exact.is_integer() {
+            Ok(NaiveValue::Constant(exact.to_integer()))
+        } else if step && self.options.round_each_step() {
+            Ok(NaiveValue::Constant(self.integer(value)?))
+        } else {
+            Ok(value)
+        }
+    }
+
+    // The total of a value that has to be an integer, the decimals rounded
+    // on the way are recorded
+    fn integer(&mut self, value: NaiveValue) -> RollerResult<i64> {
+        let total = value.total()?;
+        if let NaiveValue::Decimal {
+            exact, rounding, ..
+        } = value
+        {
+            self.roundings.push(Rounded {
+                exact,
+                total,
+                rounding,
+            });
+        }
+
+        Ok(total)
+    }
+}
+
+impl<R: Rng> Visitor<NaiveResult> for NaiveRoller<R> {
+    fn enter(&mut self, expr: &Expression) -> Option<NaiveResult> {
+        if let Some(trace) = &mut self.trace {
+            trace.enter(expr);
+        }
+
+        if let Err(err) = self.usage.burn(1, &self.options) {
+            return Some(Err(err));
+        }
+
+        if let Some(provenance) = &mut self.provenance {
+            provenance.enter(expr);
+        }
+
+        None
+    }
+
+    fn visit_dice(
+        &mut self,
+        quantity: NaiveResult,
+        power: NaiveResult,
+        augments: &[Augmentation],
+    ) -> NaiveResult {
+        let node = self.provenance.as_mut().and_then(|p| p.close().pop()).flatten();
+
+        let power = self.integer(power?)?;
+        let quantity = self.integer(quantity?)?;
+
+        self.roll_dice(node, quantity, Die::Plain(power), augments)
+    }
+
+    fn visit_weighted(
+        &mut self,
+        quantity: NaiveResult,
+        faces: &[Face],
+        augments: &[Augmentation],
+    ) -> NaiveResult {
+        let node = self.provenance.as_mut().and_then(|p| p.close().pop()).flatten();
+
+        let quantity = self.integer(quantity?)?;
+        let sampler = WeightedSampler::new(faces)?;
+
+        self.roll_dice(node, quantity, Die::Weighted(&sampler), augments)
+    }
+
+    fn visit_group(
+        &mut self,
+        members: Vec<NaiveResult>,
+        augments: &[Augmentation],
+    ) -> NaiveResult {
+        let nodes = self.provenance.as_mut().map(Provenance::close);
+
+        // Every member acts as a single dice, so the augments
+        // keep and drop whole members instead of their dice
+        let mut members = members
+            .into_iter()
+            .map(|member| Ok(TaggedDiceRoll::from(self.integer(member?)?)))
+            .collect::<RollerResult<Vec<_>>>()?;
+        for (member, node) in members.iter_mut().zip(nodes.into_iter().flatten()) {
+            member.node = node;
+        }
+
+        if augments.iter().any(|aug| {
+            matches!(
+                aug,
+                Augmentation::Explode { .. } | Augmentation::Compound { .. }
             )
-            .map(|dice| NaiveValue::Dice(dice.into_iter().collect()))
+        }) {
+            return Err(RollerError::GroupExplosion);
+        }
+
+        if augments
+            .iter()
+            .any(|aug| matches!(aug, Augmentation::Reroll { .. }))
+        {
+            return Err(RollerError::GroupReroll);
         }
+
+        let mut isolated = self.streams.as_mut().map(|s| s.group(members.len()));
+        let mut rng: &mut dyn RngCore = match &mut isolated {
+            Some(rng) => rng,
+            None => &mut self.rng,
+        };
+
+        let is_pool = augments.iter().any(Augmentation::is_counting);
+        let members = augment(
+            &mut rng,
+            members,
+            augments,
+            &self.options,
+            &self.augments,
+            Die::Plain(0),
+            &mut self.trace,
+            &mut self.usage,
+        )?
+        .into_iter()
+        .collect();
+
+        Ok(if is_pool {
+            NaiveValue::Pool(members)
+        } else {
+            NaiveValue::Dice(members)
+        })
     }
 
-    fn visit_constant(&mut self, c: Integer) -> NaiveResult {
-        Ok(NaiveValue::Constant(i64::try_from(c).unwrap()))
+    fn visit_repeat(&mut self, count: NaiveResult, expr: &Expression) -> NaiveResult {
+        let count = self.integer(count?)?;
+        if count < 0 {
+            return Err(RollerError::InvalidRepetition { count });
+        }
+
+        // Every repetition visits at least its expression and rolls at
+        // least its fewest dice, a count past the fuel or the limits fails
+        // before the first one is rolled
+        if self.usage.fuel.is_some_and(|left| count as u64 > left) {
+            return Err(RollerError::OutOfFuel {
+                fuel: self.options.fuel().unwrap_or_default(),
+            });
+        }
+        if let Some(max) = self.options.max_total_dice() {
+            let dice = fewest_dice(expr, &self.options).saturating_mul(count as usize);
+            if self.usage.dice_rolled.saturating_add(dice) > max {
+                return Err(RollerError::LimitExceeded {
+                    limit: Limit::TotalDice,
+                    max,
+                });
+            }
+        }
+
+        // Without the limits the values may not fit into the memory
+        let too_large = || RollerError::ValueTooLarge {
+            value: count.into(),
+        };
+        let mut values = vec![];
+        values
+            .try_reserve_exact(usize::try_from(count).map_err(|_| too_large())?)
+            .map_err(|_| too_large())?;
+        for _ in 0..count {
+            values.push(self.visit(expr)?);
+        }
+
+        Ok(NaiveValue::List(values))
+    }
+
+    fn visit_conditional(
+        &mut self,
+        condition: NaiveResult,
+        then: &Expression,
+        otherwise: &Expression,
+    ) -> NaiveResult {
+        if self.integer(condition?)? != 0 {
+            self.visit(then)
+        } else {
+            self.visit(otherwise)
+        }
+    }
+
+    fn visit_call(&mut self, function: &FunctionName, args: Vec<NaiveResult>) -> NaiveResult {
+        let value = self.call(function, args);
+        if let Some(provenance) = &mut self.provenance {
+            provenance.close_reference();
+        }
+
+        value
+    }
+
+    fn visit_reference(&mut self, name: &MacroName) -> NaiveResult {
+        // The registry never holds a cycle, so this terminates.
+        // The body is cloned since rolling it needs the roller mutably.
+        let body = self
+            .options
+            .macros()
+            .get(name)
+            .cloned()
+            .ok_or_else(|| RollerError::UnknownMacro { name: name.clone() });
+
+        let value = body.and_then(|body| {
+            self.expand_trace(&body);
+            let value = self.visit(&body);
+            self.close_trace_expansion();
+
+            value
+        });
+        if let Some(provenance) = &mut self.provenance {
+            provenance.close_reference();
+        }
+
+        value
+    }
+
+    fn visit_constant(&mut self, c: &Integer) -> NaiveResult {
+        constant(c.clone())
+    }
+
+    fn visit_decimal(&mut self, d: &Decimal) -> NaiveResult {
+        let exact = d.to_ratio().ok_or_else(|| RollerError::ValueTooLarge {
+            value: d.digits.clone().into(),
+        })?;
+
+        self.decimal(exact, false)
     }
 
     fn visit_binop(
@@ -344,8 +1381,12 @@ impl<R: Rng> Visitor<NaiveResult> for NaiveRoller<R> {
         let lhs = lhs?;
         let rhs = rhs?;
 
-        let lhs_total = lhs.total();
-        let rhs_total = rhs.total();
+        if matches!(lhs, NaiveValue::Decimal { .. }) || matches!(rhs, NaiveValue::Decimal { .. }) {
+            return self.decimal_binop(op, lhs, rhs);
+        }
+
+        let lhs_total = lhs.total()?;
+        let rhs_total = rhs.total()?;
 
         let from_int = |x: i64| Ok(NaiveValue::Constant(x));
 
@@ -361,18 +1402,524 @@ impl<R: Rng> Visitor<NaiveResult> for NaiveRoller<R> {
     }
 
     fn visit_negation(&mut self, value: NaiveResult) -> NaiveResult {
-        value.map(|value| NaiveValue::Constant(-value.total()))
+        value.and_then(|value| match value {
+            NaiveValue::Decimal {
+                exact,
+                rounding,
+                precision,
+            } => Ok(NaiveValue::Decimal {
+                exact: -exact,
+                rounding,
+                precision,
+            }),
+            value => value
+                .total()?
+                .checked_neg()
+                .map(NaiveValue::Constant)
+                .ok_or(RollerError::Overflow),
+        })
     }
 
     fn default_power(&self) -> NaiveResult {
-        Ok(NaiveValue::Constant(
-            i64::try_from(self.options.power()).unwrap(),
-        ))
+        constant(self.options.power().into())
     }
 
     fn default_quantity(&self) -> NaiveResult {
-        Ok(NaiveValue::Constant(
-            i64::try_from(self.options.quantity()).unwrap(),
-        ))
+        constant(self.options.quantity().into())
+    }
+}
+
+// The constants are arbitrarily large, the naive roller only rolls the ones
+// that fit
+fn constant(c: Integer) -> NaiveResult {
+    match i64::try_from(&c) {
+        Ok(c) => Ok(NaiveValue::Constant(c)),
+        Err(_) => Err(RollerError::ValueTooLarge { value: c }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        interpreter::{NaiveValue, RollerError},
+        prelude::{parse, StandardNaiveRoller},
+    };
+
+    #[test]
+    fn test_net_hits() {
+        let mut roller = StandardNaiveRoller::new_seeded(0);
+
+        for _ in 0..100 {
+            let value = roller.roll_naive(parse("10d10s>=8f<=1").unwrap()).unwrap();
+            let hits = value.hits().unwrap();
+
+            assert!(hits.successes + hits.failures <= 10);
+            assert_eq!(value.total().unwrap(), hits.successes - hits.failures);
+        }
+
+        let value = roller.roll_naive(parse("10d10s>10f<1").unwrap()).unwrap();
+        assert_eq!(value.total().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_group_truncation() {
+        let mut roller = StandardNaiveRoller::new_seeded(0);
+
+        let value = roller
+            .roll_naive(parse("{3, 10, 2 + 5}kh").unwrap())
+            .unwrap();
+        assert_eq!(value.total().unwrap(), 10);
+
+        let value = roller
+            .roll_naive(parse("{3, 10, 2 + 5}dh").unwrap())
+            .unwrap();
+        assert_eq!(value.total().unwrap(), 10);
+
+        let value = roller
+            .roll_naive(parse("{3, 10, 2 + 5}dl2").unwrap())
+            .unwrap();
+        assert_eq!(value.total().unwrap(), 10);
+
+        assert!(roller.roll_naive(parse("{3, 10}dl3").unwrap()).is_err());
+
+        let value = roller
+            .roll_naive(parse("{3, 10, 2 + 5}s>5").unwrap())
+            .unwrap();
+        assert_eq!(value.total().unwrap(), 2);
+
+        for _ in 0..100 {
+            let value = roller
+                .roll_naive(parse("{2d6, 1d8 + 3}kh1").unwrap())
+                .unwrap();
+            assert!((4..=12).contains(&value.total().unwrap()));
+        }
+
+        assert!(roller.roll_naive(parse("{d6, d6}!").unwrap()).is_err());
+
+        // Every member fits, their sum doesn't
+        let value = crate::roll_str("{9223372036854775807, 9223372036854775807}").unwrap();
+        assert_eq!(value.total(), Err(RollerError::Overflow));
+        assert!(matches!(
+            roller.roll_naive(parse("{9223372036854775807, 1} + 0").unwrap()),
+            Err(RollerError::Overflow)
+        ));
+    }
+
+    #[test]
+    fn test_min_max() {
+        let mut roller = StandardNaiveRoller::new_seeded(0);
+
+        let value = roller
+            .roll_naive(parse("max(3, 1 + 5, 2) * 2").unwrap())
+            .unwrap();
+        assert_eq!(value.total().unwrap(), 12);
+
+        let value = roller
+            .roll_naive(parse("min(3, 1 + 5, 2)").unwrap())
+            .unwrap();
+        assert_eq!(value.total().unwrap(), 2);
+
+        for _ in 0..100 {
+            let value = roller.roll_naive(parse("max(d4, 3)").unwrap()).unwrap();
+            assert!((3..=4).contains(&value.total().unwrap()));
+        }
+    }
+
+    #[test]
+    fn test_call() {
+        let mut roller = StandardNaiveRoller::new_seeded(0);
+
+        let value = roller
+            .roll_naive(parse("abs(2 - 10) + floor(3)").unwrap())
+            .unwrap();
+        assert_eq!(value.total().unwrap(), 11);
+
+        assert!(roller.roll_naive(parse("abs(1, 2)").unwrap()).is_err());
+        assert!(roller.roll_naive(parse("frobnicate(1)").unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_macros() {
+        let mut roller = StandardNaiveRoller::new_seeded(0);
+        roller.options_mut().macros_mut().load("bonus := 3 + 4").unwrap();
+
+        let value = roller.roll_naive(parse("@bonus * 2").unwrap()).unwrap();
+        assert_eq!(value.total().unwrap(), 14);
+
+        assert!(roller.roll_naive(parse("@missing").unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_roll_borrowed() {
+        let expr = parse("6x(4d6kh3) + {d20, d20}kh1").unwrap();
+
+        let mut borrowed = StandardNaiveRoller::new_seeded(5);
+        let mut owned = StandardNaiveRoller::new_seeded(5);
+        for _ in 0..10 {
+            assert_eq!(
+                borrowed.roll_naive(&expr).unwrap().total(),
+                owned.roll_naive(expr.clone()).unwrap().total()
+            );
+        }
+    }
+
+    #[test]
+    fn test_repeat() {
+        let mut roller = StandardNaiveRoller::new_seeded(0);
+
+        let Ok(NaiveValue::List(stats)) = roller.roll_naive(parse("6x(4d6kh3)").unwrap()) else {
+            panic!()
+        };
+
+        assert_eq!(stats.len(), 6);
+        assert!(stats.iter().all(|v| (3..=18).contains(&v.total().unwrap())));
+
+        assert!(roller.roll_naive(parse("(0 - 1)x(d6)").unwrap()).is_err());
+
+        // Every roll fits, their sum doesn't
+        let rolls = roller
+            .roll_naive(parse("2x(9223372036854775807)").unwrap())
+            .unwrap();
+        assert_eq!(rolls.total(), Err(RollerError::Overflow));
+        let rolls = roller
+            .roll(parse("2x(9223372036854775807)").unwrap())
+            .unwrap();
+        assert_eq!(rolls.total(), Err(RollerError::Overflow));
+        assert!(matches!(
+            roller.roll_naive(parse("2x(9223372036854775807) + 0").unwrap()),
+            Err(RollerError::Overflow)
+        ));
+    }
+
+    #[test]
+    fn test_decimal() {
+        use num::rational::Ratio;
+
+        use crate::{
+            interpreter::Rounded,
+            options::{RollerOptions, Rounding},
+        };
+
+        let roll = |input, options: RollerOptions| {
+            StandardNaiveRoller::new_seeded(0)
+                .with_options(options)
+                .roll_naive(parse(input).unwrap())
+                .and_then(|value| value.total())
+        };
+        let default = RollerOptions::default;
+        let half_up = || RollerOptions::default().with_rounding(Rounding::HalfUp);
+
+        assert_eq!(roll("1.5 * 3", default()), Ok(4));
+        assert_eq!(roll("1.5 * 3", half_up()), Ok(5));
+        assert_eq!(roll("-1.5", default()), Ok(-2));
+        assert_eq!(roll("0.1 + 0.2 = 0.3", default()), Ok(1));
+        assert_eq!(roll("2.5 > 2", default()), Ok(1));
+
+        // Rounded once at the end, or after every operation
+        assert_eq!(roll("1.5 * 3 + 1.5 * 3", default()), Ok(9));
+        let each_step = default().with_round_each_step(true);
+        assert_eq!(roll("1.5 * 3 + 1.5 * 3", each_step), Ok(8));
+
+        // The builtins round their own way
+        assert_eq!(roll("floor(2.5) + ceil(2.5) + round(2.5)", half_up()), Ok(8));
+        assert_eq!(roll("round(-2.5)", default()), Ok(-2));
+        // The quantities are totals, rounded the usual way
+        assert_eq!(roll("2.9d1", default()), Ok(2));
+
+        for _ in 0..100 {
+            let total = roll("1.5 * 2d6", default()).unwrap();
+            assert!((3..=18).contains(&total));
+        }
+
+        assert!(matches!(
+            StandardNaiveRoller::new_seeded(0).roll_naive(parse("0.5").unwrap()),
+            Ok(NaiveValue::Decimal { .. })
+        ));
+
+        // The roundings before the total are kept for the audits
+        let mut roller = StandardNaiveRoller::new_seeded(0)
+            .with_options(default().with_round_each_step(true).with_precision(1));
+        roller
+            .roll_naive(parse("1.5 * 3 + 2.9d1").unwrap())
+            .unwrap();
+        let rounded = |numer, denom, total| Rounded {
+            exact: Ratio::new(numer, denom),
+            total,
+            rounding: Rounding::Floor,
+        };
+        assert_eq!(roller.roundings(), [rounded(9, 2, 4), rounded(29, 10, 2)]);
+
+        let value = roller.roll_naive(parse("-0.25").unwrap()).unwrap();
+        assert!(roller.roundings().is_empty());
+        assert_eq!(value.rounding(), Some(Rounding::Floor));
+        assert_eq!(value.decimal_string().as_deref(), Some("-0.3"));
+
+        let decimal = |input, options: RollerOptions| {
+            let mut roller = StandardNaiveRoller::new_seeded(0).with_options(options);
+            roller
+                .roll_naive(parse(input).unwrap())
+                .unwrap()
+                .decimal_string()
+        };
+        assert_eq!(
+            decimal("2.25", half_up().with_precision(1)).as_deref(),
+            Some("2.3")
+        );
+        assert_eq!(decimal("1.5 * 3", default()).as_deref(), Some("4.50"));
+        assert_eq!(
+            decimal("2.5", default().with_precision(0)).as_deref(),
+            Some("2")
+        );
+        assert_eq!(decimal("2", default()), None);
+        assert_eq!(
+            roll("0.00000000000000000001", default()),
+            Err(RollerError::ValueTooLarge { value: 1.into() })
+        );
+    }
+
+    #[test]
+    fn test_weighted() {
+        let mut roller = StandardNaiveRoller::new_seeded(0);
+        let mut roll = |input| roller.roll_naive(parse(input).unwrap());
+
+        // The faces are off by six deviations about once in a hundred
+        // million runs
+        let dice = roll("40000d{1:3, 2:1, 7:0}").unwrap();
+        let ones = dice.dice().filter(|d| d.value == 1).count() as f64;
+        let twos = dice.dice().filter(|d| d.value == 2).count() as f64;
+        assert_eq!(ones + twos, 40000.);
+        assert!((ones - 30000.).abs() < 6. * 7500f64.sqrt());
+
+        let kept = roll("4d{1:1, 6:1}kh1").unwrap();
+        assert!([1, 6].contains(&kept.total().unwrap()));
+        assert_eq!(roll("3d{-2:1}").unwrap().total().unwrap(), -6);
+        assert_eq!(roll("-2d{5:1}").unwrap().total().unwrap(), -10);
+        // The highest face explodes
+        assert!(roll("d{1:1, 2:1}!").unwrap().dice().count() >= 1);
+        assert!(matches!(roll("d{1:0}"), Err(RollerError::NoFaces)));
+
+        // Any face fits, the sum of a few of them may not
+        let dice = roll("3d{9223372036854775807:1}").unwrap();
+        assert_eq!(dice.total(), Err(RollerError::Overflow));
+        assert!(matches!(
+            roll("3d{9223372036854775807:1} + 0"),
+            Err(RollerError::Overflow)
+        ));
+    }
+
+    #[test]
+    fn test_isolated_streams() {
+        let members = |input| {
+            let value = StandardNaiveRoller::new_seeded(3)
+                .with_isolated_streams()
+                .roll_naive(parse(input).unwrap())
+                .unwrap();
+            value.dice().map(|d| d.value).collect::<Vec<_>>()
+        };
+
+        let before = members("{d20, 4d6, 4d6}");
+        let after = members("{3d8, d20, d4 + 1, 4d6, 4d6, 4d6}");
+        assert_eq!(before, [after[1], after[3], after[4]]);
+
+        // The next roll gets different streams
+        let mut roller = StandardNaiveRoller::new_seeded(3).with_isolated_streams();
+        let rolls = (0..20)
+            .map(|_| {
+                roller
+                    .roll_naive(parse("d1000").unwrap())
+                    .unwrap()
+                    .total()
+                    .unwrap()
+            })
+            .collect::<std::collections::HashSet<_>>();
+        assert!(rolls.len() > 1);
+    }
+
+    #[test]
+    fn test_conditional() {
+        let mut roller = StandardNaiveRoller::new_seeded(0);
+        let mut roll = |input| {
+            roller
+                .roll_naive(parse(input).unwrap())
+                .and_then(|v| v.total())
+        };
+
+        assert_eq!(roll("1 + 1 > 1 ? 10 : 20"), Ok(10));
+        assert_eq!(roll("0 ? 10 : 1 ? 20 : 30"), Ok(20));
+
+        // The other branch is not rolled
+        assert_eq!(roll("1 ? 5 : @missing"), Ok(5));
+        assert!(roll("0 ? 5 : @missing").is_err());
+    }
+
+    #[test]
+    fn test_limits() {
+        use crate::{
+            interpreter::{Limit, RollerError},
+            prelude::RollerOptions,
+        };
+
+        let roll = |options: RollerOptions, input| {
+            StandardNaiveRoller::new_seeded(0)
+                .with_options(options)
+                .roll_naive(parse(input).unwrap())
+                .and_then(|v| v.total())
+        };
+        let exceeded = |limit, max| Err(RollerError::LimitExceeded { limit, max });
+
+        let options = RollerOptions::default().with_max_dice_per_term(100);
+        assert!(roll(options.clone(), "100d20 + 50d6").is_ok());
+        assert_eq!(
+            roll(options, "10000000d20"),
+            exceeded(Limit::DicePerTerm, 100)
+        );
+
+        let options = RollerOptions::default().with_max_total_dice(10);
+        assert!(roll(options.clone(), "4d6 + 6d6").is_ok());
+        assert_eq!(roll(options.clone(), "4d6 + 7d6"), exceeded(Limit::TotalDice, 10));
+        assert_eq!(roll(options.clone(), "3x(4d6)"), exceeded(Limit::TotalDice, 10));
+        // The repetitions are counted before any of them is rolled, the
+        // dice that may not be rolled are not
+        let mut roller = StandardNaiveRoller::new_seeded(0)
+            .with_options(options.clone())
+            .with_provenance();
+        let repeated = roller.roll_naive(parse("1000000000x(d6)").unwrap());
+        assert_eq!(
+            repeated.and_then(|v| v.total()),
+            exceeded(Limit::TotalDice, 10)
+        );
+        assert!(roller.provenance().unwrap().dice().is_empty());
+        assert!(roll(options.clone(), "9x(d6 > 7 ? d6 : 0d6)").is_ok());
+        assert!(roll(options.clone(), "2x(2x(2d6) + 1d6)").is_ok());
+        // Every exploding d1 rolls another dice
+        assert_eq!(
+            roll(options.clone(), "10d1!"),
+            exceeded(Limit::TotalDice, 10)
+        );
+        // The chained explosions are counted as they are rolled
+        let chained = options.with_chain_explosions(true);
+        assert_eq!(
+            roll(chained, "d{1:1, 2:1000000}!>=2"),
+            exceeded(Limit::TotalDice, 10)
+        );
+
+        let options = RollerOptions::default()
+            .with_chain_explosions(true)
+            .with_max_explosion_depth(50);
+        assert_eq!(roll(options.clone(), "d1!"), exceeded(Limit::ExplosionDepth, 50));
+        assert!(roll(options, "d20!").is_ok());
+
+        // Each roll starts counting anew
+        let mut roller = StandardNaiveRoller::new_seeded(0)
+            .with_options(RollerOptions::default().with_max_total_dice(10));
+        for _ in 0..5 {
+            assert!(roller.roll_naive(parse("10d6").unwrap()).is_ok());
+        }
+
+        // Without the limits the dice that don't fit into the memory fail
+        // instead of aborting
+        assert_eq!(
+            roll(RollerOptions::default(), "9223372036854775807d6"),
+            Err(RollerError::ValueTooLarge {
+                value: i64::MAX.into()
+            })
+        );
+    }
+
+    #[test]
+    fn test_fuel() {
+        use crate::{
+            dialect::Roll20,
+            interpreter::RollerError,
+            prelude::{parse_dialect, RollerOptions},
+        };
+
+        let roll = |fuel, input| {
+            StandardNaiveRoller::new_seeded(0)
+                .with_options(RollerOptions::default().with_fuel(fuel))
+                .roll_naive(parse(input).unwrap())
+                .and_then(|v| v.total())
+        };
+
+        // Four nodes and a single dice
+        assert!(roll(5, "d20 + 5").is_ok());
+        assert_eq!(roll(4, "d20 + 5"), Err(RollerError::OutOfFuel { fuel: 4 }));
+        assert_eq!(
+            roll(1000, "1000x(d6)"),
+            Err(RollerError::OutOfFuel { fuel: 1000 })
+        );
+        assert_eq!(
+            roll(1000, "9223372036854775807x(1)"),
+            Err(RollerError::OutOfFuel { fuel: 1000 })
+        );
+        assert_eq!(
+            roll(1000, "100000d6"),
+            Err(RollerError::OutOfFuel { fuel: 1000 })
+        );
+        // The rerolls burn the fuel as they are rolled
+        let rerolled = StandardNaiveRoller::new_seeded(0)
+            .with_options(RollerOptions::default().with_fuel(100))
+            .roll_naive(parse_dialect("d100000r<99999", &Roll20).unwrap());
+        assert_eq!(rerolled.err(), Some(RollerError::OutOfFuel { fuel: 100 }));
+
+        // Every roll gets the whole fuel
+        let mut roller = StandardNaiveRoller::new_seeded(0)
+            .with_options(RollerOptions::default().with_fuel(20));
+        for _ in 0..10 {
+            assert!(roller.roll_naive(parse("4d6kh3 + 2").unwrap()).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_fixed_dice() {
+        use crate::{
+            dialect::Roll20,
+            options::{FixedDice, RollerOptions},
+            prelude::parse_dialect,
+        };
+
+        let mut roller = StandardNaiveRoller::new_seeded(0);
+        let mut roll = |input, fixed| {
+            roller
+                .roll_fixed(parse(input).unwrap(), fixed)
+                .and_then(|v| v.total())
+        };
+
+        assert_eq!(roll("d20 + 5", FixedDice::Midpoint), Ok(15));
+        assert_eq!(roll("d20", FixedDice::Constant(20)), Ok(20));
+        assert_eq!(roll("3d6", FixedDice::Constant(10)), Ok(18));
+        assert_eq!(roll("4d6kh3", FixedDice::Minimum), Ok(3));
+        assert_eq!(roll("2d8 - d4", FixedDice::Maximum), Ok(12));
+        assert_eq!(roll("-2d6", FixedDice::Maximum), Ok(-12));
+        assert_eq!(roll("d{-1:1, 0:1, 1:1}", FixedDice::Minimum), Ok(-1));
+        assert_eq!(roll("d{2:1, 9:5}", FixedDice::Midpoint), Ok(5));
+        // Nothing is rolled again
+        assert_eq!(roll("d6!", FixedDice::Maximum), Ok(6));
+        let reroll = parse_dialect("d20r1", &Roll20).unwrap();
+        assert_eq!(
+            roller
+                .roll_fixed(reroll, FixedDice::Minimum)
+                .and_then(|v| v.total()),
+            Ok(1)
+        );
+
+        // The options are left as they were
+        assert_eq!(roller.options().fixed_dice(), None);
+
+        let mut passive = StandardNaiveRoller::new_seeded(0).with_options(
+            RollerOptions::builder()
+                .fixed_dice(FixedDice::Midpoint)
+                .build(),
+        );
+        for _ in 0..10 {
+            assert_eq!(
+                passive
+                    .roll_naive(parse("d20 + 4").unwrap())
+                    .unwrap()
+                    .total()
+                    .unwrap(),
+                14
+            );
+        }
     }
 }
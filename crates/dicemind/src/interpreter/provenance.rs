@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::syntax::Expression;
+
+use super::{NaiveValue, TaggedDiceRoll};
+
+/// A node of an expression, numbered in the order the nodes are written
+/// with the expression itself first. The same expression always numbers
+/// its nodes the same way, the ids survive serializing both the
+/// expression and the result.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct NodeId(pub u32);
+
+impl Expression {
+    /// Every node of the expression, the index of a node is its `NodeId`
+    pub fn nodes(&self) -> Vec<&Expression> {
+        let mut nodes = vec![];
+        let mut stack = vec![self];
+
+        while let Some(expr) = stack.pop() {
+            nodes.push(expr);
+            stack.extend(expr.children().into_iter().rev());
+        }
+
+        nodes
+    }
+
+    pub fn node(&self, id: NodeId) -> Option<&Expression> {
+        self.nodes().get(id.0 as usize).copied()
+    }
+}
+
+impl NaiveValue {
+    /// The dice of the value rolled by the node. The sums keep no dice, a
+    /// roller `with_provenance` has all of them.
+    pub fn dice_of(&self, node: NodeId) -> impl Iterator<Item = &TaggedDiceRoll> {
+        self.dice().filter(move |d| d.node == Some(node))
+    }
+}
+
+/// Every dice of the last roll with the node that rolled it, for the front
+/// ends to show which part of `2d20kh1 + 4d6!` rolled which numbers. The
+/// expressions of the macros are not a part of the rolled expression, so
+/// their dice belong to the reference.
+#[derive(Debug, Clone, Default)]
+pub struct Provenance {
+    dice: Vec<TaggedDiceRoll>,
+    // The nodes are told apart by their addresses
+    ids: HashMap<usize, NodeId>,
+    // For every dice or group entered, the node of every dice it rolls
+    open: Vec<Vec<Option<NodeId>>>,
+    expanding: Vec<Option<NodeId>>,
+}
+
+impl Provenance {
+    /// The dice in the order they were rolled, with the discarded ones
+    pub fn dice(&self) -> &[TaggedDiceRoll] {
+        &self.dice
+    }
+
+    /// The dice rolled by the node, `2d20kh1` is `NodeId(1)` of `2d20kh1 + 4d6!`
+    pub fn dice_of(&self, node: NodeId) -> impl Iterator<Item = &TaggedDiceRoll> {
+        self.dice.iter().filter(move |d| d.node == Some(node))
+    }
+
+    pub(crate) fn start(&mut self, expr: &Expression) {
+        self.ids = expr
+            .nodes()
+            .into_iter()
+            .enumerate()
+            .map(|(i, node)| (node as *const _ as usize, NodeId(i as u32)))
+            .collect();
+        self.dice.clear();
+        self.open.clear();
+        self.expanding.clear();
+    }
+
+    fn id(&self, expr: &Expression) -> Option<NodeId> {
+        self.ids
+            .get(&(expr as *const _ as usize))
+            .copied()
+            .or_else(|| self.expanding.last().copied().flatten())
+    }
+
+    pub(crate) fn enter(&mut self, expr: &Expression) {
+        match expr {
+            Expression::Dice { .. } | Expression::Weighted { .. } => {
+                self.open.push(vec![self.id(expr)])
+            }
+            Expression::Group { members, .. } => {
+                let members = members.iter().map(|member| self.id(member)).collect();
+                self.open.push(members);
+            }
+            // The dice of a macro or of a builtin like `step` are the
+            // node's own
+            Expression::Reference(_) | Expression::Call { .. } => {
+                self.expanding.push(self.id(expr))
+            }
+            _ => {}
+        }
+    }
+
+    // The nodes of the dice or the members of the group closed last
+    pub(crate) fn close(&mut self) -> Vec<Option<NodeId>> {
+        self.open.pop().unwrap_or_default()
+    }
+
+    pub(crate) fn record(&mut self, dice: &[TaggedDiceRoll]) {
+        self.dice.extend_from_slice(dice);
+    }
+
+    pub(crate) fn close_reference(&mut self) {
+        self.expanding.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        interpreter::{NodeId, StandardNaiveRoller},
+        macros::MacroRegistry,
+        options::RollerOptions,
+        parser::parse,
+    };
+
+    #[test]
+    fn test_provenance() {
+        let expr = parse("2d20kh1 + 4d6!").unwrap();
+        let ids: Vec<_> = expr.nodes().iter().map(ToString::to_string).collect();
+        assert_eq!(
+            ids,
+            vec!["2d20kh1 + 4d6!", "2d20kh1", "2", "20", "4d6!", "4", "6"]
+        );
+        assert_eq!(expr.node(NodeId(4)).unwrap().to_string(), "4d6!");
+        assert_eq!(expr.node(NodeId(7)), None);
+
+        let mut roller = StandardNaiveRoller::new_seeded(3).with_provenance();
+        roller.roll_naive(&expr).unwrap();
+        let provenance = roller.provenance().unwrap();
+        let kept = provenance.dice_of(NodeId(1)).filter(|d| !d.is_discarded());
+        assert_eq!(kept.count(), 1);
+        assert!(provenance.dice_of(NodeId(4)).count() >= 4);
+        assert_eq!(
+            provenance.dice_of(NodeId(1)).count() + provenance.dice_of(NodeId(4)).count(),
+            provenance.dice().len()
+        );
+
+        // Without it, the dice come from nowhere
+        let value = StandardNaiveRoller::new_seeded(3)
+            .roll_naive(parse("4d6!").unwrap())
+            .unwrap();
+        assert!(value.dice().all(|d| d.node.is_none()));
+        let value = roller.roll_naive(parse("4d6!").unwrap()).unwrap();
+        assert!(value.dice_of(NodeId(0)).count() >= 4);
+
+        // The members of a group are its dice
+        let expr = parse("{d20 + 2, 3d4}kh1").unwrap();
+        let value = roller.roll_naive(&expr).unwrap();
+        let nodes: Vec<_> = value.dice().map(|d| d.node).collect();
+        assert_eq!(nodes, vec![Some(NodeId(1)), Some(NodeId(5))]);
+        let provenance = roller.provenance().unwrap();
+        let nodes: Vec<_> = provenance.dice().iter().map(|d| d.node).collect();
+        assert_eq!(
+            nodes,
+            [[Some(NodeId(2))].as_slice(), &[Some(NodeId(5)); 3]].concat()
+        );
+
+        // The dice of a macro belong to its reference
+        let mut macros = MacroRegistry::default();
+        macros.define("attack", parse("d20 + 7").unwrap()).unwrap();
+        let mut roller = StandardNaiveRoller::new_seeded(0)
+            .with_options(RollerOptions::builder().macros(macros).build())
+            .with_provenance();
+        roller.roll_naive(parse("@attack + d4").unwrap()).unwrap();
+        let provenance = roller.provenance().unwrap();
+        let nodes: Vec<_> = provenance.dice().iter().map(|d| d.node).collect();
+        assert_eq!(nodes, vec![Some(NodeId(1)), Some(NodeId(2))]);
+    }
+}
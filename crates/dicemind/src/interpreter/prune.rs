@@ -0,0 +1,149 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use super::{Hits, NaiveValue, RollerResult};
+
+/// How much detail a pruned value keeps
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct PruneOptions {
+    /// Pools with more dice are summarized
+    pub max_dice: usize,
+    /// Lists with more rolls are summarized as a whole
+    pub max_list: usize,
+}
+
+impl Default for PruneOptions {
+    fn default() -> Self {
+        Self {
+            max_dice: 20,
+            max_list: 100,
+        }
+    }
+}
+
+/// What is left of the dice after pruning, enough to show the roll
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct RollSummary {
+    pub total: i64,
+    /// Every dice that was rolled, including the discarded ones
+    pub dice: usize,
+    pub discarded: usize,
+    /// The lowest and the highest of the kept dice
+    pub lowest: Option<i64>,
+    pub highest: Option<i64>,
+    /// Set if the dice were a pool
+    pub hits: Option<Hits>,
+}
+
+impl RollSummary {
+    pub fn of(value: &NaiveValue) -> RollerResult<Self> {
+        let kept = || value.dice().filter(|d| !d.is_discarded()).map(|d| d.value);
+
+        Ok(Self {
+            total: value.total()?,
+            dice: value.dice().count(),
+            discarded: value.dice().filter(|d| d.is_discarded()).count(),
+            lowest: kept().min(),
+            highest: kept().max(),
+            hits: value.hits(),
+        })
+    }
+}
+
+/// `42 from 12 dice (3 discarded, 1..6)`
+impl Display for RollSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} from {} dice", self.total, self.dice)?;
+
+        let mut details = vec![];
+        if self.discarded > 0 {
+            details.push(format!("{} discarded", self.discarded));
+        }
+        if let Some(hits) = self.hits {
+            details.push(format!("{} hits, {} misses", hits.successes, hits.failures));
+        }
+        if let (Some(lowest), Some(highest)) = (self.lowest, self.highest) {
+            details.push(format!("{lowest}..{highest}"));
+        }
+
+        if !details.is_empty() {
+            write!(f, " ({})", details.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl NaiveValue {
+    /// A smaller value with the same total, the big pools and lists are
+    /// replaced with their summaries. Meant for keeping the history of
+    /// long sessions without storing every dice of them. A value whose
+    /// total doesn't fit is kept whole.
+    pub fn prune(&self, options: &PruneOptions) -> NaiveValue {
+        match self {
+            NaiveValue::Dice(dice) | NaiveValue::Pool(dice) if dice.len() > options.max_dice => {
+                RollSummary::of(self).map_or_else(|_| self.clone(), NaiveValue::Summary)
+            }
+            NaiveValue::List(values) if values.len() > options.max_list => {
+                RollSummary::of(self).map_or_else(|_| self.clone(), NaiveValue::Summary)
+            }
+            NaiveValue::List(values) => {
+                NaiveValue::List(values.iter().map(|v| v.prune(options)).collect())
+            }
+            _ => self.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        interpreter::{NaiveValue, PruneOptions, StandardNaiveRoller},
+        parser::parse,
+    };
+
+    #[test]
+    fn test_prune() {
+        let mut roller = StandardNaiveRoller::new_seeded(0);
+        let options = PruneOptions {
+            max_dice: 10,
+            max_list: 5,
+        };
+
+        let small = roller.roll_naive(parse("4d6kh3").unwrap()).unwrap();
+        assert!(matches!(small.prune(&options), NaiveValue::Dice(dice) if dice.len() == 4));
+
+        let big = roller.roll_naive(parse("100d6kh50").unwrap()).unwrap();
+        let NaiveValue::Summary(summary) = big.prune(&options) else {
+            panic!("100 dice are not summarized");
+        };
+        assert_eq!(summary.total, big.total().unwrap());
+        assert_eq!((summary.dice, summary.discarded), (100, 50));
+        assert!(summary.lowest.unwrap() >= 1 && summary.highest.unwrap() <= 6);
+        assert!(summary.to_string().starts_with(&format!(
+            "{} from 100 dice (50 discarded, ",
+            big.total().unwrap()
+        )));
+
+        let pool = roller.roll_naive(parse("30d10s>=8").unwrap()).unwrap();
+        let pruned = pool.prune(&options);
+        assert_eq!(pruned.total(), pool.total());
+        assert_eq!(pruned.hits(), pool.hits());
+
+        let stats = roller.roll_naive(parse("3x(20d6)").unwrap()).unwrap();
+        let NaiveValue::List(pruned) = stats.prune(&options) else {
+            panic!("short lists are kept");
+        };
+        assert!(pruned.iter().all(|v| matches!(v, NaiveValue::Summary(_))));
+
+        let session = roller.roll_naive(parse("1000x(d20)").unwrap()).unwrap();
+        let pruned = session.prune(&options);
+        assert_eq!(pruned.total(), session.total());
+        assert_eq!(pruned.dice().count(), 0);
+        assert!(
+            serde_json::to_vec(&pruned).unwrap().len() * 100
+                < serde_json::to_vec(&session).unwrap().len()
+        );
+    }
+}
@@ -0,0 +1,315 @@
+use std::borrow::Borrow;
+
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+use crate::syntax::Expression;
+
+use super::{NaiveResult, NaiveRoller, RollerError};
+
+/// A number drawn from a generator, in the width it was drawn in
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Draw {
+    U32(u32),
+    U64(u64),
+    Bytes(Vec<u8>),
+}
+
+/// Everything a roll drew from its generator. Rolling the same expression
+/// from it with a `ReplayRoller` rolls the very same dice, for the save
+/// files and settling the disputes.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Recording {
+    /// The expression rolled, if the recording was made by `record`
+    #[serde(default)]
+    pub expression: Option<Expression>,
+    pub draws: Vec<Draw>,
+}
+
+/// Draws from the generator and writes down every number
+#[derive(Debug, Clone)]
+pub struct Recorder<R: RngCore> {
+    rng: R,
+    recording: Recording,
+}
+
+impl<R: RngCore> Recorder<R> {
+    pub fn new(rng: R) -> Self {
+        Self {
+            rng,
+            recording: Recording::default(),
+        }
+    }
+
+    pub fn recording(&self) -> &Recording {
+        &self.recording
+    }
+
+    /// The recording so far, the recorder starts over
+    pub fn take(&mut self) -> Recording {
+        std::mem::take(&mut self.recording)
+    }
+}
+
+impl<R: RngCore> RngCore for Recorder<R> {
+    fn next_u32(&mut self) -> u32 {
+        let n = self.rng.next_u32();
+        self.recording.draws.push(Draw::U32(n));
+        n
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let n = self.rng.next_u64();
+        self.recording.draws.push(Draw::U64(n));
+        n
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest);
+        if !dest.is_empty() {
+            self.recording.draws.push(Draw::Bytes(dest.to_vec()));
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.rng.try_fill_bytes(dest)?;
+        if !dest.is_empty() {
+            self.recording.draws.push(Draw::Bytes(dest.to_vec()));
+        }
+        Ok(())
+    }
+}
+
+/// Hands out the numbers of a recording in order. Once the roll asks for
+/// something else than what was recorded it only draws zeroes, and the
+/// roller stops at the next dice it rolls again and tells it diverged.
+#[derive(Debug, Clone)]
+pub struct Replayer {
+    recording: Recording,
+    next: usize,
+    diverged: Option<usize>,
+}
+
+impl Replayer {
+    pub fn new(recording: Recording) -> Self {
+        Self {
+            recording,
+            next: 0,
+            diverged: None,
+        }
+    }
+
+    /// Where the roll stopped following the recording, if it did. A roll
+    /// that didn't draw everything diverged at the first draw it left.
+    pub fn diverged(&self) -> Option<usize> {
+        self.diverged
+            .or((self.next < self.recording.draws.len()).then_some(self.next))
+    }
+
+    fn draw(&mut self, matches: impl FnOnce(&Draw) -> bool) -> Option<&Draw> {
+        if self.diverged.is_some() {
+            return None;
+        }
+
+        match self.recording.draws.get(self.next) {
+            Some(draw) if matches(draw) => {
+                self.next += 1;
+                self.recording.draws.get(self.next - 1)
+            }
+            _ => {
+                self.diverged = Some(self.next);
+                None
+            }
+        }
+    }
+}
+
+impl RngCore for Replayer {
+    fn next_u32(&mut self) -> u32 {
+        match self.draw(|draw| matches!(draw, Draw::U32(_))) {
+            Some(Draw::U32(n)) => *n,
+            _ => 0,
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        match self.draw(|draw| matches!(draw, Draw::U64(_))) {
+            Some(Draw::U64(n)) => *n,
+            _ => 0,
+        }
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        if dest.is_empty() {
+            return;
+        }
+
+        // The dice drawn one by one were recorded a number each, a batch
+        // of them draws the very same numbers at once
+        let numbers = matches!(self.recording.draws.get(self.next), Some(Draw::U64(_)));
+        if numbers && dest.len() % 8 == 0 {
+            for bytes in dest.chunks_exact_mut(8) {
+                bytes.copy_from_slice(&self.next_u64().to_le_bytes());
+            }
+            return;
+        }
+
+        let len = dest.len();
+        match self.draw(|draw| matches!(draw, Draw::Bytes(bytes) if bytes.len() == len)) {
+            Some(Draw::Bytes(bytes)) => dest.copy_from_slice(bytes),
+            _ => dest.fill(0),
+        }
+    }
+
+    // Only an empty fill fails, `Rng::fill` panics on the others. The
+    // roller asks with one whether the replay can go on.
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        match self.diverged {
+            Some(at) if dest.is_empty() => {
+                Err(rand::Error::new(RollerError::ReplayDiverged { at }))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A roller drawing from the numbers of a recording, see `Recording`
+pub type ReplayRoller = NaiveRoller<Replayer>;
+
+impl<R: RngCore> NaiveRoller<Recorder<R>> {
+    /// What the rolls drew so far
+    pub fn recording(&self) -> &Recording {
+        self.rng().recording()
+    }
+
+    /// Roll and record only this roll, along with the expression so that
+    /// a replay of anything else fails right away
+    pub fn record(&mut self, expr: impl Borrow<Expression>) -> (NaiveResult, Recording) {
+        self.rng_mut().take();
+        let value = self.roll_naive(expr.borrow());
+
+        let recording = Recording {
+            expression: Some(expr.borrow().clone()),
+            ..self.rng_mut().take()
+        };
+        (value, recording)
+    }
+}
+
+impl ReplayRoller {
+    pub fn new_replay(recording: Recording) -> Self {
+        Self::from_rng(Replayer::new(recording))
+    }
+
+    /// Roll the expression the recording was made with. Any other fails
+    /// with `RollerError::ReplayDiverged`, even if it rolled fine. Without
+    /// the expression in the recording, only the draws are compared and a
+    /// `d12` can replay the draws of a `d20`.
+    pub fn replay(&mut self, expr: impl Borrow<Expression>) -> NaiveResult {
+        let recorded = self.rng().recording.expression.as_ref();
+        if recorded.is_some_and(|recorded| recorded != expr.borrow()) {
+            return Err(RollerError::ReplayDiverged { at: 0 });
+        }
+
+        let value = self.roll_naive(expr);
+
+        match self.rng().diverged() {
+            Some(at) => Err(RollerError::ReplayDiverged { at }),
+            None => value,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, RngCore, SeedableRng};
+
+    use crate::{
+        dialect::Roll20,
+        interpreter::{Draw, NaiveRoller, Recorder, Recording, ReplayRoller, RollerError},
+        parser::{parse, parse_dialect},
+    };
+
+    #[test]
+    fn test_replay() {
+        let expr = parse("4d6kh3 + 3d6! + d20").unwrap();
+        let mut recorder = NaiveRoller::from_rng(Recorder::new(StdRng::from_entropy()));
+        recorder.roll_naive(parse("d100").unwrap()).unwrap();
+        let (rolled, recording) = recorder.record(&expr);
+        let rolled = rolled.unwrap();
+
+        // Survives being saved
+        let saved = serde_json::to_string(&recording).unwrap();
+        assert_eq!(recording, serde_json::from_str(&saved).unwrap());
+        assert!(recorder.recording().draws.is_empty());
+
+        let replayed = ReplayRoller::new_replay(recording.clone())
+            .replay(&expr)
+            .unwrap();
+        assert_eq!(replayed.total(), rolled.total());
+        assert!(replayed
+            .dice()
+            .map(|d| d.value)
+            .eq(rolled.dice().map(|d| d.value)));
+
+        // More dice, fewer dice and other dice
+        let draws = Recording {
+            expression: None,
+            ..recording.clone()
+        };
+        for other in ["4d6kh3 + 3d6! + 2d20", "4d6kh3", "4d6kh3 + 3d6! + d12"] {
+            let replayed =
+                ReplayRoller::new_replay(recording.clone()).replay(parse(other).unwrap());
+            assert!(
+                matches!(replayed, Err(RollerError::ReplayDiverged { .. })),
+                "{other}"
+            );
+        }
+        for other in ["4d6kh3 + 3d6! + 2d20", "4d6kh3"] {
+            let replayed = ReplayRoller::new_replay(draws.clone()).replay(parse(other).unwrap());
+            assert!(
+                matches!(replayed, Err(RollerError::ReplayDiverged { .. })),
+                "{other}"
+            );
+        }
+        assert!(ReplayRoller::new_replay(draws).replay(&expr).is_ok());
+
+        let mut empty = ReplayRoller::new_replay(Recording::default());
+        assert_eq!(
+            empty.replay(parse("d6").unwrap()).err(),
+            Some(RollerError::ReplayDiverged { at: 0 })
+        );
+        let mut empty = ReplayRoller::new_replay(Recording::default());
+        assert_eq!(
+            empty
+                .replay(parse("2 + 2").unwrap())
+                .unwrap()
+                .total()
+                .unwrap(),
+            4
+        );
+
+        // Recorded a number for every dice, before they were batched
+        let mut rng = StdRng::seed_from_u64(3);
+        let numbers = Recording {
+            expression: None,
+            draws: (0..4).map(|_| Draw::U64(rng.next_u64())).collect(),
+        };
+        let expected = NaiveRoller::<StdRng>::new_seeded(3).roll_naive(parse("4d6").unwrap());
+        let replayed = ReplayRoller::new_replay(numbers).replay(parse("4d6").unwrap());
+        assert_eq!(replayed.unwrap().total(), expected.unwrap().total());
+
+        // The zeroes drawn after the recording would be rerolled forever
+        let mut empty = ReplayRoller::new_replay(Recording::default());
+        assert_eq!(
+            empty.replay(parse_dialect("d6r1", &Roll20).unwrap()).err(),
+            Some(RollerError::ReplayDiverged { at: 0 })
+        );
+        let expr = parse_dialect("3d6r1 + d6", &Roll20).unwrap();
+        let (_, mut recording) = recorder.record(&expr);
+        recording.draws.truncate(1);
+        let replayed = ReplayRoller::new_replay(recording).replay(&expr);
+        assert!(matches!(replayed, Err(RollerError::ReplayDiverged { .. })));
+    }
+}
@@ -0,0 +1,256 @@
+use std::fmt::Debug;
+
+use rand::{
+    rngs::{OsRng, StdRng},
+    CryptoRng, RngCore, SeedableRng,
+};
+
+/// A source of randomness picked at runtime, for when the type of the
+/// generator can't be spelled out. Everything the rollers need is there,
+/// `NaiveRoller::<DiceRng>::from_rng(DiceRng::os())` rolls with the OS.
+pub struct DiceRng(Box<dyn RngCore + Send>);
+
+impl DiceRng {
+    /// Any generator
+    pub fn new(rng: impl RngCore + Send + 'static) -> Self {
+        Self(Box::new(rng))
+    }
+
+    /// The generator of the thread, the one `rand::thread_rng` returns
+    pub fn thread() -> Self {
+        Self::new(Thread)
+    }
+
+    /// The randomness of the operating system, slower but unpredictable
+    pub fn os() -> Self {
+        Self::new(OsRng)
+    }
+
+    /// The same rolls every time for the same seed
+    pub fn seeded(seed: u64) -> Self {
+        Self::new(StdRng::seed_from_u64(seed))
+    }
+
+    /// The numbers the closure returns, for the tests and the sources that
+    /// aren't generators
+    pub fn from_fn(f: impl FnMut() -> u64 + Send + 'static) -> Self {
+        Self::new(FromFn(f))
+    }
+}
+
+impl Debug for DiceRng {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("DiceRng")
+    }
+}
+
+impl RngCore for DiceRng {
+    fn next_u32(&mut self) -> u32 {
+        self.0.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.0.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.0.try_fill_bytes(dest)
+    }
+}
+
+// Seeded and drawn from the entropy like the standard generator, so the
+// rollers construct it the usual way
+impl SeedableRng for DiceRng {
+    type Seed = <StdRng as SeedableRng>::Seed;
+
+    fn from_seed(seed: Self::Seed) -> Self {
+        Self::new(StdRng::from_seed(seed))
+    }
+
+    fn seed_from_u64(seed: u64) -> Self {
+        Self::seeded(seed)
+    }
+}
+
+/// A cryptographically secure generator seeded by the operating system.
+/// It can't be seeded, cloned or printed, so its rolls can't be replayed.
+pub struct SecureRng(StdRng);
+
+impl SecureRng {
+    pub fn new() -> Self {
+        Self(StdRng::from_rng(OsRng).expect("the operating system has no randomness"))
+    }
+}
+
+impl Default for SecureRng {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debug for SecureRng {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SecureRng")
+    }
+}
+
+impl RngCore for SecureRng {
+    fn next_u32(&mut self) -> u32 {
+        self.0.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.0.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.0.try_fill_bytes(dest)
+    }
+}
+
+impl CryptoRng for SecureRng {}
+
+// Looks up the generator of the thread on every call, so it can be sent
+// to another thread unlike `ThreadRng`
+struct Thread;
+
+impl RngCore for Thread {
+    fn next_u32(&mut self) -> u32 {
+        rand::thread_rng().next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        rand::thread_rng().next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        rand::thread_rng().fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        rand::thread_rng().try_fill_bytes(dest)
+    }
+}
+
+struct FromFn<F>(F);
+
+impl<F: FnMut() -> u64> RngCore for FromFn<F> {
+    fn next_u32(&mut self) -> u32 {
+        (self.0)() as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        (self.0)()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = (self.0)().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::OsRng;
+
+    use crate::{
+        interpreter::{DiceRng, NaiveRoller, RollerError, SecureRoller, StandardNaiveRoller},
+        options::RollerOptions,
+        parser::parse,
+    };
+
+    #[test]
+    fn test_dice_rng() {
+        let expr = parse("10d20 + d6").unwrap();
+        let total = |rng: DiceRng| {
+            NaiveRoller::from_rng(rng)
+                .roll_naive(&expr)
+                .unwrap()
+                .total()
+                .unwrap()
+        };
+
+        assert_eq!(
+            total(DiceRng::seeded(7)),
+            NaiveRoller::<DiceRng>::new_seeded(7)
+                .roll_naive(&expr)
+                .unwrap()
+                .total()
+                .unwrap()
+        );
+        assert_eq!(total(DiceRng::seeded(7)), total(DiceRng::seeded(7)));
+
+        for rng in [DiceRng::thread(), DiceRng::os()] {
+            assert!((11..=206).contains(&total(rng)));
+        }
+        let entropy = NaiveRoller::<DiceRng>::default().roll_naive(&expr).unwrap();
+        assert!((11..=206).contains(&entropy.total().unwrap()));
+
+        // The smallest number there is rolls ones
+        assert_eq!(total(DiceRng::from_fn(|| 0)), 11);
+
+        let mut n = 0;
+        let counting = DiceRng::from_fn(move || {
+            n += 1;
+            n << 40
+        });
+        assert!((11..=206).contains(&total(counting)));
+
+        fn sendable(_: impl Send) {}
+        sendable(NaiveRoller::from_rng(DiceRng::thread()));
+    }
+
+    #[test]
+    fn test_secure() {
+        let expr = parse("10d20 + d6").unwrap();
+        let secure = RollerOptions::default().with_secure(true);
+
+        let total = SecureRoller::new_secure()
+            .with_options(secure.clone())
+            .roll_naive(&expr)
+            .unwrap()
+            .total()
+            .unwrap();
+        assert!((11..=206).contains(&total));
+        assert!(NaiveRoller::from_secure_rng(OsRng)
+            .with_options(secure.clone())
+            .roll_naive(&expr)
+            .is_ok());
+
+        // Anything else has to be told apart
+        for mut roller in [
+            StandardNaiveRoller::new_seeded(0),
+            StandardNaiveRoller::default(),
+        ] {
+            roller = roller.with_options(secure.clone());
+            assert_eq!(
+                roller.roll_naive(&expr).err(),
+                Some(RollerError::InsecureRng)
+            );
+            assert_eq!(roller.roll(&expr).err(), Some(RollerError::InsecureRng));
+        }
+        let streams = SecureRoller::new_secure()
+            .with_options(secure)
+            .with_isolated_streams()
+            .roll_naive(&expr);
+        assert_eq!(streams.err(), Some(RollerError::InsecureRng));
+
+        // Not asking for it, every roller rolls
+        assert!(StandardNaiveRoller::new_seeded(0).roll_naive(&expr).is_ok());
+    }
+}
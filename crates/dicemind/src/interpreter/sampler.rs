@@ -0,0 +1,251 @@
+use rand::{Rng, RngCore};
+
+use crate::syntax::Face;
+
+use super::{RollerError, RollerResult};
+
+// How many numbers are drawn from the generator at once
+const BATCH: usize = 256;
+
+/// Rolls many dice of the same power in one pass. The numbers are drawn
+/// from the generator a whole batch at once and mapped to the faces the
+/// way `gen_range` maps them, so a seeded roller rolls the very same dice
+/// either way, only faster.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiceSampler {
+    power: i64,
+    range: u64,
+    // The largest low half of the product that isn't biased
+    zone: u64,
+}
+
+impl DiceSampler {
+    pub fn new(power: i64) -> Self {
+        let range = power.unsigned_abs();
+        Self {
+            power,
+            range,
+            zone: range
+                .checked_shl(range.leading_zeros())
+                .unwrap_or_default()
+                .wrapping_sub(1),
+        }
+    }
+
+    /// A single dice, `rng.gen_range(1..=power)` for the positive powers
+    pub fn sample(&self, rng: &mut (impl RngCore + ?Sized)) -> i64 {
+        if self.range == 0 {
+            return 0;
+        }
+
+        loop {
+            if let Some(face) = self.face(rng.next_u64()) {
+                return face;
+            }
+        }
+    }
+
+    /// Fill the dice with as many rolls, the same as `sample` for every
+    /// one of them
+    pub fn fill(&self, rng: &mut (impl RngCore + ?Sized), dice: &mut [i64]) {
+        if self.range == 0 {
+            dice.fill(0);
+            return;
+        }
+
+        let mut draws = [0u64; BATCH];
+        let mut filled = 0;
+        while filled < dice.len() {
+            // Every dice left needs at least a number, so the generator
+            // is never asked for more than rolling one by one would ask
+            let draws = &mut draws[..(dice.len() - filled).min(BATCH)];
+            rng.fill(draws);
+
+            for &draw in draws.iter() {
+                if let Some(face) = self.face(draw) {
+                    dice[filled] = face;
+                    filled += 1;
+                }
+            }
+        }
+    }
+
+    /// Fill the dice with as many rolls, packing several dice into every
+    /// number drawn. Only a fraction of the numbers are drawn, a hundred
+    /// `d6` take six, but the dice are not the ones `fill` rolls.
+    pub fn fill_packed(&self, rng: &mut (impl RngCore + ?Sized), dice: &mut [i64]) {
+        if self.range <= 1 {
+            dice.fill(self.power);
+            return;
+        }
+
+        // As many dice as fit into 48 bits, the rest of the number keeps
+        // the rejections rare
+        let range = self.range as u128;
+        let (mut per_draw, mut product) = (1, range);
+        while product * range <= 1 << 48 {
+            per_draw += 1;
+            product *= range;
+        }
+        let product = product as u64;
+        let threshold = product.wrapping_neg() % product;
+
+        for dice in dice.chunks_mut(per_draw) {
+            loop {
+                let mut draw = rng.next_u64();
+                for face in dice.iter_mut() {
+                    let product = draw as u128 * range;
+                    *face = (1 + (product >> 64) as i64) * self.power.signum();
+                    draw = product as u64;
+                }
+
+                // What is left of the number tells the biased ones apart
+                if draw >= threshold {
+                    break;
+                }
+            }
+        }
+    }
+
+    // Lemire's widening multiplication, rejecting the biased numbers
+    fn face(&self, draw: u64) -> Option<i64> {
+        let product = draw as u128 * self.range as u128;
+        let (hi, lo) = ((product >> 64) as u64, product as u64);
+
+        (lo <= self.zone).then(|| (1 + hi as i64) * self.power.signum())
+    }
+}
+
+/// Rolls the dice with weighted faces, every face comes up as often as
+/// its weight says
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WeightedSampler {
+    // Only the faces that can come up, in the order they are written
+    faces: Vec<i64>,
+    // The total weight of the faces up to and including every one
+    cumulative: Vec<u64>,
+}
+
+impl WeightedSampler {
+    /// Fails for the faces or the weights that don't fit into the machine
+    /// integers and for the dice without a single face that can come up
+    pub fn new(faces: &[Face]) -> RollerResult<Self> {
+        let mut sampler = Self {
+            faces: vec![],
+            cumulative: vec![],
+        };
+
+        let mut total = 0u64;
+        for face in faces {
+            let too_large = |value| RollerError::ValueTooLarge { value };
+            let weight = u64::try_from(&face.weight)
+                .map_err(|_| too_large(face.weight.clone().into()))?;
+            if weight == 0 {
+                continue;
+            }
+
+            total = total.checked_add(weight).ok_or(RollerError::Overflow)?;
+            sampler
+                .faces
+                .push(i64::try_from(&face.value).map_err(|_| too_large(face.value.clone()))?);
+            sampler.cumulative.push(total);
+        }
+
+        if sampler.faces.is_empty() {
+            return Err(RollerError::NoFaces);
+        }
+
+        Ok(sampler)
+    }
+
+    /// The faces that can come up
+    pub fn faces(&self) -> &[i64] {
+        &self.faces
+    }
+
+    pub fn highest(&self) -> i64 {
+        self.faces.iter().copied().max().unwrap_or_default()
+    }
+
+    /// Every face that can come up with its chance
+    pub fn chances(&self) -> impl Iterator<Item = (i64, f64)> + '_ {
+        let total = *self.cumulative.last().unwrap_or(&1) as f64;
+        let mut below = 0;
+        self.faces
+            .iter()
+            .zip(&self.cumulative)
+            .map(move |(&face, &upto)| {
+                let weight = upto - below;
+                below = upto;
+                (face, weight as f64 / total)
+            })
+    }
+
+    pub fn sample(&self, rng: &mut (impl RngCore + ?Sized)) -> i64 {
+        let total = *self.cumulative.last().expect("a face can come up");
+        let draw = rng.gen_range(0..total);
+        self.faces[self.cumulative.partition_point(|&upto| upto <= draw)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use crate::interpreter::DiceSampler;
+
+    #[test]
+    fn test_dice_sampler() {
+        // Some of them reject a good share of the numbers
+        for power in [1, 6, 20, 100, 1 << 40, (1 << 62) + 1, i64::MAX, -8] {
+            let sampler = DiceSampler::new(power);
+            let mut batched = StdRng::seed_from_u64(power as u64);
+            let mut single = batched.clone();
+
+            let mut dice = [0; 1000];
+            sampler.fill(&mut batched, &mut dice);
+            for face in dice {
+                let expected = single.gen_range(1..=power.abs()) * power.signum();
+                assert_eq!(face, expected, "d{power}");
+            }
+            assert_eq!(batched.gen::<u64>(), single.gen::<u64>(), "d{power}");
+
+            assert_eq!(
+                sampler.sample(&mut batched),
+                single.gen_range(1..=power.abs()) * power.signum()
+            );
+        }
+
+        for power in [1000, 3 << 40] {
+            let mut dice = [0; 1000];
+            DiceSampler::new(power).fill_packed(&mut StdRng::seed_from_u64(0), &mut dice);
+            assert!(dice.iter().all(|face| (1..=power).contains(face)));
+        }
+
+        // Every face comes up about as often as any other, the counts are
+        // off by six deviations about once in a hundred million runs
+        for power in [2, 6, 20, -4] {
+            let mut dice = vec![0; 60_000];
+            DiceSampler::new(power).fill_packed(&mut StdRng::seed_from_u64(0), &mut dice);
+
+            let expected = dice.len() as f64 / power.abs() as f64;
+            for face in 1..=power.abs() {
+                let count = dice.iter().filter(|&&d| d == face * power.signum()).count();
+                assert!(
+                    (count as f64 - expected).abs() < 6. * expected.sqrt(),
+                    "d{power}"
+                );
+            }
+        }
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut dice = [7; 3];
+        DiceSampler::new(1).fill_packed(&mut rng, &mut dice);
+        assert_eq!(dice, [1; 3]);
+        DiceSampler::new(0).fill_packed(&mut rng, &mut dice);
+        assert_eq!(dice, [0; 3]);
+        DiceSampler::new(0).fill(&mut rng, &mut dice);
+        assert_eq!(dice, [0; 3]);
+        assert_eq!(DiceSampler::new(0).sample(&mut rng), 0);
+    }
+}
@@ -0,0 +1,54 @@
+use std::collections::HashMap;
+
+use rand::{rngs::StdRng, SeedableRng};
+
+use crate::seed::{fnv1a, mix};
+
+// What kind of term a stream is for, so a dice and a call never share one
+const DICE: u64 = 0;
+const GROUP: u64 = 1;
+const CALL: u64 = 2;
+const WEIGHTED: u64 = 3;
+
+/// Hands out a generator to every term of a roll. The stream of a term is
+/// derived from the seed of the roll, what the term rolls and how many of
+/// the same terms came before it, so the other terms don't affect it.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Streams {
+    roll: u64,
+    seen: HashMap<u64, u64>,
+}
+
+impl Streams {
+    pub fn start(&mut self, seed: u64) {
+        self.roll = seed;
+        self.seen.clear();
+    }
+
+    pub fn dice(&mut self, quantity: i64, power: i64) -> StdRng {
+        self.stream(&[DICE, quantity as u64, power as u64])
+    }
+
+    pub fn weighted(&mut self, quantity: i64, faces: &[i64]) -> StdRng {
+        let faces = faces.iter().fold(0, |hash, &face| mix(hash ^ face as u64));
+        self.stream(&[WEIGHTED, quantity as u64, faces])
+    }
+
+    pub fn group(&mut self, members: usize) -> StdRng {
+        self.stream(&[GROUP, members as u64])
+    }
+
+    pub fn call(&mut self, function: &str) -> StdRng {
+        self.stream(&[CALL, fnv1a(function.bytes())])
+    }
+
+    fn stream(&mut self, term: &[u64]) -> StdRng {
+        let key = term.iter().fold(0, |hash, x| mix(hash ^ x));
+
+        let nth = self.seen.entry(key).or_default();
+        let seed = mix(mix(self.roll ^ key) ^ *nth);
+        *nth += 1;
+
+        StdRng::seed_from_u64(seed)
+    }
+}
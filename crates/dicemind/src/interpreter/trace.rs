@@ -0,0 +1,258 @@
+use std::{collections::HashMap, fmt::Display};
+
+use serde::{Deserialize, Serialize};
+
+use crate::syntax::{Augmentation, Expression};
+
+use super::{NodeId, TaggedDiceRoll};
+
+/// A single step of a roll
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Step {
+    /// A node about to be evaluated, see `Trace::nodes`
+    Visit { node: NodeId },
+    /// The dice as they were first rolled
+    Roll {
+        quantity: i64,
+        power: i64,
+        dice: Vec<TaggedDiceRoll>,
+    },
+    /// The weighted dice as they were first rolled, with every face that
+    /// could come up
+    RollWeighted {
+        quantity: i64,
+        faces: Vec<i64>,
+        dice: Vec<TaggedDiceRoll>,
+    },
+    /// The dice right after the augment was applied, the discarded ones
+    /// included
+    Augment {
+        augment: Augmentation,
+        dice: Vec<TaggedDiceRoll>,
+    },
+}
+
+/// Everything a roll did, in the order it was done. For explaining the
+/// result and for looking into the rolls a user says came out wrong.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Trace {
+    /// The rolled expression, then the bodies of the macros and the
+    /// builtins it expanded in the order they were expanded
+    pub expressions: Vec<Expression>,
+    pub steps: Vec<Step>,
+    // The nodes of the expressions being rolled told apart by their
+    // addresses, the innermost expansion last
+    #[serde(skip)]
+    ids: Vec<HashMap<usize, NodeId>>,
+    #[serde(skip)]
+    numbered: u32,
+}
+
+impl Trace {
+    pub fn push(&mut self, step: Step) {
+        self.steps.push(step);
+    }
+
+    /// Every node of the expressions, the index of a node is its `NodeId`.
+    /// The nodes of the rolled expression come first and keep their ids.
+    pub fn nodes(&self) -> Vec<&Expression> {
+        self.expressions
+            .iter()
+            .flat_map(Expression::nodes)
+            .collect()
+    }
+
+    pub(crate) fn start(&mut self, expr: &Expression) {
+        self.expressions.clear();
+        self.steps.clear();
+        self.ids.clear();
+        self.numbered = 0;
+        self.expand(expr);
+    }
+
+    // The expression is rolled in place, the trace keeps a copy of it
+    pub(crate) fn expand(&mut self, expr: &Expression) {
+        let ids = expr
+            .nodes()
+            .into_iter()
+            .zip(self.numbered..)
+            .map(|(node, id)| (node as *const _ as usize, NodeId(id)))
+            .collect::<HashMap<_, _>>();
+
+        self.numbered += ids.len() as u32;
+        self.ids.push(ids);
+        self.expressions.push(expr.clone());
+    }
+
+    pub(crate) fn close_expansion(&mut self) {
+        self.ids.pop();
+    }
+
+    pub(crate) fn enter(&mut self, expr: &Expression) {
+        let address = expr as *const _ as usize;
+        if let Some(&node) = self.ids.iter().rev().find_map(|ids| ids.get(&address)) {
+            self.steps.push(Step::Visit { node });
+        }
+    }
+
+    /// Every dice as it was first rolled, before the augments
+    pub fn rolled(&self) -> impl Iterator<Item = &TaggedDiceRoll> {
+        self.steps.iter().flat_map(|step| match step {
+            Step::Roll { dice, .. } | Step::RollWeighted { dice, .. } => &dice[..],
+            _ => &[],
+        })
+    }
+}
+
+fn write_dice(f: &mut std::fmt::Formatter<'_>, dice: &[TaggedDiceRoll]) -> std::fmt::Result {
+    let dice: Vec<_> = dice
+        .iter()
+        .map(|d| match d.is_discarded() {
+            true => format!("~{}", d.value),
+            false => d.value.to_string(),
+        })
+        .collect();
+
+    write!(f, "[{}]", dice.join(", "))
+}
+
+/// A step a line, `visit`, `roll` and `augment` followed by what was done.
+/// The discarded dice are marked with `~`.
+impl Display for Trace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let nodes = self.nodes();
+
+        for step in &self.steps {
+            match step {
+                Step::Visit { node } => match nodes.get(node.0 as usize) {
+                    Some(expression) => write!(f, "visit {expression}")?,
+                    None => write!(f, "visit #{}", node.0)?,
+                },
+                Step::Roll {
+                    quantity,
+                    power,
+                    dice,
+                } => {
+                    write!(f, "roll {quantity}d{power} ")?;
+                    write_dice(f, dice)?;
+                }
+                Step::RollWeighted {
+                    quantity,
+                    faces,
+                    dice,
+                } => {
+                    let faces = faces.iter().map(ToString::to_string).collect::<Vec<_>>();
+                    write!(f, "roll {quantity}d{{{}}} ", faces.join(", "))?;
+                    write_dice(f, dice)?;
+                }
+                Step::Augment { augment, dice } => {
+                    write!(f, "augment {augment} ")?;
+                    write_dice(f, dice)?;
+                }
+            }
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        interpreter::{StandardNaiveRoller, Step},
+        parser::parse,
+    };
+
+    #[test]
+    fn test_trace() {
+        let roller = StandardNaiveRoller::new_seeded(4);
+        assert!(roller.trace().is_none());
+
+        let mut roller = roller.with_trace();
+        let total = roller
+            .roll_naive(parse("4d6kh3 + 2").unwrap())
+            .unwrap()
+            .total()
+            .unwrap();
+        let trace = roller.trace().unwrap();
+
+        let rolled: Vec<_> = trace.rolled().map(|d| d.value).collect();
+        assert_eq!(rolled.len(), 4);
+        let mut kept = rolled.clone();
+        kept.sort();
+        assert_eq!(kept[1..].iter().sum::<i64>() + 2, total);
+
+        let visits = trace
+            .steps
+            .iter()
+            .filter(|step| matches!(step, Step::Visit { .. }))
+            .count();
+        assert_eq!(visits, 5);
+
+        let Some(Step::Augment { dice, .. }) = trace
+            .steps
+            .iter()
+            .find(|step| matches!(step, Step::Augment { .. }))
+        else {
+            panic!("{trace}");
+        };
+        assert_eq!(dice.iter().filter(|d| d.is_discarded()).count(), 1);
+
+        let shown = trace.to_string();
+        let lines: Vec<_> = shown.lines().collect();
+        assert_eq!(lines[0], "visit 4d6kh3 + 2");
+        assert!(lines.iter().any(|line| line.starts_with("roll 4d6 [")));
+        let augment = lines
+            .iter()
+            .position(|line| line.starts_with("augment kh3 ["));
+        assert!(lines[augment.unwrap()].contains('~'));
+        assert_eq!(lines.last(), Some(&"visit 2"));
+
+        // Every roll starts a new trace
+        roller.roll_naive(parse("d20").unwrap()).unwrap();
+        assert_eq!(roller.trace().unwrap().rolled().count(), 1);
+
+        // The weighted dice are shown with their faces, not as a plain dice
+        roller
+            .roll_naive(parse("2d{1:1, 2:3, 9:1}").unwrap())
+            .unwrap();
+        let shown = roller.trace().unwrap().to_string();
+        assert!(shown
+            .lines()
+            .any(|line| line.starts_with("roll 2d{1, 2, 9} [")));
+    }
+
+    #[test]
+    fn test_trace_nodes() {
+        let mut roller = StandardNaiveRoller::new_seeded(0).with_trace();
+        roller
+            .options_mut()
+            .macros_mut()
+            .load("bonus := d4 + 1")
+            .unwrap();
+
+        let expr = parse("d20 + @bonus + max(1, 2)").unwrap();
+        roller.roll_naive(&expr).unwrap();
+        let trace = roller.trace().unwrap();
+
+        // The macro is rolled after the expression, its nodes are numbered
+        // after the nodes of the expression
+        assert_eq!(trace.expressions.len(), 2);
+        assert_eq!(trace.expressions[0], expr);
+        let visited: Vec<_> = trace
+            .steps
+            .iter()
+            .filter_map(|step| match step {
+                Step::Visit { node } => Some(node.0),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(visited, [0, 1, 2, 3, 4, 8, 9, 10, 11, 5, 6, 7]);
+
+        let nodes = trace.nodes();
+        assert_eq!(nodes.len(), 12);
+        assert_eq!(nodes[8].to_string(), "d4 + 1");
+        assert!(trace.to_string().contains("visit @bonus\nvisit d4 + 1\n"));
+    }
+}
@@ -0,0 +1,274 @@
+use thiserror::Error;
+
+use crate::{
+    options::RollerOptions,
+    syntax::{Augmentation, Expression, PositiveInteger, Selector, SelectorOp},
+};
+
+use super::{evaluate_constant, naive::faces, RollerError, WeightedSampler};
+
+/// A problem found without rolling the expression
+#[derive(Debug, Clone, Error, PartialEq)]
+pub enum ValidationIssue {
+    /// Every roll of the expression fails with the error
+    #[error("`{expression}` can never be rolled: {error}")]
+    Fails {
+        expression: Expression,
+        error: RollerError,
+    },
+    /// Rolls fine, but there is nothing to roll
+    #[error("`{expression}` has no sides and never rolls anything")]
+    ZeroSided { expression: Expression },
+}
+
+/// Look for the rolls that are certain to fail or make no sense before
+/// rolling them. Only the quantities and the sides that are written as
+/// constants are checked, the macros are not expanded.
+pub fn validate(expr: &Expression, options: &RollerOptions) -> Vec<ValidationIssue> {
+    let mut issues = vec![];
+    let mut stack = vec![expr];
+
+    while let Some(expr) = stack.pop() {
+        let mut fails = |error| {
+            issues.push(ValidationIssue::Fails {
+                expression: expr.clone(),
+                error,
+            })
+        };
+
+        match expr {
+            Expression::Constant(c) if i64::try_from(c).is_err() => {
+                fails(RollerError::ValueTooLarge { value: c.clone() })
+            }
+            Expression::Dice {
+                quantity,
+                power,
+                augmentations,
+            } => {
+                let constant = |e: &Option<Box<Expression>>, assumed: PositiveInteger| match e {
+                    Some(e) => evaluate_constant(e).and_then(Result::ok),
+                    None => i64::try_from(&assumed).ok(),
+                };
+                let quantity = constant(quantity, options.quantity());
+                let power = constant(power, options.power());
+
+                if let Some(error) = check_augments(
+                    augmentations,
+                    quantity.map(i64::unsigned_abs),
+                    power,
+                    options,
+                ) {
+                    fails(error);
+                }
+
+                if power == Some(0) {
+                    issues.push(ValidationIssue::ZeroSided {
+                        expression: expr.clone(),
+                    });
+                }
+            }
+            Expression::Weighted {
+                quantity,
+                faces,
+                augmentations,
+            } => {
+                let quantity = match quantity {
+                    Some(e) => evaluate_constant(e).and_then(Result::ok),
+                    None => i64::try_from(&options.quantity()).ok(),
+                };
+
+                // The explosions and the rerolls of the faces are left to
+                // the roller
+                if let Some(error) = WeightedSampler::new(faces).err().or_else(|| {
+                    check_augments(
+                        augmentations,
+                        quantity.map(i64::unsigned_abs),
+                        None,
+                        options,
+                    )
+                }) {
+                    fails(error);
+                }
+            }
+            Expression::Group {
+                members,
+                augmentations,
+            } => {
+                let error = augmentations.iter().find_map(|augment| match augment {
+                    Augmentation::Explode { .. } | Augmentation::Compound { .. } => {
+                        Some(RollerError::GroupExplosion)
+                    }
+                    Augmentation::Reroll { .. } => Some(RollerError::GroupReroll),
+                    _ => None,
+                });
+
+                if let Some(error) = error.or_else(|| {
+                    check_augments(augmentations, Some(members.len() as u64), Some(0), options)
+                }) {
+                    fails(error);
+                }
+            }
+            _ => {}
+        }
+
+        stack.extend(expr.children());
+    }
+
+    issues
+}
+
+// The first error the augments are certain to fail with, following the
+// naive roller. `rolled` is how many dice there are if it is known.
+fn check_augments(
+    augments: &[Augmentation],
+    mut rolled: Option<u64>,
+    power: Option<i64>,
+    options: &RollerOptions,
+) -> Option<RollerError> {
+    // Whether all the faces of the dice match, the selectors too large to
+    // compare with are left to the roller
+    let all_faces = |matches: &dyn Fn(i64) -> bool| power.is_some_and(|p| faces(p).all(matches));
+    let selects = |selector: &Option<Selector>, value: i64| match selector {
+        Some(selector) => i64::try_from(&selector.n).is_ok() && selector.matches(value),
+        None => Some(value) == power,
+    };
+    let too_large = |n: &PositiveInteger| {
+        i64::try_from(n)
+            .is_err()
+            .then(|| RollerError::ValueTooLarge {
+                value: n.clone().into(),
+            })
+    };
+
+    for augment in augments {
+        let error = match augment {
+            Augmentation::Truncate {
+                op: SelectorOp::Drop,
+                n,
+                ..
+            } => {
+                let removed = n
+                    .as_ref()
+                    .map_or(1, |n| u64::try_from(n).unwrap_or(u64::MAX));
+                rolled.filter(|rolled| removed > *rolled).map(|rolled| {
+                    RollerError::TruncationFailure {
+                        rolled: rolled as u32,
+                        removed: removed as u32,
+                    }
+                })
+            }
+            Augmentation::Explode { selector } => {
+                // The dice from the explosions are never truncated away
+                rolled = None;
+                (options.chain_explosions()
+                    && power != Some(0)
+                    && all_faces(&|value| selects(selector, value)))
+                .then_some(RollerError::InfiniteExplosion)
+            }
+            Augmentation::Compound { selector } => all_faces(&|value| selects(selector, value))
+                .then_some(RollerError::InfiniteExplosion),
+            Augmentation::Reroll {
+                selector,
+                once: false,
+            } => all_faces(&|value| selects(&Some(selector.clone()), value))
+                .then_some(RollerError::InfiniteReroll),
+            Augmentation::Minimum { n } | Augmentation::Maximum { n } => too_large(n),
+            Augmentation::Custom { n: Some(n), .. } => too_large(n),
+            _ => None,
+        };
+
+        if error.is_some() {
+            return error;
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        dialect::Roll20,
+        interpreter::{validate, RollerError, StandardNaiveRoller, ValidationIssue},
+        options::RollerOptions,
+        parser::{parse, parse_dialect},
+        syntax::Expression,
+    };
+
+    fn errors(expr: &Expression, options: &RollerOptions) -> Vec<RollerError> {
+        validate(expr, options)
+            .into_iter()
+            .filter_map(|issue| match issue {
+                ValidationIssue::Fails { error, .. } => Some(error),
+                ValidationIssue::ZeroSided { .. } => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_validate() {
+        let options = RollerOptions::default();
+        let chained = RollerOptions::default().with_chain_explosions(true);
+        let native = |input: &str| parse(input).unwrap();
+        let roll20 = |input: &str| parse_dialect(input, &Roll20).unwrap();
+
+        assert!(validate(&native("4d6kh3 + d20! [fire]"), &options).is_empty());
+
+        assert_eq!(errors(&native("d1!"), &options), vec![]);
+        assert_eq!(
+            errors(&native("d1!"), &chained),
+            vec![RollerError::InfiniteExplosion]
+        );
+        assert_eq!(
+            errors(&roll20("2 + 3d4!!>0"), &options),
+            vec![RollerError::InfiniteExplosion]
+        );
+        assert_eq!(
+            errors(&roll20("3d4r<5"), &options),
+            vec![RollerError::InfiniteReroll]
+        );
+        assert_eq!(errors(&roll20("3d4ro<5"), &options), vec![]);
+        assert_eq!(
+            errors(&native("2d20dl3"), &options),
+            vec![RollerError::TruncationFailure {
+                rolled: 2,
+                removed: 3
+            }]
+        );
+        // The explosions could roll enough dice to drop
+        assert_eq!(errors(&native("2d6!dl3"), &options), vec![]);
+        assert_eq!(
+            errors(&native("{d6, 3}!"), &options),
+            vec![RollerError::GroupExplosion]
+        );
+        assert_eq!(
+            errors(&native("d20 + 99999999999999999999"), &options),
+            vec![RollerError::ValueTooLarge {
+                value: "99999999999999999999".parse().unwrap()
+            }]
+        );
+
+        assert_eq!(
+            validate(&native("3 + 2d0"), &options),
+            vec![ValidationIssue::ZeroSided {
+                expression: native("2d0")
+            }]
+        );
+
+        // The roller fails the same way
+        for expr in [
+            roll20("2 + 3d4!!>0"),
+            roll20("3d4r<5"),
+            native("2d20dl3"),
+            native("{d6, 3}!"),
+            native("{1, 2}dl3"),
+            native("d20 + 99999999999999999999"),
+        ] {
+            assert_eq!(
+                StandardNaiveRoller::new_seeded(0).roll_naive(&expr).err(),
+                errors(&expr, &options).pop(),
+                "{expr}"
+            );
+        }
+    }
+}
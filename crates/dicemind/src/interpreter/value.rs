@@ -0,0 +1,662 @@
+use std::{borrow::Borrow, fmt::Display};
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    options::{Degrees, RollerOptions},
+    symbols::Symbols,
+    syntax::{BinaryOperator, Expression, Integer, PositiveInteger},
+    visitor::Visitor,
+};
+
+use super::{Hits, NaiveRoller, NaiveValue, RollerError, RollerResult, TaggedDiceRoll};
+
+/// What a roll means as a whole, for the front ends to match on. A
+/// comparison at the top is a yes or a no, a repetition or a group is a
+/// list, a pool is its hits and anything else is a number. The narrative
+/// dice are their symbols.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Value {
+    Number(i64),
+    /// `d20 + 5 > 15`, with the totals that were compared
+    Bool {
+        value: bool,
+        operator: BinaryOperator,
+        lhs: i64,
+        rhs: i64,
+    },
+    /// The rolls of a repetition, or the kept members of a group
+    List(Vec<Value>),
+    /// The successes and failures of a pool like `8d10s>=7`
+    Hits(Hits),
+    /// The symbols of a pool of narrative dice, as they were rolled
+    Symbols(Symbols),
+    /// A comparison told apart in degrees, see `RollerOptions::with_degrees`.
+    /// The margin is how far past the DC the total is, negative below it.
+    Degree {
+        degree: Degree,
+        margin: i64,
+        operator: BinaryOperator,
+        lhs: i64,
+        rhs: i64,
+    },
+}
+
+/// How well a check went, from the worst
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Degree {
+    CriticalFailure,
+    Failure,
+    Success,
+    CriticalSuccess,
+}
+
+impl Degree {
+    /// The degree of a check that succeeded or not by the margin, before
+    /// the dice shift it
+    pub fn of(success: bool, margin: i64, degrees: &Degrees) -> Self {
+        match (success, margin) {
+            (true, margin) if margin >= degrees.critical_margin => Degree::CriticalSuccess,
+            (true, _) => Degree::Success,
+            (false, margin) if margin <= -degrees.critical_margin => Degree::CriticalFailure,
+            (false, _) => Degree::Failure,
+        }
+    }
+
+    pub fn is_success(&self) -> bool {
+        *self >= Degree::Success
+    }
+
+    /// One degree better, a critical success stays one
+    pub fn up(self) -> Self {
+        match self {
+            Degree::CriticalFailure => Degree::Failure,
+            Degree::Failure => Degree::Success,
+            _ => Degree::CriticalSuccess,
+        }
+    }
+
+    /// One degree worse, a critical failure stays one
+    pub fn down(self) -> Self {
+        match self {
+            Degree::CriticalSuccess => Degree::Success,
+            Degree::Success => Degree::Failure,
+            _ => Degree::CriticalFailure,
+        }
+    }
+}
+
+impl Display for Degree {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Degree::CriticalFailure => "critical failure",
+            Degree::Failure => "failure",
+            Degree::Success => "success",
+            Degree::CriticalSuccess => "critical success",
+        })
+    }
+}
+
+impl Value {
+    /// The same as the total of the rolled value, a true comparison is 1.
+    /// A degree is 1 for the successes, the dice may have shifted it away
+    /// from the comparison. The symbols are their net successes.
+    pub fn total(&self) -> RollerResult<i64> {
+        match self {
+            Value::Number(n) => Ok(*n),
+            Value::Bool { value, .. } => Ok(*value as i64),
+            Value::Degree { degree, .. } => Ok(degree.is_success() as i64),
+            Value::List(values) => values.iter().try_fold(0i64, |acc, value| {
+                acc.checked_add(value.total()?).ok_or(RollerError::Overflow)
+            }),
+            Value::Hits(hits) => Ok(hits.net()),
+            Value::Symbols(symbols) => Ok(symbols.net()),
+        }
+    }
+}
+
+impl TryFrom<&NaiveValue> for Value {
+    type Error = RollerError;
+
+    fn try_from(value: &NaiveValue) -> RollerResult<Self> {
+        Ok(match value {
+            NaiveValue::List(values) => Value::List(
+                values
+                    .iter()
+                    .map(Value::try_from)
+                    .collect::<RollerResult<_>>()?,
+            ),
+            NaiveValue::Pool(_) => Value::Hits(value.hits().unwrap_or_default()),
+            NaiveValue::Summary(summary) => match summary.hits {
+                Some(hits) => Value::Hits(hits),
+                None => Value::Number(summary.total),
+            },
+            _ => Value::Number(value.total()?),
+        })
+    }
+}
+
+/// `17`, `true (17 > 15)`, `[3, 5]`, `4 hits, 1 miss`,
+/// `success by 2 (17 > 14)` and the symbols left after cancelling,
+/// `2 successes, 1 threat`
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let symbol = |operator: &BinaryOperator| match operator {
+            BinaryOperator::Equals => "=",
+            BinaryOperator::LessThan => "<",
+            _ => ">",
+        };
+
+        match self {
+            Value::Number(n) => write!(f, "{n}"),
+            Value::Bool {
+                value,
+                operator,
+                lhs,
+                rhs,
+            } => write!(f, "{value} ({lhs} {} {rhs})", symbol(operator)),
+            Value::Degree {
+                degree,
+                margin,
+                operator,
+                lhs,
+                rhs,
+            } => {
+                write!(f, "{degree}")?;
+                if *margin != 0 {
+                    write!(f, " by {}", margin.unsigned_abs())?;
+                }
+                write!(f, " ({lhs} {} {rhs})", symbol(operator))
+            }
+            Value::List(values) => {
+                let values = values.iter().map(Value::to_string).collect::<Vec<_>>();
+                write!(f, "[{}]", values.join(", "))
+            }
+            Value::Hits(Hits {
+                successes,
+                failures,
+            }) => {
+                let plural = |n: &i64, one, many| if *n == 1 { one } else { many };
+                write!(
+                    f,
+                    "{successes} {}, {failures} {}",
+                    plural(successes, "hit", "hits"),
+                    plural(failures, "miss", "misses")
+                )
+            }
+            Value::Symbols(symbols) => write!(f, "{}", symbols.cancel()),
+        }
+    }
+}
+
+/// A roll against a target number, see `NaiveRoller::roll_against`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Check {
+    pub total: i64,
+    pub dc: i64,
+    /// The total met the DC, whatever the dice came up
+    pub hit: bool,
+    /// How far past the DC the total is, negative below it
+    pub margin: i64,
+    /// A kept d20 came up critical, the natural 20 or the natural 1 when
+    /// the options don't say otherwise. The other dice are never critical.
+    pub crit: Option<Critical>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Critical {
+    Success,
+    Failure,
+}
+
+/// `17 vs DC 15 — success by 2`, `15 vs DC 15 — success` and
+/// `21 vs DC 15 — success by 6, critical`
+impl Display for Check {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let result = if self.hit { "success" } else { "failure" };
+        write!(f, "{} vs DC {} — {result}", self.total, self.dc)?;
+        if self.margin != 0 {
+            write!(f, " by {}", self.margin.unsigned_abs())?;
+        }
+
+        match self.crit {
+            Some(Critical::Success) => write!(f, ", critical"),
+            Some(Critical::Failure) => write!(f, ", fumble"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<R: Rng> NaiveRoller<R> {
+    /// Roll the expression against the DC, the total meets it or not
+    pub fn roll_against(&mut self, expr: impl Borrow<Expression>, dc: i64) -> RollerResult<Check> {
+        // The sums keep no dice, the critical ones are looked for among
+        // all of them. The roll starts the provenance over, from no dice.
+        let expr = expr.borrow();
+        self.start_provenance(expr);
+        let (value, dice) = self.with_dice_recorded(|roller| roller.roll_naive(expr));
+        let total = value?.total()?;
+
+        let dice = d20s(dice, &expr.nodes(), self.options());
+
+        // A critical success wins over a critical failure in the same roll
+        let crit = if dice.iter().any(TaggedDiceRoll::is_critical_success) {
+            Some(Critical::Success)
+        } else if dice.iter().any(TaggedDiceRoll::is_critical_failure) {
+            Some(Critical::Failure)
+        } else {
+            None
+        };
+
+        Ok(Check {
+            total,
+            dc,
+            hit: total >= dc,
+            margin: total.saturating_sub(dc),
+            crit,
+        })
+    }
+
+    /// Roll and tell what the result means. The dice are rolled in the
+    /// same order as `roll_naive` does, so under the same seed the total
+    /// of the value is the total of the naive value.
+    pub fn roll(&mut self, expr: impl Borrow<Expression>) -> RollerResult<Value> {
+        let expr = expr.borrow();
+        self.start_roll(expr)?;
+
+        // The natural shift tells the d20s apart by the nodes of the dice
+        let nodes = expr.nodes();
+        let (value, _) = self.with_dice_recorded(|roller| {
+            roller.start_provenance(expr);
+            roller.value_of(expr, &nodes)
+        });
+        value
+    }
+
+    fn value_of(&mut self, expr: &Expression, nodes: &[&Expression]) -> RollerResult<Value> {
+        use BinaryOperator::*;
+
+        match expr {
+            Expression::Subexpression(expr)
+            | Expression::Annotated {
+                expression: expr, ..
+            }
+            | Expression::Tagged {
+                expression: expr, ..
+            } => self.value_of(expr, nodes),
+            Expression::Binop {
+                operator: operator @ (Equals | LessThan | GreaterThan),
+                lhs,
+                rhs,
+            } => {
+                // The sums keep no dice, the critical ones are looked for
+                // among all the dice on the left
+                let natural_shift = self.options().degrees().is_some_and(|d| d.natural_shift);
+                let (lhs, lhs_dice) = match natural_shift {
+                    true => self.with_dice_recorded(|roller| roller.visit(lhs)),
+                    false => (self.visit(lhs), vec![]),
+                };
+                let lhs_dice = d20s(lhs_dice, nodes, self.options());
+                let (lhs, rhs) = (lhs?, self.visit(rhs)?);
+                // The decimals are compared before they are rounded, the
+                // same as `roll_naive` compares them
+                let value = match operator {
+                    Equals => lhs.exact()? == rhs.exact()?,
+                    LessThan => lhs.exact()? < rhs.exact()?,
+                    _ => lhs.exact()? > rhs.exact()?,
+                };
+
+                if let Some(degrees) = self.options().degrees().copied() {
+                    return degree(value, *operator, &lhs, &lhs_dice, &rhs, &degrees);
+                }
+
+                let (lhs, rhs) = (lhs.total()?, rhs.total()?);
+
+                Ok(Value::Bool {
+                    value,
+                    operator: *operator,
+                    lhs,
+                    rhs,
+                })
+            }
+            // Only the last of the chained expressions is the result
+            Expression::Binop {
+                operator: Chain,
+                lhs,
+                rhs,
+            } => {
+                self.visit(lhs)?;
+                self.value_of(rhs, nodes)
+            }
+            Expression::Repeat { count, expression } => {
+                let count = self.visit(count)?.total()?;
+                if count < 0 {
+                    return Err(RollerError::InvalidRepetition { count });
+                }
+
+                (0..count)
+                    .map(|_| self.value_of(expression, nodes))
+                    .collect::<RollerResult<_>>()
+                    .map(Value::List)
+            }
+            // Every member of a group is rolled as a single dice
+            Expression::Group { .. } => Ok(match self.visit(expr)? {
+                NaiveValue::Dice(members) => Value::List(
+                    members
+                        .iter()
+                        .filter(|m| !m.is_discarded())
+                        .map(|m| Value::Number(m.value))
+                        .collect(),
+                ),
+                value => Value::try_from(&value)?,
+            }),
+            _ => Value::try_from(&self.visit(expr)?),
+        }
+    }
+}
+
+// The dice rolled by the d20s among the nodes, see `rolls_d20`
+fn d20s(
+    dice: Vec<TaggedDiceRoll>,
+    nodes: &[&Expression],
+    options: &RollerOptions,
+) -> Vec<TaggedDiceRoll> {
+    dice.into_iter()
+        .filter(|d| {
+            d.node
+                .and_then(|id| nodes.get(id.0 as usize))
+                .is_some_and(|node| rolls_d20(node, options))
+        })
+        .collect()
+}
+
+// Whether the dice of the node are d20s. The dice of a macro belong to
+// its reference, they are when every dice the macro rolls is a d20.
+fn rolls_d20(node: &Expression, options: &RollerOptions) -> bool {
+    match node {
+        Expression::Dice { power: None, .. } => options.power() == PositiveInteger::from(20u32),
+        Expression::Dice {
+            power: Some(power), ..
+        } => **power == Expression::Constant(Integer::from(20)),
+        Expression::Reference(name) => {
+            let Some(expansion) = options
+                .macros()
+                .get(name)
+                .and_then(|expr| options.macros().expand(expr.clone()).ok())
+            else {
+                return false;
+            };
+
+            let dice: Vec<_> = expansion
+                .nodes()
+                .into_iter()
+                .filter(|node| {
+                    matches!(node, Expression::Dice { .. } | Expression::Weighted { .. })
+                })
+                .collect();
+            !dice.is_empty() && dice.into_iter().all(|node| rolls_d20(node, options))
+        }
+        _ => false,
+    }
+}
+
+// The DC is the lowest total that succeeds, the highest one for `<`
+fn degree(
+    success: bool,
+    operator: BinaryOperator,
+    lhs: &NaiveValue,
+    lhs_dice: &[TaggedDiceRoll],
+    rhs: &NaiveValue,
+    degrees: &Degrees,
+) -> RollerResult<Value> {
+    let (total, target) = (lhs.total()?, rhs.total()?);
+    let margin = match operator {
+        BinaryOperator::Equals => 0i64.saturating_sub_unsigned(total.abs_diff(target)),
+        BinaryOperator::LessThan => target.saturating_sub(1).saturating_sub(total),
+        _ => total.saturating_sub(target.saturating_add(1)),
+    };
+
+    let mut degree = Degree::of(success, margin, degrees);
+    if degrees.natural_shift {
+        for dice in lhs_dice {
+            if dice.is_critical_success() {
+                degree = degree.up();
+            }
+            if dice.is_critical_failure() {
+                degree = degree.down();
+            }
+        }
+    }
+
+    Ok(Value::Degree {
+        degree,
+        margin,
+        operator,
+        lhs: total,
+        rhs: target,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        interpreter::{Check, Critical, Degree, Hits, StandardNaiveRoller, Value},
+        macros::MacroRegistry,
+        options::{CriticalRange, Degrees, FixedDice, RollerOptions},
+        parser::parse,
+        syntax::BinaryOperator,
+    };
+
+    #[test]
+    fn test_value() {
+        let roll = |input: &str| {
+            StandardNaiveRoller::new_seeded(0)
+                .roll(parse(input).unwrap())
+                .unwrap()
+        };
+
+        assert_eq!(roll("2 * 3 + 1"), Value::Number(7));
+        assert_eq!(
+            roll("(2 * 3 > 4) [check]"),
+            Value::Bool {
+                value: true,
+                operator: BinaryOperator::GreaterThan,
+                lhs: 6,
+                rhs: 4
+            }
+        );
+        assert_eq!(roll("1, 2 = 3").to_string(), "false (2 = 3)");
+        assert_eq!(
+            roll("2x(3 < 4)").to_string(),
+            "[true (3 < 4), true (3 < 4)]"
+        );
+        assert_eq!(roll("{3, 10, 2 + 5}kh2").to_string(), "[10, 7]");
+        assert_eq!(
+            roll("{3, 10, 7}s>5"),
+            Value::Hits(Hits {
+                successes: 2,
+                failures: 0
+            })
+        );
+        assert_eq!(roll("{3, 10, 7}s>5f<4").to_string(), "2 hits, 1 miss");
+        // The decimals are compared as they are and shown rounded
+        assert_eq!(roll("2.5 > 2").to_string(), "true (2 > 2)");
+        assert_eq!(roll("1.5 * 3"), Value::Number(4));
+
+        for input in [
+            "d20 + 5 > 15",
+            "(d20 + 5) [attack] > 15 [dc]",
+            "3x(d6 = 6)",
+            "d4, 2d6",
+            "4d6kh3",
+            "{d20, d20}kh1",
+            "8d10s>=7f<=1",
+            "1.5 * d6 > 4",
+        ] {
+            let expr = parse(input).unwrap();
+            for seed in 0..20 {
+                assert_eq!(
+                    StandardNaiveRoller::new_seeded(seed)
+                        .roll(&expr)
+                        .unwrap()
+                        .total(),
+                    StandardNaiveRoller::new_seeded(seed)
+                        .roll_naive(&expr)
+                        .unwrap()
+                        .total(),
+                    "{input}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_degrees() {
+        let options = RollerOptions::default().with_degrees(Some(Degrees::PF2E));
+        let roll = |input: &str, options: &RollerOptions| {
+            StandardNaiveRoller::new_seeded(0)
+                .with_options(options.clone())
+                .roll(parse(input).unwrap())
+                .unwrap()
+        };
+
+        assert_eq!(
+            roll("10 + 7 > 14", &options),
+            Value::Degree {
+                degree: Degree::Success,
+                margin: 2,
+                operator: BinaryOperator::GreaterThan,
+                lhs: 17,
+                rhs: 14
+            }
+        );
+        for (input, shown) in [
+            ("25 > 14", "critical success by 10 (25 > 14)"),
+            ("24 > 14", "success by 9 (24 > 14)"),
+            ("15 > 14", "success (15 > 14)"),
+            ("6 > 14", "failure by 9 (6 > 14)"),
+            ("5 > 14", "critical failure by 10 (5 > 14)"),
+            ("3 < 5", "success by 1 (3 < 5)"),
+            ("7 = 7", "success (7 = 7)"),
+            (
+                "2x(1 = 11)",
+                "[critical failure by 10 (1 = 11), critical failure by 10 (1 = 11)]",
+            ),
+        ] {
+            assert_eq!(roll(input, &options).to_string(), shown);
+        }
+        assert_eq!(roll("20 > 14", &options).total().unwrap(), 1);
+        assert_eq!(roll("2 + 2", &options), Value::Number(4));
+
+        // Every dice is critical, the natural shift lifts the degree
+        let mut critical = options.clone();
+        critical.set_critical_success(CriticalRange::at_least(1));
+        let Value::Degree { degree, .. } = roll("d20 + 7 > 100", &critical) else {
+            panic!()
+        };
+        assert_eq!(degree, Degree::Failure);
+        // Only the d20s shift it
+        let Value::Degree { degree, .. } = roll("d6 + 7 > 100", &critical) else {
+            panic!()
+        };
+        assert_eq!(degree, Degree::CriticalFailure);
+
+        let unshifted = critical.with_degrees(Some(Degrees {
+            natural_shift: false,
+            ..Degrees::PF2E
+        }));
+        let Value::Degree { degree, .. } = roll("d20 + 7 > 100", &unshifted) else {
+            panic!()
+        };
+        assert_eq!(degree, Degree::CriticalFailure);
+
+        assert_eq!(Degree::CriticalSuccess.up(), Degree::CriticalSuccess);
+        assert_eq!(Degree::Success.down(), Degree::Failure);
+        assert!(!Degree::Failure.is_success());
+    }
+
+    #[test]
+    fn test_roll_against() {
+        let mut roller = StandardNaiveRoller::new_seeded(0);
+        let mut against = |input: &str, dc| roller.roll_against(parse(input).unwrap(), dc).unwrap();
+
+        assert_eq!(
+            against("10 + 7", 15),
+            Check {
+                total: 17,
+                dc: 15,
+                hit: true,
+                margin: 2,
+                crit: None
+            }
+        );
+        assert_eq!(
+            against("10 + 7", 15).to_string(),
+            "17 vs DC 15 — success by 2"
+        );
+        assert_eq!(against("15", 15).to_string(), "15 vs DC 15 — success");
+        assert_eq!(against("9", 15).to_string(), "9 vs DC 15 — failure by 6");
+
+        for _ in 0..200 {
+            let outcome = against("d20 + 5", 15);
+            let natural = outcome.total - 5;
+            assert_eq!(outcome.hit, outcome.total >= 15);
+            assert_eq!(outcome.margin, outcome.total - 15);
+            assert_eq!(
+                outcome.crit,
+                match natural {
+                    20 => Some(Critical::Success),
+                    1 => Some(Critical::Failure),
+                    _ => None,
+                }
+            );
+        }
+
+        // The discarded dice are not critical
+        for _ in 0..200 {
+            let outcome = against("2d20kl1", 10);
+            assert_eq!(outcome.crit == Some(Critical::Success), outcome.total == 20);
+        }
+
+        // Only the d20s are critical, those of the macros included
+        let mut macros = MacroRegistry::default();
+        macros.define("attack", parse("d20 + 7").unwrap()).unwrap();
+        macros
+            .define("damage", parse("2d6 + d20").unwrap())
+            .unwrap();
+        let crit = |input: &str, fixed| {
+            let options = RollerOptions::builder()
+                .macros(macros.clone())
+                .fixed_dice(fixed)
+                .build();
+            StandardNaiveRoller::new_seeded(0)
+                .with_options(options)
+                .roll_against(parse(input).unwrap(), 15)
+                .unwrap()
+                .crit
+        };
+        assert_eq!(
+            crit("d20 + d4", FixedDice::Maximum),
+            Some(Critical::Success)
+        );
+        assert_eq!(
+            crit("d20 + d4", FixedDice::Minimum),
+            Some(Critical::Failure)
+        );
+        assert_eq!(crit("d4 + 10", FixedDice::Maximum), None);
+        assert_eq!(crit("d4 + 10", FixedDice::Minimum), None);
+        assert_eq!(crit("2d6 + d20", FixedDice::Constant(6)), None);
+        assert_eq!(crit("d12 - 3", FixedDice::Minimum), None);
+        assert_eq!(crit("@attack", FixedDice::Maximum), Some(Critical::Success));
+        assert_eq!(crit("@damage", FixedDice::Maximum), None);
+
+        // The provenance is kept for the roll against the DC alone
+        let mut roller = StandardNaiveRoller::new_seeded(0).with_provenance();
+        roller.roll_naive(parse("4d6").unwrap()).unwrap();
+        let outcome = roller.roll_against(parse("d20 + 2").unwrap(), 10).unwrap();
+        let dice = roller.provenance().unwrap().dice();
+        assert_eq!(dice.len(), 1);
+        assert_eq!(dice[0].value + 2, outcome.total);
+    }
+}
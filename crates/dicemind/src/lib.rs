@@ -5,17 +5,68 @@
 #![feature(extract_if)]
 #![feature(is_sorted)]
 
+pub mod cache;
+pub mod compat;
+pub mod compiler;
+pub mod cost;
+pub mod dialect;
+pub mod distribution;
+pub mod divergence;
+#[cfg(feature = "hidden")]
+pub mod disclosure;
+#[cfg(feature = "entropy")]
+pub mod entropy;
+pub mod expectation;
+#[cfg(feature = "storage")]
+pub mod export;
+pub mod explain;
+pub mod extensions;
+pub mod functions;
+pub mod generate;
+pub mod histogram;
+pub mod interner;
+#[cfg(test)]
+mod grammar;
 pub mod interpreter;
+pub mod macros;
+pub mod mitigation;
 pub mod parser;
+pub mod render;
+pub mod schedule;
+#[cfg(feature = "json")]
+pub mod schema;
+#[cfg(feature = "scripting")]
+pub mod scripting;
+pub mod seed;
+#[cfg(any(feature = "bincode", feature = "cbor", feature = "postcard"))]
+pub mod serialization;
+pub mod simulation;
+#[cfg(feature = "webhooks")]
+pub mod sinks;
+#[cfg(feature = "storage")]
+pub mod storage;
+pub mod symbols;
 pub mod syntax;
+pub mod systems;
+pub mod transformer;
 mod options;
+mod roll;
 mod simplify;
 mod visitor;
 
+pub use roll::{roll_str, roll_str_seeded, RollError};
+
 pub mod prelude {
-    pub use crate::interpreter::StandardNaiveRoller;
-    pub use crate::parser::{parse, ParsingError};
-    pub use crate::options::RollerOptions;
+    #[allow(deprecated)]
+    pub use crate::compat::StandardFastRoller;
+    pub use crate::interpreter::{SecureRoller, StandardBigRoller, StandardNaiveRoller};
+    pub use crate::macros::MacroRegistry;
+    pub use crate::parser::{parse, parse_all_errors, parse_dialect, parse_spanned, ParsingError};
+    pub use crate::roll::{roll_str, roll_str_seeded, RollError};
+    pub use crate::seed::{random_seed_phrase, seed_from_phrase};
+    pub use crate::options::{
+        CriticalRange, Degrees, FixedDice, RollerOptions, RollerOptionsBuilder, Rounding,
+    };
     pub use crate::syntax::Expression;
     pub use crate::simplify::advanced_simplify;
 }
@@ -0,0 +1,388 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    parser::{parse, parse_strict, ParsingError},
+    syntax::{Expression, MacroName},
+};
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum MacroError {
+    #[error("Unknown macro `@{name}`")]
+    UnknownMacro { name: MacroName },
+    #[error("Macro `@{name}` refers to itself through {}", format_cycle(.path))]
+    Cycle {
+        name: MacroName,
+        path: Vec<MacroName>,
+    },
+    #[error("Expected a definition like `attack := d20 + 7`, got `{line}`")]
+    InvalidDefinition { line: String },
+    #[error("Invalid macro name `{name}`, only letters, digits and underscores are allowed")]
+    InvalidName { name: String },
+    #[error("Could not parse the body of `@{name}`: {error}")]
+    Parsing {
+        name: MacroName,
+        error: ParsingError,
+    },
+}
+
+fn format_cycle(path: &[MacroName]) -> String {
+    path.iter()
+        .map(|name| format!("@{name}"))
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
+
+pub fn is_macro_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Named expressions that can be referred to as `@name` from other expressions.
+/// The registry never holds a cycle, the definitions are checked when they
+/// are defined and when a registry is deserialized.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "Definitions")]
+pub struct MacroRegistry {
+    macros: BTreeMap<MacroName, Expression>,
+}
+
+// A registry as it is serialized, before its definitions are checked
+#[derive(Deserialize)]
+struct Definitions {
+    macros: BTreeMap<MacroName, Expression>,
+}
+
+impl TryFrom<Definitions> for MacroRegistry {
+    type Error = MacroError;
+
+    fn try_from(definitions: Definitions) -> Result<Self, MacroError> {
+        let registry = MacroRegistry {
+            macros: definitions.macros,
+        };
+        for name in registry.macros.keys() {
+            registry.expand_reference(name, &mut vec![])?;
+        }
+
+        Ok(registry)
+    }
+}
+
+impl MacroRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Expression> {
+        self.macros.get(name)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.macros.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&MacroName, &Expression)> {
+        self.macros.iter()
+    }
+
+    /// Register a macro, overwriting the previous one with the same name.
+    /// Definitions that would make a macro refer to itself are rejected,
+    /// so the registry never contains cycles.
+    pub fn define(
+        &mut self,
+        name: impl Into<MacroName>,
+        body: Expression,
+    ) -> Result<(), MacroError> {
+        let name = name.into();
+        if !is_macro_name(&name) {
+            return Err(MacroError::InvalidName {
+                name: name.to_string(),
+            });
+        }
+
+        let previous = self.macros.insert(name.clone(), body);
+        if let Err(err) = self.expand_reference(&name, &mut vec![]) {
+            match previous {
+                Some(previous) => self.macros.insert(name, previous),
+                None => self.macros.remove(&name),
+            };
+
+            return Err(err);
+        }
+
+        Ok(())
+    }
+
+    /// Register a macro from a definition like `attack := d20 + 7`
+    pub fn define_str(&mut self, definition: &str) -> Result<MacroName, MacroError> {
+        self.define_parsed(definition, parse)
+    }
+
+    fn define_parsed(
+        &mut self,
+        definition: &str,
+        parse: fn(&str) -> Result<Expression, ParsingError>,
+    ) -> Result<MacroName, MacroError> {
+        let (name, body) =
+            definition
+                .split_once(":=")
+                .ok_or_else(|| MacroError::InvalidDefinition {
+                    line: definition.to_string(),
+                })?;
+
+        let name = MacroName::new(name.trim().trim_start_matches('@'));
+        let body = parse(body).map_err(|error| MacroError::Parsing {
+            name: name.clone(),
+            error,
+        })?;
+
+        self.define(name.clone(), body)?;
+        Ok(name)
+    }
+
+    /// Load one definition per line, empty lines and lines starting with `#` are skipped
+    pub fn load(&mut self, source: &str) -> Result<Vec<MacroName>, MacroError> {
+        self.load_with(source, parse)
+    }
+
+    /// Load the definitions with `parse_strict`, for the curated libraries
+    /// that should not depend on the defaults of the roller
+    pub fn load_strict(&mut self, source: &str) -> Result<Vec<MacroName>, MacroError> {
+        self.load_with(source, |body| parse_strict(body).map_err(|d| d.error))
+    }
+
+    fn load_with(
+        &mut self,
+        source: &str,
+        parse: fn(&str) -> Result<Expression, ParsingError>,
+    ) -> Result<Vec<MacroName>, MacroError> {
+        source
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| self.define_parsed(line, parse))
+            .collect()
+    }
+
+    /// Replace every reference in the expression with the body of the macro
+    pub fn expand(&self, expr: Expression) -> Result<Expression, MacroError> {
+        self.expand_inner(expr, &mut vec![])
+    }
+
+    fn expand_reference(
+        &self,
+        name: &MacroName,
+        stack: &mut Vec<MacroName>,
+    ) -> Result<Expression, MacroError> {
+        if stack.contains(name) {
+            let mut path = stack.clone();
+            path.push(name.clone());
+
+            return Err(MacroError::Cycle {
+                name: name.clone(),
+                path,
+            });
+        }
+
+        let body = self
+            .macros
+            .get(name)
+            .cloned()
+            .ok_or_else(|| MacroError::UnknownMacro { name: name.clone() })?;
+
+        stack.push(name.clone());
+        let expanded = self.expand_inner(body, stack);
+        stack.pop();
+
+        // Keep the precedence of the body, `@a * 2` with `a := 1 + 1` is 4
+        Ok(Expression::Subexpression(Box::new(expanded?)))
+    }
+
+    fn expand_inner(
+        &self,
+        expr: Expression,
+        stack: &mut Vec<MacroName>,
+    ) -> Result<Expression, MacroError> {
+        use Expression::*;
+
+        let mut expand = |e: Box<Expression>| -> Result<Box<Expression>, MacroError> {
+            Ok(Box::new(self.expand_inner(*e, stack)?))
+        };
+
+        Ok(match expr {
+            Reference(name) => return self.expand_reference(&name, stack),
+            Dice {
+                quantity,
+                power,
+                augmentations,
+            } => Dice {
+                quantity: quantity.map(&mut expand).transpose()?,
+                power: power.map(&mut expand).transpose()?,
+                augmentations,
+            },
+            Weighted {
+                quantity,
+                faces,
+                augmentations,
+            } => Weighted {
+                quantity: quantity.map(&mut expand).transpose()?,
+                faces,
+                augmentations,
+            },
+            Binop { operator, lhs, rhs } => Binop {
+                operator,
+                lhs: expand(lhs)?,
+                rhs: expand(rhs)?,
+            },
+            Annotated {
+                expression,
+                annotation,
+            } => Annotated {
+                expression: expand(expression)?,
+                annotation,
+            },
+            Subexpression(e) => Subexpression(expand(e)?),
+            Tagged {
+                expression,
+                metadata,
+            } => Tagged {
+                expression: expand(expression)?,
+                metadata,
+            },
+            UnaryNegation(e) => UnaryNegation(expand(e)?),
+            Repeat { count, expression } => Repeat {
+                count: expand(count)?,
+                expression: expand(expression)?,
+            },
+            Conditional {
+                condition,
+                then,
+                otherwise,
+            } => Conditional {
+                condition: expand(condition)?,
+                then: expand(then)?,
+                otherwise: expand(otherwise)?,
+            },
+            Call { function, args } => Call {
+                function,
+                args: args
+                    .into_iter()
+                    .map(|e| self.expand_inner(e, stack))
+                    .collect::<Result<_, _>>()?,
+            },
+            Group {
+                members,
+                augmentations,
+            } => Group {
+                members: members
+                    .into_iter()
+                    .map(|e| self.expand_inner(e, stack))
+                    .collect::<Result<_, _>>()?,
+                augmentations,
+            },
+            Constant(c) => Constant(c),
+            Decimal(d) => Decimal(d),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::{
+        distribution::{distribution, DistributionError},
+        macros::{MacroError, MacroRegistry},
+        options::RollerOptions,
+        prelude::parse,
+    };
+
+    #[test]
+    fn test_expansion() {
+        let mut macros = MacroRegistry::new();
+        macros
+            .load(
+                "# a fighter
+                 bonus := 5 + 2
+                 attack := d20 + @bonus",
+            )
+            .unwrap();
+
+        let expanded = macros.expand(parse("@attack * 2").unwrap()).unwrap();
+        assert_eq!(expanded, parse("(d20 + (5 + 2)) * 2").unwrap());
+
+        assert!(matches!(
+            macros.expand(parse("@damage").unwrap()),
+            Err(MacroError::UnknownMacro { .. })
+        ));
+    }
+
+    #[test]
+    fn test_cycles() {
+        let mut macros = MacroRegistry::new();
+        macros.define_str("a := 1").unwrap();
+        macros.define_str("b := @a + 1").unwrap();
+
+        assert!(matches!(
+            macros.define_str("a := @b"),
+            Err(MacroError::Cycle { .. })
+        ));
+        assert!(matches!(
+            macros.define_str("c := 2 * @c"),
+            Err(MacroError::Cycle { .. })
+        ));
+
+        // The rejected definitions leave the registry intact
+        assert_eq!(macros.get("a"), Some(&parse("1").unwrap()));
+        assert!(macros.get("c").is_none());
+
+        // A registry put together without the checks is still not followed
+        // around in circles
+        let cyclic = MacroRegistry {
+            macros: BTreeMap::from([
+                ("a".into(), parse("@b").unwrap()),
+                ("b".into(), parse("@a").unwrap()),
+            ]),
+        };
+        let options = RollerOptions::builder().macros(cyclic).build();
+        assert!(matches!(
+            distribution(&parse("@a").unwrap(), &options),
+            Err(DistributionError::Macro(MacroError::Cycle { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_deserialize_cycles() {
+        let mut macros = MacroRegistry::new();
+        macros.define_str("a := d20").unwrap();
+        macros.define_str("b := @a + 1").unwrap();
+        let json = serde_json::to_string(&macros).unwrap();
+        assert_eq!(
+            serde_json::from_str::<MacroRegistry>(&json).unwrap(),
+            macros
+        );
+
+        // Only a registry built by hand can hold a cycle
+        let cyclic = MacroRegistry {
+            macros: [("a", "@b"), ("b", "@a")]
+                .into_iter()
+                .map(|(name, body)| (name.into(), parse(body).unwrap()))
+                .collect(),
+        };
+        let json = serde_json::to_string(&cyclic).unwrap();
+        let err = serde_json::from_str::<MacroRegistry>(&json).unwrap_err();
+        assert!(err.to_string().contains("refers to itself"));
+    }
+
+    #[test]
+    fn test_load_strict() {
+        let library = "attack := 1d20 + 7\ndamage := 2d6 + -1";
+
+        assert_eq!(MacroRegistry::new().load(library).unwrap().len(), 2);
+        assert!(matches!(
+            MacroRegistry::new().load_strict(library),
+            Err(MacroError::Parsing { name, .. }) if name == "damage"
+        ));
+    }
+}
@@ -0,0 +1,250 @@
+//! Damage that is resisted, doubled or ignored based on its type. The
+//! type of a part of the damage is its annotation, `2d6 [fire] + 1d4
+//! [cold]` deals fire and cold damage. The parts without one are untyped
+//! and are never mitigated.
+
+use std::collections::HashMap;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    interpreter::{NaiveRoller, RollerResult},
+    syntax::{AnnotationString, BinaryOperator, Expression},
+};
+
+/// How the target takes the damage of a type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Response {
+    /// Halved, rounded down
+    Resistant,
+    Vulnerable,
+    Immune,
+}
+
+impl Response {
+    pub fn apply(self, damage: i64) -> i64 {
+        match self {
+            Response::Resistant => damage.div_euclid(2),
+            Response::Vulnerable => damage.saturating_mul(2),
+            Response::Immune => 0,
+        }
+    }
+}
+
+/// The responses of a target to the types of damage, the types are
+/// matched ignoring the case
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Mitigation {
+    responses: HashMap<String, Response>,
+}
+
+impl Mitigation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn resist(self, kind: &str) -> Self {
+        self.with(kind, Response::Resistant)
+    }
+
+    pub fn vulnerable(self, kind: &str) -> Self {
+        self.with(kind, Response::Vulnerable)
+    }
+
+    pub fn immune(self, kind: &str) -> Self {
+        self.with(kind, Response::Immune)
+    }
+
+    pub fn with(mut self, kind: &str, response: Response) -> Self {
+        self.responses.insert(kind.trim().to_lowercase(), response);
+        self
+    }
+
+    pub fn response(&self, kind: &str) -> Option<Response> {
+        self.responses.get(&kind.trim().to_lowercase()).copied()
+    }
+
+    /// Mitigate every type of the damage separately
+    pub fn apply(&self, damage: &TypedDamage) -> MitigatedDamage {
+        let breakdown = damage
+            .parts
+            .iter()
+            .map(|part| {
+                let response = part.kind.as_ref().and_then(|kind| self.response(kind));
+                let taken = response.map_or(part.total, |r| r.apply(part.total));
+
+                DamageBreakdown {
+                    kind: part.kind.clone(),
+                    rolled: part.total,
+                    response,
+                    taken,
+                }
+            })
+            .collect();
+
+        MitigatedDamage { breakdown }
+    }
+}
+
+/// The damage of a single type
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DamagePart {
+    /// `None` for the untyped damage
+    pub kind: Option<AnnotationString>,
+    pub total: i64,
+}
+
+/// A roll split by the types of damage, in the order they first appeared
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TypedDamage {
+    pub parts: Vec<DamagePart>,
+}
+
+impl TypedDamage {
+    /// Roll the terms of the sum one by one, the terms of the same type
+    /// are added together. Only the annotations on the terms of the sum
+    /// give them a type, `2 * d6 [fire]` is untyped.
+    pub fn roll<R: Rng>(roller: &mut NaiveRoller<R>, expr: &Expression) -> RollerResult<Self> {
+        let mut terms = vec![];
+        split_terms(expr, 1, &mut terms);
+
+        let mut damage = TypedDamage::default();
+        for (sign, term) in terms {
+            let kind = match term {
+                Expression::Annotated { annotation, .. } => Some(annotation.clone()),
+                _ => None,
+            };
+            let total = sign * roller.roll_naive(term)?.total()?;
+
+            match damage.parts.iter_mut().find(|part| part.kind == kind) {
+                Some(part) => part.total += total,
+                None => damage.parts.push(DamagePart { kind, total }),
+            }
+        }
+
+        Ok(damage)
+    }
+
+    pub fn total(&self) -> i64 {
+        self.parts.iter().map(|part| part.total).sum()
+    }
+}
+
+fn split_terms<'a>(expr: &'a Expression, sign: i64, terms: &mut Vec<(i64, &'a Expression)>) {
+    match expr {
+        Expression::Binop {
+            operator: operator @ (BinaryOperator::Add | BinaryOperator::Subtract),
+            lhs,
+            rhs,
+        } => {
+            split_terms(lhs, sign, terms);
+            let sign = if *operator == BinaryOperator::Add {
+                sign
+            } else {
+                -sign
+            };
+            split_terms(rhs, sign, terms);
+        }
+        Expression::UnaryNegation(expr) => split_terms(expr, -sign, terms),
+        Expression::Subexpression(expr)
+        | Expression::Tagged {
+            expression: expr, ..
+        } => split_terms(expr, sign, terms),
+        _ => terms.push((sign, expr)),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DamageBreakdown {
+    pub kind: Option<AnnotationString>,
+    pub rolled: i64,
+    pub response: Option<Response>,
+    pub taken: i64,
+}
+
+/// The damage after the mitigation, with how much of every type was taken
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MitigatedDamage {
+    pub breakdown: Vec<DamageBreakdown>,
+}
+
+impl MitigatedDamage {
+    /// The damage taken over every type, a negative part lowers the rest
+    /// but the total does not heal
+    pub fn total(&self) -> i64 {
+        let total = self.breakdown.iter().map(|part| part.taken).sum::<i64>();
+        total.max(0)
+    }
+}
+
+/// Roll the damage and mitigate it in one go
+pub fn roll_damage<R: Rng>(
+    roller: &mut NaiveRoller<R>,
+    expr: &Expression,
+    mitigation: &Mitigation,
+) -> RollerResult<MitigatedDamage> {
+    Ok(mitigation.apply(&TypedDamage::roll(roller, expr)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        interpreter::StandardNaiveRoller,
+        mitigation::{roll_damage, Mitigation, Response, TypedDamage},
+        parser::parse,
+    };
+
+    #[test]
+    fn test_mitigation() {
+        let expr = parse("10 [fire] + 7 [Poison] + 3 - 1 [fire] + 5 [cold]").unwrap();
+        let mut roller = StandardNaiveRoller::new_seeded(1);
+
+        let damage = TypedDamage::roll(&mut roller, &expr).unwrap();
+        let parts = damage
+            .parts
+            .iter()
+            .map(|part| (part.kind.as_deref(), part.total))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            parts,
+            [
+                (Some("fire"), 9),
+                (Some("Poison"), 7),
+                (None, 3),
+                (Some("cold"), 5)
+            ]
+        );
+        assert_eq!(damage.total(), 24);
+
+        let mitigation = Mitigation::new()
+            .resist("Fire")
+            .immune("poison")
+            .vulnerable("cold");
+        let mitigated = roll_damage(&mut roller, &expr, &mitigation).unwrap();
+        let taken = mitigated
+            .breakdown
+            .iter()
+            .map(|part| (part.response, part.taken))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            taken,
+            [
+                (Some(Response::Resistant), 4),
+                (Some(Response::Immune), 0),
+                (None, 3),
+                (Some(Response::Vulnerable), 10)
+            ]
+        );
+        assert_eq!(mitigated.total(), 17);
+
+        let expr = parse("(2d6 + 3) [slashing] - 20").unwrap();
+        let mitigated = roll_damage(&mut roller, &expr, &Mitigation::new()).unwrap();
+        assert_eq!(mitigated.breakdown[1].taken, -20);
+        assert_eq!(mitigated.total(), 0);
+
+        let expr = parse("10 [fire] + 4 [cold] - 3").unwrap();
+        let mitigated = roll_damage(&mut roller, &expr, &mitigation).unwrap();
+        assert_eq!(mitigated.total(), 5 + 8 - 3);
+    }
+}
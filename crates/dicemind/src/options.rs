@@ -1,12 +1,183 @@
+use num::rational::Ratio;
 use serde::{Deserialize, Serialize};
 
-use crate::syntax::PositiveInteger;
+use std::cmp::Ordering;
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+use crate::{
+    macros::MacroRegistry,
+    syntax::{PositiveInteger, Selector},
+};
+
+/// Which faces of the dice are tagged as critical
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CriticalRange {
+    /// The highest face for the successes and the 1 for the failures
+    #[default]
+    Natural,
+    /// The faces the selector matches, like `>=19`
+    Matching(Selector),
+    /// The table plays without them
+    Never,
+}
+
+impl CriticalRange {
+    /// `n` and the faces above it
+    pub fn at_least(n: u64) -> Self {
+        Self::Matching(Selector {
+            relation: Ordering::Greater,
+            inclusive: true,
+            n: n.into(),
+        })
+    }
+
+    /// `n` and the faces below it
+    pub fn at_most(n: u64) -> Self {
+        Self::Matching(Selector {
+            relation: Ordering::Less,
+            inclusive: true,
+            n: n.into(),
+        })
+    }
+
+    /// Whether the face is critical, `natural` is the face of the
+    /// `Natural` range
+    pub fn matches(&self, value: i64, natural: i64) -> bool {
+        match self {
+            CriticalRange::Natural => value == natural,
+            CriticalRange::Matching(selector) => selector.matches(value),
+            CriticalRange::Never => false,
+        }
+    }
+}
+
+/// How the decimals are rounded to the integers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Rounding {
+    /// Down to the integer below, `-1.5` to -2, as most of the tables do
+    #[default]
+    Floor,
+    /// Up to the integer above
+    Ceiling,
+    /// To the nearest integer, the halves up, `2.5` to 3 and `-2.5` to -2
+    HalfUp,
+    /// To the nearest integer, the halves to the even one, `2.5` to 2 and
+    /// `3.5` to 4
+    HalfEven,
+}
+
+/// How a comparison is told apart in degrees of success, see
+/// `Value::Degree`. The margin is counted from the lowest total that
+/// succeeds, the DC, so `d20 + 7 > 14` succeeds by 2 with a 17.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Degrees {
+    /// How far past the DC a success turns critical, and how far below it
+    /// a failure does
+    pub critical_margin: i64,
+    /// Every critical success among the dice on the left moves the degree
+    /// one up, every critical failure moves it one down
+    pub natural_shift: bool,
+}
+
+impl Degrees {
+    /// The degrees of the second edition of Pathfinder, ten past the DC
+    /// and the natural 20s and 1s
+    pub const PF2E: Degrees = Degrees {
+        critical_margin: 10,
+        natural_shift: true,
+    };
+}
+
+impl Default for Degrees {
+    fn default() -> Self {
+        Self::PF2E
+    }
+}
+
+/// What every dice comes up instead of being rolled, see
+/// `RollerOptions::with_fixed_dice`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum FixedDice {
+    Minimum,
+    Maximum,
+    /// Halfway between the lowest and the highest face, rounded down, so a
+    /// d20 takes 10. The weights are not looked at, `d{2:1, 9:5}` takes 5,
+    /// which is none of its faces
+    Midpoint,
+    /// The face brought within the faces of the dice, a d20 takes 20 with
+    /// `Constant(20)` and a d6 takes 6
+    Constant(i64),
+}
+
+impl FixedDice {
+    /// The face of a dice from the lowest to the highest face
+    pub fn face(&self, lowest: i64, highest: i64) -> i64 {
+        match *self {
+            FixedDice::Minimum => lowest,
+            FixedDice::Maximum => highest,
+            FixedDice::Midpoint => (lowest + highest).div_euclid(2),
+            FixedDice::Constant(face) => face.clamp(lowest, highest),
+        }
+    }
+}
+
+impl Rounding {
+    pub fn round(&self, value: &Ratio<i64>) -> i64 {
+        // The reduced denominator is always positive
+        let (numer, denom) = (*value.numer(), *value.denom());
+        let (floor, rest) = (numer.div_euclid(denom), numer.rem_euclid(denom));
+        // Twice the rest against the denominator tells the halves apart
+        let half = (2 * rest as i128).cmp(&(denom as i128));
+
+        let up = match self {
+            Rounding::Floor => false,
+            Rounding::Ceiling => rest != 0,
+            Rounding::HalfUp => half.is_ge(),
+            Rounding::HalfEven => half.is_gt() || (half.is_eq() && floor % 2 != 0),
+        };
+
+        // Rounding up only ever happens below the integer above
+        floor + up as i64
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct RollerOptions {
     assumed_quantity: PositiveInteger,
     assumed_power: PositiveInteger,
     chain_explosions: bool,
+    #[serde(default)]
+    macros: MacroRegistry,
+    // No limits unless they are set
+    #[serde(default)]
+    max_dice_per_term: Option<usize>,
+    #[serde(default)]
+    max_total_dice: Option<usize>,
+    #[serde(default)]
+    max_explosion_depth: Option<usize>,
+    #[serde(default)]
+    fuel: Option<u64>,
+    #[serde(default)]
+    critical_success: CriticalRange,
+    #[serde(default)]
+    critical_failure: CriticalRange,
+    #[serde(default)]
+    secure: bool,
+    #[serde(default)]
+    packed_dice: bool,
+    #[serde(default)]
+    rounding: Rounding,
+    #[serde(default)]
+    round_each_step: bool,
+    #[serde(default = "default_precision")]
+    precision: u32,
+    #[serde(default)]
+    degrees: Option<Degrees>,
+    #[serde(default)]
+    fixed_dice: Option<FixedDice>,
+}
+
+fn default_precision() -> u32 {
+    2
 }
 
 impl Default for RollerOptions {
@@ -15,20 +186,403 @@ impl Default for RollerOptions {
             assumed_quantity: 1u32.into(),
             assumed_power: 6u32.into(),
             chain_explosions: false,
+            macros: MacroRegistry::default(),
+            max_dice_per_term: None,
+            max_total_dice: None,
+            max_explosion_depth: None,
+            fuel: None,
+            critical_success: CriticalRange::Natural,
+            critical_failure: CriticalRange::Natural,
+            secure: false,
+            packed_dice: false,
+            rounding: Rounding::Floor,
+            round_each_step: false,
+            precision: default_precision(),
+            degrees: None,
+            fixed_dice: None,
         }
     }
 }
 
 impl RollerOptions {
+    /// `RollerOptions::builder().power(20).chain_explosions(true).build()`
+    pub fn builder() -> RollerOptionsBuilder {
+        RollerOptionsBuilder::default()
+    }
+
     pub fn chain_explosions(&self) -> bool {
         return self.chain_explosions
     }
 
+    /// Keep exploding the dice that came from an explosion
+    pub fn with_chain_explosions(mut self, chain: bool) -> Self {
+        self.chain_explosions = chain;
+        self
+    }
+
+    pub fn set_chain_explosions(&mut self, chain: bool) {
+        self.chain_explosions = chain;
+    }
+
+    /// How many dice `d6` rolls
     pub fn quantity(&self) -> PositiveInteger {
         self.assumed_quantity.clone()
     }
 
+    pub fn set_quantity(&mut self, quantity: u64) {
+        self.assumed_quantity = quantity.into();
+    }
+
+    /// How many sides a bare `4d` has
     pub fn power(&self) -> PositiveInteger {
         self.assumed_power.clone()
     }
+
+    pub fn set_power(&mut self, power: u64) {
+        self.assumed_power = power.into();
+    }
+
+    pub fn macros(&self) -> &MacroRegistry {
+        &self.macros
+    }
+
+    pub fn macros_mut(&mut self) -> &mut MacroRegistry {
+        &mut self.macros
+    }
+
+    pub fn max_dice_per_term(&self) -> Option<usize> {
+        self.max_dice_per_term
+    }
+
+    /// Fail the dice rolling more than `max` dice at once, before they are
+    /// rolled
+    pub fn with_max_dice_per_term(mut self, max: usize) -> Self {
+        self.max_dice_per_term = Some(max);
+        self
+    }
+
+    pub fn max_total_dice(&self) -> Option<usize> {
+        self.max_total_dice
+    }
+
+    /// Fail the roll once it rolled more than `max` dice in total, the
+    /// ones from the explosions included
+    pub fn with_max_total_dice(mut self, max: usize) -> Self {
+        self.max_total_dice = Some(max);
+        self
+    }
+
+    pub fn max_explosion_depth(&self) -> Option<usize> {
+        self.max_explosion_depth
+    }
+
+    /// Fail the dice that keep exploding more than `max` times in a row
+    pub fn with_max_explosion_depth(mut self, max: usize) -> Self {
+        self.max_explosion_depth = Some(max);
+        self
+    }
+
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    /// Give every roll `fuel` steps of work, a node visited and a dice
+    /// rolled take one each. The same roll under the same seed always
+    /// takes the same amount, unlike a timeout.
+    pub fn with_fuel(mut self, fuel: u64) -> Self {
+        self.fuel = Some(fuel);
+        self
+    }
+
+    /// The faces tagged as critical successes when rolled, the `cs`
+    /// augment still overrides them for its dice
+    pub fn critical_success(&self) -> &CriticalRange {
+        &self.critical_success
+    }
+
+    pub fn set_critical_success(&mut self, range: CriticalRange) {
+        self.critical_success = range;
+    }
+
+    /// The faces tagged as critical failures when rolled, the `cf`
+    /// augment still overrides them for its dice
+    pub fn critical_failure(&self) -> &CriticalRange {
+        &self.critical_failure
+    }
+
+    pub fn set_critical_failure(&mut self, range: CriticalRange) {
+        self.critical_failure = range;
+    }
+
+    pub fn secure(&self) -> bool {
+        self.secure
+    }
+
+    /// Only roll from a cryptographically secure generator, the rollers
+    /// built any other way refuse to roll with `RollerError::InsecureRng`
+    pub fn with_secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    pub fn packed_dice(&self) -> bool {
+        self.packed_dice
+    }
+
+    /// Roll several of the plain dice from every number drawn, many times
+    /// faster for the big pools and the simulations. A seed rolls other
+    /// dice with it than without it.
+    pub fn with_packed_dice(mut self, packed: bool) -> Self {
+        self.packed_dice = packed;
+        self
+    }
+
+    /// How the decimals are rounded where the integers are needed
+    pub fn rounding(&self) -> Rounding {
+        self.rounding
+    }
+
+    pub fn with_rounding(mut self, rounding: Rounding) -> Self {
+        self.rounding = rounding;
+        self
+    }
+
+    pub fn round_each_step(&self) -> bool {
+        self.round_each_step
+    }
+
+    /// Round every operation with a decimal right away instead of carrying
+    /// the exact value on, `1.5 * d6 + 1.5 * d6` then rounds both products
+    /// instead of only the sum
+    pub fn with_round_each_step(mut self, each: bool) -> Self {
+        self.round_each_step = each;
+        self
+    }
+
+    pub fn precision(&self) -> u32 {
+        self.precision
+    }
+
+    /// How many decimal places the decimals are written out with, see
+    /// `NaiveValue::decimal_string`
+    pub fn with_precision(mut self, places: u32) -> Self {
+        self.precision = places;
+        self
+    }
+
+    pub fn degrees(&self) -> Option<&Degrees> {
+        self.degrees.as_ref()
+    }
+
+    /// Tell the comparisons of `NaiveRoller::outcome` apart in degrees of
+    /// success instead of a yes or a no. The totals rolled stay the same.
+    pub fn with_degrees(mut self, degrees: Option<Degrees>) -> Self {
+        self.degrees = degrees;
+        self
+    }
+
+    pub fn fixed_dice(&self) -> Option<FixedDice> {
+        self.fixed_dice
+    }
+
+    /// Take every dice on the same face instead of rolling it, for the
+    /// passive scores and the best and the worst cases. The dice are never
+    /// rolled again, the explosions and the rerolls are left out.
+    pub fn with_fixed_dice(mut self, fixed: Option<FixedDice>) -> Self {
+        self.fixed_dice = fixed;
+        self
+    }
+
+    pub fn set_fixed_dice(&mut self, fixed: Option<FixedDice>) {
+        self.fixed_dice = fixed;
+    }
+}
+
+/// Sets the options one at a time, the ones left out are the defaults
+#[derive(Debug, Clone, Default)]
+pub struct RollerOptionsBuilder {
+    options: RollerOptions,
+}
+
+impl RollerOptionsBuilder {
+    pub fn quantity(mut self, quantity: u64) -> Self {
+        self.options.set_quantity(quantity);
+        self
+    }
+
+    pub fn power(mut self, power: u64) -> Self {
+        self.options.set_power(power);
+        self
+    }
+
+    pub fn chain_explosions(mut self, chain: bool) -> Self {
+        self.options.set_chain_explosions(chain);
+        self
+    }
+
+    pub fn macros(mut self, macros: MacroRegistry) -> Self {
+        self.options.macros = macros;
+        self
+    }
+
+    pub fn max_dice_per_term(mut self, max: usize) -> Self {
+        self.options.max_dice_per_term = Some(max);
+        self
+    }
+
+    pub fn max_total_dice(mut self, max: usize) -> Self {
+        self.options.max_total_dice = Some(max);
+        self
+    }
+
+    pub fn max_explosion_depth(mut self, max: usize) -> Self {
+        self.options.max_explosion_depth = Some(max);
+        self
+    }
+
+    pub fn fuel(mut self, fuel: u64) -> Self {
+        self.options.fuel = Some(fuel);
+        self
+    }
+
+    pub fn critical_success(mut self, range: CriticalRange) -> Self {
+        self.options.set_critical_success(range);
+        self
+    }
+
+    pub fn critical_failure(mut self, range: CriticalRange) -> Self {
+        self.options.set_critical_failure(range);
+        self
+    }
+
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.options.secure = secure;
+        self
+    }
+
+    pub fn packed_dice(mut self, packed: bool) -> Self {
+        self.options.packed_dice = packed;
+        self
+    }
+
+    pub fn rounding(mut self, rounding: Rounding) -> Self {
+        self.options.rounding = rounding;
+        self
+    }
+
+    pub fn round_each_step(mut self, each: bool) -> Self {
+        self.options.round_each_step = each;
+        self
+    }
+
+    pub fn precision(mut self, places: u32) -> Self {
+        self.options.precision = places;
+        self
+    }
+
+    pub fn degrees(mut self, degrees: Degrees) -> Self {
+        self.options.degrees = Some(degrees);
+        self
+    }
+
+    pub fn fixed_dice(mut self, fixed: FixedDice) -> Self {
+        self.options.fixed_dice = Some(fixed);
+        self
+    }
+
+    pub fn build(self) -> RollerOptions {
+        self.options
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        options::RollerOptions,
+        prelude::{parse, StandardNaiveRoller},
+    };
+
+    #[test]
+    fn test_builder() {
+        let options = RollerOptions::builder()
+            .power(20)
+            .chain_explosions(true)
+            .build();
+
+        assert_eq!(options.power(), 20u32.into());
+        assert_eq!(options.quantity(), 1u32.into());
+        assert!(options.chain_explosions());
+
+        let mut set = RollerOptions::default();
+        set.set_power(20);
+        set.set_chain_explosions(true);
+        assert_eq!(set, options);
+
+        assert_eq!(
+            RollerOptions::builder().fuel(10).max_total_dice(4).build(),
+            RollerOptions::default().with_fuel(10).with_max_total_dice(4)
+        );
+
+        // A bare `d` rolls the assumed sides
+        let roll = StandardNaiveRoller::new_seeded(0)
+            .with_options(RollerOptions::builder().quantity(3).power(1).build())
+            .roll_naive(parse("d").unwrap());
+        assert_eq!(roll.map(|v| v.total().unwrap()), Ok(3));
+    }
+
+    #[test]
+    fn test_rounding() {
+        use num::rational::Ratio;
+
+        use crate::options::Rounding;
+
+        let rounded = |rounding: Rounding| {
+            [(-5, 2), (-3, 2), (5, 2), (7, 2), (13, 10), (17, 10), (4, 1)]
+                .map(|(numer, denom)| rounding.round(&Ratio::new(numer, denom)))
+        };
+
+        assert_eq!(rounded(Rounding::Floor), [-3, -2, 2, 3, 1, 1, 4]);
+        assert_eq!(rounded(Rounding::Ceiling), [-2, -1, 3, 4, 2, 2, 4]);
+        assert_eq!(rounded(Rounding::HalfUp), [-2, -1, 3, 4, 1, 2, 4]);
+        assert_eq!(rounded(Rounding::HalfEven), [-2, -2, 2, 4, 1, 2, 4]);
+        assert_eq!(Rounding::HalfUp.round(&Ratio::new(i64::MAX, 2)), i64::MAX / 2 + 1);
+    }
+
+    #[test]
+    fn test_critical_range() {
+        use crate::{interpreter::DiceRollTag, options::CriticalRange};
+
+        let tags = |options: RollerOptions| {
+            let value = StandardNaiveRoller::new_seeded(0)
+                .with_options(options)
+                .roll_naive(parse("200d20").unwrap())
+                .unwrap();
+            value
+                .dice()
+                .map(|d| {
+                    (
+                        d.value,
+                        d.tag.contains(DiceRollTag::SUCCESS),
+                        d.tag.contains(DiceRollTag::FAIL),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+
+        for (value, success, fail) in tags(RollerOptions::default()) {
+            assert_eq!(success, value == 20);
+            assert_eq!(fail, value == 1);
+        }
+
+        let options = RollerOptions::builder()
+            .critical_success(CriticalRange::at_least(19))
+            .critical_failure(CriticalRange::Never)
+            .build();
+        let rolled = tags(options);
+        assert!(rolled.iter().any(|&(value, success, _)| value == 19 && success));
+        for (value, success, fail) in rolled {
+            assert_eq!(success, value >= 19);
+            assert!(!fail);
+        }
+    }
 }
@@ -1,39 +1,673 @@
-use std::cmp::Ordering;
+use std::{cmp::Ordering, ops::Range};
 
-use num::{bigint::Sign, Zero};
+use num::Zero;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use crate::syntax::{
-    Affix, AnnotationString, SelectorOp, Augmentation, BinaryOperator, Expression, PositiveInteger, Selector
+use crate::{
+    dialect::{Associativity, AugmentParser, Dialect, Native, PrefixOperator},
+    extensions::AugmentRegistry,
+    options::RollerOptions,
+    syntax::{
+        Affix, AnnotationString, Augmentation, Augmentations, BinaryOperator, Decimal, Expression,
+        Face, FunctionName, Integer, MacroName, PositiveInteger, Selector, SelectorOp,
+    },
 };
 
+/// Offsets of the chars in the input, the end is exclusive
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Hash, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
 #[derive(Debug, Error, Clone, Serialize, Deserialize, Copy, Hash, PartialEq, Eq)]
 pub enum ParsingError {
     #[error("The string did not contain any expressions")]
-    EmptyExpression,
+    EmptyExpression { span: Span },
     #[error("Unbalanced left parenthis")]
-    UnbalancedLeftParen,
+    UnbalancedLeftParen { span: Span },
     #[error("Unbalanced right parenthis")]
-    UnbalancedRightParen,
+    UnbalancedRightParen { span: Span },
     #[error("Unabalanced annotation left bracket")]
-    UnbalancedLeftBracket,
+    UnbalancedLeftBracket { span: Span },
     #[error("Unabalanced annotation right bracket")]
-    UnbalancedRightBracket,
+    UnbalancedRightBracket { span: Span },
     #[error("Unexpected symbol `{char}`")]
-    UnexpectedSymbol { char: char },
+    UnexpectedSymbol { char: char, span: Span },
     #[error("No operands")]
-    NoOperands { operator: BinaryOperator },
+    NoOperands {
+        operator: BinaryOperator,
+        span: Span,
+    },
     #[error("Missing operator between operands")]
-    MissingOperator,
+    MissingOperator { span: Span },
+    #[error("Unbalanced left brace")]
+    UnbalancedLeftBrace { span: Span },
+    #[error("The conditional is missing the `:` branch")]
+    IncompleteConditional { span: Span },
+    #[error("{ambiguity}")]
+    Ambiguous { ambiguity: Ambiguity, span: Span },
+    #[error("{notation} need the notation {:?}, the parser is on {version:?}", notation.since())]
+    Unsupported {
+        notation: Notation,
+        version: NotationVersion,
+        span: Span,
+    },
+}
+
+/// The spellings with a silent default, only rejected by `parse_strict`
+#[derive(Debug, Error, Clone, Serialize, Deserialize, Copy, Hash, PartialEq, Eq)]
+pub enum Ambiguity {
+    #[error("The dice has no quantity, write `1d` for a single dice")]
+    ImplicitQuantity,
+    #[error("The dice has no sides, write them out like `d20`")]
+    ImplicitPower,
+    #[error("The truncation has no count, write it out like `kh1`")]
+    ImplicitCount,
+    #[error("The sign right after an operator reads as another operator, wrap the signed operand in parentheses")]
+    SignAfterOperator,
+}
+
+impl Ambiguity {
+    /// What the spanned part of the input should be written as instead,
+    /// the defaults written out are the ones the roller assumes
+    pub fn replacement(&self, written: &str, options: &RollerOptions) -> String {
+        match self {
+            Ambiguity::ImplicitQuantity => format!("{}{written}", options.quantity()),
+            Ambiguity::ImplicitPower => format!("{written}{}", options.power()),
+            Ambiguity::ImplicitCount => format!("{written}1"),
+            Ambiguity::SignAfterOperator => format!("({written})"),
+        }
+    }
+}
+
+/// The revisions of the notation. A bot parsing the macros of its users
+/// with a pinned version keeps parsing them the same way after the crate
+/// is upgraded, the newer syntax is rejected instead of changing what
+/// an old macro means.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Hash, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum NotationVersion {
+    /// The original notation. The dice with the truncations, the filters,
+    /// the emphasis and the explosions, the arithmetic, the comparisons,
+    /// the chains and the annotations
+    V1,
+    /// Adds the functions, the macro references, the groups, the
+    /// repetitions, the conditionals, the advantage, the rerolls, the
+    /// compounding explosions, the critical ranges, the bounds, the
+    /// success pools and the inclusive comparison points
+    V2,
+    /// Adds the weighted dice and the decimals
+    #[default]
+    V3,
+}
+
+impl NotationVersion {
+    pub const LATEST: NotationVersion = NotationVersion::V3;
+}
+
+/// The syntax that only some of the versions understand
+#[derive(Debug, Error, Clone, Serialize, Deserialize, Copy, Hash, PartialEq, Eq)]
+pub enum Notation {
+    #[error("Functions")]
+    Functions,
+    #[error("Macro references")]
+    References,
+    #[error("Groups")]
+    Groups,
+    #[error("Repetitions")]
+    Repetitions,
+    #[error("Conditionals")]
+    Conditionals,
+    #[error("Advantage rolls")]
+    Advantage,
+    #[error("Rerolls")]
+    Rerolls,
+    #[error("Compounding explosions")]
+    Compounding,
+    #[error("Critical ranges")]
+    Criticals,
+    #[error("Bounds")]
+    Bounds,
+    #[error("Success pools")]
+    Successes,
+    #[error("Inclusive comparison points")]
+    InclusiveSelectors,
+    #[error("Weighted dice")]
+    WeightedDice,
+    #[error("Decimals")]
+    Decimals,
+}
+
+impl Notation {
+    /// The first version with the syntax
+    pub fn since(&self) -> NotationVersion {
+        match self {
+            Notation::WeightedDice | Notation::Decimals => NotationVersion::V3,
+            _ => NotationVersion::V2,
+        }
+    }
+}
+
+/// How the input is parsed
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Hash, PartialEq, Eq, Default)]
+pub struct ParserOptions {
+    pub notation: NotationVersion,
+}
+
+impl ParserOptions {
+    pub fn with_notation(mut self, notation: NotationVersion) -> Self {
+        self.notation = notation;
+        self
+    }
+}
+
+impl ParsingError {
+    /// Where in the input the error is, counted in chars
+    pub fn span(&self) -> Span {
+        use ParsingError::*;
+
+        match *self {
+            EmptyExpression { span }
+            | UnbalancedLeftParen { span }
+            | UnbalancedRightParen { span }
+            | UnbalancedLeftBracket { span }
+            | UnbalancedRightBracket { span }
+            | UnexpectedSymbol { span, .. }
+            | NoOperands { span, .. }
+            | MissingOperator { span }
+            | UnbalancedLeftBrace { span }
+            | IncompleteConditional { span }
+            | Ambiguous { span, .. }
+            | Unsupported { span, .. } => span,
+        }
+    }
+}
+
+/// A parsing error along with the part of the input it is about
+#[derive(Debug, Error, Clone, Hash, PartialEq, Eq)]
+#[error("{error}")]
+pub struct Diagnostic {
+    pub error: ParsingError,
+    // Byte offsets, for slicing the input string
+    pub bytes: Range<usize>,
+}
+
+impl Diagnostic {
+    pub fn new(input: &str, error: ParsingError) -> Self {
+        let span = error.span();
+        let byte_offset = |n| {
+            input
+                .char_indices()
+                .nth(n)
+                .map_or(input.len(), |(offset, _)| offset)
+        };
+
+        Self {
+            error,
+            bytes: byte_offset(span.start)..byte_offset(span.end),
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        self.error.span()
+    }
+
+    /// A line of carets to be printed under the input
+    pub fn underline(&self) -> String {
+        let span = self.span();
+        format!(
+            "{}{}",
+            " ".repeat(span.start),
+            "^".repeat(span.len().max(1))
+        )
+    }
+
+    /// The input with the ambiguous part written out as the roller with
+    /// the options would roll it, if there is a fix
+    pub fn fix_it(&self, input: &str, options: &RollerOptions) -> Option<String> {
+        let ParsingError::Ambiguous { ambiguity, .. } = self.error else {
+            return None;
+        };
+
+        let mut fixed = input.to_string();
+        let replacement = ambiguity.replacement(&input[self.bytes.clone()], options);
+        fixed.replace_range(self.bytes.clone(), &replacement);
+
+        Some(fixed)
+    }
 }
 
 pub fn parse(input: &str) -> Result<Expression, ParsingError> {
+    parse_with(input, &AugmentRegistry::default())
+}
+
+/// Parse with the custom augments recognized after the dice
+pub fn parse_with(input: &str, augments: &AugmentRegistry) -> Result<Expression, ParsingError> {
+    let chars: Vec<char> = input.chars().collect();
+    Parser::new(&chars, &Native, augments, false).parse_expression()
+}
+
+/// Parse only the notation of the version in the options
+pub fn parse_with_options(input: &str, options: &ParserOptions) -> Result<Expression, ParsingError> {
+    let chars: Vec<char> = input.chars().collect();
+    let augments = AugmentRegistry::default();
+    let mut parser = Parser::new(&chars, &Native, &augments, false);
+    parser.notation = options.notation;
+
+    parser.parse_expression()
+}
+
+/// Parse the notation of another dice roller, like `Roll20`
+pub fn parse_dialect(input: &str, dialect: &dyn Dialect) -> Result<Expression, ParsingError> {
+    let chars: Vec<char> = input.chars().collect();
+    Parser::new(&chars, dialect, &AugmentRegistry::default(), false).parse_expression()
+}
+
+/// Parse, locating the error in the input string
+pub fn parse_spanned(input: &str) -> Result<Expression, Diagnostic> {
+    parse(input).map_err(|err| Diagnostic::new(input, err))
+}
+
+/// Parse, rejecting the spellings that lean on a default: `d6` for `1d6`,
+/// a bare `d`, `kh` without a count and signs right after an operator as
+/// in `2 - -3`. Meant for validating the curated macro libraries, where a
+/// default of the roller silently changing the roll is a bug.
+pub fn parse_strict(input: &str) -> Result<Expression, Diagnostic> {
     let chars: Vec<char> = input.chars().collect();
-    _parse(&chars[..])
+    let augments = AugmentRegistry::default();
+    let mut parser = Parser::new(&chars, &Native, &augments, false);
+    parser.strict = true;
+
+    parser
+        .parse_expression()
+        .map_err(|err| Diagnostic::new(input, err))
+}
+
+/// Parse, continuing after the errors to report all of them at once.
+/// The expression is whatever could be recovered, it should not be
+/// rolled unless the diagnostics are empty.
+pub fn parse_all_errors(input: &str) -> (Option<Expression>, Vec<Diagnostic>) {
+    let chars: Vec<char> = input.chars().collect();
+    let augments = AugmentRegistry::default();
+    let mut parser = Parser::new(&chars, &Native, &augments, true);
+
+    let result = parser.parse_expression();
+    let mut errors = parser.recovered.unwrap_or_default();
+
+    let expr = match result {
+        Ok(expr) => Some(expr),
+        Err(err) => {
+            errors.push(err);
+            None
+        }
+    };
+
+    errors.sort_by_key(|err| err.span().start);
+    errors.dedup();
+
+    let diagnostics = errors
+        .into_iter()
+        .map(|err| Diagnostic::new(input, err))
+        .collect();
+
+    (expr, diagnostics)
+}
+
+// Annotations apply to the operand right before them,
+// along with the prefix operators in front of it
+pub(crate) const PREFIX_POWER: u8 = u8::MAX;
+pub(crate) const ANNOTATION_POWER: u8 = u8::MAX - 1;
+
+// Between the commas and the comparisons, the branches are parsed at the
+// same power so `a ? b : c ? d : e` nests to the right
+pub(crate) const CONDITIONAL_POWER: u8 = 2;
+
+// The left and right binding powers, the operator takes the
+// operands with a lower binding power than its own
+pub(crate) fn binding_power(operator: BinaryOperator, associativity: Associativity) -> (u8, u8) {
+    let power = u8::from(operator) * 2 + 1;
+
+    match associativity {
+        Associativity::Left => (power, power + 1),
+        Associativity::Right => (power + 1, power),
+    }
+}
+
+// Roll the dice twice, keeping the high or the low one
+pub(crate) fn advantage(power: Expression, affix: Affix) -> Expression {
+    Expression::Dice {
+        quantity: Some(Box::new(Expression::Constant(2.into()))),
+        power: Some(Box::new(power)),
+        augmentations: smallvec::smallvec![Augmentation::Truncate {
+            op: SelectorOp::Keep,
+            affix,
+            n: Some(1u32.into()),
+        }],
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Number(PositiveInteger),
+    // `1.5`, the digits on both sides of the point
+    Decimal(Decimal),
+    // `d`, both in `2d6` and `d6`
+    Dice,
+    // `%` in `d%`
+    Percent,
+    // `x` in `6x(...)`
+    Repeat,
+    // An operator or a comma
+    Symbol(char),
+    Open(char),
+    Close(char),
+    // The name of the function right before the parenthesis
+    Function(FunctionName),
+    Reference(MacroName),
+    // `adv` and `dis`
+    Advantage(Affix),
+    Annotation {
+        text: AnnotationString,
+        closed: bool,
+    },
+    // Anything else, up to the next whitespace or operator
+    Unknown,
+    End,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Spanned {
+    token: Token,
+    span: Span,
+    // Preceded by whitespace
+    spaced: bool,
+}
+
+impl Spanned {
+    // Tokens that a term can start with
+    fn starts_term(&self) -> bool {
+        matches!(
+            self.token,
+            Token::Number(_)
+                | Token::Decimal(_)
+                | Token::Open(_)
+                | Token::Function(_)
+                | Token::Reference(_)
+        )
+    }
+
+    // Tokens that an operand can start with, a prefix operator included
+    fn starts_operand(&self, dialect: &dyn Dialect) -> bool {
+        match self.token {
+            Token::Dice | Token::Advantage(_) => true,
+            Token::Symbol(c) => {
+                dialect.infix_operator(c).is_none() && dialect.prefix_operator(c).is_some()
+            }
+            _ => self.starts_term(),
+        }
+    }
+
+    // Tokens after which there can not be an operand
+    fn ends_operands(&self) -> bool {
+        matches!(self.token, Token::Close(_) | Token::End)
+    }
+}
+
+// Splits the input into tokens on demand, the parser asks for the
+// augmentations explicitly since they are only valid after the dice
+struct Lexer<'a> {
+    chars: &'a [char],
+    dialect: &'a dyn Dialect,
+    pos: usize,
+    // End of the last token taken, missing operands are reported there
+    last_end: usize,
+    // Whether the last token ended an operand, then `d` and `x` right
+    // after it are the dice and the repetition
+    after_operand: bool,
+    // The operator or the comma that was the last token
+    last_symbol: Option<char>,
 }
 
-fn parse_augment_explode(mut chars: &[char]) -> Option<(Augmentation, &[char])> {
+impl<'a> Lexer<'a> {
+    fn new(chars: &'a [char], dialect: &'a dyn Dialect) -> Self {
+        Self {
+            chars,
+            dialect,
+            pos: 0,
+            last_end: 0,
+            after_operand: false,
+            last_symbol: None,
+        }
+    }
+
+    fn peek(&self) -> Spanned {
+        let mut start = self.pos;
+        while self.chars.get(start).is_some_and(|c| c.is_whitespace()) {
+            start += 1;
+        }
+
+        let spaced = start != self.pos;
+        let (token, len) = self.token_at(start, self.after_operand && !spaced);
+
+        Spanned {
+            token,
+            span: Span {
+                start,
+                end: start + len,
+            },
+            spaced,
+        }
+    }
+
+    fn next(&mut self) -> Spanned {
+        let spanned = self.peek();
+
+        self.pos = spanned.span.end;
+        self.last_end = spanned.span.end;
+        self.after_operand = matches!(
+            spanned.token,
+            Token::Number(_)
+                | Token::Decimal(_)
+                | Token::Close(_)
+                | Token::Reference(_)
+                | Token::Percent
+                | Token::Advantage(_)
+        );
+        self.last_symbol = match spanned.token {
+            Token::Symbol(c) => Some(c),
+            _ => None,
+        };
+
+        spanned
+    }
+
+    // Along with where each of them is written
+    fn augments(&mut self, custom: &AugmentRegistry) -> Vec<(Augmentation, Span)> {
+        let start = self.pos;
+        let (augments, rest) =
+            parse_augments(&self.chars[self.pos..], self.dialect.augments(), custom);
+        let augments = augments
+            .map(|(augment, span)| {
+                let span = Span {
+                    start: start + span.start,
+                    end: start + span.end,
+                };
+                (augment, span)
+            })
+            .collect();
+
+        self.pos = self.chars.len() - rest.len();
+        self.last_end = self.pos;
+        self.after_operand = true;
+        self.last_symbol = None;
+
+        augments
+    }
+
+    // `a` and `d` right after the dice roll it twice and keep one
+    fn advantage(&mut self) -> Option<Affix> {
+        let affix = match self.chars.get(self.pos)? {
+            'a' => Affix::High,
+            'd' => Affix::Low,
+            _ => return None,
+        };
+
+        self.pos += 1;
+        self.last_end = self.pos;
+        self.after_operand = true;
+        self.last_symbol = None;
+
+        Some(affix)
+    }
+
+    // The faces of a weighted dice right after the `d`, `{1:3, 2:1}`.
+    // Anything else in the braces is a group for the power.
+    fn weighted(&mut self) -> Option<(Vec<Face>, Span)> {
+        let start = self.pos;
+        let (faces, rest) = parse_faces(&self.chars[start..])?;
+
+        self.pos = self.chars.len() - rest.len();
+        self.last_end = self.pos;
+        self.after_operand = true;
+        self.last_symbol = None;
+
+        Some((
+            faces,
+            Span {
+                start,
+                end: self.pos,
+            },
+        ))
+    }
+
+    fn is_operator(&self, c: char) -> bool {
+        self.dialect.infix_operator(c).is_some()
+            || self.dialect.prefix_operator(c).is_some()
+            || (self.dialect.conditionals() && matches!(c, '?' | ':'))
+    }
+
+    fn token_at(&self, start: usize, after_operand: bool) -> (Token, usize) {
+        let chars = &self.chars[start..];
+        let Some(&c) = chars.first() else {
+            return (Token::End, 0);
+        };
+
+        if let Some((n, rest)) = parse_number(chars) {
+            // Only a digit right after the point makes it a decimal
+            if let ['.', fraction @ ..] = rest {
+                if let Some((digits, rest)) = parse_number(fraction) {
+                    let scale = (fraction.len() - rest.len()) as u32;
+                    let digits = n * PositiveInteger::from(10u32).pow(scale) + digits;
+                    return (
+                        Token::Decimal(Decimal::new(digits, scale)),
+                        chars.len() - rest.len(),
+                    );
+                }
+            }
+
+            return (Token::Number(n), chars.len() - rest.len());
+        }
+
+        match c {
+            '(' | '{' => return (Token::Open(c), 1),
+            ')' | '}' | ']' => return (Token::Close(c), 1),
+            '%' => return (Token::Percent, 1),
+            '[' => return annotation(chars),
+            // Commas separate the arguments even if they are not an operator
+            _ if c == ',' || self.is_operator(c) => return (Token::Symbol(c), 1),
+            _ => {}
+        }
+
+        if c == '@' {
+            let len = chars[1..]
+                .iter()
+                .take_while(|c| c.is_ascii_alphanumeric() || **c == '_')
+                .count();
+
+            if len != 0 {
+                let name = chars[1..=len].iter().cloned().collect();
+                return (Token::Reference(name), len + 1);
+            }
+        }
+
+        if after_operand {
+            match (c, chars.get(1)) {
+                ('d', _) => return (Token::Dice, 1),
+                ('x', Some('(')) => return (Token::Repeat, 1),
+                _ => {}
+            }
+        } else {
+            let len = chars.iter().take_while(|c| c.is_ascii_lowercase()).count();
+
+            // `d(...)` is a dice with a subexpression for power
+            if len != 0 && chars.get(len) == Some(&'(') && chars[..len] != ['d'] {
+                return (Token::Function(chars[..len].iter().cloned().collect()), len);
+            }
+
+            if self.dialect.advantage() {
+                match chars[..len] {
+                    ['a', 'd', 'v'] => return (Token::Advantage(Affix::High), len),
+                    ['d', 'i', 's'] => return (Token::Advantage(Affix::Low), len),
+                    _ => {}
+                }
+            }
+
+            if c == 'd' {
+                return (Token::Dice, 1);
+            }
+        }
+
+        // The whole word is skipped, standing in for an operand
+        let len = chars
+            .iter()
+            .take_while(|c| !c.is_whitespace() && !"(){}[]".contains(**c) && !self.is_operator(**c))
+            .count()
+            .max(1);
+
+        (Token::Unknown, len)
+    }
+}
+
+// The annotation is taken whole, up to the matching bracket
+fn annotation(chars: &[char]) -> (Token, usize) {
+    let mut unmatched = 0;
+
+    for (i, c) in chars.iter().enumerate() {
+        match c {
+            '[' => unmatched += 1,
+            ']' => {
+                unmatched -= 1;
+                if unmatched == 0 {
+                    let text = chars[1..i].iter().cloned().collect();
+                    return (Token::Annotation { text, closed: true }, i + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let text = chars[1..].iter().cloned().collect();
+    (
+        Token::Annotation {
+            text,
+            closed: false,
+        },
+        chars.len(),
+    )
+}
+
+pub(crate) fn parse_augment_explode(mut chars: &[char]) -> Option<(Augmentation, &[char])> {
     chars.first().filter(|c| **c == '!').map(|_| {
         chars = &chars[1..];
         let selector = parse_selector(chars).map(|(selector, rest)| {
@@ -45,7 +679,7 @@ fn parse_augment_explode(mut chars: &[char]) -> Option<(Augmentation, &[char])>
     })
 }
 
-fn parse_augment_emphasis(mut chars: &[char]) -> Option<(Augmentation, &[char])> {
+pub(crate) fn parse_augment_emphasis(mut chars: &[char]) -> Option<(Augmentation, &[char])> {
     chars.first().filter(|c| **c == 'e').map(|_| {
         chars = &chars[1..];
         let n = parse_number(chars).map(|(n, rest)| {
@@ -57,7 +691,7 @@ fn parse_augment_emphasis(mut chars: &[char]) -> Option<(Augmentation, &[char])>
     })
 }
 
-fn parse_truncation(mut chars: &[char]) -> Option<(Augmentation, &[char])> {
+pub(crate) fn parse_truncation(mut chars: &[char]) -> Option<(Augmentation, &[char])> {
     let kind = match chars.first()? {
         'k' => SelectorOp::Keep,
         'd' => SelectorOp::Drop,
@@ -80,7 +714,7 @@ fn parse_truncation(mut chars: &[char]) -> Option<(Augmentation, &[char])> {
     Some((Augmentation::Truncate { op: kind, affix, n }, chars))
 }
 
-fn parse_filter(mut chars: &[char]) -> Option<(Augmentation, &[char])> {
+pub(crate) fn parse_filter(mut chars: &[char]) -> Option<(Augmentation, &[char])> {
     let kind = match chars.first()? {
         'k' => SelectorOp::Keep,
         'd' => SelectorOp::Drop,
@@ -97,30 +731,80 @@ fn parse_filter(mut chars: &[char]) -> Option<(Augmentation, &[char])> {
     Some((Augmentation::Filter { op: kind, selector }, chars))
 }
 
-fn parse_augments(mut chars: &[char]) -> (impl Iterator<Item = Augmentation>, &[char]) {
-    let mut augments: Vec<Augmentation> = vec![];
-    let parsers = [
-        parse_augment_emphasis,
-        parse_augment_explode,
-        parse_truncation,
-        parse_filter,
-    ];
+pub(crate) fn parse_count(mut chars: &[char]) -> Option<(Augmentation, &[char])> {
+    let successes = match chars.first()? {
+        's' => true,
+        'f' => false,
+        _ => return None,
+    };
+
+    chars = &chars[1..];
+
+    let selector = parse_selector(chars).map(|(n, rest)| {
+        chars = rest;
+        n
+    })?;
+
+    let augment = if successes {
+        Augmentation::CountSuccesses { selector }
+    } else {
+        Augmentation::CountFailures { selector }
+    };
+
+    Some((augment, chars))
+}
+
+fn parse_augment_custom<'a>(
+    chars: &'a [char],
+    augments: &AugmentRegistry,
+) -> Option<(Augmentation, &'a [char])> {
+    let (augment, rest) = augments.find(chars)?;
+    let (n, rest) = augment.parse_argument(rest);
+
+    Some((
+        Augmentation::Custom {
+            token: augment.token().into(),
+            n,
+        },
+        rest,
+    ))
+}
+
+// The spans are relative to the start of the chars
+fn parse_augments<'a>(
+    whole: &'a [char],
+    parsers: &[AugmentParser],
+    custom: &AugmentRegistry,
+) -> (impl Iterator<Item = (Augmentation, Span)>, &'a [char]) {
+    let mut augments: Vec<(Augmentation, Span)> = vec![];
+    let mut chars = whole;
+    let span = |chars: &[char], rest: &[char]| Span {
+        start: whole.len() - chars.len(),
+        end: whole.len() - rest.len(),
+    };
 
     'outer: while !chars.is_empty() {
         for parser in parsers {
             if let Some((augment, rest)) = parser(chars) {
-                augments.push(augment);
+                augments.push((augment, span(chars, rest)));
                 chars = rest;
                 continue 'outer;
             }
         }
+
+        if let Some((augment, rest)) = parse_augment_custom(chars, custom) {
+            augments.push((augment, span(chars, rest)));
+            chars = rest;
+            continue;
+        }
+
         break;
     }
 
     (augments.into_iter(), chars)
 }
 
-fn parse_number(chars: &[char]) -> Option<(PositiveInteger, &[char])> {
+pub(crate) fn parse_number(chars: &[char]) -> Option<(PositiveInteger, &[char])> {
     if !chars.first()?.is_ascii_digit() {
         return None;
     }
@@ -140,38 +824,41 @@ fn parse_number(chars: &[char]) -> Option<(PositiveInteger, &[char])> {
     Some((number, &chars[len..]))
 }
 
-fn parse_operator(char: char) -> Option<BinaryOperator> {
-    use BinaryOperator::*;
+// `{value:weight, ...}` with the whitespace allowed around every part
+fn parse_faces(chars: &[char]) -> Option<(Vec<Face>, &[char])> {
+    fn skip(chars: &[char]) -> &[char] {
+        let len = chars.iter().take_while(|c| c.is_whitespace()).count();
+        &chars[len..]
+    }
 
-    match char {
-        ',' => Some(Chain),
-        '+' => Some(Add),
-        '-' => Some(Subtract),
-        '*' => Some(Multiply),
-        '>' => Some(GreaterThan),
-        '<' => Some(LessThan),
-        '=' => Some(Equals),
-        _ => None,
+    fn symbol(chars: &[char], c: char) -> Option<&[char]> {
+        skip(chars).strip_prefix(&[c])
     }
-}
 
-fn push_operator(
-    exprs: &mut Vec<Expression>,
-    operator: BinaryOperator,
-) -> Result<(), ParsingError> {
-    let rhs = exprs.pop().ok_or(ParsingError::NoOperands { operator })?;
-    let lhs = exprs.pop().ok_or(ParsingError::NoOperands { operator })?;
+    let mut chars = symbol(chars, '{')?;
+    let mut faces = vec![];
+    loop {
+        let negative = skip(chars).first() == Some(&'-');
+        chars = if negative { &skip(chars)[1..] } else { chars };
 
-    exprs.push(Expression::Binop {
-        operator,
-        lhs: Box::new(lhs),
-        rhs: Box::new(rhs),
-    });
+        let (value, rest) = parse_number(skip(chars))?;
+        let (weight, rest) = parse_number(skip(symbol(rest, ':')?))?;
+        let value = if negative {
+            -Integer::from(value)
+        } else {
+            value.into()
+        };
+        faces.push(Face { value, weight });
 
-    Ok(())
+        match skip(rest).split_first()? {
+            (',', rest) => chars = rest,
+            ('}', rest) => return Some((faces, rest)),
+            _ => return None,
+        }
+    }
 }
 
-fn parse_selector(chars: &[char]) -> Option<(Selector, &[char])> {
+pub(crate) fn parse_selector(chars: &[char]) -> Option<(Selector, &[char])> {
     let relation = match chars.first()? {
         '>' => Ordering::Greater,
         '<' => Ordering::Less,
@@ -179,215 +866,550 @@ fn parse_selector(chars: &[char]) -> Option<(Selector, &[char])> {
         _ => return None,
     };
 
-    let (n, rest) = parse_number(&chars[1..])?;
+    let inclusive = relation != Ordering::Equal && chars.get(1) == Some(&'=');
+    let chars = if inclusive { &chars[2..] } else { &chars[1..] };
+
+    let (n, rest) = parse_number(chars)?;
 
-    Some((Selector { relation, n }, rest))
+    Some((
+        Selector {
+            relation,
+            inclusive,
+            n,
+        },
+        rest,
+    ))
 }
 
-fn parse_subexpr(chars: &[char]) -> Result<Option<(Expression, &[char])>, ParsingError> {
-    if chars.is_empty() || chars[0] != '(' {
-        return Ok(None);
+// The notation of the augments newer than the original ones, the custom
+// augments are up to the users who registered them
+fn augment_notation(augment: &Augmentation) -> Option<Notation> {
+    use Augmentation::*;
+
+    match augment {
+        Reroll { .. } => Some(Notation::Rerolls),
+        Compound { .. } => Some(Notation::Compounding),
+        CriticalSuccess { .. } | CriticalFailure { .. } => Some(Notation::Criticals),
+        Minimum { .. } | Maximum { .. } => Some(Notation::Bounds),
+        CountSuccesses { .. } | CountFailures { .. } => Some(Notation::Successes),
+        Filter { selector, .. }
+        | Explode {
+            selector: Some(selector),
+        } if selector.inclusive => Some(Notation::InclusiveSelectors),
+        _ => None,
     }
+}
+
+struct Parser<'a> {
+    lexer: Lexer<'a>,
+    augments: &'a AugmentRegistry,
+    // Set when the errors are collected instead of stopping the parsing
+    recovered: Option<Vec<ParsingError>>,
+    // Reject the spellings with an implicit default
+    strict: bool,
+    // The syntax newer than it is rejected
+    notation: NotationVersion,
+}
 
-    if chars[0] == ')' {
-        return Err(ParsingError::UnbalancedRightParen);
+impl<'a> Parser<'a> {
+    fn new(
+        chars: &'a [char],
+        dialect: &'a dyn Dialect,
+        augments: &'a AugmentRegistry,
+        recover: bool,
+    ) -> Self {
+        Self {
+            lexer: Lexer::new(chars, dialect),
+            augments,
+            recovered: recover.then(Vec::new),
+            strict: false,
+            notation: NotationVersion::LATEST,
+        }
     }
 
-    let mut unmatched = 0;
-    let mut i = 0;
-
-    while i < chars.len() {
-        if chars[i] == '(' {
-            unmatched += 1;
-        } else if chars[i] == ')' {
-            unmatched -= 1;
-            if unmatched == 0 {
-                return Ok(Some((_parse(&chars[1..i])?, &chars[i + 1..])));
-            }
+    // Fails for the syntax the notation version does not have yet
+    fn requires(&mut self, notation: Notation, span: Span) -> Result<(), ParsingError> {
+        if notation.since() > self.notation {
+            self.fail(ParsingError::Unsupported {
+                notation,
+                version: self.notation,
+                span,
+            })?;
         }
-        i += 1;
+
+        Ok(())
     }
 
-    Err(ParsingError::UnbalancedLeftParen)
-}
+    // Only fails in the strict mode
+    fn ambiguous(&mut self, ambiguity: Ambiguity, span: Span) -> Result<(), ParsingError> {
+        if self.strict {
+            self.fail(ParsingError::Ambiguous { ambiguity, span })?;
+        }
 
-fn parse_annotation(chars: &[char]) -> Result<Option<(AnnotationString, &[char])>, ParsingError> {
-    if chars.is_empty() || chars[0] != '[' {
-        return Ok(None);
+        Ok(())
     }
 
-    if chars[0] == ']' {
-        return Err(ParsingError::UnbalancedLeftBracket);
+    fn augments(&mut self) -> Result<Augmentations, ParsingError> {
+        let mut augments = Augmentations::new();
+
+        for (augment, span) in self.lexer.augments(self.augments) {
+            if let Augmentation::Truncate { n: None, .. } = augment {
+                self.ambiguous(Ambiguity::ImplicitCount, span)?;
+            }
+            if let Some(notation) = augment_notation(&augment) {
+                self.requires(notation, span)?;
+            }
+            augments.push(augment);
+        }
+
+        Ok(augments)
     }
 
-    let mut unmatched = 0;
-    let mut i = 0;
-
-    while i < chars.len() {
-        if chars[i] == '[' {
-            unmatched += 1;
-        } else if chars[i] == ']' {
-            unmatched -= 1;
-            if unmatched == 0 {
-                return Ok(Some((
-                    chars[1..i].iter().cloned().collect(),
-                    &chars[i + 1..],
-                )));
+    // Keep the error and carry on when recovering
+    fn fail(&mut self, err: ParsingError) -> Result<(), ParsingError> {
+        match &mut self.recovered {
+            Some(errors) => {
+                errors.push(err);
+                Ok(())
             }
+            None => Err(err),
         }
-        i += 1;
     }
 
-    Err(ParsingError::UnbalancedRightBracket)
-}
+    // Report an unexpected token, a zero stands in for it when recovering
+    fn unexpected(&mut self, spanned: &Spanned) -> Result<Expression, ParsingError> {
+        self.fail(ParsingError::UnexpectedSymbol {
+            char: self.lexer.chars[spanned.span.start],
+            span: spanned.span,
+        })?;
 
-fn parse_term(chars: &[char]) -> Result<Option<(Expression, &[char])>, ParsingError> {
-    Ok(parse_number(chars)
-        .map(|(n, rest)| (Expression::Constant(n.into()), rest))
-        .or(parse_subexpr(chars)?
-            .map(|(subexpr, rest)| (Expression::Subexpression(Box::new(subexpr)), rest))))
-}
+        Ok(Expression::Constant(Zero::zero()))
+    }
 
-fn parse_term_or_dice(mut chars: &[char]) -> Result<Option<(Expression, &[char])>, ParsingError> {
-    let term = parse_term(chars)?.map(|(expr, rest)| {
-        chars = rest;
-        expr
-    });
+    fn parse_expression(&mut self) -> Result<Expression, ParsingError> {
+        if self.lexer.peek().token == Token::End {
+            return Err(ParsingError::EmptyExpression {
+                span: Span {
+                    start: 0,
+                    end: self.lexer.chars.len(),
+                },
+            });
+        }
 
-    if chars.is_empty() {
-        return Ok(term.map(|term| (term, chars)));
-    }
+        let mut expr = self.parse_binding(0)?;
 
-    if chars[0] == 'd' {
-        let power = if let Some((expr, rest)) = parse_term(&chars[1..])? {
-            chars = rest;
-            Some(Box::new(expr))
-        } else if chars.len() >= 2 && chars[1] == '%' {
-            chars = &chars[2..];
-            Some(Box::new(Expression::Constant(100.into())))
-        } else {
-            chars = &chars[1..];
-            None
-        };
+        // Only the closing brackets without a pair stop the parsing early
+        loop {
+            let spanned = self.lexer.next();
+            let span = spanned.span;
 
-        let (augs, rest) = parse_augments(chars);
-        chars = rest;
+            match spanned.token {
+                Token::End => break,
+                Token::Close(')') => self.fail(ParsingError::UnbalancedRightParen { span })?,
+                Token::Close(']') => self.fail(ParsingError::UnbalancedLeftBracket { span })?,
+                _ => {
+                    self.unexpected(&spanned)?;
+                }
+            }
 
-        return Ok(Some((
-            Expression::Dice {
-                quantity: term.map(Box::new),
-                power,
-                augmentations: augs.collect(),
-            },
-            chars,
-        )));
+            expr = self.parse_infix(expr, 0)?;
+        }
+
+        Ok(expr)
     }
 
-    Ok(term.map(|term| (term, chars)))
-}
+    // Everything that binds at least as tight as the power
+    fn parse_binding(&mut self, power: u8) -> Result<Expression, ParsingError> {
+        let lhs = self.parse_prefix()?;
+        self.parse_infix(lhs, power)
+    }
 
-fn _parse(mut chars: &[char]) -> Result<Expression, ParsingError> {
-    let mut expressions: Vec<Expression> = vec![];
-    let mut operators: Vec<BinaryOperator> = vec![];
+    fn parse_prefix(&mut self) -> Result<Expression, ParsingError> {
+        let spanned = self.lexer.peek();
+
+        match spanned.token {
+            Token::Symbol(c) => {
+                let Some(operator) = self.lexer.dialect.prefix_operator(c) else {
+                    // Left for the infix operator to pick up
+                    return self.unexpected(&spanned);
+                };
+
+                let after_operator = self
+                    .lexer
+                    .last_symbol
+                    .is_some_and(|c| self.lexer.dialect.infix_operator(c).is_some());
+                self.lexer.next();
+                if self.lexer.peek().ends_operands() {
+                    self.fail(ParsingError::NoOperands {
+                        operator: self
+                            .lexer
+                            .dialect
+                            .infix_operator(c)
+                            .map_or(BinaryOperator::Add, |(op, _)| op),
+                        span: spanned.span,
+                    })?;
+                    return Ok(Expression::Constant(Zero::zero()));
+                }
 
-    while !chars.is_empty() {
-        while chars[0].is_whitespace() {
-            chars = &chars[1..];
-        }
+                let operand = self.parse_binding(PREFIX_POWER)?;
+                if after_operator {
+                    let span = Span {
+                        start: spanned.span.start,
+                        end: self.lexer.last_end,
+                    };
+                    self.ambiguous(Ambiguity::SignAfterOperator, span)?;
+                }
 
-        if chars.is_empty() {
-            break;
+                Ok(match operator {
+                    PrefixOperator::Plus => operand,
+                    PrefixOperator::Negate => Expression::UnaryNegation(Box::new(operand)),
+                })
+            }
+            Token::Close(_) | Token::End => {
+                self.fail(ParsingError::EmptyExpression {
+                    span: Span {
+                        start: self.lexer.last_end.min(spanned.span.start),
+                        end: spanned.span.start,
+                    },
+                })?;
+
+                Ok(Expression::Constant(Zero::zero()))
+            }
+            Token::Dice => self.parse_dice(None),
+            Token::Advantage(affix) => {
+                self.lexer.next();
+                self.requires(Notation::Advantage, spanned.span)?;
+                Ok(advantage(Expression::Constant(20.into()), affix))
+            }
+            _ if spanned.starts_term() => self.parse_operand(),
+            _ => {
+                self.lexer.next();
+                self.unexpected(&spanned)
+            }
         }
+    }
 
-        let explicit_sign = {
-            let sign = match chars.first() {
-                Some('+') => Some(Sign::Plus),
-                Some('-') => Some(Sign::Minus),
-                _ => None,
+    fn parse_infix(&mut self, mut lhs: Expression, power: u8) -> Result<Expression, ParsingError> {
+        loop {
+            let spanned = self.lexer.peek();
+
+            lhs = match spanned.token {
+                Token::End | Token::Close(_) => break,
+                _ if spanned.starts_operand(self.lexer.dialect) => {
+                    // Pretend the operands were added together
+                    let (left, right) = binding_power(BinaryOperator::Add, Associativity::Left);
+                    if left < power {
+                        break;
+                    }
+
+                    self.fail(ParsingError::MissingOperator {
+                        span: Span {
+                            start: spanned.span.start,
+                            end: spanned.span.start,
+                        },
+                    })?;
+
+                    Expression::Binop {
+                        operator: BinaryOperator::Add,
+                        lhs: Box::new(lhs),
+                        rhs: Box::new(self.parse_binding(right)?),
+                    }
+                }
+                Token::Symbol('?') if self.lexer.dialect.conditionals() => {
+                    if CONDITIONAL_POWER < power {
+                        break;
+                    }
+
+                    self.lexer.next();
+                    self.requires(Notation::Conditionals, spanned.span)?;
+                    let then = self.parse_binding(CONDITIONAL_POWER)?;
+
+                    let otherwise = if self.lexer.peek().token == Token::Symbol(':') {
+                        self.lexer.next();
+                        self.parse_binding(CONDITIONAL_POWER)?
+                    } else {
+                        self.fail(ParsingError::IncompleteConditional { span: spanned.span })?;
+                        Expression::Constant(Zero::zero())
+                    };
+
+                    Expression::Conditional {
+                        condition: Box::new(lhs),
+                        then: Box::new(then),
+                        otherwise: Box::new(otherwise),
+                    }
+                }
+                Token::Symbol(c) => {
+                    let Some((operator, associativity)) = self.lexer.dialect.infix_operator(c)
+                    else {
+                        // Ends a member of the list or a branch of the conditional
+                        if c == ',' || c == ':' {
+                            break;
+                        }
+
+                        self.lexer.next();
+                        self.unexpected(&spanned)?;
+                        continue;
+                    };
+
+                    let (left, right) = binding_power(operator, associativity);
+                    if left < power {
+                        break;
+                    }
+
+                    self.lexer.next();
+                    let rhs = if self.lexer.peek().ends_operands() {
+                        self.fail(ParsingError::NoOperands {
+                            operator,
+                            span: spanned.span,
+                        })?;
+                        Expression::Constant(Zero::zero())
+                    } else {
+                        self.parse_binding(right)?
+                    };
+
+                    Expression::Binop {
+                        operator,
+                        lhs: Box::new(lhs),
+                        rhs: Box::new(rhs),
+                    }
+                }
+                Token::Annotation { text, closed } => {
+                    if ANNOTATION_POWER < power {
+                        break;
+                    }
+
+                    self.lexer.next();
+                    if !closed {
+                        self.fail(ParsingError::UnbalancedRightBracket {
+                            span: Span {
+                                start: spanned.span.start,
+                                end: spanned.span.start + 1,
+                            },
+                        })?;
+                    }
+
+                    Expression::Annotated {
+                        expression: Box::new(lhs),
+                        annotation: text,
+                    }
+                }
+                _ => {
+                    self.lexer.next();
+                    self.unexpected(&spanned)?;
+                    lhs
+                }
             };
+        }
 
-            if sign.is_some() {
-                chars = &chars[1..];
+        Ok(lhs)
+    }
+
+    // A term with the dice or the repetition right after it
+    fn parse_operand(&mut self) -> Result<Expression, ParsingError> {
+        let term = self.parse_term()?;
+        let spanned = self.lexer.peek();
+
+        match spanned.token {
+            Token::Dice if !spanned.spaced => self.parse_dice(Some(term)),
+            Token::Repeat => {
+                self.lexer.next();
+                self.requires(Notation::Repetitions, spanned.span)?;
+                let open = self.lexer.next();
+                let expression = self.parse_enclosed(open.span, ')', |span| {
+                    ParsingError::UnbalancedLeftParen { span }
+                })?;
+
+                Ok(Expression::Repeat {
+                    count: Box::new(term),
+                    expression: Box::new(expression),
+                })
             }
+            _ => Ok(term),
+        }
+    }
 
-            sign
-        };
+    fn parse_dice(&mut self, quantity: Option<Expression>) -> Result<Expression, ParsingError> {
+        let dice = self.lexer.next();
 
-        if chars.is_empty() {
-            return Err(ParsingError::NoOperands {
-                operator: BinaryOperator::Add,
-            });
-        }
+        let spanned = self.lexer.peek();
+        if spanned.token == Token::Open('{') && !spanned.spaced {
+            if let Some((faces, span)) = self.lexer.weighted() {
+                self.requires(Notation::WeightedDice, span)?;
+                let augmentations = self.augments()?;
 
-        let (term, rest) =
-            parse_term_or_dice(chars)?.ok_or(ParsingError::UnexpectedSymbol { char: chars[0] })?;
-        chars = rest;
+                if quantity.is_none() {
+                    self.ambiguous(Ambiguity::ImplicitQuantity, dice.span)?;
+                }
 
-        let mut expr = if explicit_sign == Some(Sign::Minus) {
-            Expression::UnaryNegation(Box::new(term))
-        } else {
-            term
+                return Ok(Expression::Weighted {
+                    quantity: quantity.map(Box::new),
+                    faces: Box::new(faces),
+                    augmentations,
+                });
+            }
+        }
+
+        let power = match spanned.token {
+            _ if spanned.spaced => None,
+            Token::Percent => {
+                self.lexer.next();
+                Some(Expression::Constant(100.into()))
+            }
+            _ if spanned.starts_term() => Some(self.parse_term()?),
+            _ => None,
         };
 
-        if chars.is_empty() {
-            expressions.push(expr);
-            break;
+        if power.is_none() {
+            self.ambiguous(Ambiguity::ImplicitPower, dice.span)?;
         }
 
-        while chars[0].is_whitespace() {
-            chars = &chars[1..];
-        }
+        let augmentations = self.augments()?;
 
-        if let Some((annotation, rest)) = parse_annotation(chars)? {
-            chars = rest;
-            expr = Expression::Annotated {
-                expression: Box::new(expr),
-                annotation,
+        // Only `d20a`, the quantity and the augments are up to the advantage
+        let affix = match power {
+            Some(_)
+                if quantity.is_none()
+                    && augmentations.is_empty()
+                    && self.lexer.dialect.advantage() =>
+            {
+                self.lexer.advantage()
             }
-        }
+            _ => None,
+        };
 
-        expressions.push(expr);
+        let power = match (power, affix) {
+            (Some(power), Some(affix)) => {
+                let end = self.lexer.last_end;
+                let span = Span {
+                    start: end - 1,
+                    end,
+                };
+                self.requires(Notation::Advantage, span)?;
+                return Ok(advantage(power, affix));
+            }
+            (power, _) => power,
+        };
 
-        if chars.is_empty() {
-            break;
+        if quantity.is_none() {
+            self.ambiguous(Ambiguity::ImplicitQuantity, dice.span)?;
         }
 
-        while chars[0].is_whitespace() {
-            chars = &chars[1..];
-        }
+        Ok(Expression::Dice {
+            quantity: quantity.map(Box::new),
+            power: power.map(Box::new),
+            augmentations,
+        })
+    }
 
-        if let Some(operator) = parse_operator(chars[0]) {
-            if let Some(top_op) = operators.pop() {
-                if operator <= top_op {
-                    push_operator(&mut expressions, top_op)?;
-                    operators.push(operator);
-                } else {
-                    operators.push(top_op);
-                    operators.push(operator);
-                }
-            } else {
-                operators.push(operator);
+    fn parse_term(&mut self) -> Result<Expression, ParsingError> {
+        let spanned = self.lexer.next();
+
+        match spanned.token {
+            Token::Number(n) => Ok(Expression::Constant(n.into())),
+            Token::Decimal(d) => {
+                self.requires(Notation::Decimals, spanned.span)?;
+                Ok(Expression::Decimal(d))
+            }
+            Token::Reference(name) => {
+                self.requires(Notation::References, spanned.span)?;
+                Ok(Expression::Reference(name))
             }
+            Token::Open('(') => {
+                let expr = self.parse_enclosed(spanned.span, ')', |span| {
+                    ParsingError::UnbalancedLeftParen { span }
+                })?;
 
-            chars = &chars[1..];
-            continue;
+                Ok(Expression::Subexpression(Box::new(expr)))
+            }
+            Token::Open(_) => {
+                self.requires(Notation::Groups, spanned.span)?;
+                let members = self.parse_list(spanned.span, '}', |span| {
+                    ParsingError::UnbalancedLeftBrace { span }
+                })?;
+
+                Ok(Expression::Group {
+                    members,
+                    augmentations: self.augments()?,
+                })
+            }
+            Token::Function(function) => {
+                self.requires(Notation::Functions, spanned.span)?;
+                let open = self.lexer.next();
+                let args = self.parse_list(open.span, ')', |span| {
+                    ParsingError::UnbalancedLeftParen { span }
+                })?;
+
+                Ok(Expression::Call { function, args })
+            }
+            _ => self.unexpected(&spanned),
         }
     }
 
-    while let Some(operator) = operators.pop() {
-        push_operator(&mut expressions, operator)?;
+    // The bracket is already taken, an unclosed one is closed at the end
+    // of the input or by the bracket of the expression around it
+    fn close(
+        &mut self,
+        open: Span,
+        close: char,
+        unbalanced: fn(Span) -> ParsingError,
+    ) -> Result<(), ParsingError> {
+        if self.lexer.peek().token == Token::Close(close) {
+            self.lexer.next();
+            Ok(())
+        } else {
+            self.fail(unbalanced(open))
+        }
     }
 
-    if expressions.len() != operators.len() + 1 {
-        return Err(ParsingError::MissingOperator);
+    fn parse_enclosed(
+        &mut self,
+        open: Span,
+        close: char,
+        unbalanced: fn(Span) -> ParsingError,
+    ) -> Result<Expression, ParsingError> {
+        let expr = self.parse_binding(0)?;
+        self.close(open, close, unbalanced)?;
+
+        Ok(expr)
     }
 
-    expressions.pop().ok_or(ParsingError::EmptyExpression)
+    // Comma separated expressions
+    fn parse_list(
+        &mut self,
+        open: Span,
+        close: char,
+        unbalanced: fn(Span) -> ParsingError,
+    ) -> Result<Vec<Expression>, ParsingError> {
+        let mut members = vec![];
+
+        // `bless()` has no arguments at all
+        if self.lexer.peek().token == Token::Close(close) {
+            self.lexer.next();
+            return Ok(members);
+        }
+
+        // The members bind tighter than the commas between them
+        let (_, power) = binding_power(BinaryOperator::Chain, Associativity::Left);
+
+        loop {
+            members.push(self.parse_binding(power)?);
+
+            if self.lexer.peek().token != Token::Symbol(',') {
+                break;
+            }
+            self.lexer.next();
+        }
+
+        self.close(open, close, unbalanced)?;
+        Ok(members)
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::parser::{parse, BinaryOperator, ParsingError};
+    use crate::parser::{
+        parse, parse_all_errors, parse_spanned, BinaryOperator, ParsingError, Span,
+    };
 
     #[test]
     fn test_operator_priority() {
@@ -396,16 +1418,505 @@ mod tests {
         assert!(BinaryOperator::Multiply >= BinaryOperator::Add);
     }
 
+    #[test]
+    fn test_precedence() {
+        use crate::syntax::Expression;
+
+        assert_eq!(
+            parse("1 + 2 * 3"),
+            parse("1 + (2 * 3)").map(unwrap_subexpressions)
+        );
+        assert_eq!(
+            parse("1 - 2 - 3"),
+            parse("(1 - 2) - 3").map(unwrap_subexpressions)
+        );
+
+        let Ok(Expression::Binop {
+            operator: BinaryOperator::Multiply,
+            lhs:
+                box Expression::Annotated {
+                    expression: box Expression::UnaryNegation(box Expression::Dice { .. }),
+                    ..
+                },
+            ..
+        }) = parse("-2d6 [hit] * 2")
+        else {
+            panic!()
+        };
+
+        assert!(parse("- 3").is_ok());
+        assert!(parse("2 *\t-d6").is_ok());
+        assert!(parse("2d6kh * -(1)").is_ok());
+    }
+
+    // The subexpressions written out only to show the precedence
+    fn unwrap_subexpressions(expr: crate::syntax::Expression) -> crate::syntax::Expression {
+        use crate::syntax::Expression::*;
+
+        match expr {
+            Subexpression(box e) => unwrap_subexpressions(e),
+            Binop { operator, lhs, rhs } => Binop {
+                operator,
+                lhs: Box::new(unwrap_subexpressions(*lhs)),
+                rhs: Box::new(unwrap_subexpressions(*rhs)),
+            },
+            e => e,
+        }
+    }
+
+    #[test]
+    fn test_advantage() {
+        use crate::{dialect::Roll20, parser::parse_dialect};
+
+        assert_eq!(parse("adv + 5"), parse("2d20kh1 + 5"));
+        assert_eq!(parse("dis[init]"), parse("2d20kl1[init]"));
+        assert_eq!(parse("d8a"), parse("2d8kh1"));
+        assert_eq!(parse("d(@power)d"), parse("2d(@power)kl1"));
+
+        // The suffix does not get in the way of the augments
+        assert!(parse("d20dl").is_ok());
+        assert!(parse("d20d>3").is_ok());
+        assert!(parse("d20a2").is_err());
+        assert!(parse("2d20a").is_err());
+        assert!(parse("d20kha").is_err());
+        assert!(parse("advantage").is_err());
+        assert!(parse("adv(1)").is_ok());
+
+        assert!(parse_dialect("adv", &Roll20).is_err());
+        assert!(parse_dialect("d20d", &Roll20).is_err());
+    }
+
+    #[test]
+    fn test_conditional() {
+        use crate::syntax::Expression;
+
+        let Ok(Expression::Conditional {
+            condition: box Expression::Binop { .. },
+            otherwise: box Expression::Conditional { .. },
+            ..
+        }) = parse("d20 + 5 > 14 ? 2d6 : d4 = 1 ? 0 : 1")
+        else {
+            panic!()
+        };
+
+        let Ok(Expression::Call { args, .. }) = parse("max(1 ? 2 : 3, 4)") else {
+            panic!()
+        };
+        assert_eq!(args.len(), 2);
+
+        assert!(matches!(
+            parse("1 ? 2"),
+            Err(ParsingError::IncompleteConditional { .. })
+        ));
+        assert!(parse("1 ? : 2").is_err());
+        assert!(parse("1 : 2").is_err());
+        assert!(parse("1 ? 2 : 3 : 4").is_err());
+    }
+
     #[test]
     fn test_missing_operator() {
         assert!(matches!(
             parse("2 + 3 4 + 5"),
-            Err(ParsingError::MissingOperator)
+            Err(ParsingError::MissingOperator { .. })
         ));
 
         assert!(matches!(
             parse("(1 2) * 3"),
-            Err(ParsingError::MissingOperator)
+            Err(ParsingError::MissingOperator { .. })
+        ));
+    }
+
+    #[test]
+    fn test_spans() {
+        let span = |input| parse(input).unwrap_err().span();
+
+        assert_eq!(span("2 + 3 4"), Span { start: 6, end: 6 });
+        assert_eq!(span("1 + (2 * 3"), Span { start: 4, end: 5 });
+        assert_eq!(span("max(1, {2, 3)"), Span { start: 7, end: 8 });
+        assert_eq!(span("2d6 + $"), Span { start: 6, end: 7 });
+        assert_eq!(span("d20 [attack"), Span { start: 4, end: 5 });
+        assert_eq!(span("   "), Span { start: 0, end: 3 });
+
+        let diagnostic = parse_spanned("«4» + ?").unwrap_err();
+        assert_eq!(diagnostic.span(), Span { start: 0, end: 3 });
+        assert_eq!(diagnostic.bytes, 0..5);
+        assert_eq!(diagnostic.underline(), "^^^");
+
+        let diagnostic = parse_spanned("1 + é").unwrap_err();
+        assert_eq!(diagnostic.bytes, 4..6);
+        assert_eq!(diagnostic.underline(), "    ^");
+    }
+
+    #[test]
+    fn test_all_errors() {
+        let (expr, diagnostics) = parse_all_errors("2d6 + 3 4 + $ * (1 + ");
+        let errors: Vec<_> = diagnostics.iter().map(|d| d.error).collect();
+
+        assert!(expr.is_some());
+        assert!(matches!(
+            &errors[..],
+            [
+                ParsingError::MissingOperator { .. },
+                ParsingError::UnexpectedSymbol { char: '$', .. },
+                ParsingError::UnbalancedLeftParen { .. },
+                ParsingError::NoOperands { .. },
+            ]
         ));
+        assert_eq!(diagnostics[0].span(), Span { start: 8, end: 8 });
+
+        let (expr, diagnostics) = parse_all_errors("max(1, ) + [oops");
+        assert!(expr.is_some());
+        assert_eq!(diagnostics.len(), 2);
+
+        let (expr, diagnostics) = parse_all_errors("2d20kh + 5 [attack]");
+        assert!(expr.is_some() && diagnostics.is_empty());
+
+        let (expr, diagnostics) = parse_all_errors("");
+        assert!(expr.is_none());
+        assert!(matches!(
+            diagnostics[0].error,
+            ParsingError::EmptyExpression { .. }
+        ));
+    }
+
+    #[test]
+    fn test_group() {
+        use crate::syntax::Expression;
+
+        let Ok(Expression::Group {
+            members,
+            augmentations,
+        }) = parse("{2d6, (1d8 + 3), {4, 5}}kh")
+        else {
+            panic!()
+        };
+
+        assert_eq!(members.len(), 3);
+        assert_eq!(augmentations.len(), 1);
+
+        assert!(matches!(
+            parse("{2d6, 3"),
+            Err(ParsingError::UnbalancedLeftBrace { .. })
+        ));
+    }
+
+    #[test]
+    fn test_weighted() {
+        use crate::syntax::{Expr, Expression, Face};
+
+        let Ok(Expression::Weighted {
+            quantity,
+            faces,
+            augmentations,
+        }) = parse("3d{ 1 : 3, -2:1 }kh1")
+        else {
+            panic!()
+        };
+
+        assert_eq!(quantity.as_deref(), Some(&Expression::Constant(3.into())));
+        assert_eq!(*faces, [Face::new(1, 3u32), Face::new(-2, 1u32)]);
+        assert_eq!(augmentations.len(), 1);
+
+        for input in ["3d{1:3, 2:1}kh1", "d{-1:1, 0:1, 1:1}", "(d4)d{5:0, 6:1}!"] {
+            assert_eq!(parse(input).unwrap().to_string(), input);
+        }
+        assert_eq!(
+            parse("2d{6:2, 1:1}").unwrap(),
+            Expr::weighted(2, [Face::new(6, 2u32), Face::new(1, 1u32)]).build()
+        );
+
+        // Anything but the faces in the braces is still a group power
+        assert!(matches!(
+            parse("d{2, 4}"),
+            Ok(Expression::Dice {
+                power: Some(box Expression::Group { .. }),
+                ..
+            })
+        ));
+        assert!(matches!(
+            parse("d{1:2, 3}"),
+            Err(ParsingError::UnbalancedLeftBrace { .. })
+        ));
+    }
+
+    #[test]
+    fn test_decimal() {
+        use crate::syntax::{Decimal, Expr, Expression};
+
+        assert_eq!(
+            parse("1.5 * 2d6").unwrap(),
+            Expr::decimal(15u32, 1).times(Expr::dice(2, 6)).build()
+        );
+        assert_eq!(
+            parse("0.05"),
+            Ok(Expression::Decimal(Decimal::new(5u32, 2)))
+        );
+
+        for input in ["1.5 * 2d6", "d6 * 0.05 + 10.00", "-2.5d6"] {
+            assert_eq!(parse(input).unwrap().to_string(), input);
+        }
+
+        assert!(parse("1.").is_err());
+        assert!(parse(".5").is_err());
+        assert!(parse("1.5.2").is_err());
+    }
+
+    #[test]
+    fn test_min_max() {
+        use crate::syntax::Expression;
+
+        let Ok(Expression::Call { function, args }) = parse("max(2d6 + 1, (d12), 4)") else {
+            panic!()
+        };
+
+        assert_eq!(function, "max");
+        assert_eq!(args.len(), 3);
+
+        assert!(parse("min(d20, d20) + 5").is_ok());
+        assert!(parse("min(,)").is_err());
+        assert!(parse("max(1, 2").is_err());
+    }
+
+    #[test]
+    fn test_call() {
+        use crate::syntax::Expression;
+
+        let Ok(Expression::Call { function, args }) = parse("floor(abs(2 - d6))") else {
+            panic!()
+        };
+
+        assert_eq!(function, "floor");
+        assert!(matches!(&args[..], [Expression::Call { function, .. }] if function == "abs"));
+
+        assert!(matches!(parse("bless()"), Ok(Expression::Call { args, .. }) if args.is_empty()));
+        assert!(parse("max(1, )").is_err());
+        assert!(matches!(parse("d(2 + 4)"), Ok(Expression::Dice { .. })));
+        assert!(matches!(parse("2dceil(4)"), Ok(Expression::Dice { .. })));
+    }
+
+    #[test]
+    fn test_reference() {
+        use crate::syntax::Expression;
+
+        assert_eq!(
+            parse("@sneak_attack"),
+            Ok(Expression::Reference("sneak_attack".into()))
+        );
+        assert!(matches!(
+            parse("(@n)d6"),
+            Ok(Expression::Dice {
+                quantity: Some(box Expression::Subexpression(box Expression::Reference(_))),
+                ..
+            })
+        ));
+        assert!(parse("@").is_err());
+    }
+
+    #[test]
+    fn test_repeat() {
+        use crate::syntax::Expression;
+
+        let Ok(Expression::Repeat { count, expression }) = parse("6x(4d6kh3)") else {
+            panic!()
+        };
+
+        assert_eq!(*count, Expression::Constant(6.into()));
+        assert!(matches!(*expression, Expression::Dice { .. }));
+
+        assert!(parse("6x4d6").is_err());
+    }
+
+    #[test]
+    fn test_counting() {
+        use std::cmp::Ordering;
+
+        use crate::syntax::{Augmentation, Expression, Selector};
+
+        let Ok(Expression::Dice { augmentations, .. }) = parse("6d10s>=8f<=1") else {
+            panic!()
+        };
+
+        assert_eq!(
+            augmentations.into_vec(),
+            vec![
+                Augmentation::CountSuccesses {
+                    selector: Selector {
+                        relation: Ordering::Greater,
+                        inclusive: true,
+                        n: 8u32.into(),
+                    }
+                },
+                Augmentation::CountFailures {
+                    selector: Selector {
+                        relation: Ordering::Less,
+                        inclusive: true,
+                        n: 1u32.into(),
+                    }
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_strict() {
+        use crate::{
+            options::RollerOptions,
+            parser::{parse_strict, Ambiguity},
+        };
+
+        let mut options = RollerOptions::default();
+        options.set_power(20);
+        let fix = |input: &str| {
+            let diagnostic = parse_strict(input).unwrap_err();
+            let ParsingError::Ambiguous { ambiguity, .. } = diagnostic.error else {
+                panic!("{input} is not ambiguous");
+            };
+            (ambiguity, diagnostic.fix_it(input, &options).unwrap())
+        };
+
+        assert_eq!(
+            fix("d6 + 2"),
+            (Ambiguity::ImplicitQuantity, "1d6 + 2".into())
+        );
+        assert_eq!(fix("3d + 2"), (Ambiguity::ImplicitPower, "3d20 + 2".into()));
+        // The sides the roller would assume, six by default
+        let diagnostic = parse_strict("3d + 2").unwrap_err();
+        assert_eq!(
+            diagnostic.fix_it("3d + 2", &RollerOptions::default()),
+            Some("3d6 + 2".into())
+        );
+        assert_eq!(
+            fix("4d6kh + 1"),
+            (Ambiguity::ImplicitCount, "4d6kh1 + 1".into())
+        );
+        assert_eq!(
+            fix("{1, d4}dl"),
+            (Ambiguity::ImplicitQuantity, "{1, 1d4}dl".into())
+        );
+        assert_eq!(
+            fix("{1, 2}dl"),
+            (Ambiguity::ImplicitCount, "{1, 2}dl1".into())
+        );
+        assert_eq!(
+            fix("2 - -3 * 4"),
+            (Ambiguity::SignAfterOperator, "2 - (-3) * 4".into())
+        );
+        assert_eq!(
+            fix("2 *-1d6 [x]"),
+            (Ambiguity::SignAfterOperator, "2 *(-1d6) [x]".into())
+        );
+
+        for input in [
+            "1d6 + 2",
+            "-3 + 2d20kh1",
+            "2 - (-3)",
+            "max(-1, 1d4)",
+            "d20a + 5",
+            "adv",
+            "1d20 > 10 ? -1 : 2",
+            "4d6kh3 [stats]",
+        ] {
+            assert_eq!(parse_strict(input).ok(), parse(input).ok(), "{input}");
+        }
+
+        // The defaults are still fine outside of the strict mode
+        assert!(parse("d + -d6kh").is_ok());
+    }
+
+    #[test]
+    fn test_notation_versions() {
+        use crate::{
+            dialect::Roll20,
+            extensions::AugmentRegistry,
+            parser::{parse_with_options, Notation, NotationVersion, Parser, ParserOptions},
+        };
+
+        let parse_v = |input: &str, notation| {
+            parse_with_options(input, &ParserOptions::default().with_notation(notation))
+        };
+
+        // Every version parses its own notation the same way
+        let conformance: &[(NotationVersion, &[&str])] = &[
+            (
+                NotationVersion::V1,
+                &[
+                    "d20 + 5",
+                    "4d6kh3 [stats]",
+                    "3d6!>4 - 2 * (1d4 + 1)",
+                    "4d6d<2e",
+                    "d20 > 15, 2d6",
+                ],
+            ),
+            (
+                NotationVersion::V2,
+                &[
+                    "max(2d6, d12)",
+                    "@sneak_attack + 2",
+                    "{d20 + 5, 2d6}kh1",
+                    "6x(4d6kh3)",
+                    "3d6!>=5",
+                    "8d10s>=7f<=1",
+                    "d20 > 15 ? 2d6 : 0",
+                    "d20a",
+                    "adv + 5",
+                ],
+            ),
+            (
+                NotationVersion::V3,
+                &["3d{1:2, 2:1}kh1", "d{-1:1, 0:1, 1:1}", "1.5 * 2d6"],
+            ),
+        ];
+
+        for (since, inputs) in conformance {
+            for input in *inputs {
+                for version in [NotationVersion::V1, NotationVersion::V2, NotationVersion::V3] {
+                    let parsed = parse_v(input, version);
+                    if version >= *since {
+                        assert_eq!(parsed, parse(input), "{input} in {version:?}");
+                    } else {
+                        assert!(
+                            matches!(parsed, Err(ParsingError::Unsupported { .. })),
+                            "{input} in {version:?}"
+                        );
+                    }
+                }
+            }
+        }
+
+        assert_eq!(
+            parse_v("1 + max(1, 2)", NotationVersion::V1),
+            Err(ParsingError::Unsupported {
+                notation: Notation::Functions,
+                version: NotationVersion::V1,
+                span: Span { start: 4, end: 7 },
+            })
+        );
+        assert_eq!(
+            parse_v("2x(d20a)", NotationVersion::V1),
+            Err(ParsingError::Unsupported {
+                notation: Notation::Repetitions,
+                version: NotationVersion::V1,
+                span: Span { start: 1, end: 2 },
+            })
+        );
+
+        // The augments of the other dialects are gated the same
+        let chars: Vec<char> = "d20ro<2 + 3d6!!".chars().collect();
+        let augments = AugmentRegistry::default();
+        let mut parser = Parser::new(&chars, &Roll20, &augments, true);
+        parser.notation = NotationVersion::V1;
+        parser.parse_expression().unwrap();
+        let notations: Vec<_> = parser
+            .recovered
+            .unwrap()
+            .into_iter()
+            .map(|err| match err {
+                ParsingError::Unsupported { notation, .. } => notation,
+                err => panic!("{err}"),
+            })
+            .collect();
+        assert_eq!(notations, [Notation::Rerolls, Notation::Compounding]);
+
+        assert_eq!(NotationVersion::default(), NotationVersion::LATEST);
     }
 }
@@ -0,0 +1,224 @@
+//! Showing the rolled dice to the people at the table
+
+use serde::{Deserialize, Serialize};
+use smallvec::SmallVec;
+
+use crate::{
+    interpreter::{NaiveValue, NodeId, Provenance, TaggedDiceRoll},
+    syntax::{Augmentation, Expression},
+};
+
+const D6_FACES: [char; 6] = ['⚀', '⚁', '⚂', '⚃', '⚄', '⚅'];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DiceStyle {
+    /// `5, 6, 3, ~2~`
+    #[default]
+    Numbers,
+    /// The d6 as their faces, `⚄ ⚅ ⚂ ~⚁~`, and the other dice in brackets
+    /// with the criticals decorated, `[20]✨` and `[1]💀`
+    Art,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct RenderOptions {
+    pub style: DiceStyle,
+    /// The pools with more dice are shown as numbers, the art gets hard
+    /// to read past a handful of dice
+    pub max_art: usize,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            style: DiceStyle::default(),
+            max_art: 10,
+        }
+    }
+}
+
+impl RenderOptions {
+    pub fn art() -> Self {
+        Self {
+            style: DiceStyle::Art,
+            ..Default::default()
+        }
+    }
+}
+
+/// The dice of a pool with `power` sides, the discarded ones are struck
+pub fn render_dice(dice: &[TaggedDiceRoll], power: i64, options: &RenderOptions) -> String {
+    let art = options.style == DiceStyle::Art && dice.len() <= options.max_art;
+
+    let rendered = dice.iter().map(|d| {
+        let face = match (art, power) {
+            (false, _) => d.value.to_string(),
+            (true, 6) if (1..=6).contains(&d.value) => D6_FACES[d.value as usize - 1].to_string(),
+            (true, _) if d.is_critical_success() => format!("[{}]✨", d.value),
+            (true, _) if d.is_critical_failure() => format!("[{}]💀", d.value),
+            (true, _) => format!("[{}]", d.value),
+        };
+
+        if d.is_discarded() {
+            format!("~{face}~")
+        } else {
+            face
+        }
+    });
+
+    rendered
+        .collect::<Vec<_>>()
+        .join(if art { " " } else { ", " })
+}
+
+/// The dice of a roll of a single dice term like `4d6kh3` or `d20 [attack]`,
+/// `None` for anything else since the sides of the dice are not known
+pub fn render_roll(
+    expr: &Expression,
+    value: &NaiveValue,
+    options: &RenderOptions,
+) -> Option<String> {
+    if let NaiveValue::Summary(summary) = value {
+        return Some(summary.to_string());
+    }
+
+    let power = match expr {
+        Expression::Annotated { expression, .. }
+        | Expression::Subexpression(expression)
+        | Expression::Tagged { expression, .. } => return render_roll(expression, value, options),
+        Expression::Dice {
+            power: Some(box Expression::Constant(power)),
+            ..
+        } => i64::try_from(power).ok()?,
+        _ => return None,
+    };
+
+    let dice = value.dice().copied().collect::<Vec<_>>();
+    Some(render_dice(&dice, power, options))
+}
+
+/// Every dice term of a roll on a line of its own, with its dice and what
+/// they add up to, `4d6kh3: [5, 6, 3, ~2~] = 14`. A pool that counts the
+/// successes adds up to its hits. The dice of a macro are shown under its
+/// reference. Only a roller `with_provenance` tells the terms apart.
+pub fn render_breakdown(
+    expr: &Expression,
+    provenance: &Provenance,
+    options: &RenderOptions,
+) -> Vec<String> {
+    let mut lines = vec![];
+
+    for (id, node) in expr.nodes().into_iter().enumerate() {
+        let (power, counted) = match node {
+            Expression::Dice {
+                power,
+                augmentations,
+                ..
+            } => {
+                let power = match power.as_deref() {
+                    Some(Expression::Constant(power)) => i64::try_from(power).unwrap_or_default(),
+                    _ => 0,
+                };
+                (power, augmentations.iter().any(Augmentation::is_counting))
+            }
+            Expression::Weighted { augmentations, .. } => {
+                (0, augmentations.iter().any(Augmentation::is_counting))
+            }
+            Expression::Reference(_) => (0, false),
+            _ => continue,
+        };
+
+        let dice = provenance
+            .dice_of(NodeId(id as u32))
+            .copied()
+            .collect::<SmallVec<_>>();
+        if dice.is_empty() {
+            continue;
+        }
+
+        let rendered = render_dice(&dice, power, options);
+        let value = if counted {
+            NaiveValue::Pool(dice)
+        } else {
+            NaiveValue::Dice(dice)
+        };
+        match value.total() {
+            Ok(total) => lines.push(format!("{node}: [{rendered}] = {total}")),
+            Err(err) => lines.push(format!("{node}: [{rendered}], {err}")),
+        }
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        interpreter::StandardNaiveRoller,
+        parser::parse,
+        render::{render_breakdown, render_roll, RenderOptions},
+    };
+
+    fn render(input: &str, seed: u64, options: &RenderOptions) -> Option<String> {
+        let expr = parse(input).unwrap();
+        let value = StandardNaiveRoller::new_seeded(seed)
+            .roll_naive(&expr)
+            .unwrap();
+        render_roll(&expr, &value, options)
+    }
+
+    #[test]
+    fn test_render() {
+        let art = RenderOptions::art();
+
+        let faces = render("4d6kh3", 0, &art).unwrap();
+        assert_eq!(faces.split(' ').count(), 4);
+        assert_eq!(faces.matches('~').count(), 2);
+        assert!(faces.chars().all(|c| "⚀⚁⚂⚃⚄⚅~ ".contains(c)));
+
+        let numbers = render("4d6kh3", 0, &RenderOptions::default()).unwrap();
+        assert_eq!(numbers.split(", ").count(), 4);
+
+        // Enough seeds to see both of the criticals
+        let d20s = (0..200)
+            .map(|seed| render("d20 [attack]", seed, &art).unwrap())
+            .collect::<Vec<_>>();
+        assert!(d20s.iter().any(|d| d == "[20]✨"));
+        assert!(d20s.iter().any(|d| d == "[1]💀"));
+        assert!(d20s.iter().any(|d| d == "[10]"));
+
+        let pool = render("20d6", 0, &art).unwrap();
+        assert!(pool.chars().all(|c| c.is_ascii()));
+
+        assert_eq!(render("d20 + 5", 0, &art), None);
+    }
+
+    #[test]
+    fn test_render_breakdown() {
+        let options = RenderOptions::default();
+        let breakdown = |input: &str, seed| {
+            let expr = parse(input).unwrap();
+            let mut roller = StandardNaiveRoller::new_seeded(seed).with_provenance();
+            let total = roller.roll_naive(&expr).unwrap().total().unwrap();
+            let lines = render_breakdown(&expr, roller.provenance().unwrap(), &options);
+            (lines, total)
+        };
+
+        let subtotal = |line: &str| line.rsplit(" = ").next().unwrap().parse::<i64>().unwrap();
+
+        let (lines, total) = breakdown("4d6kh3 + d8 + 2", 0);
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("4d6kh3: ["));
+        assert_eq!(lines[0].matches('~').count(), 2);
+        assert_eq!(lines[0].split(", ").count(), 4);
+        assert!(lines[1].starts_with("d8: ["));
+        assert_eq!(lines.iter().map(|l| subtotal(l)).sum::<i64>() + 2, total);
+
+        let (lines, total) = breakdown("6d6s>=4", 0);
+        assert_eq!(lines.len(), 1);
+        assert_eq!(subtotal(&lines[0]), total);
+
+        let (lines, _) = breakdown("2 * 3", 0);
+        assert!(lines.is_empty());
+    }
+}
@@ -0,0 +1,62 @@
+use thiserror::Error;
+
+use crate::{
+    interpreter::{NaiveValue, RollerError, StandardNaiveRoller},
+    parser::{parse, ParsingError},
+};
+
+/// Why `roll_str` failed, the input did not parse or did not roll
+#[derive(Debug, Clone, Error, PartialEq)]
+pub enum RollError {
+    #[error(transparent)]
+    Parsing(#[from] ParsingError),
+    #[error(transparent)]
+    Roller(#[from] RollerError),
+}
+
+/// Parse and roll in one go with the default options, for when the
+/// roller would only be used once
+///
+/// ```
+/// let total = dicemind::roll_str("2d6 + 3").unwrap().total().unwrap();
+/// assert!((5..=15).contains(&total));
+/// ```
+pub fn roll_str(input: &str) -> Result<NaiveValue, RollError> {
+    Ok(StandardNaiveRoller::default().roll_naive(parse(input)?)?)
+}
+
+/// The same as `roll_str`, rolling the same dice for the same seed
+pub fn roll_str_seeded(input: &str, seed: u64) -> Result<NaiveValue, RollError> {
+    Ok(StandardNaiveRoller::new_seeded(seed).roll_naive(parse(input)?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        interpreter::RollerError,
+        parser::ParsingError,
+        prelude::{parse, roll_str, roll_str_seeded, RollError, StandardNaiveRoller},
+    };
+
+    #[test]
+    fn test_roll_str() {
+        assert!((5..=15).contains(&roll_str("2d6+3").unwrap().total().unwrap()));
+        assert_eq!(
+            roll_str_seeded("4d6kh3", 9).map(|v| v.total().unwrap()),
+            StandardNaiveRoller::new_seeded(9)
+                .roll_naive(parse("4d6kh3").unwrap())
+                .and_then(|v| v.total())
+                .map_err(RollError::from)
+        );
+        assert!(matches!(
+            roll_str("99999999999999999999"),
+            Err(RollError::Roller(RollerError::ValueTooLarge { .. }))
+        ));
+
+        assert!(matches!(
+            roll_str("1 +"),
+            Err(RollError::Parsing(ParsingError::NoOperands { .. }))
+        ));
+        assert!(matches!(roll_str("@missing"), Err(RollError::Roller(_))));
+    }
+}
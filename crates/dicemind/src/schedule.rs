@@ -0,0 +1,437 @@
+//! Rolls made on a schedule, like the random encounter of the day posted
+//! to the channel of the party every morning at 9. A schedule is written
+//! one roll a line, the five fields of a cron expression, the expression
+//! to roll and optionally the webhook to post the roll to:
+//!
+//! ```
+//! use dicemind::schedule::Schedule;
+//!
+//! let schedule = Schedule::load(
+//!     "# the encounter of the day
+//!      0 9 * * *  @encounter -> https://discord.com/api/webhooks/1/abc
+//!      */15 * * * 1-5  d20",
+//! )
+//! .unwrap();
+//!
+//! // 2024-01-01 was a Monday, at midnight
+//! let (at, rolls) = schedule.next_after(1_704_067_200).unwrap();
+//! assert_eq!(at, 1_704_067_200 + 15 * 60);
+//! assert_eq!(rolls[0].source, "d20");
+//! ```
+//!
+//! The times are seconds since the Unix epoch and the schedules are in UTC.
+
+use std::{fmt::Display, str::FromStr};
+
+use thiserror::Error;
+
+use crate::{
+    parser::{parse, ParsingError},
+    syntax::Expression,
+};
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+// How far ahead to look for the next time, enough to come across every day
+// of the year on every day of the week
+const HORIZON_DAYS: i64 = 8 * 366;
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum ScheduleError {
+    #[error("Expected the five fields of a cron expression and a roll, got `{line}`")]
+    InvalidLine { line: String },
+    #[error(
+        "Invalid {field} `{value}`, expected a number from {min} to {max}, a range, a step or `*`"
+    )]
+    InvalidField {
+        field: &'static str,
+        value: String,
+        min: u32,
+        max: u32,
+    },
+    #[error("Could not parse the roll `{roll}`: {error}")]
+    Parsing { roll: String, error: ParsingError },
+}
+
+pub type ScheduleResult<T> = Result<T, ScheduleError>;
+
+// The values of a field a time has to have, one bit for every value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Field {
+    bits: u64,
+    // Set when the field is `*`, when neither of the days is then a day
+    // matching either of them will do
+    any: bool,
+}
+
+impl Field {
+    fn parse(field: &'static str, value: &str, min: u32, max: u32) -> ScheduleResult<Self> {
+        let invalid = || ScheduleError::InvalidField {
+            field,
+            value: value.to_string(),
+            min,
+            max,
+        };
+        let number = |n: &str| {
+            n.parse::<u32>()
+                .ok()
+                .filter(|n| (min..=max).contains(n))
+                .ok_or_else(invalid)
+        };
+
+        let mut bits = 0;
+        for part in value.split(',') {
+            let (range, step) = match part.split_once('/') {
+                Some((range, step)) => (range, step.parse::<u32>().ok().filter(|&s| s > 0)),
+                None => (part, Some(1)),
+            };
+            let step = step.ok_or_else(invalid)?;
+
+            let (from, to) = match range.split_once('-') {
+                _ if range == "*" => (min, max),
+                Some((from, to)) => (number(from)?, number(to)?),
+                None => {
+                    let from = number(range)?;
+                    // `5/15` goes from 5 to the end, like `5-59/15`
+                    (from, if part.contains('/') { max } else { from })
+                }
+            };
+            if from > to {
+                return Err(invalid());
+            }
+
+            for n in (from..=to).step_by(step as usize) {
+                bits |= 1 << n;
+            }
+        }
+
+        Ok(Self {
+            bits,
+            any: value == "*",
+        })
+    }
+
+    fn contains(&self, n: u32) -> bool {
+        self.bits & (1 << n) != 0
+    }
+}
+
+/// When a roll is made, the five fields of a cron expression: the
+/// minute, the hour, the day of the month, the month and the day of the
+/// week, Sunday being 0 or 7. Every field is a number, a range like `1-5`,
+/// a step like `*/15` or `0-30/10`, a list of those or `*`. When both of
+/// the days are given, a day matching either of them will do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Cron {
+    minutes: Field,
+    hours: Field,
+    days: Field,
+    months: Field,
+    weekdays: Field,
+}
+
+impl FromStr for Cron {
+    type Err = ScheduleError;
+
+    fn from_str(s: &str) -> ScheduleResult<Self> {
+        let [minutes, hours, days, months, weekdays] = s
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .try_into()
+            .map_err(|_| ScheduleError::InvalidLine {
+                line: s.to_string(),
+            })?;
+
+        let mut weekdays = Field::parse("day of the week", weekdays, 0, 7)?;
+        if weekdays.contains(7) {
+            weekdays.bits |= 1;
+        }
+
+        Ok(Self {
+            minutes: Field::parse("minute", minutes, 0, 59)?,
+            hours: Field::parse("hour", hours, 0, 23)?,
+            days: Field::parse("day of the month", days, 1, 31)?,
+            months: Field::parse("month", months, 1, 12)?,
+            weekdays,
+        })
+    }
+}
+
+impl Cron {
+    /// The first time after the given one the cron expression matches, on
+    /// a whole minute. `None` when it never does, like on February 30.
+    pub fn next_after(&self, time: i64) -> Option<i64> {
+        let start = time.div_euclid(60) * 60 + 60;
+        let first_day = start.div_euclid(SECONDS_PER_DAY);
+
+        for day in first_day..first_day + HORIZON_DAYS {
+            if !self.matches_day(day) {
+                continue;
+            }
+
+            for hour in (0..24).filter(|&h| self.hours.contains(h)) {
+                for minute in (0..60).filter(|&m| self.minutes.contains(m)) {
+                    let at = day * SECONDS_PER_DAY + (hour * 60 + minute) as i64 * 60;
+                    if at >= start {
+                        return Some(at);
+                    }
+                }
+            }
+        }
+
+        None
+    }
+
+    // The day counted from the Unix epoch
+    fn matches_day(&self, day: i64) -> bool {
+        let (_, month, day_of_month) = civil_from_days(day);
+        // The epoch was a Thursday
+        let weekday = (day + 4).rem_euclid(7) as u32;
+
+        let day_of_month = self.days.contains(day_of_month);
+        let weekday = self.weekdays.contains(weekday);
+        let day = match (self.days.any, self.weekdays.any) {
+            (false, false) => day_of_month || weekday,
+            _ => day_of_month && weekday,
+        };
+
+        day && self.months.contains(month)
+    }
+}
+
+// The year, the month and the day of the month of a day counted from the
+// Unix epoch, in the proleptic Gregorian calendar
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    (year, month, day)
+}
+
+/// A roll of a schedule, see `Schedule::load`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScheduledRoll {
+    pub cron: Cron,
+    /// The roll as it was written
+    pub source: String,
+    pub expression: Expression,
+    /// Where to post the roll to, if anywhere
+    pub webhook: Option<String>,
+}
+
+impl FromStr for ScheduledRoll {
+    type Err = ScheduleError;
+
+    fn from_str(line: &str) -> ScheduleResult<Self> {
+        let invalid = || ScheduleError::InvalidLine {
+            line: line.to_string(),
+        };
+
+        // The cron expression is the first five words
+        let mut rest = line.trim();
+        let mut fields = vec![];
+        for _ in 0..5 {
+            let (field, remainder) = rest.split_once(char::is_whitespace).ok_or_else(invalid)?;
+            fields.push(field);
+            rest = remainder.trim_start();
+        }
+        let cron = fields.join(" ").parse()?;
+
+        let (source, webhook) = match rest.rsplit_once("->") {
+            Some((source, webhook)) => (source.trim(), Some(webhook.trim().to_string())),
+            None => (rest.trim(), None),
+        };
+        if source.is_empty() || webhook.as_ref().is_some_and(String::is_empty) {
+            return Err(invalid());
+        }
+
+        let expression = parse(source).map_err(|error| ScheduleError::Parsing {
+            roll: source.to_string(),
+            error,
+        })?;
+
+        Ok(Self {
+            cron,
+            source: source.to_string(),
+            expression,
+            webhook,
+        })
+    }
+}
+
+impl Display for ScheduledRoll {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.source)?;
+        if let Some(webhook) = &self.webhook {
+            write!(f, " -> {webhook}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Every roll of a schedule in the order they are written
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Schedule {
+    pub rolls: Vec<ScheduledRoll>,
+}
+
+impl Schedule {
+    /// Load one roll a line, the empty lines and the lines starting with
+    /// `#` are skipped
+    pub fn load(source: &str) -> ScheduleResult<Self> {
+        let rolls = source
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::parse)
+            .collect::<ScheduleResult<_>>()?;
+
+        Ok(Self { rolls })
+    }
+
+    /// The next time any of the rolls is due after the given one, with
+    /// every roll due then
+    pub fn next_after(&self, time: i64) -> Option<(i64, Vec<&ScheduledRoll>)> {
+        let due = self
+            .rolls
+            .iter()
+            .filter_map(|roll| Some((roll.cron.next_after(time)?, roll)))
+            .collect::<Vec<_>>();
+        let at = due.iter().map(|(at, _)| *at).min()?;
+
+        let rolls = due
+            .into_iter()
+            .filter(|(due, _)| *due == at)
+            .map(|(_, roll)| roll)
+            .collect();
+        Some((at, rolls))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        parser::parse,
+        schedule::{civil_from_days, Cron, Schedule, ScheduleError, ScheduledRoll},
+    };
+
+    // 2024-01-01T00:00:00Z, a Monday
+    const NEW_YEAR: i64 = 1_704_067_200;
+    const DAY: i64 = 24 * 60 * 60;
+
+    fn next(cron: &str, time: i64) -> Option<i64> {
+        cron.parse::<Cron>().unwrap().next_after(time)
+    }
+
+    #[test]
+    fn test_calendar() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(NEW_YEAR / DAY), (2024, 1, 1));
+        assert_eq!(civil_from_days(NEW_YEAR / DAY + 59), (2024, 2, 29));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+    }
+
+    #[test]
+    fn test_cron() {
+        assert_eq!(next("* * * * *", NEW_YEAR), Some(NEW_YEAR + 60));
+        assert_eq!(next("* * * * *", NEW_YEAR + 59), Some(NEW_YEAR + 60));
+        assert_eq!(next("0 9 * * *", NEW_YEAR), Some(NEW_YEAR + 9 * 3600));
+        assert_eq!(
+            next("0 9 * * *", NEW_YEAR + 9 * 3600),
+            Some(NEW_YEAR + DAY + 9 * 3600)
+        );
+        assert_eq!(
+            next("*/20 * * * *", NEW_YEAR + 60),
+            Some(NEW_YEAR + 20 * 60)
+        );
+        assert_eq!(
+            next("5/30 * * * *", NEW_YEAR + 6 * 60),
+            Some(NEW_YEAR + 35 * 60)
+        );
+        assert_eq!(
+            next("0 12 * * 5", NEW_YEAR),
+            Some(NEW_YEAR + 4 * DAY + 12 * 3600)
+        );
+        // Sunday is both 0 and 7
+        assert_eq!(next("0 0 * * 7", NEW_YEAR), Some(NEW_YEAR + 6 * DAY));
+        assert_eq!(next("0 0 * * 0", NEW_YEAR), Some(NEW_YEAR + 6 * DAY));
+        assert_eq!(next("0 0 29 2 *", NEW_YEAR), Some(NEW_YEAR + 59 * DAY));
+        // Either of the days will do, the 15th or the next Saturday
+        assert_eq!(next("0 0 15 * 6", NEW_YEAR), Some(NEW_YEAR + 5 * DAY));
+        assert_eq!(next("0 0 3,15 * 6", NEW_YEAR), Some(NEW_YEAR + 2 * DAY));
+        assert_eq!(next("0 0 30 2 *", NEW_YEAR), None);
+
+        for invalid in [
+            "60 * * * *",
+            "* 24 * * *",
+            "* * 0 * *",
+            "*/0 * * * *",
+            "5-1 * * * *",
+        ] {
+            assert!(matches!(
+                invalid.parse::<Cron>(),
+                Err(ScheduleError::InvalidField { .. })
+            ));
+        }
+        assert!(matches!(
+            "* * * *".parse::<Cron>(),
+            Err(ScheduleError::InvalidLine { .. })
+        ));
+    }
+
+    #[test]
+    fn test_schedule() {
+        let schedule = Schedule::load(
+            "# the encounters
+             0 9 * * *  d20 + @bonus -> https://example.com/hook
+             0 9 * * 1  2d6
+
+             30 8 * * *  d100",
+        )
+        .unwrap();
+        assert_eq!(schedule.rolls.len(), 3);
+
+        let encounter = &schedule.rolls[0];
+        assert_eq!(encounter.expression, parse("d20 + @bonus").unwrap());
+        assert_eq!(
+            encounter.webhook.as_deref(),
+            Some("https://example.com/hook")
+        );
+        assert_eq!(
+            encounter.to_string(),
+            "d20 + @bonus -> https://example.com/hook"
+        );
+
+        let (at, rolls) = schedule.next_after(NEW_YEAR).unwrap();
+        assert_eq!(at, NEW_YEAR + 8 * 3600 + 30 * 60);
+        assert_eq!(rolls, [&schedule.rolls[2]]);
+
+        // Both rolls at 9 on a Monday are due together
+        let (at, rolls) = schedule.next_after(at).unwrap();
+        assert_eq!(at, NEW_YEAR + 9 * 3600);
+        assert_eq!(rolls, [&schedule.rolls[0], &schedule.rolls[1]]);
+
+        assert!(matches!(
+            "0 9 * * * d20 + (".parse::<ScheduledRoll>(),
+            Err(ScheduleError::Parsing { .. })
+        ));
+        assert!(matches!(
+            "0 9 * * *".parse::<ScheduledRoll>(),
+            Err(ScheduleError::InvalidLine { .. })
+        ));
+        assert!(matches!(
+            "0 9 * * * d20 ->".parse::<ScheduledRoll>(),
+            Err(ScheduleError::InvalidLine { .. })
+        ));
+        assert_eq!(Schedule::load("").unwrap().next_after(NEW_YEAR), None);
+    }
+}
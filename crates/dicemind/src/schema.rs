@@ -0,0 +1,734 @@
+//! A stable JSON representation of the expressions, for storing them
+//! across upgrades of the crate. The derived serde shape of `Expression`
+//! follows its Rust definition and changes with it, this one is
+//! versioned and only changes along with the version.
+//!
+//! Every node is an object tagged with its `kind`, the fields are named
+//! the same in every version. The numbers are JSON numbers unless they
+//! do not fit into 64 bits, then they are decimal strings.
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "expression": {
+//!     "kind": "binary",
+//!     "operator": "add",
+//!     "lhs": {
+//!       "kind": "dice",
+//!       "quantity": { "kind": "constant", "value": 4 },
+//!       "sides": { "kind": "constant", "value": 6 },
+//!       "augmentations": [
+//!         { "kind": "truncate", "op": "keep", "affix": "high", "count": 3 }
+//!       ]
+//!     },
+//!     "rhs": { "kind": "constant", "value": 2 }
+//!   }
+//! }
+//! ```
+//!
+//! `from_json` reads every version so far and the unversioned shape that
+//! was stored before the schema existed.
+
+use std::{cmp::Ordering, str::FromStr};
+
+use num::ToPrimitive;
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::syntax::{
+    Affix, AnnotationString, Augmentation, AugmentationToken, BinaryOperator, Decimal, Expression,
+    Face, FunctionName, Integer, MacroName, Metadata, PositiveInteger, Selector, SelectorOp,
+};
+
+/// The version written by `to_json`
+pub const VERSION: u64 = 1;
+
+#[derive(Debug, Error)]
+pub enum SchemaError {
+    #[error("Invalid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Unsupported schema version {version}, the latest known is {VERSION}")]
+    UnsupportedVersion { version: u64 },
+}
+
+pub fn to_json(expr: &Expression) -> String {
+    to_json_value(expr).to_string()
+}
+
+pub fn to_json_value(expr: &Expression) -> Value {
+    serde_json::to_value(Document {
+        version: VERSION,
+        expression: Node::from(expr),
+    })
+    .expect("the schema always encodes")
+}
+
+pub fn from_json(json: &str) -> Result<Expression, SchemaError> {
+    from_json_value(serde_json::from_str(json)?)
+}
+
+/// Read any version of the schema, migrating it to the current expression
+pub fn from_json_value(value: Value) -> Result<Expression, SchemaError> {
+    match value.get("version").map(Value::as_u64) {
+        Some(Some(1)) => Ok(serde_json::from_value::<Document>(value)?.expression.into()),
+        Some(Some(version)) => Err(SchemaError::UnsupportedVersion { version }),
+        Some(None) => Err(SchemaError::Json(serde_json::Error::custom(
+            "the version is not a number",
+        ))),
+        // Stored with the derived shape, before there were versions
+        None => Ok(serde_json::from_value(value)?),
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Document {
+    version: u64,
+    expression: Node,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum Node {
+    Constant {
+        value: Number,
+    },
+    Dice {
+        quantity: Option<Box<Node>>,
+        sides: Option<Box<Node>>,
+        augmentations: Vec<Augment>,
+    },
+    Weighted {
+        quantity: Option<Box<Node>>,
+        faces: Vec<WeightedFace>,
+        augmentations: Vec<Augment>,
+    },
+    Binary {
+        operator: Operator,
+        lhs: Box<Node>,
+        rhs: Box<Node>,
+    },
+    Decimal {
+        digits: Count,
+        scale: u32,
+    },
+    Negation {
+        operand: Box<Node>,
+    },
+    Parenthesized {
+        expression: Box<Node>,
+    },
+    Annotated {
+        expression: Box<Node>,
+        annotation: AnnotationString,
+    },
+    Repeat {
+        count: Box<Node>,
+        expression: Box<Node>,
+    },
+    Call {
+        function: FunctionName,
+        args: Vec<Node>,
+    },
+    Group {
+        members: Vec<Node>,
+        augmentations: Vec<Augment>,
+    },
+    Reference {
+        name: MacroName,
+    },
+    Conditional {
+        condition: Box<Node>,
+        then: Box<Node>,
+        otherwise: Box<Node>,
+    },
+    Tagged {
+        expression: Box<Node>,
+        metadata: Metadata,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+struct WeightedFace {
+    value: Number,
+    weight: Count,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Operator {
+    Chain,
+    Equals,
+    LessThan,
+    GreaterThan,
+    Add,
+    Subtract,
+    Multiply,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum Augment {
+    Truncate {
+        op: Op,
+        affix: Side,
+        count: Option<Count>,
+    },
+    Filter {
+        op: Op,
+        selector: Condition,
+    },
+    Emphasis {
+        count: Option<Count>,
+    },
+    Explode {
+        selector: Option<Condition>,
+    },
+    Compound {
+        selector: Option<Condition>,
+    },
+    Reroll {
+        selector: Condition,
+        once: bool,
+    },
+    CriticalSuccess {
+        selector: Condition,
+    },
+    CriticalFailure {
+        selector: Condition,
+    },
+    Minimum {
+        value: Count,
+    },
+    Maximum {
+        value: Count,
+    },
+    CountSuccesses {
+        selector: Condition,
+    },
+    CountFailures {
+        selector: Condition,
+    },
+    Custom {
+        token: AugmentationToken,
+        argument: Option<Count>,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Op {
+    Keep,
+    Drop,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Side {
+    High,
+    Low,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Relation {
+    Less,
+    Equal,
+    Greater,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Condition {
+    relation: Relation,
+    inclusive: bool,
+    value: Count,
+}
+
+// A JSON number, or a decimal string when it does not fit one
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum Digits {
+    Signed(i64),
+    Unsigned(u64),
+    Decimal(String),
+}
+
+impl Digits {
+    fn of(n: &Integer) -> Self {
+        match n.to_i64() {
+            Some(n) => Digits::Signed(n),
+            None => Digits::Decimal(n.to_string()),
+        }
+    }
+
+    fn parse<E: serde::de::Error>(self) -> Result<Integer, E> {
+        match self {
+            Digits::Signed(n) => Ok(n.into()),
+            Digits::Unsigned(n) => Ok(n.into()),
+            Digits::Decimal(s) => Integer::from_str(&s).map_err(E::custom),
+        }
+    }
+}
+
+struct Number(Integer);
+
+impl Serialize for Number {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Digits::of(&self.0).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Number {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Digits::deserialize(deserializer)?.parse().map(Number)
+    }
+}
+
+// The counts, sides and bounds can not be negative
+struct Count(PositiveInteger);
+
+impl Serialize for Count {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Digits::of(&self.0.clone().into()).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Count {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let n: Integer = Digits::deserialize(deserializer)?.parse()?;
+        n.to_biguint()
+            .map(Count)
+            .ok_or_else(|| D::Error::custom(format!("expected a count, got {n}")))
+    }
+}
+
+impl From<&Expression> for Node {
+    fn from(expr: &Expression) -> Self {
+        use Expression as E;
+
+        let node = |expr: &Expression| Box::new(Node::from(expr));
+        let nodes = |exprs: &[Expression]| exprs.iter().map(Node::from).collect();
+        let augments =
+            |augmentations: &[Augmentation]| augmentations.iter().map(Augment::from).collect();
+
+        match expr {
+            E::Constant(n) => Node::Constant {
+                value: Number(n.clone()),
+            },
+            E::Dice {
+                quantity,
+                power,
+                augmentations,
+            } => Node::Dice {
+                quantity: quantity.as_deref().map(node),
+                sides: power.as_deref().map(node),
+                augmentations: augments(augmentations),
+            },
+            E::Weighted {
+                quantity,
+                faces,
+                augmentations,
+            } => Node::Weighted {
+                quantity: quantity.as_deref().map(node),
+                faces: faces
+                    .iter()
+                    .map(|face| WeightedFace {
+                        value: Number(face.value.clone()),
+                        weight: Count(face.weight.clone()),
+                    })
+                    .collect(),
+                augmentations: augments(augmentations),
+            },
+            E::Binop { operator, lhs, rhs } => Node::Binary {
+                operator: match operator {
+                    BinaryOperator::Chain => Operator::Chain,
+                    BinaryOperator::Equals => Operator::Equals,
+                    BinaryOperator::LessThan => Operator::LessThan,
+                    BinaryOperator::GreaterThan => Operator::GreaterThan,
+                    BinaryOperator::Add => Operator::Add,
+                    BinaryOperator::Subtract => Operator::Subtract,
+                    BinaryOperator::Multiply => Operator::Multiply,
+                },
+                lhs: node(lhs),
+                rhs: node(rhs),
+            },
+            E::Decimal(d) => Node::Decimal {
+                digits: Count(d.digits.clone()),
+                scale: d.scale,
+            },
+            E::UnaryNegation(operand) => Node::Negation {
+                operand: node(operand),
+            },
+            E::Subexpression(expression) => Node::Parenthesized {
+                expression: node(expression),
+            },
+            E::Annotated {
+                expression,
+                annotation,
+            } => Node::Annotated {
+                expression: node(expression),
+                annotation: annotation.clone(),
+            },
+            E::Repeat { count, expression } => Node::Repeat {
+                count: node(count),
+                expression: node(expression),
+            },
+            E::Call { function, args } => Node::Call {
+                function: function.clone(),
+                args: nodes(args),
+            },
+            E::Group {
+                members,
+                augmentations,
+            } => Node::Group {
+                members: nodes(members),
+                augmentations: augments(augmentations),
+            },
+            E::Reference(name) => Node::Reference { name: name.clone() },
+            E::Conditional {
+                condition,
+                then,
+                otherwise,
+            } => Node::Conditional {
+                condition: node(condition),
+                then: node(then),
+                otherwise: node(otherwise),
+            },
+            E::Tagged {
+                expression,
+                metadata,
+            } => Node::Tagged {
+                expression: node(expression),
+                metadata: metadata.clone(),
+            },
+        }
+    }
+}
+
+impl From<Node> for Expression {
+    fn from(node: Node) -> Self {
+        use Expression as E;
+
+        let expr = |node: Box<Node>| Box::new(Expression::from(*node));
+        let exprs = |nodes: Vec<Node>| nodes.into_iter().map(Expression::from).collect();
+        let augments =
+            |augments: Vec<Augment>| augments.into_iter().map(Augmentation::from).collect();
+
+        match node {
+            Node::Constant { value } => E::Constant(value.0),
+            Node::Dice {
+                quantity,
+                sides,
+                augmentations,
+            } => E::Dice {
+                quantity: quantity.map(expr),
+                power: sides.map(expr),
+                augmentations: augments(augmentations),
+            },
+            Node::Weighted {
+                quantity,
+                faces,
+                augmentations,
+            } => E::Weighted {
+                quantity: quantity.map(expr),
+                faces: Box::new(
+                    faces
+                        .into_iter()
+                        .map(|face| Face::new(face.value.0, face.weight.0))
+                        .collect(),
+                ),
+                augmentations: augments(augmentations),
+            },
+            Node::Binary { operator, lhs, rhs } => E::Binop {
+                operator: match operator {
+                    Operator::Chain => BinaryOperator::Chain,
+                    Operator::Equals => BinaryOperator::Equals,
+                    Operator::LessThan => BinaryOperator::LessThan,
+                    Operator::GreaterThan => BinaryOperator::GreaterThan,
+                    Operator::Add => BinaryOperator::Add,
+                    Operator::Subtract => BinaryOperator::Subtract,
+                    Operator::Multiply => BinaryOperator::Multiply,
+                },
+                lhs: expr(lhs),
+                rhs: expr(rhs),
+            },
+            Node::Decimal { digits, scale } => E::Decimal(Decimal::new(digits.0, scale)),
+            Node::Negation { operand } => E::UnaryNegation(expr(operand)),
+            Node::Parenthesized { expression } => E::Subexpression(expr(expression)),
+            Node::Annotated {
+                expression,
+                annotation,
+            } => E::Annotated {
+                expression: expr(expression),
+                annotation,
+            },
+            Node::Repeat { count, expression } => E::Repeat {
+                count: expr(count),
+                expression: expr(expression),
+            },
+            Node::Call { function, args } => E::Call {
+                function,
+                args: exprs(args),
+            },
+            Node::Group {
+                members,
+                augmentations,
+            } => E::Group {
+                members: exprs(members),
+                augmentations: augments(augmentations),
+            },
+            Node::Reference { name } => E::Reference(name),
+            Node::Conditional {
+                condition,
+                then,
+                otherwise,
+            } => E::Conditional {
+                condition: expr(condition),
+                then: expr(then),
+                otherwise: expr(otherwise),
+            },
+            Node::Tagged {
+                expression,
+                metadata,
+            } => E::Tagged {
+                expression: expr(expression),
+                metadata,
+            },
+        }
+    }
+}
+
+impl From<&Augmentation> for Augment {
+    fn from(augment: &Augmentation) -> Self {
+        use Augmentation as A;
+
+        let count = |n: &PositiveInteger| Count(n.clone());
+
+        match augment {
+            A::Truncate { op, affix, n } => Augment::Truncate {
+                op: op.into(),
+                affix: match affix {
+                    Affix::High => Side::High,
+                    Affix::Low => Side::Low,
+                },
+                count: n.as_ref().map(count),
+            },
+            A::Filter { op, selector } => Augment::Filter {
+                op: op.into(),
+                selector: selector.into(),
+            },
+            A::Emphasis { n } => Augment::Emphasis {
+                count: n.as_ref().map(count),
+            },
+            A::Explode { selector } => Augment::Explode {
+                selector: selector.as_ref().map(Condition::from),
+            },
+            A::Compound { selector } => Augment::Compound {
+                selector: selector.as_ref().map(Condition::from),
+            },
+            A::Reroll { selector, once } => Augment::Reroll {
+                selector: selector.into(),
+                once: *once,
+            },
+            A::CriticalSuccess { selector } => Augment::CriticalSuccess {
+                selector: selector.into(),
+            },
+            A::CriticalFailure { selector } => Augment::CriticalFailure {
+                selector: selector.into(),
+            },
+            A::Minimum { n } => Augment::Minimum { value: count(n) },
+            A::Maximum { n } => Augment::Maximum { value: count(n) },
+            A::CountSuccesses { selector } => Augment::CountSuccesses {
+                selector: selector.into(),
+            },
+            A::CountFailures { selector } => Augment::CountFailures {
+                selector: selector.into(),
+            },
+            A::Custom { token, n } => Augment::Custom {
+                token: token.clone(),
+                argument: n.as_ref().map(count),
+            },
+        }
+    }
+}
+
+impl From<Augment> for Augmentation {
+    fn from(augment: Augment) -> Self {
+        use Augmentation as A;
+
+        match augment {
+            Augment::Truncate { op, affix, count } => A::Truncate {
+                op: op.into(),
+                affix: match affix {
+                    Side::High => Affix::High,
+                    Side::Low => Affix::Low,
+                },
+                n: count.map(|n| n.0),
+            },
+            Augment::Filter { op, selector } => A::Filter {
+                op: op.into(),
+                selector: selector.into(),
+            },
+            Augment::Emphasis { count } => A::Emphasis {
+                n: count.map(|n| n.0),
+            },
+            Augment::Explode { selector } => A::Explode {
+                selector: selector.map(Selector::from),
+            },
+            Augment::Compound { selector } => A::Compound {
+                selector: selector.map(Selector::from),
+            },
+            Augment::Reroll { selector, once } => A::Reroll {
+                selector: selector.into(),
+                once,
+            },
+            Augment::CriticalSuccess { selector } => A::CriticalSuccess {
+                selector: selector.into(),
+            },
+            Augment::CriticalFailure { selector } => A::CriticalFailure {
+                selector: selector.into(),
+            },
+            Augment::Minimum { value } => A::Minimum { n: value.0 },
+            Augment::Maximum { value } => A::Maximum { n: value.0 },
+            Augment::CountSuccesses { selector } => A::CountSuccesses {
+                selector: selector.into(),
+            },
+            Augment::CountFailures { selector } => A::CountFailures {
+                selector: selector.into(),
+            },
+            Augment::Custom { token, argument } => A::Custom {
+                token,
+                n: argument.map(|n| n.0),
+            },
+        }
+    }
+}
+
+impl From<&SelectorOp> for Op {
+    fn from(op: &SelectorOp) -> Self {
+        match op {
+            SelectorOp::Keep => Op::Keep,
+            SelectorOp::Drop => Op::Drop,
+        }
+    }
+}
+
+impl From<Op> for SelectorOp {
+    fn from(op: Op) -> Self {
+        match op {
+            Op::Keep => SelectorOp::Keep,
+            Op::Drop => SelectorOp::Drop,
+        }
+    }
+}
+
+impl From<&Selector> for Condition {
+    fn from(selector: &Selector) -> Self {
+        Condition {
+            relation: match selector.relation {
+                Ordering::Less => Relation::Less,
+                Ordering::Equal => Relation::Equal,
+                Ordering::Greater => Relation::Greater,
+            },
+            inclusive: selector.inclusive,
+            value: Count(selector.n.clone()),
+        }
+    }
+}
+
+impl From<Condition> for Selector {
+    fn from(condition: Condition) -> Self {
+        Selector {
+            relation: match condition.relation {
+                Relation::Less => Ordering::Less,
+                Relation::Equal => Ordering::Equal,
+                Relation::Greater => Ordering::Greater,
+            },
+            inclusive: condition.inclusive,
+            n: condition.value.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::{
+        dialect::{Foundry, Roll20},
+        parser::{parse, parse_dialect},
+        schema::{from_json, from_json_value, to_json, to_json_value, SchemaError},
+        syntax::{Augmentation, Expr, Expression},
+    };
+
+    #[test]
+    fn test_schema() {
+        let value = to_json_value(&parse("4d6kh3 + 2").unwrap());
+        assert_eq!(
+            value,
+            json!({
+                "version": 1,
+                "expression": {
+                    "kind": "binary",
+                    "operator": "add",
+                    "lhs": {
+                        "kind": "dice",
+                        "quantity": { "kind": "constant", "value": 4 },
+                        "sides": { "kind": "constant", "value": 6 },
+                        "augmentations": [
+                            { "kind": "truncate", "op": "keep", "affix": "high", "count": 3 }
+                        ]
+                    },
+                    "rhs": { "kind": "constant", "value": 2 }
+                }
+            })
+        );
+
+        let exprs = [
+            parse("(2d6 + 2) * (2d20kh + 3 + 2 > 13)").unwrap(),
+            parse("d20! - -d% [fire]").unwrap(),
+            parse("3x({d4, 2d8s>=5f=1}dl1)").unwrap(),
+            parse("max(@attack, 1d4e) ? d(1 + 2)!>5 : 99999999999999999999").unwrap(),
+            parse("20d6k>2d<=1").unwrap(),
+            parse_dialect("d20ro<2 + 3d6!! + d20cs>19cf<2 + 8d10r1", &Roll20).unwrap(),
+            parse_dialect("d20min10 + 4d6max5", &Foundry).unwrap(),
+            Expr::dice(4, 6)
+                .augment(Augmentation::Custom {
+                    token: "t".into(),
+                    n: Some(2u32.into()),
+                })
+                .build(),
+            Expr::die(20).tag("token", "goblin-3").build(),
+            parse("3d{-1:2, 5:0}kh").unwrap(),
+            parse("1.5 * 2d6 - 0.05").unwrap(),
+        ];
+
+        for expr in exprs {
+            assert_eq!(from_json(&to_json(&expr)).unwrap(), expr, "{expr}");
+        }
+    }
+
+    #[test]
+    fn test_migration() {
+        // Saved with the derived shape before the schema was versioned
+        let expr = parse("2d20kh1 + 5 [attack]").unwrap();
+        let legacy = serde_json::to_value(&expr).unwrap();
+        assert_eq!(from_json_value(legacy).unwrap(), expr);
+
+        assert!(matches!(
+            from_json(r#"{"version": 7, "expression": {}}"#),
+            Err(SchemaError::UnsupportedVersion { version: 7 })
+        ));
+        assert!(from_json(r#"{"version": 1, "expression": {"kind": "dice"}}"#).is_err());
+        assert!(from_json(
+            r#"{"version": 1, "expression": {"kind": "dice", "quantity": null, "sides": null,
+                "augmentations": [{"kind": "minimum", "value": -1}]}}"#
+        )
+        .is_err());
+        assert_eq!(
+            from_json(r#"{"version": 1, "expression": {"kind": "constant", "value": "-12"}}"#)
+                .unwrap(),
+            Expression::Constant((-12).into())
+        );
+    }
+}
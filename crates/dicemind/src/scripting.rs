@@ -0,0 +1,193 @@
+use std::sync::Arc;
+
+use rhai::{Array, Dynamic, Engine, FnAccess, Scope, AST, INT};
+use thiserror::Error;
+
+use crate::{
+    functions::{Arity, FunctionRegistry},
+    interpreter::{NaiveValue, RollerError, RollerResult},
+};
+
+#[derive(Debug, Error)]
+pub enum ScriptError {
+    #[error("Could not compile the script: {0}")]
+    Compile(#[from] rhai::ParseError),
+}
+
+/// Homebrew rules written as [rhai](https://rhai.rs) functions. Every public
+/// function of the script becomes callable from the expressions, receiving
+/// the rolled dice and returning the value of the call:
+///
+/// ```rhai
+/// fn brutal(dice) {
+///     let ones = 0;
+///     for d in dice { if d == 1 { ones += 1; } }
+///     -ones
+/// }
+/// ```
+///
+/// Dice and pools are passed as arrays of the kept values, repeated rolls as
+/// arrays of their totals and everything else as a plain integer.
+///
+/// The scripts are sandboxed, they can't reach outside of the engine and
+/// every call is limited to a fixed amount of operations.
+#[derive(Clone)]
+pub struct ScriptHost {
+    engine: Arc<Engine>,
+    ast: Arc<AST>,
+}
+
+impl ScriptHost {
+    /// Operations a single call can perform before it's aborted
+    pub const DEFAULT_FUEL: u64 = 10_000;
+
+    pub fn new(source: &str) -> Result<Self, ScriptError> {
+        Self::with_fuel(source, Self::DEFAULT_FUEL)
+    }
+
+    pub fn with_fuel(source: &str, fuel: u64) -> Result<Self, ScriptError> {
+        let mut engine = Engine::new();
+        engine
+            .set_max_operations(fuel.max(1))
+            .set_max_call_levels(16)
+            .set_max_expr_depths(64, 32)
+            .set_max_string_size(1024)
+            .set_max_array_size(1024)
+            .set_max_map_size(256)
+            .disable_symbol("eval")
+            .on_print(|_| {})
+            .on_debug(|_, _, _| {});
+
+        let ast = engine.compile(source)?;
+
+        Ok(Self {
+            engine: Arc::new(engine),
+            ast: Arc::new(ast),
+        })
+    }
+
+    /// Names of the public functions of the script
+    pub fn functions(&self) -> impl Iterator<Item = &str> {
+        self.ast
+            .iter_functions()
+            .filter(|f| f.access == FnAccess::Public)
+            .map(|f| f.name)
+    }
+
+    /// Make the functions of the script callable from the expressions,
+    /// they shadow the builtins and the previously registered functions
+    pub fn register(&self, functions: &mut FunctionRegistry) {
+        for f in self.ast.iter_functions() {
+            if f.access != FnAccess::Public {
+                continue;
+            }
+
+            let host = self.clone();
+            let name = f.name.to_string();
+            functions.register_with_dice(
+                f.name,
+                Arity::Exactly(f.params.len()),
+                move |args, _| host.call(&name, args),
+            );
+        }
+    }
+
+    pub fn call(&self, function: &str, args: &[NaiveValue]) -> Result<i64, RollerError> {
+        let args = args
+            .iter()
+            .map(to_dynamic)
+            .collect::<RollerResult<Vec<_>>>()?;
+
+        self.engine
+            .call_fn::<INT>(&mut Scope::new(), &self.ast, function, args)
+            .map_err(|err| RollerError::Script {
+                function: function.into(),
+                message: err.to_string(),
+            })
+    }
+}
+
+fn to_dynamic(value: &NaiveValue) -> RollerResult<Dynamic> {
+    Ok(match value {
+        NaiveValue::Constant(c) => Dynamic::from_int(*c),
+        NaiveValue::Dice(dice) | NaiveValue::Pool(dice) => Dynamic::from_array(
+            dice.iter()
+                .filter(|d| !d.is_discarded())
+                .map(|d| Dynamic::from_int(d.value))
+                .collect::<Array>(),
+        ),
+        NaiveValue::List(values) => Dynamic::from_array(
+            values
+                .iter()
+                .map(|v| v.total().map(Dynamic::from_int))
+                .collect::<RollerResult<Array>>()?,
+        ),
+        NaiveValue::Summary(summary) => Dynamic::from_int(summary.total),
+        // The scripts round the decimals themselves
+        NaiveValue::Decimal { exact, .. } => {
+            Dynamic::from_float(*exact.numer() as f64 / *exact.denom() as f64)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        functions::FunctionRegistry,
+        interpreter::RollerError,
+        prelude::{parse, StandardNaiveRoller},
+        scripting::ScriptHost,
+    };
+
+    const SCRIPT: &str = "
+        fn ones(dice) {
+            let n = 0;
+            for d in dice { if d == 1 { n += 1; } }
+            n
+        }
+
+        fn forever(x) {
+            loop { x += 1; }
+        }
+
+        private fn helper() { 0 }
+    ";
+
+    #[test]
+    fn test_scripts() {
+        let host = ScriptHost::new(SCRIPT).unwrap();
+        let mut names = host.functions().collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["forever", "ones"]);
+
+        let mut functions = FunctionRegistry::new();
+        host.register(&mut functions);
+        let mut roller = StandardNaiveRoller::new_seeded(0).with_functions(functions);
+
+        let ones = roller
+            .roll_naive(parse("ones(10d6)").unwrap())
+            .unwrap()
+            .total()
+            .unwrap();
+        assert!((0..=10).contains(&ones));
+        assert_eq!(
+            roller
+                .roll_naive(parse("ones(3d1) + 1").unwrap())
+                .unwrap()
+                .total()
+                .unwrap(),
+            4
+        );
+
+        // Runaway scripts run out of fuel
+        assert!(matches!(
+            roller.roll_naive(parse("forever(0)").unwrap()),
+            Err(RollerError::Script { .. })
+        ));
+        assert!(roller.roll_naive(parse("helper()").unwrap()).is_err());
+        assert!(roller.roll_naive(parse("ones(1, 2)").unwrap()).is_err());
+
+        assert!(ScriptHost::new("fn broken( {").is_err());
+        assert!(ScriptHost::new("fn sneaky() { eval(\"1\") }").is_err());
+    }
+}
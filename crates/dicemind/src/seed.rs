@@ -0,0 +1,106 @@
+use rand::{seq::SliceRandom, thread_rng, Rng};
+
+const ADJECTIVES: &[&str] = &[
+    "amber", "ancient", "bold", "brave", "bright", "calm", "clever", "crimson", "cunning", "dark",
+    "eager", "fierce", "gentle", "golden", "grim", "hidden", "hollow", "icy", "jolly", "lucky",
+    "mighty", "misty", "noble", "pale", "quiet", "rusty", "silent", "silver", "swift", "wild",
+    "wise", "young",
+];
+
+const ANIMALS: &[&str] = &[
+    "badger", "bear", "beetle", "boar", "crow", "deer", "dragon", "eagle", "falcon", "fox",
+    "goblin", "griffin", "hare", "hawk", "heron", "lynx", "mole", "moth", "newt", "otter", "owl",
+    "raven", "salmon", "serpent", "spider", "stag", "toad", "troll", "viper", "weasel", "wolf",
+    "wyvern",
+];
+
+/// Turn a seed phrase like `crimson-otter-42` into a seed for the rollers.
+/// Plain numbers are used as is, so `42` and `"42"` roll the same dice.
+pub fn seed_from_phrase(phrase: &str) -> u64 {
+    let phrase = phrase.trim().to_lowercase();
+
+    if let Ok(seed) = phrase.parse::<u64>() {
+        return seed;
+    }
+
+    fnv1a(phrase.bytes())
+}
+
+// FNV-1a, the std hashers are not stable across releases
+pub(crate) fn fnv1a(bytes: impl IntoIterator<Item = u8>) -> u64 {
+    bytes.into_iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+// SplitMix64, spreads the close seeds apart
+pub(crate) fn mix(mut z: u64) -> u64 {
+    z = z.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// The seed of the `nth` of the rollers sharing a seed. Unlike `seed + nth`
+/// the rollers of `1` and of `2` have no seed in common.
+pub fn derive_seed(seed: u64, nth: u64) -> u64 {
+    mix(mix(seed) ^ nth)
+}
+
+/// A memorable phrase that can be fed back into `seed_from_phrase`
+pub fn random_seed_phrase() -> String {
+    seed_phrase_from(&mut thread_rng())
+}
+
+pub fn seed_phrase_from(rng: &mut impl Rng) -> String {
+    format!(
+        "{}-{}-{}",
+        ADJECTIVES.choose(rng).unwrap(),
+        ANIMALS.choose(rng).unwrap(),
+        rng.gen_range(0..100)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::{
+        prelude::{parse, StandardNaiveRoller},
+        seed::{derive_seed, random_seed_phrase, seed_from_phrase},
+    };
+
+    #[test]
+    fn test_phrases() {
+        assert_eq!(seed_from_phrase("42"), 42);
+        assert_eq!(
+            seed_from_phrase("crimson-otter-42"),
+            seed_from_phrase(" Crimson-Otter-42 ")
+        );
+        assert_ne!(
+            seed_from_phrase("crimson-otter-42"),
+            seed_from_phrase("crimson-otter-43")
+        );
+
+        let phrase = random_seed_phrase();
+        assert_eq!(phrase.split('-').count(), 3);
+
+        let roll = |phrase: &str| {
+            StandardNaiveRoller::new_seeded(seed_from_phrase(phrase))
+                .roll_naive(parse("10d20").unwrap())
+                .unwrap()
+                .total()
+        };
+        assert_eq!(roll(&phrase), roll(&phrase));
+    }
+
+    #[test]
+    fn test_derive_seed() {
+        assert_eq!(derive_seed(42, 3), derive_seed(42, 3));
+
+        // Every roller of the adjacent seeds gets a seed of its own
+        let rollers = |seed| (0..64).map(move |nth| derive_seed(seed, nth));
+        let seeds: HashSet<_> = (0..64).flat_map(rollers).collect();
+        assert_eq!(seeds.len(), 64 * 64);
+    }
+}
@@ -0,0 +1,149 @@
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum SerializationError {
+    #[cfg(feature = "bincode")]
+    #[error("Bincode serialization failed: {0}")]
+    Bincode(#[from] bincode::Error),
+    #[cfg(feature = "cbor")]
+    #[error("CBOR serialization failed: {0}")]
+    CborEncode(#[from] ciborium::ser::Error<std::io::Error>),
+    #[cfg(feature = "cbor")]
+    #[error("CBOR deserialization failed: {0}")]
+    CborDecode(#[from] ciborium::de::Error<std::io::Error>),
+    #[cfg(feature = "postcard")]
+    #[error("Postcard serialization failed: {0}")]
+    Postcard(#[from] postcard::Error),
+}
+
+/// Encode an expression or a roll result with bincode
+#[cfg(feature = "bincode")]
+pub fn to_bincode<T: Serialize>(value: &T) -> Result<Vec<u8>, SerializationError> {
+    Ok(bincode::serialize(value)?)
+}
+
+#[cfg(feature = "bincode")]
+pub fn from_bincode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, SerializationError> {
+    Ok(bincode::deserialize(bytes)?)
+}
+
+/// Encode an expression or a roll result as CBOR
+#[cfg(feature = "cbor")]
+pub fn to_cbor<T: Serialize>(value: &T) -> Result<Vec<u8>, SerializationError> {
+    let mut buf = vec![];
+    ciborium::into_writer(value, &mut buf)?;
+    Ok(buf)
+}
+
+#[cfg(feature = "cbor")]
+pub fn from_cbor<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, SerializationError> {
+    Ok(ciborium::from_reader(bytes)?)
+}
+
+/// Encode an expression or a roll result with postcard, the smallest of
+/// the formats since the integers are varints
+#[cfg(feature = "postcard")]
+pub fn to_postcard<T: Serialize>(value: &T) -> Result<Vec<u8>, SerializationError> {
+    Ok(postcard::to_allocvec(value)?)
+}
+
+#[cfg(feature = "postcard")]
+pub fn from_postcard<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, SerializationError> {
+    Ok(postcard::from_bytes(bytes)?)
+}
+
+#[cfg(all(test, feature = "bincode", feature = "cbor", feature = "postcard"))]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+    use serde::{de::DeserializeOwned, Serialize};
+
+    use crate::{
+        generate::Constraints,
+        interpreter::{NaiveValue, PruneOptions, Value},
+        prelude::{parse, Expression, StandardNaiveRoller},
+        serialization::{
+            from_bincode, from_cbor, from_postcard, to_bincode, to_cbor, to_postcard,
+            SerializationError,
+        },
+        syntax::Expr,
+    };
+
+    // Decodes what was encoded and compares the JSON of both
+    fn assert_round_trip<T>(value: &T)
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        let json = |value: &T| serde_json::to_value(value).unwrap();
+        let formats: [(
+            fn(&T) -> Result<Vec<u8>, SerializationError>,
+            fn(&[u8]) -> Result<T, SerializationError>,
+        ); 3] = [
+            (to_bincode, from_bincode),
+            (to_cbor, from_cbor),
+            (to_postcard, from_postcard),
+        ];
+
+        for (encode, decode) in formats {
+            let decoded = decode(&encode(value).unwrap()).unwrap();
+            assert_eq!(json(&decoded), json(value));
+        }
+    }
+
+    #[test]
+    fn test_expression_round_trip() {
+        let expr = parse("(2d6 + 2) * (2d20kh + 3 + 2 > 13)").unwrap();
+
+        let bin: Expression = from_bincode(&to_bincode(&expr).unwrap()).unwrap();
+        let cbor: Expression = from_cbor(&to_cbor(&expr).unwrap()).unwrap();
+        let postcard: Expression = from_postcard(&to_postcard(&expr).unwrap()).unwrap();
+
+        assert_eq!(bin, expr);
+        assert_eq!(cbor, expr);
+        assert_eq!(postcard, expr);
+
+        for seed in 0..200 {
+            let expr =
+                Expression::arbitrary(&mut StdRng::seed_from_u64(seed), &Constraints::default());
+            let expr = Expr::from(expr).tag("seed", seed.to_string()).build();
+            assert_round_trip(&expr);
+        }
+    }
+
+    #[test]
+    fn test_roll_round_trip() {
+        let mut roller = StandardNaiveRoller::new_seeded(0);
+
+        for input in [
+            "4d6kh3 + 2",
+            "3x(d20 > 10)",
+            "{d4, 2d8}kl1",
+            "8d10s>=7f<=1",
+            "1000x(d6)",
+        ] {
+            let value = roller.roll_naive(parse(input).unwrap()).unwrap();
+            assert_round_trip(&value);
+            assert_round_trip(&value.prune(&PruneOptions::default()));
+            assert_round_trip(&Value::try_from(&value).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_smaller_than_json() {
+        let mut roller = StandardNaiveRoller::new_seeded(0);
+        let value = roller.roll_naive(parse("40d6").unwrap()).unwrap();
+
+        let json = serde_json::to_vec(&value).unwrap();
+        let bin = to_bincode(&value).unwrap();
+        let cbor = to_cbor(&value).unwrap();
+
+        let postcard = to_postcard(&value).unwrap();
+
+        assert!(bin.len() < json.len());
+        assert!(cbor.len() < json.len());
+        assert!(postcard.len() < bin.len());
+
+        let decoded: NaiveValue = from_bincode(&bin).unwrap();
+        assert_eq!(decoded.total(), value.total());
+    }
+}
@@ -1,6 +1,8 @@
 use crate::{
     options::RollerOptions,
     syntax::{Expression, Integer},
+    systems::earthdawn,
+    transformer::Transformer,
 };
 
 bitflags::bitflags! {
@@ -16,59 +18,101 @@ bitflags::bitflags! {
         const DISTRIBUTE_OPERATIONS = 1 << 3;
         // 2 + 4 => 6
         const COLLAPE_CONSTANTS = 1 << 4;
+        // @attack => (d20 + 7)
+        const INLINE_MACROS = 1 << 5;
+        // step(13) => (d12! + d10!)
+        const EXPAND_EARTHDAWN_STEPS = 1 << 6;
     }
 }
 
+// The steps are left to the rollers, they know whether `step` is the
+// Earthdawn one or a function registered in its place
 pub fn simplify(expr: Expression) -> Expression {
-    advanced_simplify(expr, &RollerOptions::default(), Steps::all())
+    let steps = Steps::all().difference(Steps::EXPAND_EARTHDAWN_STEPS);
+    advanced_simplify(expr, &RollerOptions::default(), steps)
 }
 
 pub fn advanced_simplify(expr: Expression, options: &RollerOptions, steps: Steps) -> Expression {
-    use Expression as E;
+    Simplifier { options, steps }.fold(expr)
+}
 
-    match expr {
-        E::Dice {
-            quantity,
-            power,
-            augmentations,
-        } => {
-            let mut q = quantity.map(|expr| advanced_simplify(*expr, options, steps));
-            let mut p = power.map(|expr| advanced_simplify(*expr, options, steps));
-
-            if steps.contains(Steps::INLINE_IMPLICIT_OPTIONS) {
-                q = q.or_else(|| Some(E::Constant(options.quantity().into())));
-                p = p.or_else(|| Some(E::Constant(options.power().into())));
-            }
+struct Simplifier<'a> {
+    options: &'a RollerOptions,
+    steps: Steps,
+}
+
+impl Transformer for Simplifier<'_> {
+    fn transform(&mut self, expr: Expression) -> Expression {
+        use Expression as E;
+
+        let Simplifier { options, steps } = *self;
+
+        match expr {
+            E::Dice {
+                quantity,
+                power,
+                augmentations,
+            } => {
+                let mut q = quantity.map(|expr| *expr);
+                let mut p = power.map(|expr| *expr);
 
-            if steps.contains(Steps::REPLACE_CONSTANT_VALUED_DICE) {
-                use num::One;
-                fn is_one(expr: &E) -> bool {
-                    matches!(expr, E::Constant(c) if c.is_one())
+                if steps.contains(Steps::INLINE_IMPLICIT_OPTIONS) {
+                    q = q.or_else(|| Some(E::Constant(options.quantity().into())));
+                    p = p.or_else(|| Some(E::Constant(options.power().into())));
                 }
 
-                if p.as_ref().is_some_and(is_one) {
-                    if let Some(expr) = q {
-                        return expr;
+                if steps.contains(Steps::REPLACE_CONSTANT_VALUED_DICE) {
+                    use num::One;
+                    fn is_one(expr: &E) -> bool {
+                        matches!(expr, E::Constant(c) if c.is_one())
+                    }
+
+                    if p.as_ref().is_some_and(is_one) {
+                        if let Some(expr) = q {
+                            return expr;
+                        }
+                    }
+
+                    use num::Zero;
+                    fn is_zero(expr: &E) -> bool {
+                        matches!(expr, E::Constant(c) if c.is_zero())
                     }
-                }
 
-                use num::Zero;
-                fn is_zero(expr: &E) -> bool {
-                    matches!(expr, E::Constant(c) if c.is_zero())
+                    if p.as_ref().is_some_and(is_zero) || q.as_ref().is_some_and(is_zero) {
+                        return E::Constant(Integer::zero());
+                    }
                 }
 
-                if p.as_ref().is_some_and(is_zero) || q.as_ref().is_some_and(is_zero) {
-                    return E::Constant(Integer::zero());
+                E::Dice {
+                    quantity: q.map(Box::new),
+                    power: p.map(Box::new),
+                    augmentations,
+                }
+            }
+            // Unknown macros are left as is, the roller reports them
+            E::Reference(name) if steps.contains(Steps::INLINE_MACROS) => {
+                match options.macros().expand(E::Reference(name.clone())) {
+                    Ok(expr) => self.fold(expr),
+                    Err(_) => E::Reference(name),
                 }
             }
+            // The steps that are not constants or have no dice are left
+            // as is, the roller reports them
+            E::Call { function, args } if steps.contains(Steps::EXPAND_EARTHDAWN_STEPS) => {
+                let dice = match args.as_slice() {
+                    [E::Constant(n)] if function == "step" => {
+                        u32::try_from(n).ok().and_then(earthdawn::step)
+                    }
+                    _ => None,
+                };
 
-            E::Dice {
-                quantity: q.map(Box::new),
-                power: p.map(Box::new),
-                augmentations,
+                match dice {
+                    Some(dice) => E::Subexpression(Box::new(self.fold(dice))),
+                    None => E::Call { function, args },
+                }
             }
+            expr => expr,
         }
-        _ => expr,
     }
 }
 
@@ -116,4 +160,32 @@ mod tests {
         assert_eq!(simplify(parse("4d0").unwrap()), E::Constant(Integer::zero()));
         assert_eq!(simplify(parse("8d1").unwrap()), E::Constant(Integer::from(8)));
     }
+
+    #[test]
+    fn test_inlining_macros() {
+        let mut options = RollerOptions::default();
+        options.macros_mut().load("attack := d20 + 7").unwrap();
+
+        let simplify = |s| advanced_simplify(parse(s).unwrap(), &options, Steps::INLINE_MACROS);
+
+        assert_eq!(simplify("@attack + 1"), parse("(d20 + 7) + 1").unwrap());
+        assert_eq!(simplify("@damage"), parse("@damage").unwrap());
+    }
+
+    #[test]
+    fn test_expanding_steps() {
+        let simplify = |s| {
+            advanced_simplify(
+                parse(s).unwrap(),
+                &RollerOptions::default(),
+                Steps::EXPAND_EARTHDAWN_STEPS,
+            )
+        };
+
+        assert_eq!(simplify("step(7) + 2"), parse("(d12!) + 2").unwrap());
+        assert_eq!(simplify("step(13)"), parse("(d12! + d10!)").unwrap());
+        assert_eq!(simplify("step(0)"), parse("step(0)").unwrap());
+        assert_eq!(simplify("step(d6)"), parse("step(d6)").unwrap());
+        assert_eq!(simplify("max(7)"), parse("max(7)").unwrap());
+    }
 }
@@ -0,0 +1,202 @@
+//! Rolling an expression over and over to see how its totals fall, for
+//! the charts and for the expressions `distribution` can't work out.
+//!
+//! ```
+//! use dicemind::{prelude::*, simulation::simulate_seeded};
+//!
+//! let expr = parse("4d6kh3").unwrap();
+//! let histogram = simulate_seeded(&expr, 1000, &RollerOptions::default(), 7).unwrap();
+//! assert_eq!(histogram.trials(), 1000);
+//! ```
+
+use std::borrow::Borrow;
+
+use rand::{rngs::StdRng, Rng};
+
+use crate::{
+    histogram::Histogram,
+    interpreter::{NaiveRoller, RollerError, RollerResult, StandardNaiveRoller},
+    options::RollerOptions,
+    syntax::Expression,
+};
+
+/// Roll the expression `trials` times with the options, with the dice
+/// packed, see `RollerOptions::with_packed_dice`. Fails with the first
+/// roll that fails, an expression that rolls at all rarely fails halfway.
+pub fn simulate(
+    expr: &Expression,
+    trials: u64,
+    options: &RollerOptions,
+) -> RollerResult<Histogram> {
+    simulate_with(
+        &mut StandardNaiveRoller::default().with_options(options.clone().with_packed_dice(true)),
+        expr,
+        trials,
+    )
+}
+
+/// The same as `simulate`, with the same histogram for the same seed.
+/// The batches rolled apart need seeds of their own.
+pub fn simulate_seeded(
+    expr: &Expression,
+    trials: u64,
+    options: &RollerOptions,
+    seed: u64,
+) -> RollerResult<Histogram> {
+    simulate_with(
+        &mut StandardNaiveRoller::new_seeded(seed)
+            .with_options(options.clone().with_packed_dice(true)),
+        expr,
+        trials,
+    )
+}
+
+/// Roll the expression `trials` times with the roller, its generator
+/// and options
+pub fn simulate_with<R: Rng>(
+    roller: &mut NaiveRoller<R>,
+    expr: impl Borrow<Expression>,
+    trials: u64,
+) -> RollerResult<Histogram> {
+    let expr = expr.borrow();
+
+    let mut histogram = Histogram::new();
+    for _ in 0..trials {
+        histogram.record(roller.roll_naive(expr)?.total()?);
+    }
+
+    Ok(histogram)
+}
+
+/// Rolls the expression one total at a time, for the progress bars, the
+/// live charts and stopping as soon as the answer is clear. It never ends
+/// by itself, only when a roll fails, `error` tells why.
+///
+/// ```
+/// use dicemind::{prelude::*, simulation::simulate_iter};
+///
+/// let expr = parse("d20").unwrap();
+/// let until_crit = simulate_iter(&expr).take_while(|&total| total != 20).count();
+/// ```
+#[derive(Debug)]
+pub struct Simulation<'a, R: Rng = StdRng> {
+    roller: NaiveRoller<R>,
+    expr: &'a Expression,
+    error: Option<RollerError>,
+}
+
+/// Roll the expression for as long as asked with the default options and
+/// the dice packed
+pub fn simulate_iter(expr: &Expression) -> Simulation<'_> {
+    let options = RollerOptions::default().with_packed_dice(true);
+    Simulation::new(StandardNaiveRoller::default().with_options(options), expr)
+}
+
+impl<'a, R: Rng> Simulation<'a, R> {
+    pub fn new(roller: NaiveRoller<R>, expr: &'a Expression) -> Self {
+        Self {
+            roller,
+            expr,
+            error: None,
+        }
+    }
+
+    /// Why the rolling stopped, if it did
+    pub fn error(&self) -> Option<&RollerError> {
+        self.error.as_ref()
+    }
+
+    pub fn into_roller(self) -> NaiveRoller<R> {
+        self.roller
+    }
+}
+
+impl<R: Rng> Iterator for Simulation<'_, R> {
+    type Item = i64;
+
+    fn next(&mut self) -> Option<i64> {
+        if self.error.is_some() {
+            return None;
+        }
+
+        match self
+            .roller
+            .roll_naive(self.expr)
+            .and_then(|value| value.total())
+        {
+            Ok(total) => Some(total),
+            Err(err) => {
+                self.error = Some(err);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        histogram::Histogram,
+        interpreter::{RollerError, StandardNaiveRoller},
+        options::RollerOptions,
+        parser::parse,
+        simulation::{simulate, simulate_iter, simulate_seeded, simulate_with, Simulation},
+    };
+
+    #[test]
+    fn test_simulate() {
+        let options = RollerOptions::default();
+        let expr = parse("2d6 + 3").unwrap();
+
+        let histogram = simulate(&expr, 2000, &options).unwrap();
+        assert_eq!(histogram.trials(), 2000);
+        assert!(histogram.min().unwrap() >= 5 && histogram.max().unwrap() <= 15);
+        // The mean of 2000 rolls is off by half a point far less than once
+        // in a million runs
+        assert!((histogram.mean().unwrap() - 10.).abs() < 0.5);
+
+        let seeded = simulate_seeded(&expr, 500, &options, 3).unwrap();
+        assert_eq!(seeded, simulate_seeded(&expr, 500, &options, 3).unwrap());
+        let packed = options.clone().with_packed_dice(true);
+        let mut roller = StandardNaiveRoller::new_seeded(3).with_options(packed.clone());
+        assert_eq!(seeded, simulate_with(&mut roller, &expr, 500).unwrap());
+
+        // Batches merge into the whole
+        let mut roller = StandardNaiveRoller::new_seeded(3).with_options(packed);
+        let mut batches = simulate_with(&mut roller, &expr, 200).unwrap();
+        batches.merge(&simulate_with(&mut roller, &expr, 300).unwrap());
+        assert_eq!(batches, seeded);
+
+        assert_eq!(
+            simulate(&parse("1d6dl2").unwrap(), 10, &options),
+            Err(RollerError::TruncationFailure {
+                rolled: 1,
+                removed: 2
+            })
+        );
+        assert!(simulate(&expr, 0, &options).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_simulate_iter() {
+        let expr = parse("3d6").unwrap();
+        assert!(simulate_iter(&expr)
+            .take(100)
+            .all(|total| (3..=18).contains(&total)));
+
+        let histogram = simulate_seeded(&expr, 50, &RollerOptions::default(), 8).unwrap();
+        let roller = StandardNaiveRoller::new_seeded(8)
+            .with_options(RollerOptions::default().with_packed_dice(true));
+        let streamed = Simulation::new(roller, &expr).take(50);
+        assert_eq!(streamed.collect::<Histogram>(), histogram);
+
+        let failing = parse("1d6dl2").unwrap();
+        let mut simulation = simulate_iter(&failing);
+        assert_eq!(simulation.next(), None);
+        assert!(matches!(
+            simulation.error(),
+            Some(RollerError::TruncationFailure { .. })
+        ));
+        assert_eq!(simulation.next(), None);
+    }
+}
@@ -0,0 +1,220 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::interpreter::{NaiveValue, RollerError, RollerResult};
+
+#[derive(Debug, Error)]
+pub enum SinkError {
+    #[error("Could not deliver the roll: {0}")]
+    Http(#[from] Box<ureq::Error>),
+    #[error("Could not encode the roll: {0}")]
+    Encoding(#[from] serde_json::Error),
+    #[error("Could not total the roll: {0}")]
+    Roll(#[from] RollerError),
+}
+
+pub type SinkResult<T> = Result<T, SinkError>;
+
+/// A roll that happened, as it is pushed to the sinks
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RollEvent {
+    pub actor: String,
+    pub expression: String,
+    pub value: NaiveValue,
+}
+
+impl RollEvent {
+    pub fn new(actor: impl Into<String>, expression: impl Into<String>, value: NaiveValue) -> Self {
+        Self {
+            actor: actor.into(),
+            expression: expression.into(),
+            value,
+        }
+    }
+
+    pub fn total(&self) -> RollerResult<i64> {
+        self.value.total()
+    }
+}
+
+/// Anywhere the rolls can be pushed to
+pub trait Sink {
+    fn notify(&self, event: &RollEvent) -> SinkResult<()>;
+}
+
+/// A JSON payload with `{{actor}}`, `{{expression}}`, `{{total}}`,
+/// `{{dice}}` and `{{value}}` placeholders. Everything but `{{value}}`
+/// is escaped to be put inside of a JSON string, `{{value}}` is the
+/// whole roll encoded as JSON.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct PayloadTemplate(pub String);
+
+impl PayloadTemplate {
+    pub const DISCORD: &'static str =
+        r#"{"content": "**{{actor}}** rolled `{{expression}}`: **{{total}}** {{dice}}"}"#;
+    pub const GENERIC: &'static str = r#"{"actor": "{{actor}}", "expression": "{{expression}}", "total": {{total}}, "value": {{value}}}"#;
+
+    pub fn new(template: impl Into<String>) -> Self {
+        Self(template.into())
+    }
+
+    pub fn render(&self, event: &RollEvent) -> SinkResult<String> {
+        let dice = event
+            .value
+            .dice()
+            .filter(|d| !d.is_discarded())
+            .map(|d| d.value.to_string())
+            .collect::<Vec<_>>();
+        let dice = if dice.is_empty() {
+            String::new()
+        } else {
+            format!("[{}]", dice.join(", "))
+        };
+
+        let value = serde_json::to_string(&event.value)?;
+
+        // A single pass, so the placeholders inside of the values stay as is
+        let mut out = String::with_capacity(self.0.len());
+        let mut rest = self.0.as_str();
+        while let Some(start) = rest.find("{{") {
+            out.push_str(&rest[..start]);
+            rest = &rest[start..];
+
+            let Some(end) = rest.find("}}") else {
+                break;
+            };
+
+            match &rest[2..end] {
+                "actor" => out.push_str(&escape(&event.actor)),
+                "expression" => out.push_str(&escape(&event.expression)),
+                "total" => out.push_str(&event.total()?.to_string()),
+                "dice" => out.push_str(&escape(&dice)),
+                "value" => out.push_str(&value),
+                _ => out.push_str(&rest[..end + 2]),
+            }
+            rest = &rest[end + 2..];
+        }
+        out.push_str(rest);
+
+        Ok(out)
+    }
+}
+
+// Escape the string to be put between the quotes of a JSON string
+fn escape(s: &str) -> String {
+    let quoted = serde_json::Value::String(s.to_string()).to_string();
+    quoted[1..quoted.len() - 1].to_string()
+}
+
+/// POSTs every roll to an HTTP endpoint
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct WebhookSink {
+    pub url: String,
+    pub template: PayloadTemplate,
+}
+
+impl WebhookSink {
+    /// A Discord webhook, posting a message into the channel
+    pub fn discord(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            template: PayloadTemplate::new(PayloadTemplate::DISCORD),
+        }
+    }
+
+    /// Any endpoint accepting JSON
+    pub fn generic(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            template: PayloadTemplate::new(PayloadTemplate::GENERIC),
+        }
+    }
+
+    /// Pick the payload based on the URL, Discord webhooks are recognized
+    pub fn from_url(url: impl Into<String>) -> Self {
+        let url = url.into();
+
+        if url.contains("discord.com/api/webhooks") || url.contains("discordapp.com/api/webhooks") {
+            Self::discord(url)
+        } else {
+            Self::generic(url)
+        }
+    }
+
+    pub fn with_template(mut self, template: PayloadTemplate) -> Self {
+        self.template = template;
+        self
+    }
+}
+
+impl Sink for WebhookSink {
+    fn notify(&self, event: &RollEvent) -> SinkResult<()> {
+        let payload = self.template.render(event)?;
+
+        ureq::post(&self.url)
+            .timeout(Duration::from_secs(10))
+            .set("Content-Type", "application/json")
+            .send_string(&payload)
+            .map_err(Box::new)?;
+
+        Ok(())
+    }
+}
+
+/// Pushes every roll to all of the sinks, failing on the first error
+#[derive(Default)]
+pub struct Sinks(pub Vec<Box<dyn Sink>>);
+
+impl Sinks {
+    pub fn push(&mut self, sink: impl Sink + 'static) {
+        self.0.push(Box::new(sink));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl Sink for Sinks {
+    fn notify(&self, event: &RollEvent) -> SinkResult<()> {
+        self.0.iter().try_for_each(|sink| sink.notify(event))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        interpreter::NaiveValue,
+        sinks::{PayloadTemplate, RollEvent, WebhookSink},
+    };
+
+    #[test]
+    fn test_templates() {
+        let event = RollEvent::new("\"alice\"", "d20 + 5", NaiveValue::Constant(17));
+
+        let discord = WebhookSink::from_url("https://discord.com/api/webhooks/1/abc");
+        let payload = discord.template.render(&event).unwrap();
+        let payload: serde_json::Value = serde_json::from_str(&payload).unwrap();
+        assert_eq!(
+            payload["content"],
+            "**\"alice\"** rolled `d20 + 5`: **17** "
+        );
+
+        let generic = WebhookSink::from_url("http://localhost:8080/rolls");
+        let payload = generic.template.render(&event).unwrap();
+        let payload: serde_json::Value = serde_json::from_str(&payload).unwrap();
+        assert_eq!(payload["total"], 17);
+        assert_eq!(payload["value"]["Constant"], 17);
+
+        let custom = PayloadTemplate::new("{{actor}}: {{total}} {{unknown}}");
+        assert_eq!(
+            custom.render(&event).unwrap(),
+            "\\\"alice\\\": 17 {{unknown}}"
+        );
+
+        let sneaky = RollEvent::new("{{total}}", "d20", NaiveValue::Constant(1));
+        assert_eq!(custom.render(&sneaky).unwrap(), "{{total}}: 1 {{unknown}}");
+    }
+}
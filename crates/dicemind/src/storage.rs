@@ -0,0 +1,266 @@
+use std::{
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use rusqlite::{params, params_from_iter, types::Type, Connection, ToSql};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::interpreter::{NaiveValue, RollerError, RollerResult};
+
+#[derive(Debug, Error)]
+pub enum StorageError {
+    #[error("Database error: {0}")]
+    Database(#[from] rusqlite::Error),
+    #[error("Could not encode the roll: {0}")]
+    Encoding(#[from] serde_json::Error),
+    #[error("Could not total the roll: {0}")]
+    Roll(#[from] RollerError),
+}
+
+pub type StorageResult<T> = Result<T, StorageError>;
+
+/// A single roll as it is kept in the history
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RollRecord {
+    // Who rolled the dice, a player name or a user id
+    pub actor: String,
+    // The expression as the actor typed it
+    pub expression: String,
+    // Free-form context, like a channel or a campaign
+    pub context: Option<String>,
+    // Seconds since the unix epoch
+    pub rolled_at: i64,
+    pub value: NaiveValue,
+}
+
+impl RollRecord {
+    /// Record a roll that happened just now
+    pub fn new(actor: impl Into<String>, expression: impl Into<String>, value: NaiveValue) -> Self {
+        let rolled_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or_default();
+
+        Self {
+            actor: actor.into(),
+            expression: expression.into(),
+            context: None,
+            rolled_at,
+            value,
+        }
+    }
+
+    pub fn with_context(mut self, context: impl Into<String>) -> Self {
+        self.context = Some(context.into());
+        self
+    }
+
+    pub fn total(&self) -> RollerResult<i64> {
+        self.value.total()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredRoll {
+    pub id: i64,
+    pub record: RollRecord,
+}
+
+/// Filters for looking up rolls, empty fields match everything
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct RollQuery {
+    pub actor: Option<String>,
+    pub expression: Option<String>,
+    pub context: Option<String>,
+    // Inclusive range of `rolled_at`
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+}
+
+impl RollQuery {
+    fn where_clause(&self) -> (String, Vec<&dyn ToSql>) {
+        let filters: [(&str, Option<&dyn ToSql>); 5] = [
+            ("actor =", self.actor.as_ref().map(|v| v as &dyn ToSql)),
+            ("expression =", self.expression.as_ref().map(|v| v as &dyn ToSql)),
+            ("context =", self.context.as_ref().map(|v| v as &dyn ToSql)),
+            ("rolled_at >=", self.since.as_ref().map(|v| v as &dyn ToSql)),
+            ("rolled_at <=", self.until.as_ref().map(|v| v as &dyn ToSql)),
+        ];
+
+        let mut conditions = vec![];
+        let mut params = vec![];
+        for (condition, param) in filters {
+            if let Some(param) = param {
+                params.push(param);
+                conditions.push(format!("{condition} ?{}", params.len()));
+            }
+        }
+
+        if conditions.is_empty() {
+            (String::new(), params)
+        } else {
+            (format!("WHERE {}", conditions.join(" AND ")), params)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct RollStats {
+    pub count: i64,
+    pub sum: i64,
+    pub mean: f64,
+    pub min: Option<i64>,
+    pub max: Option<i64>,
+}
+
+/// Roll history backed by sqlite
+pub struct RollStore {
+    conn: Connection,
+}
+
+impl RollStore {
+    pub fn open(path: impl AsRef<Path>) -> StorageResult<Self> {
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> StorageResult<Self> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    /// Use an existing connection, creating the tables if needed
+    pub fn from_connection(conn: Connection) -> StorageResult<Self> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS rolls (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                actor TEXT NOT NULL,
+                expression TEXT NOT NULL,
+                context TEXT,
+                rolled_at INTEGER NOT NULL,
+                total INTEGER NOT NULL,
+                value TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS rolls_actor ON rolls (actor, rolled_at);
+            CREATE INDEX IF NOT EXISTS rolls_expression ON rolls (expression);",
+        )?;
+
+        Ok(Self { conn })
+    }
+
+    pub fn insert(&self, record: &RollRecord) -> StorageResult<i64> {
+        self.conn.execute(
+            "INSERT INTO rolls (actor, expression, context, rolled_at, total, value)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                record.actor,
+                record.expression,
+                record.context,
+                record.rolled_at,
+                record.total()?,
+                serde_json::to_string(&record.value)?,
+            ],
+        )?;
+
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    /// All the rolls matching the query, oldest first
+    pub fn query(&self, query: &RollQuery) -> StorageResult<Vec<StoredRoll>> {
+        let (clause, params) = query.where_clause();
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT id, actor, expression, context, rolled_at, value FROM rolls {clause}
+             ORDER BY rolled_at, id"
+        ))?;
+
+        let rolls = stmt.query_map(params_from_iter(params), |row| {
+            let value: String = row.get(5)?;
+            let value = serde_json::from_str(&value).map_err(|err| {
+                rusqlite::Error::FromSqlConversionFailure(5, Type::Text, Box::new(err))
+            })?;
+
+            Ok(StoredRoll {
+                id: row.get(0)?,
+                record: RollRecord {
+                    actor: row.get(1)?,
+                    expression: row.get(2)?,
+                    context: row.get(3)?,
+                    rolled_at: row.get(4)?,
+                    value,
+                },
+            })
+        })?;
+
+        Ok(rolls.collect::<Result<_, _>>()?)
+    }
+
+    pub fn stats(&self, query: &RollQuery) -> StorageResult<RollStats> {
+        let (clause, params) = query.where_clause();
+
+        let stats = self.conn.query_row(
+            &format!(
+                "SELECT COUNT(*), COALESCE(SUM(total), 0), COALESCE(AVG(total), 0.0), MIN(total), MAX(total)
+                 FROM rolls {clause}"
+            ),
+            params_from_iter(params),
+            |row| {
+                Ok(RollStats {
+                    count: row.get(0)?,
+                    sum: row.get(1)?,
+                    mean: row.get(2)?,
+                    min: row.get(3)?,
+                    max: row.get(4)?,
+                })
+            },
+        )?;
+
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        interpreter::NaiveValue,
+        storage::{RollQuery, RollRecord, RollStore},
+    };
+
+    #[test]
+    fn test_query_and_stats() {
+        let store = RollStore::open_in_memory().unwrap();
+
+        let record = |actor: &str, total, rolled_at| RollRecord {
+            rolled_at,
+            ..RollRecord::new(actor, "d20", NaiveValue::Constant(total))
+        };
+
+        store.insert(&record("alice", 12, 100)).unwrap();
+        store.insert(&record("alice", 20, 200)).unwrap();
+        store.insert(&record("bob", 3, 300)).unwrap();
+
+        let alice = RollQuery {
+            actor: Some("alice".into()),
+            ..Default::default()
+        };
+        let rolls = store.query(&alice).unwrap();
+        assert_eq!(rolls.len(), 2);
+        assert_eq!(rolls[1].record.total().unwrap(), 20);
+
+        let stats = store.stats(&alice).unwrap();
+        assert_eq!(stats.count, 2);
+        assert_eq!(stats.sum, 32);
+        assert_eq!(stats.max, Some(20));
+
+        let late = RollQuery {
+            since: Some(150),
+            ..Default::default()
+        };
+        assert_eq!(store.stats(&late).unwrap().min, Some(3));
+
+        let nobody = RollQuery {
+            actor: Some("carol".into()),
+            ..Default::default()
+        };
+        assert_eq!(store.stats(&nobody).unwrap().max, None);
+    }
+}
@@ -0,0 +1,212 @@
+//! Dice showing symbols instead of numbers, like the narrative dice of
+//! Genesys. Every face is a handful of symbols, the faces rolled are added
+//! up and the opposing symbols cancel out.
+
+use std::{
+    fmt::Display,
+    iter::Sum,
+    ops::{Add, AddAssign},
+};
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Symbol {
+    Success,
+    Failure,
+    Advantage,
+    Threat,
+    /// Also a success, which can be cancelled while the triumph stays
+    Triumph,
+    /// Also a failure, which can be cancelled while the despair stays
+    Despair,
+}
+
+/// How many of every symbol there are, a face of a dice or a whole pool
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Symbols {
+    pub successes: u32,
+    pub failures: u32,
+    pub advantages: u32,
+    pub threats: u32,
+    pub triumphs: u32,
+    pub despairs: u32,
+}
+
+impl Symbols {
+    /// A blank face
+    pub fn blank() -> Self {
+        Self::default()
+    }
+
+    pub fn of(symbols: &[Symbol]) -> Self {
+        symbols.iter().fold(Self::blank(), |mut acc, symbol| {
+            *acc.count_mut(*symbol) += 1;
+            acc
+        })
+    }
+
+    pub fn count(&self, symbol: Symbol) -> u32 {
+        match symbol {
+            Symbol::Success => self.successes,
+            Symbol::Failure => self.failures,
+            Symbol::Advantage => self.advantages,
+            Symbol::Threat => self.threats,
+            Symbol::Triumph => self.triumphs,
+            Symbol::Despair => self.despairs,
+        }
+    }
+
+    fn count_mut(&mut self, symbol: Symbol) -> &mut u32 {
+        match symbol {
+            Symbol::Success => &mut self.successes,
+            Symbol::Failure => &mut self.failures,
+            Symbol::Advantage => &mut self.advantages,
+            Symbol::Threat => &mut self.threats,
+            Symbol::Triumph => &mut self.triumphs,
+            Symbol::Despair => &mut self.despairs,
+        }
+    }
+
+    pub fn is_blank(&self) -> bool {
+        *self == Self::blank()
+    }
+
+    /// Every failure cancels a success and every threat an advantage. The
+    /// successes of the triumphs and the failures of the despairs are
+    /// counted among the successes and the failures, the triumphs and the
+    /// despairs themselves stay. The symbols of a pool as it was rolled,
+    /// cancelling them twice counts the triumphs twice.
+    pub fn cancel(&self) -> Self {
+        let successes = self.successes + self.triumphs;
+        let failures = self.failures + self.despairs;
+
+        Self {
+            successes: successes.saturating_sub(failures),
+            failures: failures.saturating_sub(successes),
+            advantages: self.advantages.saturating_sub(self.threats),
+            threats: self.threats.saturating_sub(self.advantages),
+            triumphs: self.triumphs,
+            despairs: self.despairs,
+        }
+    }
+
+    /// More successes than failures once they cancel out
+    pub fn is_success(&self) -> bool {
+        self.successes + self.triumphs > self.failures + self.despairs
+    }
+
+    /// The successes left after the cancelling, negative for the failures
+    pub fn net(&self) -> i64 {
+        (self.successes + self.triumphs) as i64 - (self.failures + self.despairs) as i64
+    }
+}
+
+impl Add for Symbols {
+    type Output = Symbols;
+
+    fn add(mut self, rhs: Self) -> Self::Output {
+        self += rhs;
+        self
+    }
+}
+
+impl AddAssign for Symbols {
+    fn add_assign(&mut self, rhs: Self) {
+        self.successes += rhs.successes;
+        self.failures += rhs.failures;
+        self.advantages += rhs.advantages;
+        self.threats += rhs.threats;
+        self.triumphs += rhs.triumphs;
+        self.despairs += rhs.despairs;
+    }
+}
+
+impl Sum for Symbols {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::blank(), Add::add)
+    }
+}
+
+/// `2 successes, 1 advantage, 1 triumph`, the symbols that are not there
+/// are left out and no symbols at all are `blank`
+impl Display for Symbols {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names = [
+            (self.successes, "success", "successes"),
+            (self.failures, "failure", "failures"),
+            (self.advantages, "advantage", "advantages"),
+            (self.threats, "threat", "threats"),
+            (self.triumphs, "triumph", "triumphs"),
+            (self.despairs, "despair", "despairs"),
+        ];
+
+        let counts = names
+            .into_iter()
+            .filter(|(n, ..)| *n > 0)
+            .map(|(n, one, many)| format!("{n} {}", if n == 1 { one } else { many }))
+            .collect::<Vec<_>>();
+
+        if counts.is_empty() {
+            f.write_str("blank")
+        } else {
+            f.write_str(&counts.join(", "))
+        }
+    }
+}
+
+/// A dice with a handful of symbols on every face, the faces are equally
+/// likely
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SymbolDie {
+    pub faces: Vec<Symbols>,
+}
+
+impl SymbolDie {
+    pub fn new(faces: impl IntoIterator<Item = Symbols>) -> Self {
+        Self {
+            faces: faces.into_iter().collect(),
+        }
+    }
+
+    /// A dice without faces comes up blank
+    pub fn roll(&self, rng: &mut impl Rng) -> Symbols {
+        if self.faces.is_empty() {
+            return Symbols::blank();
+        }
+
+        self.faces[rng.gen_range(0..self.faces.len())]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::symbols::{Symbol::*, SymbolDie, Symbols};
+
+    #[test]
+    fn test_cancel() {
+        let pool = Symbols::of(&[
+            Success, Success, Triumph, Failure, Advantage, Threat, Threat,
+        ]);
+        let net = pool.cancel();
+        assert_eq!(net, Symbols::of(&[Success, Success, Threat, Triumph]));
+        assert_eq!(net.to_string(), "2 successes, 1 threat, 1 triumph");
+        assert!(pool.is_success());
+        assert_eq!(pool.net(), 2);
+
+        // The despair cancels the success of the triumph
+        let pool = Symbols::of(&[Triumph, Despair]);
+        assert_eq!(pool.cancel(), pool);
+        assert!(!pool.is_success());
+        assert_eq!(Symbols::blank().to_string(), "blank");
+
+        let die = SymbolDie::new([Symbols::of(&[Success]), Symbols::of(&[Advantage])]);
+        let mut rng = StdRng::seed_from_u64(0);
+        let rolled: Symbols = (0..100).map(|_| die.roll(&mut rng)).sum();
+        assert_eq!(rolled.successes + rolled.advantages, 100);
+        assert!(rolled.successes > 0 && rolled.advantages > 0);
+    }
+}
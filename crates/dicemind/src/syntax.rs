@@ -1,12 +1,19 @@
 use std::{
     cmp::Ordering,
+    collections::BTreeMap,
     fmt::{Display, Write},
 };
 
+use num::rational::Ratio;
 use serde::{Deserialize, Serialize};
 use smallvec::SmallVec;
 use smol_str::SmolStr;
 
+use crate::{
+    dialect::{Associativity, Dialect, Native},
+    parser::{binding_power, ANNOTATION_POWER, CONDITIONAL_POWER, PREFIX_POWER},
+};
+
 pub type Integer = num::bigint::BigInt;
 pub type PositiveInteger = num::bigint::BigUint;
 
@@ -50,13 +57,21 @@ impl PartialOrd for BinaryOperator {
 }
 
 pub type AnnotationString = SmolStr;
+pub type FunctionName = SmolStr;
+pub type MacroName = SmolStr;
+pub type AugmentationToken = SmolStr;
+// Keys and values attached by the integrations, like a token id or a color
+pub type Metadata = BTreeMap<SmolStr, SmolStr>;
+// Most dice have one augmentation at most, keeping it inline saves an
+// allocation on every clone of the tree, see `benches/ast.rs`
+pub type Augmentations = SmallVec<[Augmentation; 1]>;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub enum Expression {
     Dice {
         quantity: Option<Box<Expression>>,
         power: Option<Box<Expression>>,
-        augmentations: SmallVec<[Augmentation; 1]>,
+        augmentations: Augmentations,
     },
     Binop {
         operator: BinaryOperator,
@@ -70,6 +85,104 @@ pub enum Expression {
     },
     Subexpression(Box<Expression>),
     UnaryNegation(Box<Expression>),
+    // 6x(4d6kh3)
+    Repeat {
+        count: Box<Expression>,
+        expression: Box<Expression>,
+    },
+    // max(2d6, d12)
+    Call {
+        function: FunctionName,
+        args: Vec<Expression>,
+    },
+    // {2d6, d8 + 3}kh
+    Group {
+        members: Vec<Expression>,
+        augmentations: Augmentations,
+    },
+    // @attack
+    Reference(MacroName),
+    // d20 > 15 ? 2d6 : 0, only the taken branch is rolled
+    Conditional {
+        condition: Box<Expression>,
+        then: Box<Expression>,
+        otherwise: Box<Expression>,
+    },
+    // Not a part of the syntax, it is written out and rolled as the
+    // expression it wraps
+    Tagged {
+        expression: Box<Expression>,
+        metadata: Metadata,
+    },
+    // 3d{1:3, 2:1}, every face comes up as often as its weight says
+    Weighted {
+        quantity: Option<Box<Expression>>,
+        // Behind a thin pointer, a slice would grow the enum
+        #[allow(clippy::box_collection)]
+        faces: Box<Vec<Face>>,
+        augmentations: Augmentations,
+    },
+    // 1.5, rounded where it meets the integers
+    Decimal(Decimal),
+}
+
+/// A constant with a fractional part, `1.25` are the digits 125 with a
+/// scale of 2. The zeroes written at the end are kept, `1.50` is not
+/// `1.5` but it rolls the same.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct Decimal {
+    pub digits: PositiveInteger,
+    pub scale: u32,
+}
+
+impl Decimal {
+    pub fn new(digits: impl Into<PositiveInteger>, scale: u32) -> Self {
+        Self {
+            digits: digits.into(),
+            scale,
+        }
+    }
+
+    /// The exact value, `None` if it doesn't fit into the machine integers
+    pub fn to_ratio(&self) -> Option<Ratio<i64>> {
+        let digits = i64::try_from(&self.digits).ok()?;
+        let denominator = 10i64.checked_pow(self.scale)?;
+        Some(Ratio::new(digits, denominator))
+    }
+}
+
+impl Display for Decimal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let digits = self.digits.to_string();
+        let scale = self.scale as usize;
+        // The zeroes in front of the fraction are not a part of the digits
+        let digits = format!("{digits:0>width$}", width = scale + 1);
+        let (whole, fraction) = digits.split_at(digits.len() - scale);
+        write!(f, "{whole}.{fraction}")
+    }
+}
+
+/// A face of a weighted dice, `6:2` comes up twice as often as a face
+/// weighing 1. The faces weighing 0 never come up.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct Face {
+    pub value: Integer,
+    pub weight: PositiveInteger,
+}
+
+impl Face {
+    pub fn new(value: impl Into<Integer>, weight: impl Into<PositiveInteger>) -> Self {
+        Self {
+            value: value.into(),
+            weight: weight.into(),
+        }
+    }
+}
+
+impl Display for Face {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.value, self.weight)
+    }
 }
 
 impl Expression {
@@ -83,6 +196,47 @@ impl Expression {
             Subexpression(_) => true,
             UnaryNegation(_) => false,
             Annotated { .. } => false,
+            Group { .. } => true,
+            Repeat { .. } => true,
+            Call { .. } => true,
+            Reference(_) => true,
+            Conditional { .. } => false,
+            Tagged { expression, .. } => expression.is_trivial(),
+            Weighted { .. } => true,
+            Expression::Decimal(_) => true,
+        }
+    }
+
+    /// The metadata attached right to this node
+    pub fn metadata(&self) -> Option<&Metadata> {
+        match self {
+            Expression::Tagged { metadata, .. } => Some(metadata),
+            _ => None,
+        }
+    }
+
+    /// The nodes right below this one, in the order they are written
+    pub fn children(&self) -> Vec<&Expression> {
+        use Expression::*;
+
+        match self {
+            Dice {
+                quantity, power, ..
+            } => quantity.iter().chain(power).map(|e| &**e).collect(),
+            Weighted { quantity, .. } => quantity.iter().map(|e| &**e).collect(),
+            Binop { lhs, rhs, .. } => vec![&**lhs, &**rhs],
+            Annotated { expression, .. }
+            | Tagged { expression, .. }
+            | Subexpression(expression)
+            | UnaryNegation(expression) => vec![&**expression],
+            Repeat { count, expression } => vec![&**count, &**expression],
+            Call { args: members, .. } | Group { members, .. } => members.iter().collect(),
+            Conditional {
+                condition,
+                then,
+                otherwise,
+            } => vec![&**condition, &**then, &**otherwise],
+            Constant(_) | Reference(_) | Expression::Decimal(_) => vec![],
         }
     }
 }
@@ -111,18 +265,21 @@ enum SerdeOrdering {
 pub struct Selector {
     #[serde(with = "SerdeOrdering")]
     pub relation: Ordering,
+    // Also match when the value is equal, `>=` and `<=`
+    #[serde(default)]
+    pub inclusive: bool,
     pub n: PositiveInteger,
 }
 
 impl Selector {
     pub fn matches(&self, m: i64) -> bool {
-        let n = match i64::try_from(&self.n) {
-            Ok(n) => n,
-            // value too big or too small, can be handled without an error
-            Err(_) => todo!()
+        let ordering = match i64::try_from(&self.n) {
+            Ok(n) => m.cmp(&n),
+            // Past every value a die can roll
+            Err(_) => Ordering::Less,
         };
 
-        m.cmp(&n) == self.relation
+        ordering == self.relation || (self.inclusive && ordering == Ordering::Equal)
     }
 }
 
@@ -150,10 +307,284 @@ pub enum Augmentation {
         // On what values to explode
         selector: Option<Selector>,
     },
+    // !!, the explosions are added to the dice that exploded
+    Compound {
+        selector: Option<Selector>,
+    },
+    // r<2 ro1, roll the matching dice again
+    Reroll {
+        selector: Selector,
+        // Only once instead of until the dice stop matching
+        once: bool,
+    },
+    // cs>19 cf<3, what counts as a critical
+    CriticalSuccess {
+        selector: Selector,
+    },
+    CriticalFailure {
+        selector: Selector,
+    },
+    // min10 max5, the dice are raised or lowered to the bound
+    Minimum {
+        n: PositiveInteger,
+    },
+    Maximum {
+        n: PositiveInteger,
+    },
+    // s>=5
+    CountSuccesses {
+        selector: Selector,
+    },
+    // f<=1
+    CountFailures {
+        selector: Selector,
+    },
+    // Registered by the users of the library, like `t2`
+    Custom {
+        token: AugmentationToken,
+        n: Option<PositiveInteger>,
+    },
 }
 
-impl Display for Expression {
+impl Augmentation {
+    /// Whether the augmentation turns the dice into a pool of hits
+    pub fn is_counting(&self) -> bool {
+        matches!(
+            self,
+            Augmentation::CountSuccesses { .. } | Augmentation::CountFailures { .. }
+        )
+    }
+}
+
+impl Display for Selector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.relation {
+            Ordering::Less => f.write_char('<')?,
+            Ordering::Equal => f.write_char('=')?,
+            Ordering::Greater => f.write_char('>')?,
+        }
+
+        if self.inclusive && self.relation != Ordering::Equal {
+            f.write_char('=')?;
+        }
+
+        write!(f, "{}", self.n)
+    }
+}
+
+/// The native spelling, the augments the native notation does not have
+/// are written as in Roll20 and Foundry
+impl Display for Augmentation {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use Augmentation::*;
+
+        let op = |op: &SelectorOp| match op {
+            SelectorOp::Keep => 'k',
+            SelectorOp::Drop => 'd',
+        };
+
+        match self {
+            Truncate { op: o, affix, n } => {
+                f.write_char(op(o))?;
+                f.write_char(match affix {
+                    Affix::High => 'h',
+                    Affix::Low => 'l',
+                })?;
+                n.iter().try_for_each(|n| write!(f, "{n}"))
+            }
+            Filter { op: o, selector } => write!(f, "{}{selector}", op(o)),
+            Emphasis { n } => {
+                f.write_char('e')?;
+                n.iter().try_for_each(|n| write!(f, "{n}"))
+            }
+            Explode { selector } => {
+                f.write_char('!')?;
+                selector.iter().try_for_each(|s| write!(f, "{s}"))
+            }
+            Compound { selector } => {
+                f.write_str("!!")?;
+                selector.iter().try_for_each(|s| write!(f, "{s}"))
+            }
+            Reroll {
+                selector,
+                once: true,
+            } => write!(f, "ro{selector}"),
+            Reroll {
+                selector,
+                once: false,
+            } => write!(f, "r{selector}"),
+            CriticalSuccess { selector } => write!(f, "cs{selector}"),
+            CriticalFailure { selector } => write!(f, "cf{selector}"),
+            Minimum { n } => write!(f, "min{n}"),
+            Maximum { n } => write!(f, "max{n}"),
+            CountSuccesses { selector } => write!(f, "s{selector}"),
+            CountFailures { selector } => write!(f, "f{selector}"),
+            Custom { token, n } => {
+                f.write_str(token)?;
+                n.iter().try_for_each(|n| write!(f, "{n}"))
+            }
+        }
+    }
+}
+
+// Where a child goes, decides whether it needs the parenthesis
+enum Position {
+    // The quantity of a dice, the count of a repetition
+    Term,
+    // The power of a dice, whether any augments and a letter come after it
+    Power { augmented: bool, word: bool },
+    // The operand of a prefix operator
+    Prefix,
+    Annotated,
+    // How tightly the parent holds on to the child
+    Binding(u8),
+}
+
+impl Expression {
+    /// Strip the parenthesis and spell the negative constants as negations.
+    /// The precedence is in the shape of the tree, so this does not change
+    /// what the expression rolls. For any expression,
+    /// `parse(&expr.to_string())` is the same as `expr` after normalizing
+    /// both of them.
+    pub fn normalize(self) -> Expression {
+        use Expression::*;
+
+        let normalize = |expr: Box<Expression>| Box::new(expr.normalize());
+        let normalize_all =
+            |exprs: Vec<Expression>| exprs.into_iter().map(Expression::normalize).collect();
+
+        match self {
+            Dice {
+                quantity,
+                power,
+                augmentations,
+            } => Dice {
+                quantity: quantity.map(normalize),
+                power: power.map(normalize),
+                augmentations,
+            },
+            Binop { operator, lhs, rhs } => Binop {
+                operator,
+                lhs: normalize(lhs),
+                rhs: normalize(rhs),
+            },
+            Constant(c) if c < Integer::from(0) => UnaryNegation(Box::new(Constant(-c))),
+            Constant(c) => Constant(c),
+            Annotated {
+                expression,
+                annotation,
+            } => Annotated {
+                expression: normalize(expression),
+                annotation,
+            },
+            Subexpression(box expr) => expr.normalize(),
+            UnaryNegation(expr) => UnaryNegation(normalize(expr)),
+            Repeat { count, expression } => Repeat {
+                count: normalize(count),
+                expression: normalize(expression),
+            },
+            Call { function, args } => Call {
+                function,
+                args: normalize_all(args),
+            },
+            Group {
+                members,
+                augmentations,
+            } => Group {
+                members: normalize_all(members),
+                augmentations,
+            },
+            Reference(name) => Reference(name),
+            Expression::Decimal(d) => Expression::Decimal(d),
+            Conditional {
+                condition,
+                then,
+                otherwise,
+            } => Conditional {
+                condition: normalize(condition),
+                then: normalize(then),
+                otherwise: normalize(otherwise),
+            },
+            Tagged {
+                expression,
+                metadata,
+            } => Tagged {
+                expression: normalize(expression),
+                metadata,
+            },
+            Weighted {
+                quantity,
+                faces,
+                augmentations,
+            } => Weighted {
+                quantity: quantity.map(normalize),
+                faces,
+                augmentations,
+            },
+        }
+    }
+
+    // How tightly the expression holds together when written out
+    fn binding(&self) -> u8 {
+        use Expression::*;
+
+        match self {
+            Binop { operator, .. } => binding_power(*operator, Associativity::Left).0,
+            Conditional { .. } => CONDITIONAL_POWER,
+            Annotated { .. } => ANNOTATION_POWER,
+            Tagged { expression, .. } => expression.binding(),
+            _ => PREFIX_POWER,
+        }
+    }
+
+    fn needs_parenthesis(&self, position: Position) -> bool {
+        use Expression::*;
+
+        if let Tagged { expression, .. } = self {
+            return expression.needs_parenthesis(position);
+        }
+
+        match position {
+            // The references would take the rest of the term into their name
+            Position::Term => {
+                !matches!(self, Subexpression(_) | Group { .. } | Call { .. })
+                    && !matches!(self, Constant(c) if *c >= Integer::from(0))
+                    && !matches!(self, Expression::Decimal(_))
+            }
+            // The augments would go to the group or into the name instead
+            Position::Power {
+                augmented: true, ..
+            } if matches!(self, Group { .. }) => true,
+            Position::Power { word, .. } if matches!(self, Reference(_)) => word,
+            Position::Power { .. } => self.needs_parenthesis(Position::Term),
+            Position::Prefix => self.binding() < PREFIX_POWER,
+            Position::Annotated => self.binding() < ANNOTATION_POWER,
+            Position::Binding(power) => self.binding() < power,
+        }
+    }
+
+    fn fmt_child(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        position: Position,
+        dialect: &dyn Dialect,
+    ) -> std::fmt::Result {
+        if self.needs_parenthesis(position) {
+            f.write_char('(')?;
+            self.fmt_in(f, dialect)?;
+            f.write_char(')')
+        } else {
+            self.fmt_in(f, dialect)
+        }
+    }
+
+    /// The expression with the augments spelled the way the dialect spells
+    /// them, `parse_dialect` reads it back as the same expression
+    pub fn display_in<'a>(&'a self, dialect: &'a dyn Dialect) -> impl Display + 'a {
+        InDialect(self, dialect)
+    }
+
+    fn fmt_in(&self, f: &mut std::fmt::Formatter<'_>, dialect: &dyn Dialect) -> std::fmt::Result {
         use BinaryOperator::*;
         use Expression::*;
 
@@ -163,63 +594,552 @@ impl Display for Expression {
                 power,
                 augmentations,
             } => {
-                if let Some(n) = quantity {
-                    if n.is_trivial() {
-                        f.write_fmt(format_args!("{}", n))?;
-                    } else {
-                        f.write_char('(')?;
-                        f.write_fmt(format_args!("{}", n))?;
-                        f.write_char(')')?;
+                // `d{...}a` is the only way to augment a group as a power
+                if let (
+                    Some(box Constant(n)),
+                    Some(group @ box Group { .. }),
+                    [Augmentation::Truncate {
+                        op: SelectorOp::Keep,
+                        affix,
+                        n: Some(one),
+                    }],
+                ) = (quantity, power, augmentations.as_slice())
+                {
+                    if *n == Integer::from(2) && *one == PositiveInteger::from(1u32) {
+                        let suffix = match affix {
+                            Affix::High => 'a',
+                            Affix::Low => 'd',
+                        };
+                        f.write_char('d')?;
+                        group.fmt_in(f, dialect)?;
+                        return f.write_char(suffix);
                     }
                 }
-                f.write_char('d')?;
 
-                if let Some(p) = power {
-                    f.write_fmt(format_args!("{}", p))?;
+                if let Some(n) = quantity {
+                    n.fmt_child(f, Position::Term, dialect)?;
                 }
+                f.write_char('d')?;
 
-                if augmentations.len() != 0 {
-                    todo!()
+                if let Some(p) = power {
+                    let word = augmentations.first().is_some_and(|a| {
+                        Spelled(a, dialect)
+                            .to_string()
+                            .starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_')
+                    });
+                    let position = Position::Power {
+                        augmented: !augmentations.is_empty(),
+                        word,
+                    };
+                    p.fmt_child(f, position, dialect)?;
                 }
 
-                Ok(())
+                augmentations
+                    .iter()
+                    .try_for_each(|a| dialect.write_augment(a, f))
             }
             Binop { operator, lhs, rhs } => {
-                match lhs.as_ref() {
-                    Subexpression(box Binop {
-                        operator: child_operator,
-                        ..
-                    }) if child_operator < operator => f.write_fmt(format_args!("({lhs}) ")),
-                    _ if !lhs.is_trivial() => f.write_fmt(format_args!("({lhs}) ")),
-                    _ => f.write_fmt(format_args!("{lhs} ")),
-                }?;
-
-                match operator {
-                    Equals => f.write_char('='),
-                    LessThan => f.write_char('<'),
-                    GreaterThan => f.write_char('>'),
-                    Add => f.write_char('+'),
-                    Subtract => f.write_char('-'),
-                    Multiply => f.write_char('*'),
-                    Chain => f.write_char(','),
-                }?;
-
-                match rhs.as_ref() {
-                    Subexpression(box Binop {
-                        operator: child_operator,
-                        ..
-                    }) if child_operator < operator => f.write_fmt(format_args!(" ({rhs})")),
-                    _ if !rhs.is_trivial() => f.write_fmt(format_args!(" ({rhs})")),
-                    _ => f.write_fmt(format_args!(" {rhs}")),
-                }
+                let (left, right) = binding_power(*operator, Associativity::Left);
+
+                lhs.fmt_child(f, Position::Binding(left), dialect)?;
+                f.write_str(match operator {
+                    Equals => " = ",
+                    LessThan => " < ",
+                    GreaterThan => " > ",
+                    Add => " + ",
+                    Subtract => " - ",
+                    Multiply => " * ",
+                    Chain => ", ",
+                })?;
+                rhs.fmt_child(f, Position::Binding(right), dialect)
             }
-            Constant(c) => f.write_fmt(format_args!("{c}")),
+            Constant(c) => write!(f, "{c}"),
+            Expression::Decimal(d) => write!(f, "{d}"),
             Annotated {
                 expression,
                 annotation,
-            } => f.write_fmt(format_args!("{expression} [{annotation}]")),
-            Subexpression(expr) => f.write_fmt(format_args!("{expr}")),
-            UnaryNegation(expr) => f.write_fmt(format_args!("-{expr}")),
+            } => {
+                expression.fmt_child(f, Position::Annotated, dialect)?;
+                write!(f, " [{annotation}]")
+            }
+            Subexpression(expr) => {
+                f.write_char('(')?;
+                expr.fmt_in(f, dialect)?;
+                f.write_char(')')
+            }
+            Tagged { expression, .. } => expression.fmt_in(f, dialect),
+            UnaryNegation(expr) => {
+                f.write_char('-')?;
+                expr.fmt_child(f, Position::Prefix, dialect)
+            }
+            Repeat { count, expression } => {
+                count.fmt_child(f, Position::Term, dialect)?;
+                f.write_str("x(")?;
+                expression.fmt_in(f, dialect)?;
+                f.write_char(')')
+            }
+            Call { function, args } => {
+                write!(f, "{function}(")?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i != 0 {
+                        f.write_str(", ")?;
+                    }
+                    // The commas would split the argument in two
+                    arg.fmt_child(f, Position::Binding(CONDITIONAL_POWER), dialect)?;
+                }
+                f.write_char(')')
+            }
+            Group {
+                members,
+                augmentations,
+            } => {
+                f.write_char('{')?;
+                for (i, member) in members.iter().enumerate() {
+                    if i != 0 {
+                        f.write_str(", ")?;
+                    }
+                    member.fmt_child(f, Position::Binding(CONDITIONAL_POWER), dialect)?;
+                }
+                f.write_char('}')?;
+
+                augmentations
+                    .iter()
+                    .try_for_each(|a| dialect.write_augment(a, f))
+            }
+            Reference(name) => write!(f, "@{name}"),
+            Conditional {
+                condition,
+                then,
+                otherwise,
+            } => {
+                // The conditionals nest to the right
+                condition.fmt_child(f, Position::Binding(CONDITIONAL_POWER + 1), dialect)?;
+                f.write_str(" ? ")?;
+                then.fmt_child(f, Position::Binding(CONDITIONAL_POWER), dialect)?;
+                f.write_str(" : ")?;
+                otherwise.fmt_child(f, Position::Binding(CONDITIONAL_POWER), dialect)
+            }
+            Weighted {
+                quantity,
+                faces,
+                augmentations,
+            } => {
+                if let Some(n) = quantity {
+                    n.fmt_child(f, Position::Term, dialect)?;
+                }
+                f.write_str("d{")?;
+                for (i, face) in faces.iter().enumerate() {
+                    if i != 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{face}")?;
+                }
+                f.write_char('}')?;
+
+                augmentations
+                    .iter()
+                    .try_for_each(|a| dialect.write_augment(a, f))
+            }
+        }
+    }
+}
+
+impl Display for Expression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.fmt_in(f, &Native)
+    }
+}
+
+// See `Expression::display_in`
+struct InDialect<'a>(&'a Expression, &'a dyn Dialect);
+
+impl Display for InDialect<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt_in(f, self.1)
+    }
+}
+
+// An augment spelled in a dialect
+struct Spelled<'a>(&'a Augmentation, &'a dyn Dialect);
+
+impl Display for Spelled<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.1.write_augment(self.0, f)
+    }
+}
+
+/// Builds the expressions without going through a string
+///
+/// ```
+/// # use dicemind::syntax::Expr;
+/// let expr = Expr::dice(3, 6).keep_high(2).plus(Expr::constant(4)).build();
+/// assert_eq!(expr, dicemind::parser::parse("3d6kh2 + 4").unwrap());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expr(Expression);
+
+impl Expr {
+    pub fn constant(n: impl Into<Integer>) -> Self {
+        Self(Expression::Constant(n.into()))
+    }
+
+    /// `digits` with the point moved `scale` places to the left, `1.5` is
+    /// `Expr::decimal(15u32, 1)`
+    pub fn decimal(digits: impl Into<PositiveInteger>, scale: u32) -> Self {
+        Self(Expression::Decimal(Decimal::new(digits, scale)))
+    }
+
+    /// `quantity`d`power`
+    pub fn dice(quantity: impl Into<Integer>, power: impl Into<Integer>) -> Self {
+        Self::dice_of(Self::constant(quantity), Self::constant(power))
+    }
+
+    /// A single dice, `d`power
+    pub fn die(power: impl Into<Integer>) -> Self {
+        Self(Expression::Dice {
+            quantity: None,
+            power: Some(Box::new(Expression::Constant(power.into()))),
+            augmentations: SmallVec::new(),
+        })
+    }
+
+    /// `quantity`d{`value`:`weight`, ...}
+    pub fn weighted(quantity: impl Into<Integer>, faces: impl IntoIterator<Item = Face>) -> Self {
+        Self(Expression::Weighted {
+            quantity: Some(Box::new(Expression::Constant(quantity.into()))),
+            faces: Box::new(faces.into_iter().collect()),
+            augmentations: SmallVec::new(),
+        })
+    }
+
+    /// The dice with the quantity and the power rolled from expressions
+    pub fn dice_of(quantity: Expr, power: Expr) -> Self {
+        Self(Expression::Dice {
+            quantity: Some(Box::new(quantity.0)),
+            power: Some(Box::new(power.0)),
+            augmentations: SmallVec::new(),
+        })
+    }
+
+    pub fn call(function: impl Into<FunctionName>, args: impl IntoIterator<Item = Expr>) -> Self {
+        Self(Expression::Call {
+            function: function.into(),
+            args: args.into_iter().map(Expr::build).collect(),
+        })
+    }
+
+    pub fn group(members: impl IntoIterator<Item = Expr>) -> Self {
+        Self(Expression::Group {
+            members: members.into_iter().map(Expr::build).collect(),
+            augmentations: SmallVec::new(),
+        })
+    }
+
+    /// `@name`
+    pub fn reference(name: impl Into<MacroName>) -> Self {
+        Self(Expression::Reference(name.into()))
+    }
+
+    pub fn plus(self, rhs: Expr) -> Self {
+        self.binop(BinaryOperator::Add, rhs)
+    }
+
+    pub fn minus(self, rhs: Expr) -> Self {
+        self.binop(BinaryOperator::Subtract, rhs)
+    }
+
+    pub fn times(self, rhs: Expr) -> Self {
+        self.binop(BinaryOperator::Multiply, rhs)
+    }
+
+    pub fn equals(self, rhs: Expr) -> Self {
+        self.binop(BinaryOperator::Equals, rhs)
+    }
+
+    pub fn less_than(self, rhs: Expr) -> Self {
+        self.binop(BinaryOperator::LessThan, rhs)
+    }
+
+    pub fn greater_than(self, rhs: Expr) -> Self {
+        self.binop(BinaryOperator::GreaterThan, rhs)
+    }
+
+    pub fn binop(self, operator: BinaryOperator, rhs: Expr) -> Self {
+        Self(Expression::Binop {
+            operator,
+            lhs: Box::new(self.0),
+            rhs: Box::new(rhs.0),
+        })
+    }
+
+    pub fn negate(self) -> Self {
+        Self(Expression::UnaryNegation(Box::new(self.0)))
+    }
+
+    pub fn annotate(self, annotation: impl Into<AnnotationString>) -> Self {
+        Self(Expression::Annotated {
+            expression: Box::new(self.0),
+            annotation: annotation.into(),
+        })
+    }
+
+    /// Attach the metadata to the expression, the keys that are already
+    /// there are overwritten
+    pub fn tag(self, key: impl Into<SmolStr>, value: impl Into<SmolStr>) -> Self {
+        let (expression, mut metadata) = match self.0 {
+            Expression::Tagged {
+                expression,
+                metadata,
+            } => (expression, metadata),
+            expr => (Box::new(expr), Metadata::new()),
+        };
+        metadata.insert(key.into(), value.into());
+
+        Self(Expression::Tagged {
+            expression,
+            metadata,
+        })
+    }
+
+    /// `count`x(self), every result is kept
+    pub fn repeat(self, count: impl Into<Integer>) -> Self {
+        Self(Expression::Repeat {
+            count: Box::new(Expression::Constant(count.into())),
+            expression: Box::new(self.0),
+        })
+    }
+
+    /// self ? then : otherwise
+    pub fn then_else(self, then: Expr, otherwise: Expr) -> Self {
+        Self(Expression::Conditional {
+            condition: Box::new(self.0),
+            then: Box::new(then.0),
+            otherwise: Box::new(otherwise.0),
+        })
+    }
+
+    pub fn keep_high(self, n: u32) -> Self {
+        self.truncate(SelectorOp::Keep, Affix::High, n.into())
+    }
+
+    pub fn keep_low(self, n: u32) -> Self {
+        self.truncate(SelectorOp::Keep, Affix::Low, n.into())
+    }
+
+    pub fn drop_high(self, n: u32) -> Self {
+        self.truncate(SelectorOp::Drop, Affix::High, n.into())
+    }
+
+    pub fn drop_low(self, n: u32) -> Self {
+        self.truncate(SelectorOp::Drop, Affix::Low, n.into())
+    }
+
+    /// Explode on the highest face
+    pub fn explode(self) -> Self {
+        self.augment(Augmentation::Explode { selector: None })
+    }
+
+    /// Explode on the highest face and add the explosions to the dice
+    pub fn compound(self) -> Self {
+        self.augment(Augmentation::Compound { selector: None })
+    }
+
+    fn truncate(self, op: SelectorOp, affix: Affix, n: PositiveInteger) -> Self {
+        self.augment(Augmentation::Truncate {
+            op,
+            affix,
+            n: Some(n),
+        })
+    }
+
+    /// Only the dice and the groups take augmentations, anything else is
+    /// put into a group of its own first
+    pub fn augment(self, augmentation: Augmentation) -> Self {
+        let mut expr = match self.0 {
+            dice @ (Expression::Dice { .. } | Expression::Weighted { .. }) => dice,
+            group @ Expression::Group { .. } => group,
+            other => Expression::Group {
+                members: vec![other],
+                augmentations: SmallVec::new(),
+            },
+        };
+
+        if let Expression::Dice { augmentations, .. }
+        | Expression::Group { augmentations, .. }
+        | Expression::Weighted { augmentations, .. } = &mut expr
+        {
+            augmentations.push(augmentation);
+        }
+
+        Self(expr)
+    }
+
+    pub fn build(self) -> Expression {
+        self.0
+    }
+}
+
+impl From<Expression> for Expr {
+    fn from(expr: Expression) -> Self {
+        Self(expr)
+    }
+}
+
+impl From<Expr> for Expression {
+    fn from(expr: Expr) -> Self {
+        expr.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use crate::{
+        dialect::{Foundry, Roll20},
+        interpreter::{DiceRollTag, StandardNaiveRoller},
+        parser::{parse, parse_dialect},
+        prelude::{advanced_simplify, RollerOptions},
+        simplify::Steps,
+        syntax::{Expr, Expression, Selector},
+    };
+
+    // The trees are cloned by the rollers, a variant growing the enum
+    // should be boxed instead
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn test_expression_size() {
+        assert!(std::mem::size_of::<Expression>() <= 88);
+    }
+
+    #[test]
+    fn test_augmentation_display() {
+        for input in [
+            "4d6kh3",
+            "2d20dl",
+            "8d6k>=3 + 4d6d<2",
+            "3d6e + 2d8e2",
+            "d6! + 2d10!>=9",
+            "d4!=1",
+            "10d10s>=8f=1",
+            "{2d6, d8 + 3}kh1",
+            "d20kl1e!",
+        ] {
+            let expr = parse(input).unwrap();
+            assert_eq!(expr.to_string(), input);
+            assert_eq!(parse(&expr.to_string()).unwrap(), expr);
         }
+
+        let expr = parse_dialect("3d6!!>5 + d20ro<2 + d20cs>19cf<3", &Roll20).unwrap();
+        assert_eq!(expr.to_string(), "3d6!!>=5 + d20ro<=2 + d20cs>=19cf<=3");
+
+        let expr = parse_dialect("d20min10 + 4d6max5 + d6rr<3", &Foundry).unwrap();
+        assert_eq!(expr.to_string(), "d20min10 + 4d6max5 + d6r<3");
+    }
+
+    #[test]
+    fn test_builder() {
+        let cases = [
+            (
+                Expr::dice(3, 6).keep_high(2).plus(Expr::constant(4)),
+                "3d6kh2 + 4",
+            ),
+            (Expr::die(20).minus(Expr::die(4).negate()), "d20 - -d4"),
+            (
+                Expr::call("max", [Expr::dice(2, 6), Expr::die(12)]),
+                "max(2d6, d12)",
+            ),
+            (
+                Expr::group([Expr::dice(2, 6), Expr::die(8).plus(Expr::constant(3))]).keep_high(1),
+                "{2d6, d8 + 3}kh1",
+            ),
+            (Expr::dice(4, 6).drop_low(1).repeat(6), "6x(4d6dl1)"),
+            (
+                Expr::die(20)
+                    .greater_than(Expr::constant(14))
+                    .then_else(Expr::dice(2, 6).explode(), Expr::constant(0)),
+                "d20 > 14 ? 2d6! : 0",
+            ),
+            (
+                Expr::reference("attack")
+                    .annotate("crit")
+                    .times(Expr::constant(2)),
+                "@attack [crit] * 2",
+            ),
+        ];
+
+        for (built, input) in cases {
+            assert_eq!(built.build(), parse(input).unwrap(), "{input}");
+        }
+
+        assert_eq!(
+            Expr::reference("attack").keep_high(1).build(),
+            Expr::group([Expr::reference("attack")])
+                .keep_high(1)
+                .build()
+        );
+    }
+
+    #[test]
+    fn test_selector_matches() {
+        let huge: u128 = 99999999999999999999;
+        let selector = |relation, inclusive| Selector {
+            relation,
+            inclusive,
+            n: huge.into(),
+        };
+
+        assert!(selector(Ordering::Less, false).matches(i64::MAX));
+        assert!(selector(Ordering::Less, true).matches(i64::MIN));
+        assert!(!selector(Ordering::Greater, true).matches(i64::MAX));
+        assert!(!selector(Ordering::Equal, false).matches(20));
+
+        let mut roller = StandardNaiveRoller::new_seeded(0);
+        let crit = parse_dialect("d20cs>99999999999999999999", &Roll20).unwrap();
+        let value = roller.roll_naive(crit).unwrap();
+        assert!(value.dice().all(|d| !d.tag.contains(DiceRollTag::SUCCESS)));
+        let kept = roller.roll_naive(parse("4d6k<99999999999999999999").unwrap());
+        assert_eq!(kept.unwrap().dice().count(), 4);
+    }
+
+    #[test]
+    fn test_metadata() {
+        let attack = Expr::die(20).tag("token", "goblin-3").tag("color", "red");
+        let expr = attack
+            .plus(
+                Expr::dice(2, 1)
+                    .tag("sheet", "str")
+                    .times(Expr::constant(2)),
+            )
+            .build();
+
+        // Rolled and written out as if it was not there
+        assert_eq!(expr.to_string(), "d20 + 2d1 * 2");
+        let mut roller = StandardNaiveRoller::new_seeded(0);
+        let plain = parse("d20 + 2d1 * 2").unwrap();
+        assert_eq!(
+            roller.roll_naive(&expr).unwrap().total(),
+            StandardNaiveRoller::new_seeded(0)
+                .roll_naive(plain)
+                .unwrap()
+                .total()
+        );
+
+        let json = serde_json::to_string(&expr).unwrap();
+        assert_eq!(serde_json::from_str::<Expression>(&json).unwrap(), expr);
+
+        let simplified = advanced_simplify(expr, &RollerOptions::default(), Steps::all());
+        let Expression::Binop { lhs, rhs, .. } = &simplified else {
+            panic!("{simplified:?}");
+        };
+        let metadata = lhs.metadata().unwrap();
+        assert_eq!(metadata["token"], "goblin-3");
+        assert_eq!(metadata["color"], "red");
+
+        // The dice are gone, the tag on them stays
+        let Expression::Binop { lhs, .. } = &**rhs else {
+            panic!("{rhs:?}");
+        };
+        assert_eq!(**lhs, Expr::constant(2).tag("sheet", "str").build());
     }
 }
@@ -0,0 +1,131 @@
+//! Blades in the Dark and the other Forged in the Dark games
+
+use std::fmt::Display;
+
+use rand::Rng;
+
+use crate::{
+    interpreter::{NaiveRoller, RollerResult, StandardNaiveRoller, TaggedDiceRoll},
+    syntax::{Expr, Expression},
+};
+
+/// What the highest dice of a pool makes of the action, from the worst
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Tier {
+    /// 1 to 3
+    Failure,
+    /// 4 or 5, a success with a consequence
+    Partial,
+    /// A 6
+    Success,
+    /// Two or more 6s
+    Critical,
+}
+
+/// `critical`, `success`, `partial success` and `failure`
+impl Display for Tier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Tier::Failure => "failure",
+            Tier::Partial => "partial success",
+            Tier::Success => "success",
+            Tier::Critical => "critical",
+        })
+    }
+}
+
+/// How an action went, see `action`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActionRoll {
+    pub tier: Tier,
+    /// The dice of the pool, the ones not kept are discarded
+    pub dice: Vec<TaggedDiceRoll>,
+}
+
+impl ActionRoll {
+    /// The dice that decided the tier
+    pub fn result(&self) -> i64 {
+        self.dice
+            .iter()
+            .find(|d| !d.is_discarded())
+            .map_or(0, |d| d.value)
+    }
+}
+
+/// `Nd6kh1`, the highest dice of the pool. A pool of none rolls `2d6kl1`,
+/// the lowest of two.
+pub fn pool(size: u32) -> Expression {
+    let pool = match size {
+        0 => Expr::dice(2, 6).keep_low(1),
+        size => Expr::dice(size, 6).keep_high(1),
+    };
+
+    pool.build()
+}
+
+/// Roll a pool of the given amount of dice
+pub fn action(size: u32) -> RollerResult<ActionRoll> {
+    action_with(&mut StandardNaiveRoller::default(), size)
+}
+
+/// `action` with the dice from the given roller
+pub fn action_with<R: Rng>(roller: &mut NaiveRoller<R>, size: u32) -> RollerResult<ActionRoll> {
+    let value = roller.roll_naive(pool(size))?;
+    let dice = value.dice().copied().collect::<Vec<_>>();
+
+    // The lowest of the two dice of an empty pool is never a critical
+    let sixes = dice.iter().filter(|d| d.value == 6).count();
+    let tier = match value.total()? {
+        6 if size > 0 && sixes >= 2 => Tier::Critical,
+        6 => Tier::Success,
+        4 | 5 => Tier::Partial,
+        _ => Tier::Failure,
+    };
+
+    Ok(ActionRoll { tier, dice })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        interpreter::StandardNaiveRoller,
+        parser::parse,
+        systems::{
+            blades::{action_with, pool, ActionRoll, Tier},
+            fixed,
+        },
+    };
+
+    #[test]
+    fn test_action() {
+        assert_eq!(pool(3), parse("3d6kh1").unwrap());
+        assert_eq!(pool(0), parse("2d6kl1").unwrap());
+
+        let tier = |size, face| action_with(&mut fixed(face), size).unwrap().tier;
+        assert_eq!(tier(3, 6), Tier::Critical);
+        assert_eq!(tier(1, 6), Tier::Success);
+        assert_eq!(tier(3, 5), Tier::Partial);
+        assert_eq!(tier(3, 4), Tier::Partial);
+        assert_eq!(tier(3, 3), Tier::Failure);
+        // The two dice of an empty pool are never a critical
+        assert_eq!(tier(0, 6), Tier::Success);
+
+        let roll =
+            |seed, size| action_with(&mut StandardNaiveRoller::new_seeded(seed), size).unwrap();
+        let values = |roll: &ActionRoll| roll.dice.iter().map(|d| d.value).collect::<Vec<_>>();
+
+        let success = roll(6, 3);
+        assert_eq!(values(&success), [3, 5, 6]);
+        assert_eq!((success.result(), success.tier), (6, Tier::Success));
+        assert_eq!(success.dice.iter().filter(|d| d.is_discarded()).count(), 2);
+
+        let partial = roll(0, 3);
+        assert_eq!(values(&partial), [5, 5, 1]);
+        assert_eq!((partial.result(), partial.tier), (5, Tier::Partial));
+
+        // The empty pool keeps the lowest of its two dice
+        let empty = roll(2, 0);
+        assert_eq!(values(&empty), [1, 2]);
+        assert_eq!((empty.result(), empty.tier), (1, Tier::Failure));
+    }
+}
@@ -0,0 +1,134 @@
+//! Burning Wheel, pools of d6 against an obstacle
+
+use rand::Rng;
+
+use crate::{
+    interpreter::{NaiveRoller, RollerResult, StandardNaiveRoller, TaggedDiceRoll},
+    syntax::{Augmentation, Expr, Expression},
+};
+
+use super::{at_least, roll_chained};
+
+/// The shade of an ability, the lowest face that is a success
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Shade {
+    /// 4 and up
+    #[default]
+    Black,
+    /// 3 and up
+    Grey,
+    /// 2 and up
+    White,
+}
+
+impl Shade {
+    pub fn target(self) -> u32 {
+        match self {
+            Shade::Black => 4,
+            Shade::Grey => 3,
+            Shade::White => 2,
+        }
+    }
+}
+
+/// How a test went, see `test`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObstacleTest {
+    /// Every dice rolled, the ones the open-ended 6s rolled included
+    pub dice: Vec<TaggedDiceRoll>,
+    pub successes: i64,
+    pub obstacle: i64,
+}
+
+impl ObstacleTest {
+    /// The successes meet the obstacle
+    pub fn passed(&self) -> bool {
+        self.successes >= self.obstacle
+    }
+
+    /// How many successes are left over, negative when the test failed
+    pub fn margin(&self) -> i64 {
+        self.successes - self.obstacle
+    }
+}
+
+/// `Nd6s>=4`, every 6 of an open-ended roll rolls another dice, `Nd6!s>=4`
+pub fn pool(exponent: u32, shade: Shade, open_ended: bool) -> Expression {
+    let dice = Expr::dice(exponent, 6);
+    let dice = if open_ended { dice.explode() } else { dice };
+
+    dice.augment(Augmentation::CountSuccesses {
+        selector: at_least(shade.target()),
+    })
+    .build()
+}
+
+/// Roll a black shaded pool against the obstacle
+pub fn test(exponent: u32, obstacle: i64) -> RollerResult<ObstacleTest> {
+    test_with(
+        &mut StandardNaiveRoller::default(),
+        exponent,
+        Shade::Black,
+        false,
+        obstacle,
+    )
+}
+
+/// `test` with the dice from the given roller, the shade of the ability
+/// and the open-ended 6s
+pub fn test_with<R: Rng>(
+    roller: &mut NaiveRoller<R>,
+    exponent: u32,
+    shade: Shade,
+    open_ended: bool,
+    obstacle: i64,
+) -> RollerResult<ObstacleTest> {
+    // An open-ended 6 rolled by another 6 is open-ended too
+    let value = roll_chained(roller, pool(exponent, shade, open_ended))?;
+
+    Ok(ObstacleTest {
+        dice: value.dice().copied().collect(),
+        successes: value.total()?,
+        obstacle,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        interpreter::StandardNaiveRoller,
+        parser::parse,
+        systems::burning_wheel::{pool, test_with, Shade},
+    };
+
+    #[test]
+    fn test_obstacle() {
+        assert_eq!(pool(4, Shade::Black, false), parse("4d6s>=4").unwrap());
+        assert_eq!(pool(5, Shade::Grey, true), parse("5d6!s>=3").unwrap());
+
+        let mut roller = StandardNaiveRoller::new_seeded(0);
+        let mut open = false;
+
+        for _ in 0..200 {
+            let test = test_with(&mut roller, 4, Shade::Black, true, 3).unwrap();
+            let sixes = test.dice.iter().filter(|d| d.value == 6).count();
+            assert_eq!(test.dice.len(), 4 + sixes);
+            open |= sixes > 0;
+
+            let successes = test.dice.iter().filter(|d| d.value >= 4).count() as i64;
+            assert_eq!(test.successes, successes);
+            assert_eq!(test.margin(), successes - 3);
+            assert_eq!(test.passed(), successes >= 3);
+
+            let test = test_with(&mut roller, 3, Shade::White, false, 2).unwrap();
+            assert_eq!(test.dice.len(), 3);
+            assert_eq!(
+                test.successes,
+                test.dice.iter().filter(|d| d.value >= 2).count() as i64
+            );
+        }
+
+        assert!(open);
+        assert!(!roller.options().chain_explosions());
+    }
+}
@@ -0,0 +1,131 @@
+//! Cortex Prime, pools of dice of every size
+
+use rand::Rng;
+
+use crate::{
+    interpreter::{NaiveRoller, RollerResult, StandardNaiveRoller, TaggedDiceRoll},
+    syntax::{Expr, Expression},
+};
+
+use super::roll_members;
+
+/// The effect die when no dice is left over
+pub const DEFAULT_EFFECT: u32 = 4;
+
+/// How a pool went, see `roll_pool`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CortexRoll {
+    /// The sides of every dice with what it came up, in the order of the
+    /// pool. The two dice of the total are kept, the rest are discarded.
+    pub dice: Vec<(u32, TaggedDiceRoll)>,
+    /// The two dice kept added up, a 1 adds nothing
+    pub total: i64,
+    /// The sides of the largest dice left over, not counting the 1s
+    pub effect: u32,
+    /// Every dice that came up 1
+    pub hitches: usize,
+}
+
+impl CortexRoll {
+    /// Every dice came up 1
+    pub fn is_botch(&self) -> bool {
+        !self.dice.is_empty() && self.hitches == self.dice.len()
+    }
+}
+
+/// `{d8, d6, d10}kh2`, every dice of the pool as a member of a group so
+/// that the two highest can be kept whatever their sides
+pub fn pool(sides: &[u32]) -> Expression {
+    Expr::group(sides.iter().map(|&sides| Expr::die(sides)))
+        .keep_high(2)
+        .build()
+}
+
+/// Roll a pool of the dice with the given sides
+pub fn roll_pool(sides: &[u32]) -> RollerResult<CortexRoll> {
+    roll_pool_with(&mut StandardNaiveRoller::default(), sides)
+}
+
+/// `roll_pool` with the dice from the given roller
+pub fn roll_pool_with<R: Rng>(
+    roller: &mut NaiveRoller<R>,
+    sides: &[u32],
+) -> RollerResult<CortexRoll> {
+    let members = roll_members(roller, pool(sides))?;
+    let dice = sides.iter().copied().zip(members).collect::<Vec<_>>();
+
+    let is_hitch = |d: &TaggedDiceRoll| d.value == 1;
+    let total = dice
+        .iter()
+        .filter(|(_, d)| !d.is_discarded() && !is_hitch(d))
+        .map(|(_, d)| d.value)
+        .sum();
+    let effect = dice
+        .iter()
+        .filter(|(_, d)| d.is_discarded() && !is_hitch(d))
+        .map(|(sides, _)| *sides)
+        .max()
+        .unwrap_or(DEFAULT_EFFECT);
+
+    Ok(CortexRoll {
+        hitches: dice.iter().filter(|(_, d)| is_hitch(d)).count(),
+        dice,
+        total,
+        effect,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        interpreter::StandardNaiveRoller,
+        parser::parse,
+        systems::{
+            cortex::{pool, roll_pool_with, CortexRoll},
+            fixed,
+        },
+    };
+
+    #[test]
+    fn test_roll_pool() {
+        assert_eq!(pool(&[8, 6, 10]), parse("{d8, d6, d10}kh2").unwrap());
+
+        let sides = [10, 8, 6, 4];
+        let summary = |roll: &CortexRoll| (roll.total, roll.effect, roll.hitches);
+        let values = |roll: &CortexRoll| {
+            roll.dice
+                .iter()
+                .map(|(sides, d)| (*sides, d.value, d.is_discarded()))
+                .collect::<Vec<_>>()
+        };
+
+        // The d6 and the d4 can't reach a 7, the d6 is the effect
+        let roll = roll_pool_with(&mut fixed(7), &sides).unwrap();
+        assert_eq!(summary(&roll), (14, 6, 0));
+        assert!(!roll.is_botch());
+
+        let roll = roll_pool_with(&mut fixed(1), &sides).unwrap();
+        assert_eq!(summary(&roll), (0, 4, 4));
+        assert!(roll.is_botch());
+
+        // The d6 left over came up a hitch, so the d4 is the effect
+        let roll = roll_pool_with(&mut StandardNaiveRoller::new_seeded(0), &sides).unwrap();
+        assert_eq!(
+            values(&roll),
+            [(10, 8, false), (8, 7, false), (6, 1, true), (4, 3, true)]
+        );
+        assert_eq!(summary(&roll), (15, 4, 1));
+
+        // The d4 is kept over the d8 and the d6
+        let roll = roll_pool_with(&mut StandardNaiveRoller::new_seeded(1), &sides).unwrap();
+        assert_eq!(
+            values(&roll),
+            [(10, 5, false), (8, 2, true), (6, 2, true), (4, 3, false)]
+        );
+        assert_eq!(summary(&roll), (8, 8, 0));
+
+        // A single dice has nothing left over for the effect
+        let roll = roll_pool_with(&mut fixed(9), &[12]).unwrap();
+        assert_eq!(summary(&roll), (9, 4, 0));
+    }
+}
@@ -0,0 +1,111 @@
+//! The d20 systems, as in the 5th edition of D&D
+
+use rand::Rng;
+
+use crate::{
+    interpreter::{NaiveRoller, RollerResult, StandardNaiveRoller},
+    syntax::Expr,
+};
+
+/// How a group check went, see `group_check`
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupCheck {
+    pub dc: i64,
+    /// The total of every participant, in the order they were given
+    pub totals: Vec<i64>,
+    pub successes: usize,
+    /// At least half of the group succeeded
+    pub passed: bool,
+    /// The chance of the group passing, before anything was rolled
+    pub probability: f64,
+}
+
+/// Every participant rolls `d20` plus their modifier against the DC, the
+/// group passes when at least half of them meet it
+pub fn group_check(modifiers: &[i64], dc: i64) -> RollerResult<GroupCheck> {
+    group_check_with(&mut StandardNaiveRoller::default(), modifiers, dc)
+}
+
+/// `group_check` with the dice from the given roller
+pub fn group_check_with<R: Rng>(
+    roller: &mut NaiveRoller<R>,
+    modifiers: &[i64],
+    dc: i64,
+) -> RollerResult<GroupCheck> {
+    let totals = modifiers
+        .iter()
+        .map(|&modifier| {
+            let check = Expr::die(20).plus(Expr::constant(modifier)).build();
+            roller.roll_naive(check)?.total()
+        })
+        .collect::<RollerResult<Vec<_>>>()?;
+
+    let successes = totals.iter().filter(|&&total| total >= dc).count();
+
+    Ok(GroupCheck {
+        dc,
+        successes,
+        passed: 2 * successes >= modifiers.len(),
+        probability: group_probability(modifiers, dc),
+        totals,
+    })
+}
+
+/// The chance of `d20 + modifier` meeting the DC
+pub fn check_probability(modifier: i64, dc: i64) -> f64 {
+    (21 - (dc - modifier)).clamp(0, 20) as f64 / 20.
+}
+
+/// The exact chance of at least half of the group meeting the DC
+pub fn group_probability(modifiers: &[i64], dc: i64) -> f64 {
+    // The chances of every amount of successes, adding one participant
+    // at a time
+    let mut successes = vec![1.];
+    for &modifier in modifiers {
+        let p = check_probability(modifier, dc);
+
+        let mut next = vec![0.; successes.len() + 1];
+        for (k, chance) in successes.iter().enumerate() {
+            next[k] += chance * (1. - p);
+            next[k + 1] += chance * p;
+        }
+        successes = next;
+    }
+
+    let needed = (modifiers.len() + 1) / 2;
+    successes[needed..].iter().sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        interpreter::StandardNaiveRoller,
+        systems::d20::{check_probability, group_check, group_check_with, group_probability},
+    };
+
+    #[test]
+    fn test_group_check() {
+        assert_eq!(check_probability(5, 15), 0.55);
+        assert_eq!(check_probability(0, 25), 0.);
+        assert_eq!(check_probability(10, 5), 1.);
+
+        // Two out of four, each at a half
+        assert!((group_probability(&[0, 0, 0, 0], 11) - 11. / 16.).abs() < 1e-12);
+        assert_eq!(group_probability(&[], 10), 1.);
+        assert_eq!(group_probability(&[0, 0, 0], 30), 0.);
+
+        let mut roller = StandardNaiveRoller::new_seeded(5);
+        let check = group_check_with(&mut roller, &[3, 5, -1], 12).unwrap();
+        assert_eq!(check.totals.len(), 3);
+        assert_eq!(
+            check.successes,
+            check.totals.iter().filter(|&&t| t >= 12).count()
+        );
+        assert_eq!(check.passed, check.successes >= 2);
+
+        let check = group_check(&[100, 100, -100, -100], 50).unwrap();
+        assert_eq!(check.successes, 2);
+        assert!(check.passed);
+        assert_eq!(check.probability, 1.);
+    }
+}
@@ -0,0 +1,81 @@
+//! Earthdawn, the step numbers and their dice
+
+use crate::syntax::{Expr, Expression};
+
+// The dice of the steps up to 14 and what is added to them, every dice
+// explodes
+const STEPS: [(&[(u32, u32)], i64); 14] = [
+    (&[(1, 4)], -2),
+    (&[(1, 4)], -1),
+    (&[(1, 4)], 0),
+    (&[(1, 6)], 0),
+    (&[(1, 8)], 0),
+    (&[(1, 10)], 0),
+    (&[(1, 12)], 0),
+    (&[(2, 6)], 0),
+    (&[(1, 8), (1, 6)], 0),
+    (&[(2, 8)], 0),
+    (&[(1, 10), (1, 8)], 0),
+    (&[(2, 10)], 0),
+    (&[(1, 12), (1, 10)], 0),
+    (&[(2, 12)], 0),
+];
+
+/// The dice of the step, `step(7)` is `d12!` and `step(13)` is
+/// `d12! + d10!`. Past step 14 every 7 steps add a `d12!`, step 15 is
+/// `d12! + 2d6!`. There are no steps below 1.
+pub fn step(n: u32) -> Option<Expression> {
+    if n == 0 {
+        return None;
+    }
+
+    let (base, extra) = if n <= 14 {
+        (n, 0)
+    } else {
+        let extra = (n - 8) / 7;
+        (n - 7 * extra, extra)
+    };
+    let (dice, modifier) = STEPS[base as usize - 1];
+
+    let exploding = |(quantity, sides): (u32, u32)| {
+        let dice = match quantity {
+            1 => Expr::die(sides),
+            quantity => Expr::dice(quantity, sides),
+        };
+        dice.explode()
+    };
+
+    let d12s = (extra > 0).then_some((extra, 12));
+    let dice = d12s.into_iter().chain(dice.iter().copied()).map(exploding);
+
+    let sum = dice.reduce(Expr::plus)?;
+    let sum = match modifier {
+        0 => sum,
+        m => sum.minus(Expr::constant(-m)),
+    };
+
+    Some(sum.build())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{parser::parse, systems::earthdawn::step};
+
+    #[test]
+    fn test_step() {
+        let step = |n| step(n).map(|expr| expr.to_string());
+
+        assert_eq!(step(0), None);
+        assert_eq!(step(1).as_deref(), Some("d4! - 2"));
+        assert_eq!(step(3).as_deref(), Some("d4!"));
+        assert_eq!(step(7).as_deref(), Some("d12!"));
+        assert_eq!(step(8).as_deref(), Some("2d6!"));
+        assert_eq!(step(13).as_deref(), Some("d12! + d10!"));
+        assert_eq!(step(14).as_deref(), Some("2d12!"));
+        assert_eq!(step(15).as_deref(), Some("d12! + 2d6!"));
+        assert_eq!(step(21).as_deref(), Some("d12! + 2d12!"));
+        assert_eq!(step(22).as_deref(), Some("2d12! + 2d6!"));
+
+        assert_eq!(super::step(13), Some(parse("d12! + d10!").unwrap()));
+    }
+}
@@ -0,0 +1,216 @@
+//! The narrative dice of Genesys and the Star Wars games of FFG
+
+use rand::Rng;
+
+use crate::{
+    interpreter::{NaiveRoller, Value},
+    symbols::{
+        Symbol::{self, *},
+        SymbolDie, Symbols,
+    },
+};
+
+/// The six dice, the good ones first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NarrativeDie {
+    /// The green d8
+    Ability,
+    /// The yellow d12
+    Proficiency,
+    /// The blue d6
+    Boost,
+    /// The purple d8
+    Difficulty,
+    /// The red d12
+    Challenge,
+    /// The black d6
+    Setback,
+}
+
+impl NarrativeDie {
+    /// The faces of the dice as printed
+    pub fn die(self) -> SymbolDie {
+        let faces: &[&[Symbol]] = match self {
+            NarrativeDie::Ability => &[
+                &[],
+                &[Success],
+                &[Success],
+                &[Success, Success],
+                &[Advantage],
+                &[Advantage],
+                &[Success, Advantage],
+                &[Advantage, Advantage],
+            ],
+            NarrativeDie::Proficiency => &[
+                &[],
+                &[Success],
+                &[Success],
+                &[Success, Success],
+                &[Success, Success],
+                &[Advantage],
+                &[Success, Advantage],
+                &[Success, Advantage],
+                &[Success, Advantage],
+                &[Advantage, Advantage],
+                &[Advantage, Advantage],
+                &[Triumph],
+            ],
+            NarrativeDie::Boost => &[
+                &[],
+                &[],
+                &[Success],
+                &[Success, Advantage],
+                &[Advantage, Advantage],
+                &[Advantage],
+            ],
+            NarrativeDie::Difficulty => &[
+                &[],
+                &[Failure],
+                &[Failure, Failure],
+                &[Threat],
+                &[Threat],
+                &[Threat],
+                &[Threat, Threat],
+                &[Failure, Threat],
+            ],
+            NarrativeDie::Challenge => &[
+                &[],
+                &[Failure],
+                &[Failure],
+                &[Failure, Failure],
+                &[Failure, Failure],
+                &[Threat],
+                &[Threat],
+                &[Failure, Threat],
+                &[Failure, Threat],
+                &[Threat, Threat],
+                &[Threat, Threat],
+                &[Despair],
+            ],
+            NarrativeDie::Setback => &[&[], &[], &[Failure], &[Failure], &[Threat], &[Threat]],
+        };
+
+        SymbolDie::new(faces.iter().map(|face| Symbols::of(face)))
+    }
+}
+
+/// How many of every dice there are in a pool
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct NarrativePool {
+    pub ability: u32,
+    pub proficiency: u32,
+    pub boost: u32,
+    pub difficulty: u32,
+    pub challenge: u32,
+    pub setback: u32,
+}
+
+impl NarrativePool {
+    /// Every dice of the pool in the order of `NarrativeDie`
+    pub fn dice(&self) -> impl Iterator<Item = NarrativeDie> {
+        use NarrativeDie::*;
+
+        [
+            (Ability, self.ability),
+            (Proficiency, self.proficiency),
+            (Boost, self.boost),
+            (Difficulty, self.difficulty),
+            (Challenge, self.challenge),
+            (Setback, self.setback),
+        ]
+        .into_iter()
+        .flat_map(|(die, n)| std::iter::repeat(die).take(n as usize))
+    }
+}
+
+/// A rolled pool, see `roll_pool`
+#[derive(Debug, Clone, PartialEq)]
+pub struct NarrativeRoll {
+    /// The face every dice came up on, in the order of `NarrativePool::dice`
+    pub faces: Vec<(NarrativeDie, Symbols)>,
+    /// Every symbol rolled, before they cancel out
+    pub symbols: Symbols,
+}
+
+impl NarrativeRoll {
+    /// The symbols left after cancelling
+    pub fn net(&self) -> Symbols {
+        self.symbols.cancel()
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.symbols.is_success()
+    }
+
+    pub fn value(&self) -> Value {
+        Value::Symbols(self.symbols)
+    }
+}
+
+pub fn roll_pool(pool: &NarrativePool) -> NarrativeRoll {
+    roll_pool_with(&mut rand::thread_rng(), pool)
+}
+
+/// `roll_pool` with the dice from the given generator, a roller lends its
+/// own with `NaiveRoller::rng_mut`
+pub fn roll_pool_with(rng: &mut impl Rng, pool: &NarrativePool) -> NarrativeRoll {
+    let faces = pool
+        .dice()
+        .map(|die| (die, die.die().roll(rng)))
+        .collect::<Vec<_>>();
+    let symbols = faces.iter().map(|(_, face)| *face).sum();
+
+    NarrativeRoll { faces, symbols }
+}
+
+impl<R: Rng> NaiveRoller<R> {
+    /// Roll the narrative dice with the generator of the roller
+    pub fn roll_narrative(&mut self, pool: &NarrativePool) -> NarrativeRoll {
+        roll_pool_with(self.rng_mut(), pool)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        interpreter::{StandardNaiveRoller, Value},
+        symbols::Symbols,
+        systems::genesys::{NarrativeDie, NarrativePool},
+    };
+
+    #[test]
+    fn test_roll_pool() {
+        let all = |die: NarrativeDie| die.die().faces.into_iter().sum::<Symbols>();
+        assert_eq!(all(NarrativeDie::Ability).successes, 5);
+        assert_eq!(all(NarrativeDie::Proficiency).triumphs, 1);
+        assert_eq!(all(NarrativeDie::Challenge).despairs, 1);
+        assert_eq!(NarrativeDie::Boost.die().faces.len(), 6);
+
+        let pool = NarrativePool {
+            ability: 2,
+            proficiency: 1,
+            difficulty: 2,
+            setback: 1,
+            ..Default::default()
+        };
+        assert_eq!(pool.dice().count(), 6);
+
+        let mut roller = StandardNaiveRoller::new_seeded(0);
+        for _ in 0..100 {
+            let roll = roller.roll_narrative(&pool);
+            assert_eq!(roll.faces.len(), 6);
+            assert_eq!(
+                roll.symbols,
+                roll.faces.iter().map(|(_, face)| *face).sum::<Symbols>()
+            );
+            assert_eq!(roll.symbols.despairs, 0);
+
+            let net = roll.net();
+            assert!(net.successes == 0 || net.failures == 0);
+            assert!(net.advantages == 0 || net.threats == 0);
+            assert_eq!(roll.is_success(), net.successes > 0);
+            assert_eq!(roll.value().to_string(), net.to_string());
+            assert!(matches!(roll.value(), Value::Symbols(_)));
+        }
+    }
+}
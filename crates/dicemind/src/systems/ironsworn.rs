@@ -0,0 +1,133 @@
+//! Ironsworn, the action roll against two challenge dice
+
+use std::fmt::Display;
+
+use rand::Rng;
+
+use crate::{
+    interpreter::{NaiveRoller, RollerResult, StandardNaiveRoller},
+    syntax::{Expr, Expression},
+};
+
+use super::roll_members;
+
+/// The action score never beats a 10 on its own
+pub const MAX_ACTION_SCORE: i64 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Hit {
+    Miss,
+    Weak,
+    Strong,
+}
+
+/// `strong hit`, `weak hit` and `miss`
+impl Display for Hit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Hit::Miss => "miss",
+            Hit::Weak => "weak hit",
+            Hit::Strong => "strong hit",
+        })
+    }
+}
+
+/// How an action roll went, see `action_roll`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ActionRoll {
+    pub action_die: i64,
+    /// The action die plus the modifiers, capped at 10
+    pub action_score: i64,
+    pub challenge_dice: [i64; 2],
+    pub hit: Hit,
+    /// Both challenge dice came up the same
+    pub matched: bool,
+}
+
+/// `{d6 + modifier, d10, d10}`, the action score and the challenge dice
+pub fn action_dice(modifier: i64) -> Expression {
+    Expr::group([
+        Expr::die(6).plus(Expr::constant(modifier)),
+        Expr::die(10),
+        Expr::die(10),
+    ])
+    .build()
+}
+
+/// Roll the action die plus the stat and the adds
+pub fn action_roll(modifier: i64) -> RollerResult<ActionRoll> {
+    action_roll_with(&mut StandardNaiveRoller::default(), modifier)
+}
+
+/// `action_roll` with the dice from the given roller
+pub fn action_roll_with<R: Rng>(
+    roller: &mut NaiveRoller<R>,
+    modifier: i64,
+) -> RollerResult<ActionRoll> {
+    let members = roll_members(roller, action_dice(modifier))?;
+    let [score, first, second] = [members[0].value, members[1].value, members[2].value];
+
+    let action_score = score.min(MAX_ACTION_SCORE);
+    let beaten = [first, second]
+        .into_iter()
+        .filter(|&challenge| action_score > challenge)
+        .count();
+
+    Ok(ActionRoll {
+        action_die: score - modifier,
+        action_score,
+        challenge_dice: [first, second],
+        hit: match beaten {
+            2 => Hit::Strong,
+            1 => Hit::Weak,
+            _ => Hit::Miss,
+        },
+        matched: first == second,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        interpreter::StandardNaiveRoller,
+        parser::parse,
+        systems::{
+            fixed,
+            ironsworn::{action_dice, action_roll_with, ActionRoll, Hit},
+        },
+    };
+
+    #[test]
+    fn test_action_roll() {
+        assert_eq!(action_dice(3), parse("{d6 + 3, d10, d10}").unwrap());
+
+        let roll = action_roll_with(&mut fixed(4), 2).unwrap();
+        assert_eq!(
+            roll,
+            ActionRoll {
+                action_die: 4,
+                action_score: 6,
+                challenge_dice: [4, 4],
+                hit: Hit::Strong,
+                matched: true,
+            }
+        );
+
+        // A tie goes to the challenge dice
+        assert_eq!(action_roll_with(&mut fixed(6), 0).unwrap().hit, Hit::Miss);
+
+        // A 10 on a challenge dice can't be beaten
+        let roll = action_roll_with(&mut fixed(10), 20).unwrap();
+        assert_eq!((roll.action_die, roll.action_score), (6, 10));
+        assert_eq!(roll.hit, Hit::Miss);
+
+        let seeded =
+            |seed| action_roll_with(&mut StandardNaiveRoller::new_seeded(seed), 2).unwrap();
+        let outcome = |roll: ActionRoll| (roll.action_score, roll.challenge_dice, roll.hit);
+        assert_eq!(outcome(seeded(0)), (7, [1, 3], Hit::Strong));
+        assert_eq!(outcome(seeded(5)), (5, [1, 5], Hit::Weak));
+        assert_eq!(outcome(seeded(2)), (3, [5, 3], Hit::Miss));
+
+        assert_eq!(Hit::Weak.to_string(), "weak hit");
+    }
+}
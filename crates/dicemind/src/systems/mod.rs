@@ -0,0 +1,68 @@
+//! The rules of particular game systems, built on top of the rollers
+
+pub mod blades;
+pub mod burning_wheel;
+pub mod cortex;
+pub mod d20;
+pub mod earthdawn;
+pub mod genesys;
+pub mod ironsworn;
+pub mod savage;
+pub mod wod;
+pub mod year_zero;
+
+use std::cmp::Ordering;
+
+use rand::Rng;
+
+use crate::{
+    interpreter::{NaiveResult, NaiveRoller, NaiveValue, RollerResult, TaggedDiceRoll},
+    syntax::{Expression, Selector},
+};
+
+// Roll with the dice coming from the explosions exploding again, whatever
+// the options of the roller say
+fn roll_chained<R: Rng>(roller: &mut NaiveRoller<R>, expr: Expression) -> NaiveResult {
+    let chain = roller.options().chain_explosions();
+    roller.options_mut().set_chain_explosions(true);
+    let value = roller.roll_naive(expr);
+    roller.options_mut().set_chain_explosions(chain);
+
+    value
+}
+
+// The members of a group in their order, each with its total as the value
+// of a dice. The ones the group doesn't keep are discarded.
+fn roll_members<R: Rng>(
+    roller: &mut NaiveRoller<R>,
+    group: Expression,
+) -> RollerResult<Vec<TaggedDiceRoll>> {
+    match roller.roll_naive(group)? {
+        NaiveValue::Dice(members) => Ok(members.into_vec()),
+        _ => unreachable!("a group without counting augments rolls dice"),
+    }
+}
+
+// `>=n`
+fn at_least(n: u32) -> Selector {
+    Selector {
+        relation: Ordering::Greater,
+        inclusive: true,
+        n: n.into(),
+    }
+}
+
+// A roller with every dice on the face, as close as its sides allow, for
+// the tests of the systems
+#[cfg(test)]
+fn fixed(face: i64) -> crate::interpreter::StandardNaiveRoller {
+    use crate::{
+        interpreter::StandardNaiveRoller,
+        options::{FixedDice, RollerOptions},
+    };
+
+    let options = RollerOptions::builder()
+        .fixed_dice(FixedDice::Constant(face))
+        .build();
+    StandardNaiveRoller::new_seeded(0).with_options(options)
+}
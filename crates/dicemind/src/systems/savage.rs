@@ -0,0 +1,144 @@
+//! Savage Worlds, the trait tests of the Wild Cards
+
+use rand::Rng;
+
+use crate::{
+    interpreter::{NaiveRoller, RollerResult, StandardNaiveRoller},
+    syntax::{Expr, Expression},
+};
+
+use super::roll_members;
+
+/// The target number of a trait test, every 4 above it is a raise
+pub const TARGET_NUMBER: i64 = 4;
+
+/// Which dice of a trait test was kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SavageDie {
+    Trait,
+    Wild,
+}
+
+/// How a trait test went, see `trait_test`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraitTest {
+    pub sides: u32,
+    /// The trait die with its aces added up
+    pub trait_die: i64,
+    pub wild_die: i64,
+    pub used: SavageDie,
+    pub modifier: i64,
+    /// The higher of the two dice plus the modifier
+    pub total: i64,
+    /// Both dice came up 1, whatever the modifier
+    pub critical_failure: bool,
+}
+
+impl TraitTest {
+    /// The die came up on its highest face at least once
+    pub fn aced(&self, die: SavageDie) -> bool {
+        match die {
+            SavageDie::Trait => self.trait_die > self.sides as i64,
+            SavageDie::Wild => self.wild_die > 6,
+        }
+    }
+
+    pub fn is_success(&self) -> bool {
+        !self.critical_failure && self.total >= TARGET_NUMBER
+    }
+
+    /// Every full 4 above the target number
+    pub fn raises(&self) -> i64 {
+        if self.is_success() {
+            (self.total - TARGET_NUMBER) / 4
+        } else {
+            0
+        }
+    }
+}
+
+/// `{dN!!, d6!!}kh1`, the trait die and the wild die both ace and the
+/// higher one is kept
+pub fn trait_dice(sides: u32) -> Expression {
+    Expr::group([Expr::die(sides).compound(), Expr::die(6).compound()])
+        .keep_high(1)
+        .build()
+}
+
+/// Roll a trait with the given die plus the wild die
+pub fn trait_test(sides: u32, modifier: i64) -> RollerResult<TraitTest> {
+    trait_test_with(&mut StandardNaiveRoller::default(), sides, modifier)
+}
+
+/// `trait_test` with the dice from the given roller
+pub fn trait_test_with<R: Rng>(
+    roller: &mut NaiveRoller<R>,
+    sides: u32,
+    modifier: i64,
+) -> RollerResult<TraitTest> {
+    // The group keeps the totals of its members in order, the one that
+    // was not kept is discarded
+    let dice = roll_members(roller, trait_dice(sides))?;
+    let (trait_die, wild_die) = (dice[0].value, dice[1].value);
+
+    let used = if dice[1].is_discarded() {
+        SavageDie::Trait
+    } else {
+        SavageDie::Wild
+    };
+
+    Ok(TraitTest {
+        sides,
+        trait_die,
+        wild_die,
+        used,
+        modifier,
+        total: trait_die.max(wild_die) + modifier,
+        // A 1 never aces, so a total of 1 is a natural 1
+        critical_failure: trait_die == 1 && wild_die == 1,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        interpreter::StandardNaiveRoller,
+        systems::savage::{trait_dice, trait_test_with, SavageDie},
+    };
+
+    #[test]
+    fn test_trait_test() {
+        assert_eq!(trait_dice(8).to_string(), "{d8!!, d6!!}kh1");
+
+        let mut roller = StandardNaiveRoller::new_seeded(0);
+        let mut aced = false;
+        let mut critical = false;
+
+        for _ in 0..1000 {
+            let test = trait_test_with(&mut roller, 4, 1).unwrap();
+            let kept = match test.used {
+                SavageDie::Trait => test.trait_die,
+                SavageDie::Wild => test.wild_die,
+            };
+            assert_eq!(kept, test.trait_die.max(test.wild_die));
+            assert_eq!(test.total, kept + 1);
+            // An ace never stops on the highest face
+            assert_ne!(test.trait_die % 4, 0);
+
+            aced |= test.aced(SavageDie::Trait);
+            critical |= test.critical_failure;
+            if test.critical_failure {
+                assert_eq!(test.total, 2);
+                assert!(!test.is_success());
+            }
+            if test.is_success() {
+                assert_eq!(test.raises(), (test.total - 4) / 4);
+            } else {
+                assert_eq!(test.raises(), 0);
+            }
+        }
+
+        assert!(aced);
+        assert!(critical);
+    }
+}
@@ -0,0 +1,163 @@
+//! The dice pools of the Chronicles of Darkness
+
+use std::cmp::Ordering;
+
+use rand::Rng;
+
+use crate::{
+    interpreter::{NaiveRoller, RollerResult, StandardNaiveRoller, TaggedDiceRoll},
+    syntax::{Augmentation, Expr, Expression, Selector},
+};
+
+use super::{at_least, roll_chained};
+
+/// The successes that make a success exceptional
+pub const EXCEPTIONAL: i64 = 5;
+
+/// Which faces are rolled again and add to the pool
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Again {
+    #[default]
+    Ten,
+    Nine,
+    Eight,
+    /// Nothing is rolled again, the chance die never is
+    Never,
+}
+
+impl Again {
+    fn selector(self) -> Option<Selector> {
+        let n: u32 = match self {
+            Again::Ten => 10,
+            Again::Nine => 9,
+            Again::Eight => 8,
+            Again::Never => return None,
+        };
+
+        Some(at_least(n))
+    }
+}
+
+/// How a pool went, see `roll_pool`
+#[derive(Debug, Clone, PartialEq)]
+pub struct PoolRoll {
+    /// Every dice rolled, the ones rolled again included
+    pub dice: Vec<TaggedDiceRoll>,
+    pub successes: i64,
+    /// The pool was empty and a single chance die was rolled instead
+    pub chance: bool,
+    /// The chance die came up 1
+    pub dramatic_failure: bool,
+}
+
+impl PoolRoll {
+    pub fn is_success(&self) -> bool {
+        self.successes > 0
+    }
+
+    pub fn is_exceptional(&self) -> bool {
+        self.successes >= EXCEPTIONAL
+    }
+}
+
+/// `Nd10!>=10s>=8`, the faces rolled again explode and every 8 or more is
+/// a success. An empty pool rolls the chance die, `d10s=10`.
+pub fn pool(size: i64, again: Again) -> Expression {
+    if size <= 0 {
+        return Expr::die(10)
+            .augment(Augmentation::CountSuccesses {
+                selector: Selector {
+                    relation: Ordering::Equal,
+                    inclusive: false,
+                    n: 10u32.into(),
+                },
+            })
+            .build();
+    }
+
+    let dice = Expr::dice(size, 10);
+    let dice = match again.selector() {
+        Some(selector) => dice.augment(Augmentation::Explode {
+            selector: Some(selector),
+        }),
+        None => dice,
+    };
+
+    dice.augment(Augmentation::CountSuccesses {
+        selector: at_least(8),
+    })
+    .build()
+}
+
+/// Roll a pool of the given size with the 10-again
+pub fn roll_pool(size: i64) -> RollerResult<PoolRoll> {
+    roll_pool_with(&mut StandardNaiveRoller::default(), size, Again::Ten)
+}
+
+/// `roll_pool` with the dice from the given roller and the faces rolled
+/// again
+pub fn roll_pool_with<R: Rng>(
+    roller: &mut NaiveRoller<R>,
+    size: i64,
+    again: Again,
+) -> RollerResult<PoolRoll> {
+    // The dice rolled again are rolled again as long as they match
+    let value = roll_chained(roller, pool(size, again))?;
+
+    let chance = size <= 0;
+    let dice: Vec<_> = value.dice().copied().collect();
+
+    Ok(PoolRoll {
+        successes: value.total()?,
+        dramatic_failure: chance && dice.iter().any(|d| d.value == 1),
+        chance,
+        dice,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        interpreter::StandardNaiveRoller,
+        parser::parse,
+        systems::wod::{pool, roll_pool_with, Again},
+    };
+
+    #[test]
+    fn test_roll_pool() {
+        assert_eq!(pool(5, Again::Ten), parse("5d10!>=10s>=8").unwrap());
+        assert_eq!(pool(3, Again::Eight), parse("3d10!>=8s>=8").unwrap());
+        assert_eq!(pool(3, Again::Never), parse("3d10s>=8").unwrap());
+        assert_eq!(pool(0, Again::Ten), parse("d10s=10").unwrap());
+
+        let mut roller = StandardNaiveRoller::new_seeded(0);
+        let mut rolled_again = false;
+
+        for _ in 0..200 {
+            let roll = roll_pool_with(&mut roller, 6, Again::Nine).unwrap();
+            assert!(!roll.chance);
+            assert!(roll.dice.len() >= 6);
+            // Every 9 and 10 rolls another dice
+            let again = roll.dice.iter().filter(|d| d.value >= 9).count();
+            assert_eq!(roll.dice.len(), 6 + again);
+            rolled_again |= again > 0;
+
+            let successes = roll.dice.iter().filter(|d| d.value >= 8).count();
+            assert_eq!(roll.successes, successes as i64);
+            assert_eq!(roll.is_exceptional(), successes >= 5);
+        }
+        assert!(rolled_again);
+        assert!(!roller.options().chain_explosions());
+
+        let mut dramatic = false;
+        for _ in 0..200 {
+            let roll = roll_pool_with(&mut roller, -1, Again::Ten).unwrap();
+            assert!(roll.chance);
+            let [die] = roll.dice[..] else { panic!() };
+            assert_eq!(roll.successes, (die.value == 10) as i64);
+            assert_eq!(roll.dramatic_failure, die.value == 1);
+            dramatic |= roll.dramatic_failure;
+        }
+        assert!(dramatic);
+    }
+}
@@ -0,0 +1,230 @@
+//! The Year Zero Engine of Mutant: Year Zero and its descendants
+
+use rand::Rng;
+
+use crate::{
+    interpreter::{NaiveRoller, RollerResult, StandardNaiveRoller},
+    syntax::Expr,
+};
+
+/// The kinds of d6 in a pool, a 6 is a success on any of them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum YearZeroDie {
+    /// A 1 damages the attribute
+    Base,
+    /// A 1 does nothing and is rolled again by a push
+    Skill,
+    /// A 1 damages the gear
+    Gear,
+}
+
+impl YearZeroDie {
+    /// A 1 on the dice is a bane
+    pub fn has_banes(self) -> bool {
+        self != YearZeroDie::Skill
+    }
+}
+
+/// How many of every dice there are in a pool
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct YearZeroPool {
+    pub base: u32,
+    pub skill: u32,
+    pub gear: u32,
+}
+
+/// A rolled pool, see `roll_pool` and `push_with`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct YearZeroRoll {
+    /// Every dice with what it came up, the base dice first
+    pub dice: Vec<(YearZeroDie, i64)>,
+}
+
+impl YearZeroRoll {
+    pub fn successes(&self) -> usize {
+        self.dice.iter().filter(|(_, value)| *value == 6).count()
+    }
+
+    pub fn banes(&self, die: YearZeroDie) -> usize {
+        self.dice
+            .iter()
+            .filter(|(d, value)| *d == die && die.has_banes() && *value == 1)
+            .count()
+    }
+
+    /// The dice a push rolls again, anything but the successes and the
+    /// banes
+    pub fn pushable(&self) -> usize {
+        self.dice
+            .iter()
+            .filter(|(d, value)| !is_locked(*d, *value))
+            .count()
+    }
+}
+
+/// Both rolls of a pushed pool
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Push {
+    pub initial: YearZeroRoll,
+    pub pushed: YearZeroRoll,
+}
+
+impl Push {
+    /// The banes of the dice the push came up with, the ones of the
+    /// initial roll were already there
+    pub fn new_banes(&self, die: YearZeroDie) -> usize {
+        self.pushed
+            .banes(die)
+            .saturating_sub(self.initial.banes(die))
+    }
+}
+
+pub fn roll_pool(pool: &YearZeroPool) -> RollerResult<YearZeroRoll> {
+    roll_pool_with(&mut StandardNaiveRoller::default(), pool)
+}
+
+/// `roll_pool` with the dice from the given roller
+pub fn roll_pool_with<R: Rng>(
+    roller: &mut NaiveRoller<R>,
+    pool: &YearZeroPool,
+) -> RollerResult<YearZeroRoll> {
+    let kinds = [
+        (YearZeroDie::Base, pool.base),
+        (YearZeroDie::Skill, pool.skill),
+        (YearZeroDie::Gear, pool.gear),
+    ];
+
+    let mut dice = vec![];
+    for (die, n) in kinds {
+        dice.extend(roll_d6s(roller, n as usize)?.into_iter().map(|v| (die, v)));
+    }
+
+    Ok(YearZeroRoll { dice })
+}
+
+/// Roll every dice that is neither a success nor a bane again, once
+pub fn push_with<R: Rng>(roller: &mut NaiveRoller<R>, initial: YearZeroRoll) -> RollerResult<Push> {
+    let mut rerolled = roll_d6s(roller, initial.pushable())?.into_iter();
+
+    let dice = initial
+        .dice
+        .iter()
+        .map(|&(die, value)| {
+            if is_locked(die, value) {
+                (die, value)
+            } else {
+                (die, rerolled.next().unwrap_or(value))
+            }
+        })
+        .collect();
+
+    Ok(Push {
+        pushed: YearZeroRoll { dice },
+        initial,
+    })
+}
+
+fn is_locked(die: YearZeroDie, value: i64) -> bool {
+    value == 6 || (value == 1 && die.has_banes())
+}
+
+fn roll_d6s<R: Rng>(roller: &mut NaiveRoller<R>, n: usize) -> RollerResult<Vec<i64>> {
+    if n == 0 {
+        return Ok(vec![]);
+    }
+
+    let value = roller.roll_naive(Expr::dice(n as u64, 6).build())?;
+    Ok(value.dice().map(|d| d.value).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        interpreter::StandardNaiveRoller,
+        systems::{
+            fixed,
+            year_zero::{push_with, roll_pool_with, Push, YearZeroDie, YearZeroPool, YearZeroRoll},
+        },
+    };
+
+    #[test]
+    fn test_push() {
+        use YearZeroDie::*;
+
+        let pool = YearZeroPool {
+            base: 3,
+            skill: 2,
+            gear: 1,
+        };
+
+        // The successes stay, there is nothing to push
+        let sixes = roll_pool_with(&mut fixed(6), &pool).unwrap();
+        assert_eq!((sixes.successes(), sixes.pushable()), (6, 0));
+
+        // The push comes up all 1s, the skill dice don't count as banes
+        let initial = roll_pool_with(&mut fixed(3), &pool).unwrap();
+        assert_eq!((initial.successes(), initial.pushable()), (0, 6));
+        let push = push_with(&mut fixed(1), initial).unwrap();
+        assert_eq!(push.new_banes(Base), 3);
+        assert_eq!(push.new_banes(Gear), 1);
+        assert_eq!(push.new_banes(Skill), 0);
+
+        // The 1 on the base dice is a bane and stays, the 1s the push
+        // brings up on the skill dice are not
+        let mut roller = StandardNaiveRoller::new_seeded(0);
+        let initial = roll_pool_with(&mut roller, &pool).unwrap();
+        assert_eq!(
+            initial.dice,
+            [
+                (Base, 5),
+                (Base, 5),
+                (Base, 1),
+                (Skill, 4),
+                (Skill, 2),
+                (Gear, 5)
+            ]
+        );
+        assert_eq!(initial.pushable(), 5);
+        let push = push_with(&mut roller, initial).unwrap();
+        assert_eq!(
+            push.pushed.dice,
+            [
+                (Base, 2),
+                (Base, 5),
+                (Base, 1),
+                (Skill, 1),
+                (Skill, 1),
+                (Gear, 3)
+            ]
+        );
+        assert_eq!(push.new_banes(Base), 0);
+        assert_eq!(push.pushed.successes(), 0);
+
+        let mut roller = StandardNaiveRoller::new_seeded(3);
+        let initial = roll_pool_with(&mut roller, &pool).unwrap();
+        let push = push_with(&mut roller, initial).unwrap();
+        assert_eq!(
+            push.pushed.dice,
+            [
+                (Base, 1),
+                (Base, 3),
+                (Base, 4),
+                (Skill, 4),
+                (Skill, 6),
+                (Gear, 6)
+            ]
+        );
+        assert_eq!(push.pushed.successes(), 2);
+
+        // A push put together by hand can have fewer banes than before
+        let push = Push {
+            initial: YearZeroRoll {
+                dice: vec![(Base, 1)],
+            },
+            pushed: YearZeroRoll {
+                dice: vec![(Base, 4)],
+            },
+        };
+        assert_eq!(push.new_banes(Base), 0);
+    }
+}
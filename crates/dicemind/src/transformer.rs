@@ -0,0 +1,230 @@
+//! Rewriting the expressions node by node. The recursion is done once
+//! here, a rewrite only says what happens to a single node.
+//!
+//! ```
+//! use dicemind::{prelude::*, transformer::Transformer};
+//!
+//! // Roll every d20 with advantage
+//! let mut advantage = |expr: Expression| match expr {
+//!     Expression::Dice {
+//!         quantity: None,
+//!         power: Some(power),
+//!         augmentations,
+//!     } if *power == Expression::Constant(20.into()) && augmentations.is_empty() => {
+//!         parse("2d20kh1").unwrap()
+//!     }
+//!     expr => expr,
+//! };
+//!
+//! let expr = advantage.fold(parse("d20 + 5 + d6").unwrap());
+//! assert_eq!(expr, parse("2d20kh1 + 5 + d6").unwrap());
+//! ```
+
+use crate::{
+    parser::advantage,
+    syntax::{Affix, Expression, Integer},
+};
+
+pub trait Transformer {
+    /// Rewrite a single node, its children are already transformed
+    fn transform(&mut self, expr: Expression) -> Expression;
+
+    /// Transform the whole tree from the leaves up
+    fn fold(&mut self, expr: Expression) -> Expression {
+        let expr = fold_children(self, expr);
+        self.transform(expr)
+    }
+}
+
+impl<F: FnMut(Expression) -> Expression> Transformer for F {
+    fn transform(&mut self, expr: Expression) -> Expression {
+        self(expr)
+    }
+}
+
+/// Rolls the d20s of the arithmetic twice and keeps the higher or the
+/// lower one, `d20 + 5 > 15` becomes `2d20kh1 + 5 > 15`. The d20s in the
+/// dice, the groups and the calls are left as they are, `{d20, d20}kh1`
+/// already is an advantage.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Advantage(pub Affix);
+
+impl Transformer for Advantage {
+    fn transform(&mut self, expr: Expression) -> Expression {
+        let d20 = Expression::Constant(20.into());
+        match expr {
+            Expression::Dice {
+                quantity,
+                power: Some(power),
+                augmentations,
+            } if quantity
+                .as_deref()
+                .map_or(true, |q| q == &Expression::Constant(1.into()))
+                && *power == d20
+                && augmentations.is_empty() =>
+            {
+                advantage(d20, self.0.clone())
+            }
+            expr => expr,
+        }
+    }
+
+    fn fold(&mut self, expr: Expression) -> Expression {
+        match expr {
+            Expression::Binop { .. }
+            | Expression::UnaryNegation(_)
+            | Expression::Subexpression(_)
+            | Expression::Annotated { .. }
+            | Expression::Tagged { .. }
+            | Expression::Repeat { .. }
+            | Expression::Conditional { .. } => fold_children(self, expr),
+            expr => self.transform(expr),
+        }
+    }
+}
+
+/// Fold the children of the node and leave the node itself as is, for
+/// the transformers that override `fold` to visit the nodes top down
+pub fn fold_children<T: Transformer + ?Sized>(transformer: &mut T, expr: Expression) -> Expression {
+    use Expression as E;
+
+    match expr {
+        E::Dice {
+            quantity,
+            power,
+            augmentations,
+        } => E::Dice {
+            quantity: quantity.map(|e| fold_boxed(transformer, e)),
+            power: power.map(|e| fold_boxed(transformer, e)),
+            augmentations,
+        },
+        E::Weighted {
+            quantity,
+            faces,
+            augmentations,
+        } => E::Weighted {
+            quantity: quantity.map(|e| fold_boxed(transformer, e)),
+            faces,
+            augmentations,
+        },
+        E::Binop { operator, lhs, rhs } => E::Binop {
+            operator,
+            lhs: fold_boxed(transformer, lhs),
+            rhs: fold_boxed(transformer, rhs),
+        },
+        E::Annotated {
+            expression,
+            annotation,
+        } => E::Annotated {
+            expression: fold_boxed(transformer, expression),
+            annotation,
+        },
+        E::Tagged {
+            expression,
+            metadata,
+        } => E::Tagged {
+            expression: fold_boxed(transformer, expression),
+            metadata,
+        },
+        E::Subexpression(e) => E::Subexpression(fold_boxed(transformer, e)),
+        E::UnaryNegation(e) => E::UnaryNegation(fold_boxed(transformer, e)),
+        E::Repeat { count, expression } => E::Repeat {
+            count: fold_boxed(transformer, count),
+            expression: fold_boxed(transformer, expression),
+        },
+        E::Conditional {
+            condition,
+            then,
+            otherwise,
+        } => E::Conditional {
+            condition: fold_boxed(transformer, condition),
+            then: fold_boxed(transformer, then),
+            otherwise: fold_boxed(transformer, otherwise),
+        },
+        E::Call { function, args } => E::Call {
+            function,
+            args: args.into_iter().map(|e| transformer.fold(e)).collect(),
+        },
+        E::Group {
+            members,
+            augmentations,
+        } => E::Group {
+            members: members.into_iter().map(|e| transformer.fold(e)).collect(),
+            augmentations,
+        },
+        E::Constant(_) | E::Decimal(_) | E::Reference(_) => expr,
+    }
+}
+
+// Reuses the allocation of the child
+fn fold_boxed<T: Transformer + ?Sized>(
+    transformer: &mut T,
+    mut expr: Box<Expression>,
+) -> Box<Expression> {
+    let child = std::mem::replace(&mut *expr, Expression::Constant(Integer::default()));
+    *expr = transformer.fold(child);
+    expr
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        parser::parse,
+        syntax::{Affix, Expression, Integer},
+        transformer::{fold_children, Advantage, Transformer},
+    };
+
+    // Counts the nodes on the way down, the parents come first
+    struct Preorder(Vec<String>);
+
+    impl Transformer for Preorder {
+        fn transform(&mut self, expr: Expression) -> Expression {
+            expr
+        }
+
+        fn fold(&mut self, expr: Expression) -> Expression {
+            self.0.push(expr.to_string());
+            fold_children(self, expr)
+        }
+    }
+
+    #[test]
+    fn test_transformer() {
+        let mut double = |expr: Expression| match expr {
+            Expression::Constant(c) => Expression::Constant(c * Integer::from(2)),
+            expr => expr,
+        };
+        assert_eq!(
+            double.fold(parse("max(1, {2, 3d4}kh) ? 5x(d6 + 6) : -(7) [x]").unwrap()),
+            parse("max(2, {4, 6d8}kh) ? 10x(d12 + 12) : -(14) [x]").unwrap()
+        );
+
+        let mut preorder = Preorder(vec![]);
+        preorder.fold(parse("1 + 2 * 3").unwrap());
+        assert_eq!(preorder.0, ["1 + 2 * 3", "1", "2 * 3", "2", "3"]);
+    }
+
+    #[test]
+    fn test_advantage() {
+        let mut advantage = Advantage(Affix::High);
+        assert_eq!(
+            advantage.fold(parse("(1d20 + 5) [attack] > 15 ? 2d6 : -d20").unwrap()),
+            parse("(2d20kh1 + 5) [attack] > 15 ? 2d6 : -2d20kh1").unwrap()
+        );
+        assert_eq!(
+            Advantage(Affix::Low).fold(parse("2x(d20)").unwrap()),
+            parse("2x(2d20kl1)").unwrap()
+        );
+
+        for input in [
+            "max(d20, 10)",
+            "{d20, d20}kh1",
+            "(d20)d6",
+            "2d20",
+            "d20!",
+            "d12",
+        ] {
+            assert_eq!(advantage.fold(parse(input).unwrap()), parse(input).unwrap());
+        }
+    }
+}
@@ -1,60 +1,138 @@
-use smallvec::SmallVec;
-
-use crate::syntax::{AnnotationString, Augmentation, BinaryOperator, Expression, Integer};
+use crate::syntax::{
+    AnnotationString, Augmentation, BinaryOperator, Decimal, Expression, Face, FunctionName,
+    Integer, MacroName,
+};
 
+// The tree is only borrowed, rolling the same expression
+// over and over does not clone it
 pub trait Visitor<T> {
-    fn visit(&mut self, expr: Expression) -> T {
+    fn visit(&mut self, expr: &Expression) -> T {
         use Expression::*;
 
+        if let Some(value) = self.enter(expr) {
+            return value;
+        }
+
         match expr {
             Dice {
                 quantity,
                 power,
                 augmentations,
             } => {
-                let quantity = quantity.map(|e| self.visit(*e)).unwrap_or_else(|| self.default_quantity());
-                let power = power.map(|e| self.visit(*e)).unwrap_or_else(|| self.default_power());
+                let quantity = quantity.as_deref().map(|e| self.visit(e)).unwrap_or_else(|| self.default_quantity());
+                let power = power.as_deref().map(|e| self.visit(e)).unwrap_or_else(|| self.default_power());
 
                 self.visit_dice(quantity, power, augmentations)
             }
+            Weighted {
+                quantity,
+                faces,
+                augmentations,
+            } => {
+                let quantity = quantity
+                    .as_deref()
+                    .map(|e| self.visit(e))
+                    .unwrap_or_else(|| self.default_quantity());
+                self.visit_weighted(quantity, faces, augmentations)
+            }
             Binop { operator, lhs, rhs } => {
-                let lhs = self.visit(*lhs);
-                let rhs = self.visit(*rhs);
+                let lhs = self.visit(lhs);
+                let rhs = self.visit(rhs);
 
-                self.visit_binop(operator, lhs, rhs)
+                self.visit_binop(*operator, lhs, rhs)
             }
             Constant(c) => self.visit_constant(c),
+            Decimal(d) => self.visit_decimal(d),
             Subexpression(box e) => self.visit_subexpression(e),
             Annotated {
                 expression: box expr,
                 annotation,
             } => self.visit_annotated(expr, annotation),
+            Tagged {
+                expression: box expr,
+                ..
+            } => self.visit(expr),
             UnaryNegation(box UnaryNegation(box v)) => self.visit(v),
             UnaryNegation(box v) => {
                 let v = self.visit(v);
                 self.visit_negation(v)
             }
+            Repeat {
+                count,
+                expression: box expr,
+            } => {
+                let count = self.visit(count);
+                self.visit_repeat(count, expr)
+            }
+            Call { function, args } => {
+                let args = args.iter().map(|e| self.visit(e)).collect();
+                self.visit_call(function, args)
+            }
+            Group {
+                members,
+                augmentations,
+            } => {
+                let members = members.iter().map(|e| self.visit(e)).collect();
+                self.visit_group(members, augmentations)
+            }
+            Reference(name) => self.visit_reference(name),
+            Conditional {
+                condition,
+                then: box then,
+                otherwise: box otherwise,
+            } => {
+                let condition = self.visit(condition);
+                self.visit_conditional(condition, then, otherwise)
+            }
         }
     }
 
+    // Called before every node is visited, the value returned
+    // here is used instead of visiting the node
+    fn enter(&mut self, _expr: &Expression) -> Option<T> {
+        None
+    }
+
     fn visit_negation(&mut self, value: T) -> T;
 
     fn visit_dice(
         &mut self,
         quantity: T,
         power: T,
-        augments: SmallVec<[Augmentation; 1]>,
+        augments: &[Augmentation],
     ) -> T;
 
-    fn visit_constant(&mut self, c: Integer) -> T;
+    // A dice with as many sides as there are faces unless the visitor
+    // knows better, the weights and the values of the faces are ignored
+    fn visit_weighted(&mut self, quantity: T, faces: &[Face], augments: &[Augmentation]) -> T {
+        let power = self.visit_constant(&Integer::from(faces.len()));
+        self.visit_dice(quantity, power, augments)
+    }
+
+    fn visit_group(&mut self, members: Vec<T>, augments: &[Augmentation]) -> T;
+
+    // The expression is not visited beforehand, it has
+    // to be evaluated as many times as `count` says
+    fn visit_repeat(&mut self, count: T, expr: &Expression) -> T;
+
+    // Only one of the branches should be visited
+    fn visit_conditional(&mut self, condition: T, then: &Expression, otherwise: &Expression) -> T;
+
+    fn visit_call(&mut self, function: &FunctionName, args: Vec<T>) -> T;
+
+    fn visit_reference(&mut self, name: &MacroName) -> T;
+
+    fn visit_constant(&mut self, c: &Integer) -> T;
+
+    fn visit_decimal(&mut self, d: &Decimal) -> T;
 
     fn visit_binop(&mut self, op: BinaryOperator, lhs: T, rhs: T) -> T;
 
-    fn visit_annotated(&mut self, expr: Expression, _annotation: AnnotationString) -> T {
+    fn visit_annotated(&mut self, expr: &Expression, _annotation: &AnnotationString) -> T {
         self.visit(expr)
     }
 
-    fn visit_subexpression(&mut self, subexpr: Expression) -> T {
+    fn visit_subexpression(&mut self, subexpr: &Expression) -> T {
         self.visit(subexpr)
     }
 